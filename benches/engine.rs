@@ -0,0 +1,145 @@
+// Fixed inputs so runs compare: `cargo bench -- --save-baseline before`, change the
+// engine, then `cargo bench -- --baseline before` prints the change of every benchmark.
+//
+// benchmark              input                                      baseline
+// win_check/random_3x3   1000 seeded random 3x3 boards               1.20 ms
+// win_check/random_7x7   1000 seeded random 7x7 boards, 4 in a row   4.49 ms
+// solve/empty_3x3        full search from the empty board            15.0 ms
+// solve/midgame_4x4      alpha-beta from XO.X/.OX./..../..O.          186 ms
+// lookahead/5x5_1_threads  hard CPU 4 plies ahead, ...../.X.../..O..   649 ms
+// lookahead/5x5_4_threads  the same, root moves split over 4 threads     -
+// playouts/random_1m     one million random 3x3 games                 7.01 s
+//
+// The baseline is the array board with plain alpha-beta, before bitboards and a
+// transposition table. The four-thread lookahead has no baseline of its own: compare it
+// with the one-thread run on a machine with at least four cores, on one core it can't be
+// any faster.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hint::black_box;
+use tic_tac_toe_rs::ai::{self, Cpu, Difficulty};
+use tic_tac_toe_rs::arena;
+use tic_tac_toe_rs::board::{Board, State};
+use tic_tac_toe_rs::rules::Rules;
+
+const SEED: u64 = 0x7ac7_0e5e;
+
+fn random_boards(rules: &Rules, count: usize) -> Vec<Board> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    (0..count)
+        .map(|_| {
+            let mut board = rules.new_board();
+            for i in 0..board.size() {
+                board[i] = match rng.gen_range(0..3) {
+                    0 => State::X,
+                    1 => State::O,
+                    _ => State::Empty,
+                };
+            }
+            board
+        })
+        .collect()
+}
+
+fn win_check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("win_check");
+    let sizes = [
+        ("random_3x3", Rules::default()),
+        (
+            "random_7x7",
+            Rules {
+                rows: 7,
+                cols: 7,
+                win_len: 4,
+                ..Rules::default()
+            },
+        ),
+    ];
+    for (name, rules) in sizes {
+        let boards = random_boards(&rules, 1000);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                boards
+                    .iter()
+                    .filter(|board| rules.winner(black_box(board)).is_some())
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn solve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solve");
+    group.sample_size(10);
+    let empty = Rules::default().new_board();
+    group.bench_function("empty_3x3", |b| {
+        b.iter(|| ai::solve(black_box(&empty), &Rules::default(), State::X))
+    });
+
+    let midgame: Board = "XO.X/.OX./..../..O.".parse().expect("valid position");
+    let rules = Rules {
+        rows: 4,
+        cols: 4,
+        win_len: 4,
+        ..Rules::default()
+    };
+    group.bench_function("midgame_4x4", |b| {
+        b.iter(|| ai::solve(black_box(&midgame), &rules, midgame.to_move()))
+    });
+    group.finish();
+}
+
+// The hard CPU's move on a board too big to solve, on one thread and on four
+fn lookahead(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookahead");
+    group.sample_size(10);
+    let board: Board = "...../.X.../..O../...../....."
+        .parse()
+        .expect("valid position");
+    let rules = Rules {
+        rows: 5,
+        cols: 5,
+        win_len: 4,
+        ..Rules::default()
+    };
+    let cpu = Cpu {
+        difficulty: Difficulty::Hard,
+        depth: Some(4),
+        ..Cpu::default()
+    };
+    for threads in [1, 4] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("threads to search on");
+        group.bench_function(format!("5x5_{}_threads", threads), |b| {
+            b.iter(|| {
+                pool.install(|| {
+                    ai::choose_move(black_box(&board), &rules, State::X, cpu, &mut ai::First)
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn playouts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("playouts");
+    group.sample_size(10);
+    let rules = Rules::default();
+    group.bench_function("random_1m", |b| {
+        b.iter(|| {
+            let mut rng = StdRng::seed_from_u64(SEED);
+            (0..1_000_000)
+                .map(|_| arena::play_out(&rules, Cpu::default(), Cpu::default(), &mut rng).1)
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, win_check, solve, lookahead, playouts);
+criterion_main!(benches);
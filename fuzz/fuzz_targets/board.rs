@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tic_tac_toe_rs::board::Board;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(board) = text.parse::<Board>() {
+        let again: Board = board.to_string().parse().expect("compact form parses back");
+        assert_eq!(again, board);
+        // Symmetries stay on the board
+        assert_eq!(board.canonical().size(), board.size());
+    }
+});
@@ -0,0 +1,39 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tic_tac_toe_rs::game::parse_move;
+use tic_tac_toe_rs::rules::{Rules, Variant};
+use tic_tac_toe_rs::settings::Numbering;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let variants = [
+        Rules::default(),
+        Rules::gravity(6, 7),
+        Rules::cube(),
+        Rules {
+            variant: Variant::Wild,
+            ..Rules::default()
+        },
+        Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        },
+    ];
+    for (rules, numbering) in variants
+        .into_iter()
+        .flat_map(|rules| [(rules, Numbering::ZeroBased), (rules, Numbering::OneBased)])
+    {
+        let Some(parsed) = parse_move(input, &rules, numbering) else {
+            continue;
+        };
+        // Coordinates are checked by the parser, a plain index later by pick_player
+        if input.contains(',') {
+            assert!(parsed.index < rules.cells());
+        }
+        assert_eq!(parsed.mark.is_some(), rules.variant == Variant::Wild);
+        assert!(parsed.digit.map_or(true, |digit| (1..=9).contains(&digit)));
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tic_tac_toe_rs::replay::Replay;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(replay) = Replay::parse(text) {
+        let json = serde_json::to_string(&replay).expect("replay serializes");
+        let again = Replay::parse(&json).expect("saved replay parses back");
+        assert_eq!(again, replay);
+    }
+});
@@ -0,0 +1,277 @@
+use crate::ai::{self, Difficulty};
+use crate::board::{Board, State};
+use crate::game::Status;
+use crate::migrations;
+use crate::rules::{Rules, Variant};
+use crate::save;
+use crate::timestamp::rfc3339_now;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Bumped on every incompatible change of the stats file, with a step in `migrations`
+pub const STATS_VERSION: u32 = 1;
+
+// Wins in a row for `Achievement::Streak`
+const STREAK: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    FirstWin,
+    // Won without the CPU ever being one move from a line
+    Untouched,
+    // Won with no more moves than a line needs
+    QuickWin,
+    Streak,
+    // Tied the hard CPU, which never loses
+    PerfectDraw,
+}
+
+impl Achievement {
+    pub const ALL: [Achievement; 5] = [
+        Achievement::FirstWin,
+        Achievement::Untouched,
+        Achievement::QuickWin,
+        Achievement::Streak,
+        Achievement::PerfectDraw,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Achievement::FirstWin => "First win",
+            Achievement::Untouched => "Untouched",
+            Achievement::QuickWin => "Quick win",
+            Achievement::Streak => "On a roll",
+            Achievement::PerfectDraw => "Perfect draw",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Achievement::FirstWin => "Win a round",
+            Achievement::Untouched => "Win without the Cpu ever threatening a line",
+            Achievement::QuickWin => "Win in the fewest moves possible",
+            Achievement::Streak => "Win 10 rounds in a row",
+            Achievement::PerfectDraw => "Tie against the hard Cpu",
+        }
+    }
+
+    // Whether `round`, finishing a run of `streak` wins (counting it), unlocks this
+    pub fn unlocked_by(self, round: &RoundRecord, streak: u32) -> bool {
+        let won = round.status == Status::Won(round.human_mark);
+        match self {
+            Achievement::FirstWin => won,
+            Achievement::Untouched => {
+                let cpu = round.human_mark.opponent();
+                // Threats only mean something where lines belong to one mark
+                let classic = matches!(round.rules.variant, Variant::Classic | Variant::Gravity);
+                won && classic
+                    && round
+                        .boards
+                        .iter()
+                        .filter(|(mover, _)| *mover == cpu)
+                        .all(|(_, board)| {
+                            ai::find_threats(board, &round.rules, cpu).next().is_none()
+                        })
+            }
+            Achievement::QuickWin => {
+                let moves = round
+                    .boards
+                    .iter()
+                    .filter(|(mover, _)| *mover == round.human_mark);
+                won && moves.count() <= round.rules.win_len
+            }
+            Achievement::Streak => won && streak >= STREAK,
+            Achievement::PerfectDraw => {
+                round.status == Status::Tie && round.difficulty == Some(Difficulty::Hard)
+            }
+        }
+    }
+}
+
+// A finished round against the CPU, as the achievements see it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundRecord {
+    pub rules: Rules,
+    pub human_mark: State,
+    // Strength of the built-in CPU, None when another player stood in for it
+    pub difficulty: Option<Difficulty>,
+    // Each mover and the board after its move
+    pub boards: Vec<(State, Board)>,
+    pub status: Status,
+}
+
+// What carries over between sessions: unlocked achievements with when, and the current
+// win streak
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub version: u32,
+    pub streak: u32,
+    pub unlocked: BTreeMap<Achievement, String>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            version: STATS_VERSION,
+            streak: 0,
+            unlocked: BTreeMap::new(),
+        }
+    }
+}
+
+impl Stats {
+    // A missing file is a fresh start
+    pub fn load(path: impl AsRef<Path>) -> Result<Stats, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Stats::default());
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|err| format!("Not a stats file: {}", err))?;
+        let value = migrations::upgrade(&migrations::STATS, value)?;
+        serde_json::from_value(value).map_err(|err| format!("Invalid stats file: {}", err))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        save::write_atomic(path.as_ref(), &(json + "\n"))
+    }
+
+    // Counts the round and returns the achievements it newly unlocked
+    pub fn record(&mut self, round: &RoundRecord) -> Vec<Achievement> {
+        self.streak = match round.status {
+            Status::Won(mark) if mark == round.human_mark => self.streak + 1,
+            _ => 0,
+        };
+        let date = rfc3339_now();
+        let unlocked: Vec<Achievement> = Achievement::ALL
+            .into_iter()
+            .filter(|achievement| !self.unlocked.contains_key(achievement))
+            .filter(|achievement| achievement.unlocked_by(round, self.streak))
+            .collect();
+        for &achievement in &unlocked {
+            self.unlocked.insert(achievement, date.clone());
+        }
+        unlocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // A round on the 3x3 board with the player as X, moving first
+    fn round(moves: &[usize], difficulty: Difficulty) -> RoundRecord {
+        let rules = Rules::default();
+        let mut board = rules.new_board();
+        let mut boards = Vec::new();
+        let mut mark = State::X;
+        for &index in moves {
+            board[index] = mark;
+            boards.push((mark, board));
+            mark = mark.opponent();
+        }
+        let status = match rules.winner(&board) {
+            Some(winner) => Status::Won(winner),
+            None if board.is_full() => Status::Tie,
+            None => Status::InProgress,
+        };
+        RoundRecord {
+            rules,
+            human_mark: State::X,
+            difficulty: Some(difficulty),
+            boards,
+            status,
+        }
+    }
+
+    const QUICK: [usize; 5] = [0, 3, 1, 4, 2];
+    // The Cpu never has two in a line with the third cell free
+    const UNTOUCHED: [usize; 7] = [4, 1, 0, 8, 6, 3, 2];
+    const LOST: [usize; 6] = [0, 3, 1, 4, 8, 5];
+    const TIE: [usize; 9] = [0, 4, 8, 2, 6, 3, 5, 7, 1];
+
+    #[test]
+    fn first_win_needs_a_win() {
+        let unlocks =
+            |moves: &[usize]| Achievement::FirstWin.unlocked_by(&round(moves, Difficulty::Easy), 1);
+        assert!(unlocks(&QUICK));
+        assert!(!unlocks(&LOST));
+        assert!(!unlocks(&TIE));
+    }
+
+    #[test]
+    fn untouched_win_never_faced_a_threat() {
+        let unlocks = |moves: &[usize]| {
+            Achievement::Untouched.unlocked_by(&round(moves, Difficulty::Easy), 1)
+        };
+        assert!(unlocks(&UNTOUCHED));
+        // O on 3 and 4 threatened the middle row
+        assert!(!unlocks(&QUICK));
+    }
+
+    #[test]
+    fn quick_win_takes_a_line_of_moves() {
+        let unlocks =
+            |moves: &[usize]| Achievement::QuickWin.unlocked_by(&round(moves, Difficulty::Easy), 1);
+        assert!(unlocks(&QUICK));
+        assert!(!unlocks(&UNTOUCHED));
+    }
+
+    #[test]
+    fn streak_counts_ten_wins() {
+        let won = round(&QUICK, Difficulty::Easy);
+        assert!(!Achievement::Streak.unlocked_by(&won, STREAK - 1));
+        assert!(Achievement::Streak.unlocked_by(&won, STREAK));
+        let lost = round(&LOST, Difficulty::Easy);
+        assert!(!Achievement::Streak.unlocked_by(&lost, STREAK));
+    }
+
+    #[test]
+    fn perfect_draw_is_against_the_hard_cpu() {
+        let unlocks = |moves: &[usize], difficulty| {
+            Achievement::PerfectDraw.unlocked_by(&round(moves, difficulty), 0)
+        };
+        assert!(unlocks(&TIE, Difficulty::Hard));
+        assert!(!unlocks(&TIE, Difficulty::Medium));
+        assert!(!unlocks(&QUICK, Difficulty::Hard));
+    }
+
+    #[test]
+    fn stats_unlock_once_and_keep_the_streak() {
+        let mut stats = Stats::default();
+        let won = round(&QUICK, Difficulty::Easy);
+        assert_eq!(
+            stats.record(&won),
+            [Achievement::FirstWin, Achievement::QuickWin]
+        );
+        assert_eq!(stats.streak, 1);
+        assert!(stats.record(&won).is_empty());
+        assert_eq!(stats.streak, 2);
+        stats.record(&round(&LOST, Difficulty::Easy));
+        assert_eq!(stats.streak, 0);
+        for _ in 0..STREAK - 1 {
+            assert!(stats.record(&won).is_empty());
+        }
+        assert_eq!(stats.record(&won), [Achievement::Streak]);
+    }
+
+    #[test]
+    fn stats_round_trip_through_a_file() {
+        let path = env::temp_dir().join(format!("ttt-stats-{}.json", process::id()));
+        assert_eq!(Stats::load(&path), Ok(Stats::default()));
+        let mut stats = Stats::default();
+        stats.record(&round(&TIE, Difficulty::Hard));
+        stats.save(&path).unwrap();
+        let loaded = Stats::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Ok(stats));
+    }
+}
@@ -0,0 +1,1533 @@
+use crate::board::{Board, Line, MoveList, State, MAX_CELLS};
+use crate::rules::Rules;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::str::FromStr;
+#[cfg(feature = "rand")]
+use rand::{Rng, RngCore};
+#[cfg(feature = "std")]
+use rayon::prelude::*;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// Score of a won position, reduced by the plies it takes to get there
+pub const WIN: i32 = 1000;
+
+// Most empty cells the hard CPU searches to the end, larger positions are searched
+// `Cpu::depth` plies ahead and estimated there
+pub const MAX_SEARCH_CELLS: usize = 10;
+
+// Plies the hard CPU looks ahead on boards too big to search to the end, unless told
+pub const DEFAULT_DEPTH: usize = 4;
+
+// Largest `estimate` of a position nobody has won yet, well below any win's score
+pub const MAX_ESTIMATE: i32 = WIN / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Difficulty {
+    // Any legal move
+    #[default]
+    Easy,
+    // Takes wins and blocks the opponent's, otherwise a move that leaves the opponent no
+    // fork, as its personality weighs them
+    Medium,
+    // Perfect play by full search, looking a few moves ahead on boards too big to solve
+    Hard,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Difficulty, String> {
+        match s.to_lowercase().as_str() {
+            "easy" | "random" => Ok(Difficulty::Easy),
+            "medium" | "heuristic" => Ok(Difficulty::Medium),
+            "hard" | "perfect" => Ok(Difficulty::Hard),
+            _ => Err(format!("Unknown difficulty: {} (easy, medium or hard)", s)),
+        }
+    }
+}
+
+// What the heuristic CPU goes for first, used where it doesn't search to the end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Personality {
+    // Wins, then blocks, otherwise any move
+    #[default]
+    Balanced,
+    // Builds its own threats and forks before blocking
+    Aggressive,
+    // Blocks lines and forks before building its own
+    Defensive,
+}
+
+impl Personality {
+    pub fn weights(self) -> Weights {
+        match self {
+            Personality::Balanced => Weights {
+                win: 100,
+                block: 50,
+                ..Weights::default()
+            },
+            Personality::Aggressive => Weights {
+                win: 100,
+                fork: 60,
+                block: 40,
+                threat: 15,
+                block_fork: 0,
+                center: 5,
+                corner: 3,
+            },
+            Personality::Defensive => Weights {
+                win: 100,
+                block: 60,
+                block_fork: 40,
+                fork: 10,
+                threat: 2,
+                center: 6,
+                corner: 4,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Personality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Personality::Balanced => write!(f, "balanced"),
+            Personality::Aggressive => write!(f, "aggressive"),
+            Personality::Defensive => write!(f, "defensive"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl FromStr for Personality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Personality, String> {
+        match s.to_lowercase().as_str() {
+            "balanced" => Ok(Personality::Balanced),
+            "aggressive" => Ok(Personality::Aggressive),
+            "defensive" => Ok(Personality::Defensive),
+            _ => Err(format!(
+                "Unknown personality: {} (balanced, aggressive or defensive)",
+                s
+            )),
+        }
+    }
+}
+
+// Points the heuristic scorer gives a move for each thing it does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Weights {
+    // Completes a line
+    pub win: i32,
+    // Takes the cell the opponent would complete a line on
+    pub block: i32,
+    // Leaves two or more ways to win next move
+    pub fork: i32,
+    // Leaves exactly one way to win next move
+    pub threat: i32,
+    // Takes the cell the opponent would fork on
+    pub block_fork: i32,
+    pub center: i32,
+    pub corner: i32,
+}
+
+// A CPU player: how strong it is and, below perfect play, what it prefers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cpu {
+    pub difficulty: Difficulty,
+    pub personality: Personality,
+    // Plies the hard CPU looks ahead where it can't search to the end, DEFAULT_DEPTH if None
+    pub depth: Option<usize>,
+}
+
+impl From<Difficulty> for Cpu {
+    fn from(difficulty: Difficulty) -> Self {
+        Cpu {
+            difficulty,
+            ..Cpu::default()
+        }
+    }
+}
+
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.difficulty, self.personality)
+    }
+}
+
+// Why the CPU picked its move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    // Nothing made it better than any other move
+    Random,
+    Win,
+    // The opponent's line that the move cut
+    Block { line: Line },
+    Fork,
+    BlockFork,
+    Threat,
+    Center,
+    Corner,
+    // Highest value in a learned table
+    Learned,
+    // A perfect reply from an opening book
+    Book,
+    // Perfect play: `score` is the value of the position before the move, as `solve`
+    // gives it, and `plies` the length of the game from there on
+    Search { score: i32, plies: usize },
+    // The best `score` found looking `depth` plies ahead, as `search_to_depth` gives it
+    Lookahead { score: i32, depth: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveDecision {
+    pub index: usize,
+    pub reason: Reason,
+    // What the search behind the move did, for moves found by searching
+    pub search: Option<SearchResult>,
+}
+
+// The CPU's move for `mark` and why, None when the board is full.
+// Ties between equally good moves are broken by `rng`.
+pub fn choose_move(
+    board: &Board,
+    rules: &Rules,
+    mark: State,
+    cpu: Cpu,
+    rng: &mut impl Tiebreak,
+) -> Option<MoveDecision> {
+    let moves = rules.legal_moves(board);
+    if moves.is_empty() {
+        return None;
+    }
+    // Empty cells, not legal moves: a gravity board has few columns but a deep game left
+    let searchable = board.count(State::Empty) <= MAX_SEARCH_CELLS;
+
+    let decision = match cpu.difficulty {
+        Difficulty::Easy => MoveDecision {
+            index: pick(&moves, rng),
+            reason: Reason::Random,
+            search: None,
+        },
+        Difficulty::Hard if searchable => {
+            let (index, search) = search_move(board, rules, mark, &moves, None, rng);
+            // Scores count plies from after the move, the position before it is one more away
+            let score = search.score - search.score.signum();
+            let plies = match score {
+                0 => board.count(State::Empty),
+                _ => (WIN - score.abs()) as usize,
+            };
+            MoveDecision {
+                index,
+                reason: Reason::Search { score, plies },
+                search: Some(search),
+            }
+        }
+        Difficulty::Hard => {
+            let depth = cpu.depth.unwrap_or(DEFAULT_DEPTH).max(1);
+            let (index, search) = search_move(board, rules, mark, &moves, Some(depth), rng);
+            MoveDecision {
+                index,
+                reason: Reason::Lookahead {
+                    score: search.score,
+                    depth,
+                },
+                search: Some(search),
+            }
+        }
+        Difficulty::Medium => {
+            let weights = cpu.personality.weights();
+            heuristic_move(board, rules, mark, &moves, &weights, true, rng)
+        }
+    };
+    Some(decision)
+}
+
+// The heuristic CPU's move with any weights, e.g. a tuned set; None when the board is full
+pub fn choose_weighted(
+    board: &Board,
+    rules: &Rules,
+    mark: State,
+    weights: &Weights,
+    rng: &mut impl Tiebreak,
+) -> Option<MoveDecision> {
+    let moves = rules.legal_moves(board);
+    if moves.is_empty() {
+        return None;
+    }
+    Some(heuristic_move(
+        board, rules, mark, &moves, weights, false, rng,
+    ))
+}
+
+// The best scored move. With `avoid_forks`, when there is nothing to win or block, only
+// moves that leave the opponent no fork are scored, unless every move leaves one.
+fn heuristic_move(
+    board: &Board,
+    rules: &Rules,
+    mark: State,
+    moves: &[usize],
+    weights: &Weights,
+    avoid_forks: bool,
+    rng: &mut impl Tiebreak,
+) -> MoveDecision {
+    let forced =
+        winning_moves(board, rules, mark) > 0 || winning_moves(board, rules, mark.opponent()) > 0;
+    let safe: MoveList = moves
+        .iter()
+        .copied()
+        .filter(|&index| !avoid_forks || forced || !hands_fork(board, rules, mark, index))
+        .collect();
+    let candidates = if safe.is_empty() { moves } else { &safe };
+    let index = pick(
+        &preferred_moves(board, rules, mark, candidates, weights),
+        rng,
+    );
+    let reason = match Features::of(board, rules, mark, index, weights).reason(weights) {
+        // Nothing but the fork it keeps the opponent from made the move
+        Reason::Random if candidates.len() < moves.len() => Reason::BlockFork,
+        reason => reason,
+    };
+    MoveDecision {
+        index,
+        reason,
+        search: None,
+    }
+}
+
+// Whether `mark` playing `index` lets the opponent fork next move. A move that threatens
+// a line forces the reply on it, so only that reply counts; a move that forks itself
+// wins first.
+fn hands_fork(board: &Board, rules: &Rules, mark: State, index: usize) -> bool {
+    let mut after = *board;
+    after[index] = mark;
+    let forks_with = |reply: usize| {
+        let mut replied = after;
+        replied[reply] = mark.opponent();
+        has_fork(&replied, rules, mark.opponent())
+    };
+    match winning_moves(&after, rules, mark) {
+        0 => rules.legal_moves(&after).into_iter().any(forks_with),
+        1 => find_threats(&after, rules, mark)
+            .map(|threat| threat.completing)
+            .find(|cell| rules.legal_moves(&after).contains(cell))
+            .is_some_and(forks_with),
+        _ => false,
+    }
+}
+
+// Breaks ties between equally good moves: any of rand's generators, or without rand the
+// seeded XorShift or the deterministic First
+pub trait Tiebreak {
+    // A number in 0..n, n > 0
+    fn below(&mut self, n: usize) -> usize;
+}
+
+#[cfg(feature = "rand")]
+impl<R: RngCore> Tiebreak for R {
+    fn below(&mut self, n: usize) -> usize {
+        self.gen_range(0..n)
+    }
+}
+
+// xorshift64*, good enough to vary the CPU's moves in builds without rand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    // Its state can't be 0, so that seed is replaced
+    pub fn new(seed: u64) -> Self {
+        XorShift(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+}
+
+impl Tiebreak for XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) % n as u64) as usize
+    }
+}
+
+// Always the first of the tied moves, for CPUs that play the same game every time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct First;
+
+impl Tiebreak for First {
+    fn below(&mut self, _n: usize) -> usize {
+        0
+    }
+}
+
+// Anything that can make the CPU's moves
+#[cfg(feature = "rand")]
+pub trait Player: fmt::Debug + fmt::Display {
+    fn choose_move(
+        &self,
+        board: &Board,
+        rules: &Rules,
+        mark: State,
+        rng: &mut dyn RngCore,
+    ) -> Option<MoveDecision>;
+}
+
+#[cfg(feature = "rand")]
+impl Player for Cpu {
+    fn choose_move(
+        &self,
+        board: &Board,
+        rules: &Rules,
+        mark: State,
+        mut rng: &mut dyn RngCore,
+    ) -> Option<MoveDecision> {
+        choose_move(board, rules, mark, *self, &mut rng)
+    }
+}
+
+fn pick(candidates: &[usize], rng: &mut impl Tiebreak) -> usize {
+    candidates[rng.below(candidates.len())]
+}
+
+// All moves with the highest heuristic score
+fn preferred_moves(
+    board: &Board,
+    rules: &Rules,
+    mark: State,
+    moves: &[usize],
+    weights: &Weights,
+) -> MoveList {
+    let mut best = MoveList::new();
+    let mut best_score = i32::MIN;
+    for &index in moves {
+        let score = Features::of(board, rules, mark, index, weights).score(weights);
+        if score > best_score {
+            best_score = score;
+            best.clear();
+        }
+        if score == best_score {
+            best.push(index);
+        }
+    }
+    best
+}
+
+// What a move does, as far as the heuristic scorer can tell
+struct Features {
+    win: bool,
+    // The opponent's line the move cuts
+    block: Option<Line>,
+    fork: bool,
+    threat: bool,
+    block_fork: bool,
+    center: bool,
+    corner: bool,
+}
+
+impl Features {
+    fn of(board: &Board, rules: &Rules, mark: State, index: usize, weights: &Weights) -> Self {
+        let mut mine = *board;
+        mine[index] = mark;
+        let mut theirs = *board;
+        theirs[index] = mark.opponent();
+        let block = find_threats(board, rules, mark.opponent())
+            .find(|threat| threat.completing == index)
+            .map(|threat| threat.line);
+        // Counting follow-up wins is the expensive part, skipped when nothing rewards it
+        let follow_ups = if weights.fork != 0 || weights.threat != 0 {
+            winning_moves(&mine, rules, mark)
+        } else {
+            0
+        };
+        let block_fork =
+            weights.block_fork != 0 && winning_moves(&theirs, rules, mark.opponent()) >= 2;
+
+        let area = board.rows() * board.cols();
+        let (layer, row, col) = (
+            index / area,
+            index % area / board.cols(),
+            index % board.cols(),
+        );
+        let middle = |at: usize, len: usize| len % 2 == 1 && at == len / 2;
+        let edge = |at: usize, len: usize| at == 0 || at + 1 == len;
+        Features {
+            win: find_threats(board, rules, mark).any(|threat| threat.completing == index),
+            block,
+            fork: follow_ups >= 2,
+            threat: follow_ups == 1,
+            block_fork,
+            center: middle(row, board.rows())
+                && middle(col, board.cols())
+                && middle(layer, board.layers()),
+            corner: edge(row, board.rows())
+                && edge(col, board.cols())
+                && edge(layer, board.layers()),
+        }
+    }
+
+    fn score(&self, weights: &Weights) -> i32 {
+        [
+            (self.win, weights.win),
+            (self.block.is_some(), weights.block),
+            (self.fork, weights.fork),
+            (self.threat, weights.threat),
+            (self.block_fork, weights.block_fork),
+            (self.center, weights.center),
+            (self.corner, weights.corner),
+        ]
+        .iter()
+        .filter(|(applies, _)| *applies)
+        .map(|(_, weight)| weight)
+        .sum()
+    }
+
+    // The weighted feature that counted most
+    fn reason(&self, weights: &Weights) -> Reason {
+        let block = self.block.map(|line| Reason::Block { line });
+        [
+            (self.win.then_some(Reason::Win), weights.win),
+            (block, weights.block),
+            (self.fork.then_some(Reason::Fork), weights.fork),
+            (self.threat.then_some(Reason::Threat), weights.threat),
+            (
+                self.block_fork.then_some(Reason::BlockFork),
+                weights.block_fork,
+            ),
+            (self.center.then_some(Reason::Center), weights.center),
+            (self.corner.then_some(Reason::Corner), weights.corner),
+        ]
+        .into_iter()
+        .filter(|&(_, weight)| weight > 0)
+        .filter_map(|(reason, weight)| Some((reason?, weight)))
+        .max_by_key(|&(_, weight)| weight)
+        .map_or(Reason::Random, |(reason, _)| reason)
+    }
+}
+
+// A line `mark` is one move away from completing: all its cells but `completing` are
+// taken by `mark`. With gravity the completing cell may not be playable yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threat {
+    pub line: Line,
+    pub completing: usize,
+}
+
+impl Threat {
+    // The cells `mark` already holds
+    pub fn occupied(&self) -> impl Iterator<Item = usize> {
+        let completing = self.completing;
+        self.line.cells().filter(move |&cell| cell != completing)
+    }
+}
+
+// Every threat of `mark`, found in one pass over the lines without allocating. An empty
+// cell that completes two lines shows up once for each.
+pub fn find_threats<'a>(
+    board: &'a Board,
+    rules: &Rules,
+    mark: State,
+) -> impl Iterator<Item = Threat> + 'a {
+    board.lines(rules.win_len).filter_map(move |line| {
+        let mut completing = None;
+        for cell in line.cells() {
+            match board[cell] {
+                state if state == mark => (),
+                State::Empty if completing.is_none() => completing = Some(cell),
+                _ => return None,
+            }
+        }
+        Some(Threat {
+            line,
+            completing: completing?,
+        })
+    })
+}
+
+#[cfg(feature = "std")]
+pub fn threats(board: &Board, rules: &Rules, mark: State) -> Vec<Threat> {
+    find_threats(board, rules, mark).collect()
+}
+
+// How many ways `mark` has to complete a line with its next move, stopping at two
+fn winning_moves(board: &Board, rules: &Rules, mark: State) -> usize {
+    let legal = rules.legal_moves(board);
+    let mut cells = MoveList::new();
+    for threat in find_threats(board, rules, mark) {
+        if legal.contains(&threat.completing) && !cells.contains(&threat.completing) {
+            cells.push(threat.completing);
+            if cells.len() == 2 {
+                break;
+            }
+        }
+    }
+    cells.len()
+}
+
+// Whether `mark` can complete a line in two different places next move
+pub fn has_fork(board: &Board, rules: &Rules, mark: State) -> bool {
+    winning_moves(board, rules, mark) >= 2
+}
+
+// The best move by search, `rng` choosing among equally good ones: by perfect play, or
+// looking `depth` plies ahead including the move itself. The result covers the search of
+// every move, its pv starting with the move chosen.
+fn search_move(
+    board: &Board,
+    rules: &Rules,
+    mark: State,
+    moves: &[usize],
+    depth: Option<usize>,
+    rng: &mut impl Tiebreak,
+) -> (usize, SearchResult) {
+    let search_after = |index: usize| {
+        let mut child = *board;
+        child[index] = mark;
+        let depth = depth.map_or(usize::MAX, |depth| depth - 1);
+        let search = search_to_depth(&child, rules, mark.opponent(), depth, &mut || false);
+        debug_assert!(search.is_some(), "a search that is never stopped finishes");
+        let mut result = search.unwrap_or_default();
+        result.score = -result.score;
+        result
+    };
+    // Every move's subtree is searched on its own, with std on all of rayon's threads and
+    // each with a table of its own.
+    // The results come back in move order, so which moves are kept doesn't depend on
+    // which thread finished first.
+    let mut results = [SearchResult::default(); MAX_CELLS];
+    #[cfg(feature = "std")]
+    results[..moves.len()]
+        .par_iter_mut()
+        .zip(moves)
+        .for_each(|(result, &index)| *result = search_after(index));
+    #[cfg(not(feature = "std"))]
+    for (result, &index) in results.iter_mut().zip(moves) {
+        *result = search_after(index);
+    }
+    let results = &results[..moves.len()];
+
+    // Positions in `moves` of the best ones
+    let mut best = MoveList::new();
+    let mut best_score = i32::MIN;
+    for (at, result) in results.iter().enumerate() {
+        if result.score > best_score {
+            best_score = result.score;
+            best.clear();
+        }
+        if result.score == best_score {
+            best.push(at);
+        }
+    }
+    let chosen = pick(&best, rng);
+    let mut pv = MoveList::new();
+    pv.push(moves[chosen]);
+    pv.extend_from_slice(&results[chosen].pv);
+    let search = SearchResult {
+        score: best_score,
+        pv,
+        nodes: 1 + results.iter().map(|result| result.nodes).sum::<u64>(),
+        depth: depth.map_or(pv.len(), |depth| depth.min(moves.len())),
+    };
+    (moves[chosen], search)
+}
+
+// Perfect-play value of the position for the side to move: positive wins,
+// negative loses, 0 is a draw. Quicker wins and slower losses score higher.
+pub fn evaluate(board: &Board, rules: &Rules, to_move: State) -> i32 {
+    solve(board, rules, to_move).0
+}
+
+// `evaluate` plus the principal variation: the moves of best play to the end
+pub fn solve(board: &Board, rules: &Rules, to_move: State) -> (i32, MoveList) {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!("search", nodes = tracing::field::Empty).entered();
+
+    let mut search = Search {
+        rules,
+        nodes: 0,
+        depth: i32::MAX,
+        stop: &mut || false,
+        stopped: false,
+        #[cfg(feature = "std")]
+        table: HashMap::new(),
+    };
+    let mut board = *board;
+    let mut pv = MoveList::new();
+    let score = search.negamax(&mut board, to_move, 0, -WIN - 1, WIN + 1, &mut pv);
+
+    #[cfg(feature = "tracing")]
+    span.record("nodes", search.nodes);
+    (score, pv)
+}
+
+// What a search found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchResult {
+    pub score: i32,
+    pub pv: MoveList,
+    pub nodes: u64,
+    // Plies searched, never more than the empty cells
+    pub depth: usize,
+}
+
+// Searches `depth` plies ahead, giving positions still open there their `estimate`. `stop`
+// is asked now and then; None if it said to stop before the search was done.
+pub fn search_to_depth(
+    board: &Board,
+    rules: &Rules,
+    to_move: State,
+    depth: usize,
+    stop: &mut dyn FnMut() -> bool,
+) -> Option<SearchResult> {
+    let mut search = Search {
+        rules,
+        nodes: 0,
+        depth: depth.min(i32::MAX as usize) as i32,
+        stop,
+        stopped: false,
+        #[cfg(feature = "std")]
+        table: HashMap::new(),
+    };
+    let mut board = *board;
+    let mut pv = MoveList::new();
+    let score = search.negamax(&mut board, to_move, 0, -WIN - 1, WIN + 1, &mut pv);
+    (!search.stopped).then_some(SearchResult {
+        score,
+        pv,
+        nodes: search.nodes,
+        depth: depth.min(board.count(State::Empty)),
+    })
+}
+
+// Nodes between two calls of the stop check
+const STOP_INTERVAL: u64 = 1024;
+
+struct Search<'a> {
+    rules: &'a Rules,
+    // Positions visited, for profiling
+    nodes: u64,
+    // Plies to look ahead
+    depth: i32,
+    stop: &'a mut dyn FnMut() -> bool,
+    // Once set, every node returns at once and the result is thrown away
+    stopped: bool,
+    // Positions already searched, one table per search so threads never share one
+    #[cfg(feature = "std")]
+    table: HashMap<(u64, State), Entry>,
+}
+
+// Largest board `Board::key` tells apart from every other, bigger ones go without a table
+#[cfg(feature = "std")]
+const KEYED_CELLS: usize = 40;
+
+// What the search found below a position it may reach again by another order of moves
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+struct Entry {
+    // Forced results count their plies from this position, not from the root
+    score: i32,
+    bound: Bound,
+    // Plies that were left to search, only a search as deep may reuse the entry
+    plies: i32,
+    pv: MoveList,
+}
+
+// Whether an entry's score is the position's value or only a limit on it, after the
+// search stopped early at a cutoff
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+impl Search<'_> {
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        to_move: State,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut MoveList,
+    ) -> i32 {
+        self.nodes += 1;
+        pv.clear();
+        // Only the previous mover can have just completed a line
+        if board.has_line(to_move.opponent(), self.rules.win_len) {
+            return -(WIN - ply);
+        }
+        let moves = self.rules.legal_moves(board);
+        if moves.is_empty() {
+            return 0;
+        }
+        #[cfg(feature = "std")]
+        let key = (board.size() <= KEYED_CELLS).then(|| (board.key(), to_move));
+        #[cfg(feature = "std")]
+        if let Some(entry) = key.and_then(|key| self.table.get(&key)) {
+            let score = from_node(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if entry.plies == self.depth - ply && usable {
+                *pv = entry.pv;
+                return score;
+            }
+        }
+        if ply >= self.depth {
+            let score = estimate(board, self.rules, to_move);
+            #[cfg(feature = "std")]
+            self.remember(key, score, Bound::Exact, ply, pv);
+            return score;
+        }
+        if self.nodes.is_multiple_of(STOP_INTERVAL) && (self.stop)() {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        #[cfg(feature = "std")]
+        let first_alpha = alpha;
+
+        let mut best = -WIN - 1;
+        let mut line = MoveList::new();
+        for index in moves {
+            board[index] = to_move;
+            let score = -self.negamax(board, to_move.opponent(), ply + 1, -beta, -alpha, &mut line);
+            board[index] = State::Empty;
+            if score > best {
+                best = score;
+                pv.clear();
+                pv.push(index);
+                pv.extend_from_slice(&line);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        #[cfg(feature = "std")]
+        if !self.stopped {
+            let bound = match best {
+                s if s <= first_alpha => Bound::Upper,
+                s if s >= beta => Bound::Lower,
+                _ => Bound::Exact,
+            };
+            self.remember(key, best, bound, ply, pv);
+        }
+        best
+    }
+
+    #[cfg(feature = "std")]
+    fn remember(
+        &mut self,
+        key: Option<(u64, State)>,
+        score: i32,
+        bound: Bound,
+        ply: i32,
+        pv: &MoveList,
+    ) {
+        if let Some(key) = key {
+            let entry = Entry {
+                score: to_node(score, ply),
+                bound,
+                plies: self.depth - ply,
+                pv: *pv,
+            };
+            self.table.insert(key, entry);
+        }
+    }
+}
+
+// A score seen from the root as the table keeps it, forced results counted from the
+// position `ply` plies down
+#[cfg(feature = "std")]
+fn to_node(score: i32, ply: i32) -> i32 {
+    match score {
+        s if s > MAX_ESTIMATE => s + ply,
+        s if s < -MAX_ESTIMATE => s - ply,
+        s => s,
+    }
+}
+
+// The other way round, a score from the table as the search at `ply` sees it
+#[cfg(feature = "std")]
+fn from_node(score: i32, ply: i32) -> i32 {
+    match score {
+        s if s > MAX_ESTIMATE => s - ply,
+        s if s < -MAX_ESTIMATE => s + ply,
+        s => s,
+    }
+}
+
+// How good `board` looks for `mark` without searching: every line `mark` could still
+// complete counts the square of the marks already in it, the opponent's lines count
+// against. A won position is worth WIN, everything else stays within MAX_ESTIMATE.
+pub fn estimate(board: &Board, rules: &Rules, mark: State) -> i32 {
+    if board.has_line(mark, rules.win_len) {
+        return WIN;
+    }
+    if board.has_line(mark.opponent(), rules.win_len) {
+        return -WIN;
+    }
+    let mut score = 0;
+    for line in board.lines(rules.win_len) {
+        let (mut mine, mut theirs) = (0, 0);
+        for cell in line.cells() {
+            match board[cell] {
+                State::Empty => (),
+                state if state == mark => mine += 1,
+                _ => theirs += 1,
+            }
+        }
+        match (mine, theirs) {
+            (0, theirs) => score -= theirs * theirs,
+            (mine, 0) => score += mine * mine,
+            _ => (),
+        }
+    }
+    score.clamp(-MAX_ESTIMATE, MAX_ESTIMATE)
+}
+
+// Whether a search score is a forced win or loss rather than an estimate
+pub fn is_decisive(score: i32) -> bool {
+    score.abs() > MAX_ESTIMATE
+}
+
+// Human readable form of an `evaluate` score, e.g. "X wins in 2" or "draw"
+#[cfg(feature = "std")]
+pub fn describe(score: i32, to_move: State) -> String {
+    let winner = match score {
+        0 => return "draw".to_string(),
+        s if s > 0 => to_move,
+        _ => to_move.opponent(),
+    };
+    // Count only the winner's own moves
+    let plies = WIN - score.abs();
+    format!("{:?} wins in {}", winner, (plies + 1) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 3x3 board from its compact form, such as "X...O...."
+    fn board(cells: &str) -> Board {
+        let mut board = Board::new(3, 3, 1);
+        for (i, cell) in cells.chars().enumerate() {
+            board[i] = match cell {
+                'X' => State::X,
+                'O' => State::O,
+                _ => State::Empty,
+            };
+        }
+        board
+    }
+
+    // Plays the principal variation out, alternating from `to_move`
+    fn play_out(mut board: Board, to_move: State, pv: &[usize]) -> Board {
+        let mut mark = to_move;
+        for &index in pv {
+            assert_eq!(board[index], State::Empty);
+            board[index] = mark;
+            mark = mark.opponent();
+        }
+        board
+    }
+
+    #[test]
+    fn empty_board_is_a_draw_to_the_last_cell() {
+        let rules = Rules::default();
+        let (score, pv) = solve(&board("........."), &rules, State::X);
+        assert_eq!(score, 0);
+        assert_eq!(pv.len(), 9);
+        let end = play_out(board("........."), State::X, &pv);
+        assert!(end.is_full());
+        assert_eq!(rules.winner(&end), None);
+    }
+
+    #[test]
+    fn edge_reply_to_the_center_loses() {
+        let rules = Rules::default();
+        let start = board(".O..X....");
+        let (score, pv) = solve(&start, &rules, State::X);
+        assert!(score > 0);
+        // Quicker wins score higher: X's third move from here wins, five plies on
+        assert_eq!(pv.len(), 5);
+        assert_eq!(WIN - score, 5);
+        #[cfg(feature = "std")]
+        assert_eq!(describe(score, State::X), "X wins in 3");
+        assert_eq!(
+            rules.winner(&play_out(start, State::X, &pv)),
+            Some(State::X)
+        );
+        assert_eq!(evaluate(&start, &rules, State::X), score);
+    }
+
+    #[test]
+    fn lost_positions_score_below_zero() {
+        let rules = Rules::default();
+        // X threatens two lines at once
+        let start = board("XX.XO...O");
+        let (score, pv) = solve(&start, &rules, State::O);
+        assert!(score < 0);
+        assert_eq!(
+            rules.winner(&play_out(start, State::O, &pv)),
+            Some(State::X)
+        );
+    }
+
+    // The CPU runs on the injected tiebreaks alone, without rand or std
+    #[test]
+    fn injected_tiebreaks_drive_every_difficulty() {
+        let rules = Rules::default();
+        let start = board("XX..O....");
+        for difficulty in [Difficulty::Medium, Difficulty::Hard] {
+            let decision = choose_move(&start, &rules, State::O, difficulty.into(), &mut First);
+            assert_eq!(decision.map(|decision| decision.index), Some(2));
+        }
+        let easy = |seed| {
+            let mut rng = XorShift::new(seed);
+            choose_move(&start, &rules, State::O, Difficulty::Easy.into(), &mut rng)
+                .map(|decision| decision.index)
+        };
+        assert_eq!(easy(11), easy(11));
+        assert_eq!(
+            choose_move(
+                &board("XOXXOOOXX"),
+                &rules,
+                State::X,
+                Cpu::default(),
+                &mut First
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn xorshift_stays_in_range_and_follows_its_seed() {
+        let mut rng = XorShift::new(7);
+        let mut seen = [false; 9];
+        for _ in 0..200 {
+            seen[rng.below(9)] = true;
+        }
+        assert_eq!(seen, [true; 9]);
+        let draws = |seed| {
+            let mut rng = XorShift::new(seed);
+            [rng.below(1000), rng.below(1000), rng.below(1000)]
+        };
+        assert_eq!(draws(7), draws(7));
+        assert_ne!(draws(7), draws(8));
+        // A zero seed would only ever give 0
+        assert_ne!(draws(0), [0, 0, 0]);
+        assert_eq!(XorShift::new(0).below(1), 0);
+        assert_eq!(First.below(5), 0);
+    }
+
+    // Two hard CPUs without rand still play a whole game, which is a draw
+    #[test]
+    fn a_game_needs_no_rand() {
+        let rules = Rules::default();
+        let mut board = rules.new_board();
+        let (mut first, mut xorshift) = (First, XorShift::new(3));
+        let mut mark = State::X;
+        let mut plies = 0;
+        while rules.winner(&board).is_none() {
+            let hard = Cpu::from(Difficulty::Hard);
+            let decision = if mark == State::X {
+                choose_move(&board, &rules, mark, hard, &mut first)
+            } else {
+                choose_move(&board, &rules, mark, hard, &mut xorshift)
+            };
+            let Some(decision) = decision else { break };
+            board[decision.index] = mark;
+            mark = mark.opponent();
+            plies += 1;
+        }
+        assert_eq!((plies, rules.winner(&board)), (9, None));
+    }
+
+    // Forks made by `personality` and games it lost, over games against the easy, the
+    // balanced and the aggressive CPU with either mark
+    fn personality_record(personality: Personality) -> (u32, u32) {
+        let rules = Rules::default();
+        let me = Cpu {
+            difficulty: Difficulty::Medium,
+            personality,
+            depth: None,
+        };
+        let opponents = [
+            Cpu::from(Difficulty::Easy),
+            Cpu::from(Difficulty::Medium),
+            Cpu {
+                personality: Personality::Aggressive,
+                ..me
+            },
+        ];
+        let mut rng = XorShift::new(5);
+        let (mut forks, mut losses) = (0, 0);
+        for game in 0..300 {
+            let mine = if game % 2 == 0 { State::X } else { State::O };
+            let mut board = rules.new_board();
+            let mut mark = State::X;
+            while rules.winner(&board).is_none() {
+                let cpu = if mark == mine {
+                    me
+                } else {
+                    opponents[game % 3]
+                };
+                let Some(decision) = choose_move(&board, &rules, mark, cpu, &mut rng) else {
+                    break;
+                };
+                if mark == mine && decision.reason == Reason::Fork {
+                    forks += 1;
+                }
+                board[decision.index] = mark;
+                mark = mark.opponent();
+            }
+            if rules.winner(&board) == Some(mine.opponent()) {
+                losses += 1;
+            }
+        }
+        (forks, losses)
+    }
+
+    #[test]
+    fn aggressive_cpu_forks_more_and_loses_more() {
+        let (aggressive_forks, aggressive_losses) = personality_record(Personality::Aggressive);
+        let (defensive_forks, defensive_losses) = personality_record(Personality::Defensive);
+        assert!(aggressive_forks > defensive_forks + 50);
+        assert!(aggressive_losses > 2 * defensive_losses + 10);
+    }
+
+    // The two corner traps: X in opposite corners around O's center, and X in a corner
+    // and on the far edge. Only the moves listed keep O from a lost game.
+    const CORNER_TRAPS: [(&str, &[usize]); 2] =
+        [("X...O...X", &[1, 3, 5, 7]), ("X...OX...", &[1, 2, 7, 8])];
+
+    #[test]
+    fn medium_cpu_steps_around_both_corner_traps() {
+        let rules = Rules::default();
+        for (trap, safe) in CORNER_TRAPS {
+            let board = board(trap);
+            for index in rules.legal_moves(&board) {
+                assert_eq!(
+                    hands_fork(&board, &rules, State::O, index),
+                    !safe.contains(&index),
+                    "{} at {}",
+                    trap,
+                    index
+                );
+            }
+            for personality in [Personality::Balanced, Personality::Defensive] {
+                let cpu = Cpu {
+                    difficulty: Difficulty::Medium,
+                    personality,
+                    depth: None,
+                };
+                let mut rng = XorShift::new(11);
+                for _ in 0..50 {
+                    let decision = choose_move(&board, &rules, State::O, cpu, &mut rng).unwrap();
+                    assert!(safe.contains(&decision.index), "{} {:?}", trap, decision);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn medium_cpu_draws_the_corner_traps_against_perfect_play() {
+        let rules = Rules::default();
+        let mut rng = XorShift::new(3);
+        for (trap, _) in CORNER_TRAPS {
+            for _ in 0..20 {
+                let mut board = board(trap);
+                let mut mark = State::O;
+                while rules.winner(&board).is_none() {
+                    let cpu = match mark {
+                        State::O => Cpu::from(Difficulty::Medium),
+                        _ => Cpu::from(Difficulty::Hard),
+                    };
+                    let Some(decision) = choose_move(&board, &rules, mark, cpu, &mut rng) else {
+                        break;
+                    };
+                    board[decision.index] = mark;
+                    mark = mark.opponent();
+                }
+                assert_eq!(rules.winner(&board), None, "{} ended {}", trap, board);
+            }
+        }
+    }
+
+    #[test]
+    fn fork_squares_are_taken_only_when_nothing_is_forced() {
+        let rules = Rules::default();
+        // O must block X's row even though X could fork elsewhere later
+        let block = board("XX..O....");
+        let decision = choose_move(
+            &block,
+            &rules,
+            State::O,
+            Difficulty::Medium.into(),
+            &mut First,
+        );
+        assert_eq!(decision.unwrap().index, 2);
+        // The weighted CPUs, such as learned models, keep their own choices
+        let trap = board("X...O...X");
+        let weights = Personality::Balanced.weights();
+        let mut picked = [false; 9];
+        let mut rng = XorShift::new(9);
+        for _ in 0..200 {
+            picked[choose_weighted(&trap, &rules, State::O, &weights, &mut rng)
+                .unwrap()
+                .index] = true;
+        }
+        assert!(picked[2] && picked[6]);
+        let decision = choose_move(
+            &trap,
+            &rules,
+            State::O,
+            Difficulty::Medium.into(),
+            &mut First,
+        );
+        assert_eq!(decision.unwrap().reason, Reason::BlockFork);
+    }
+
+    #[test]
+    fn personalities_weigh_blocks_differently() {
+        let aggressive = Personality::Aggressive.weights();
+        let defensive = Personality::Defensive.weights();
+        assert!(aggressive.fork > aggressive.block);
+        assert!(defensive.block > defensive.fork);
+        assert_eq!(Personality::default(), Personality::Balanced);
+        #[cfg(feature = "std")]
+        for personality in [
+            Personality::Balanced,
+            Personality::Aggressive,
+            Personality::Defensive,
+        ] {
+            assert_eq!(personality.to_string().parse(), Ok(personality));
+        }
+    }
+
+    fn decide(cells: &str, mark: State, cpu: Cpu) -> MoveDecision {
+        choose_move(&board(cells), &Rules::default(), mark, cpu, &mut First).unwrap()
+    }
+
+    fn medium(personality: Personality) -> Cpu {
+        Cpu {
+            difficulty: Difficulty::Medium,
+            personality,
+            depth: None,
+        }
+    }
+
+    #[test]
+    fn decisions_name_their_reason() {
+        let balanced = medium(Personality::Balanced);
+        let aggressive = medium(Personality::Aggressive);
+        let defensive = medium(Personality::Defensive);
+
+        let win = decide("XX.OO....", State::X, balanced);
+        assert_eq!((win.index, win.reason), (2, Reason::Win));
+        let block = decide("OO..X....", State::X, balanced);
+        let line = Line {
+            start: 0,
+            step: 1,
+            len: 3,
+        };
+        assert_eq!((block.index, block.reason), (2, Reason::Block { line }));
+        let fork = decide("XO..X...O", State::X, aggressive);
+        assert!([3, 6].contains(&fork.index), "{:?}", fork);
+        assert_eq!(fork.reason, Reason::Fork);
+        let block_fork = decide(".X.XO....", State::O, defensive);
+        assert_eq!(
+            (block_fork.index, block_fork.reason),
+            (0, Reason::BlockFork)
+        );
+        let threat = decide("X...O...X", State::O, aggressive);
+        assert_eq!(threat.reason, Reason::Threat);
+        let center = decide(".........", State::X, aggressive);
+        assert_eq!((center.index, center.reason), (4, Reason::Center));
+        let corner = decide("....X....", State::O, aggressive);
+        assert_eq!(corner.reason, Reason::Corner);
+        assert!([0, 2, 6, 8].contains(&corner.index));
+        let random = decide("....X....", State::O, Cpu::from(Difficulty::Easy));
+        assert_eq!(random.reason, Reason::Random);
+    }
+
+    #[test]
+    fn searched_decisions_carry_the_score() {
+        let solved = decide(".........", State::X, Cpu::from(Difficulty::Hard));
+        assert_eq!(solved.reason, Reason::Search { score: 0, plies: 9 });
+    }
+
+    #[test]
+    fn empty_board_has_no_threats() {
+        let rules = Rules::default();
+        let empty = rules.new_board();
+        assert_eq!(find_threats(&empty, &rules, State::X).count(), 0);
+        assert_eq!(find_threats(&empty, &rules, State::O).count(), 0);
+    }
+
+    #[test]
+    fn one_cell_can_complete_two_threats() {
+        let rules = Rules::default();
+        let start = board(".XXX..XOO");
+        let mut found = 0;
+        for threat in find_threats(&start, &rules, State::X) {
+            let mut occupied = threat.occupied();
+            let cells = [occupied.next().unwrap(), occupied.next().unwrap()];
+            assert_eq!(occupied.next(), None);
+            assert!(cells.iter().all(|&cell| start[cell] == State::X));
+            assert_eq!(start[threat.completing], State::Empty);
+            found += 1;
+        }
+        // Rows 0-1-2 and columns 0-3-6 both finish on 0, the diagonal 2-4-6 on 4
+        assert_eq!(found, 3);
+        assert_eq!(
+            find_threats(&start, &rules, State::X)
+                .filter(|threat| threat.completing == 0)
+                .count(),
+            2
+        );
+        assert!(has_fork(&start, &rules, State::X));
+        // O's row is blocked by X
+        assert_eq!(find_threats(&start, &rules, State::O).count(), 0);
+        #[cfg(feature = "std")]
+        assert_eq!(threats(&start, &rules, State::X).len(), 3);
+    }
+
+    fn five_by_five() -> Rules {
+        Rules {
+            rows: 5,
+            cols: 5,
+            win_len: 4,
+            ..Rules::default()
+        }
+    }
+
+    // A 5x5 board from its compact form, rows one after the other
+    fn big_board(cells: &str) -> Board {
+        let mut board = Board::new(5, 5, 1);
+        for (i, cell) in cells.chars().enumerate() {
+            board[i] = match cell {
+                'X' => State::X,
+                'O' => State::O,
+                _ => State::Empty,
+            };
+        }
+        board
+    }
+
+    #[test]
+    fn estimate_favors_the_side_with_more_open_lines() {
+        let rules = five_by_five();
+        assert_eq!(estimate(&rules.new_board(), &rules, State::X), 0);
+        let center = big_board("............X............");
+        assert!(estimate(&center, &rules, State::X) > 0);
+        assert!(estimate(&center, &rules, State::O) < 0);
+        let won = big_board("XXXX.OOO.................");
+        assert_eq!(estimate(&won, &rules, State::X), WIN);
+        assert_eq!(estimate(&won, &rules, State::O), -WIN);
+    }
+
+    #[test]
+    fn estimate_is_the_same_for_both_sides_but_negated() {
+        let rules = five_by_five();
+        for cells in [
+            "............X............",
+            "X.....O.....X.....O......",
+            "XX...OO......X.......O...",
+            "XXXX.OOO.................",
+            ".O.X..X.O..XO..O.X..X.O..",
+        ] {
+            let board = big_board(cells);
+            let x = estimate(&board, &rules, State::X);
+            assert_eq!(x, -estimate(&board, &rules, State::O), "{}", cells);
+            assert!(x.abs() <= MAX_ESTIMATE || x.abs() == WIN);
+        }
+    }
+
+    #[test]
+    fn estimate_ranks_better_positions_higher() {
+        let rules = five_by_five();
+        // The same marks, lined up or spread out
+        let lined = big_board("......XXX......OO.....O..");
+        let spread = big_board("X...X.......X...OO.....O.");
+        assert!(estimate(&lined, &rules, State::X) > estimate(&spread, &rules, State::X));
+        // A line the opponent has blocked is worth less than an open one
+        let open = big_board(".XXX.....................");
+        let blocked = big_board("OXXX.....................");
+        assert!(estimate(&open, &rules, State::X) > estimate(&blocked, &rules, State::X));
+    }
+
+    // Gravity boards offer a move per column, far fewer than their empty cells
+    #[test]
+    fn hard_looks_ahead_on_big_gravity_boards() {
+        let rules = Rules::gravity(6, 7);
+        let cpu = Cpu {
+            depth: Some(2),
+            ..Cpu::from(Difficulty::Hard)
+        };
+        let decision = choose_move(&rules.new_board(), &rules, State::X, cpu, &mut First).unwrap();
+        assert!(
+            matches!(decision.reason, Reason::Lookahead { depth: 2, .. }),
+            "{:?}",
+            decision.reason
+        );
+    }
+
+    #[test]
+    fn shallow_search_takes_a_win_in_reach() {
+        let rules = five_by_five();
+        let start = big_board(".XXX.....OO.....O........");
+        let result = search_to_depth(&start, &rules, State::X, 2, &mut || false).unwrap();
+        assert!(is_decisive(result.score) && result.score > 0);
+        assert!([0, 4].contains(&result.pv[0]));
+    }
+
+    // The search's move is the same on one thread as on four, whichever finishes first
+    #[cfg(feature = "std")]
+    #[test]
+    fn search_is_the_same_on_any_number_of_threads() {
+        let rules = five_by_five();
+        let cpu = Cpu {
+            depth: Some(2),
+            ..Cpu::from(Difficulty::Hard)
+        };
+        let on = |threads: usize, board: &Board, seed: u64| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| choose_move(board, &rules, State::X, cpu, &mut XorShift::new(seed)))
+                .unwrap()
+        };
+        for cells in [
+            ".........................",
+            "............O............",
+            "......XO....O....X.......",
+        ] {
+            let board = big_board(cells);
+            for seed in 1..4 {
+                assert_eq!(on(1, &board, seed), on(4, &board, seed), "{}", cells);
+            }
+        }
+    }
+
+    #[test]
+    fn replaying_the_pv_ends_in_the_claimed_score() {
+        let rules = Rules::default();
+        let hard = Cpu::from(Difficulty::Hard);
+        for (cells, mark) in [
+            (".........", State::X),
+            (".O..X....", State::X),
+            ("XX.XO...O", State::O),
+            ("X...O...X", State::O),
+        ] {
+            let start = board(cells);
+            let decision = choose_move(&start, &rules, mark, hard, &mut First).unwrap();
+            let search = decision.search.unwrap();
+            assert_eq!(search.pv[0], decision.index);
+            assert_eq!(search.depth, search.pv.len());
+            assert!(search.nodes >= search.pv.len() as u64);
+            let end = play_out(start, mark, &search.pv);
+            let expected = match search.score {
+                0 => None,
+                score if score > 0 => Some(mark),
+                _ => Some(mark.opponent()),
+            };
+            assert_eq!(rules.winner(&end), expected, "{}", cells);
+            assert!(expected.is_some() || end.is_full());
+        }
+    }
+
+    #[test]
+    fn shallow_pv_stays_within_its_depth() {
+        let rules = five_by_five();
+        let start = big_board("......XO....O....X.......");
+        for depth in 1..4 {
+            let result = search_to_depth(&start, &rules, State::X, depth, &mut || false).unwrap();
+            assert_eq!(result.depth, depth);
+            assert!(!result.pv.is_empty() && result.pv.len() <= depth);
+            assert!(result.nodes > result.pv.len() as u64);
+            play_out(start, State::X, &result.pv);
+        }
+    }
+
+    // Every move to `depth` plies without pruning or a table, what the search must agree with
+    fn minimax(board: &mut Board, rules: &Rules, to_move: State, ply: i32, depth: i32) -> i32 {
+        if board.has_line(to_move.opponent(), rules.win_len) {
+            return -(WIN - ply);
+        }
+        let moves = rules.legal_moves(board);
+        if moves.is_empty() {
+            return 0;
+        }
+        if ply >= depth {
+            return estimate(board, rules, to_move);
+        }
+        let mut best = -WIN - 1;
+        for index in moves {
+            board[index] = to_move;
+            best = best.max(-minimax(board, rules, to_move.opponent(), ply + 1, depth));
+            board[index] = State::Empty;
+        }
+        best
+    }
+
+    #[test]
+    fn positions_met_twice_keep_their_scores() {
+        let rules = Rules::default();
+        for cells in ["X...O....", "XO.......", ".X..O..X.", "X.O.X.O.."] {
+            let mut start = board(cells);
+            let to_move = start.to_move();
+            let (score, pv) = solve(&start, &rules, to_move);
+            assert_eq!(score, minimax(&mut start, &rules, to_move, 0, i32::MAX));
+            // A forced result ends the game after as many plies as the score says
+            let end = play_out(start, to_move, &pv);
+            assert!(
+                score == 0 || WIN - score.abs() == pv.len() as i32,
+                "{}",
+                cells
+            );
+            assert!(rules.winner(&end).is_some() || end.is_full(), "{}", cells);
+        }
+
+        let rules = five_by_five();
+        for cells in ["......XO....O....X.......", ".XXX.....OO.....O........"] {
+            let mut start = big_board(cells);
+            for depth in 1..4 {
+                let result = search_to_depth(&start, &rules, State::X, depth, &mut || false);
+                let expected = minimax(&mut start, &rules, State::X, 0, depth as i32);
+                assert_eq!(result.unwrap().score, expected, "{} {}", cells, depth);
+            }
+        }
+    }
+}
@@ -0,0 +1,960 @@
+use crate::ai::{self, Cpu, Difficulty, MoveDecision, Player, Weights};
+use crate::board::{Board, State};
+use crate::game::Status;
+use crate::rng::{GameRng, RngKind};
+use crate::rules::Rules;
+use rand::{Rng, RngCore};
+use rayon::prelude::*;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+// Who plays a batch of CPU against CPU games, and the master seed they derive from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulationConfig {
+    pub rules: Rules,
+    pub x: Cpu,
+    pub o: Cpu,
+    pub seed: u64,
+}
+
+// Totals of a batch of CPU against CPU games
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulationReport {
+    pub games: u32,
+    pub x_wins: u32,
+    pub o_wins: u32,
+    pub ties: u32,
+    // Games in which each side left itself two ways to win at once
+    pub x_forks: u32,
+    pub o_forks: u32,
+    pub total_moves: u64,
+    pub elapsed: Duration,
+}
+
+impl SimulationReport {
+    pub fn average_length(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.total_moves as f64 / self.games as f64
+        }
+    }
+
+    fn merge(self, other: SimulationReport) -> SimulationReport {
+        SimulationReport {
+            games: self.games + other.games,
+            x_wins: self.x_wins + other.x_wins,
+            o_wins: self.o_wins + other.o_wins,
+            ties: self.ties + other.ties,
+            x_forks: self.x_forks + other.x_forks,
+            o_forks: self.o_forks + other.o_forks,
+            total_moves: self.total_moves + other.total_moves,
+            elapsed: self.elapsed,
+        }
+    }
+}
+
+// Plays one game to the end, X moving first; returns the result and the number of moves
+pub fn play_out(rules: &Rules, x: Cpu, o: Cpu, rng: &mut impl Rng) -> (Status, usize) {
+    play_out_from(rules.new_board(), State::X, rules, x, o, rng)
+}
+
+// Plays on from `board` with `to_move` to move, counting only the moves made from there
+pub fn play_out_from(
+    board: Board,
+    to_move: State,
+    rules: &Rules,
+    x: Cpu,
+    o: Cpu,
+    rng: &mut impl Rng,
+) -> (Status, usize) {
+    play(board, to_move, rules, &x, &o, rng, |_, _, _| ())
+}
+
+// `play_out` for any kind of players, e.g. a learned one against a CPU
+pub fn play_between(
+    rules: &Rules,
+    x: &dyn Player,
+    o: &dyn Player,
+    rng: &mut impl Rng,
+) -> (Status, usize) {
+    play(rules.new_board(), State::X, rules, x, o, rng, |_, _, _| ())
+}
+
+// The game loop, calling `after_move` with the board, the mover and the cell played
+// after every move that doesn't end the game
+fn play(
+    mut board: Board,
+    mut to_move: State,
+    rules: &Rules,
+    x: &dyn Player,
+    o: &dyn Player,
+    rng: &mut impl Rng,
+    mut after_move: impl FnMut(&Board, State, usize),
+) -> (Status, usize) {
+    let mut moves = 0;
+    loop {
+        let player = if to_move == State::X { x } else { o };
+        let index = match player.choose_move(&board, rules, to_move, rng) {
+            Some(decision) => decision.index,
+            None => return (Status::Tie, moves),
+        };
+        board[index] = to_move;
+        moves += 1;
+        if board.has_line(to_move, rules.win_len) {
+            return (Status::Won(to_move), moves);
+        }
+        after_move(&board, to_move, index);
+        to_move = to_move.opponent();
+    }
+}
+
+// Seed of one game, spread out from the master seed (SplitMix64) so that neighbouring
+// games don't get related streams
+fn game_seed(seed: u64, game: u32) -> u64 {
+    let mut z = seed.wrapping_add((game as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Plays `games` games on rayon's thread pool. Every game has its own seed, so the
+// totals only depend on the master seed and not on how many threads ran them.
+pub fn simulate_many(config: &SimulationConfig, games: u32) -> SimulationReport {
+    simulate_between(&config.rules, &config.x, &config.o, config.seed, games)
+}
+
+// `simulate_many` for any kind of players, e.g. a loaded model against a CPU
+pub fn simulate_between(
+    rules: &Rules,
+    x: &(dyn Player + Sync),
+    o: &(dyn Player + Sync),
+    seed: u64,
+    games: u32,
+) -> SimulationReport {
+    let started = Instant::now();
+    let mut report = (0..games)
+        .into_par_iter()
+        .map(|game| play_seeded(rules, x, o, seed, game).0)
+        .reduce(SimulationReport::default, SimulationReport::merge);
+    report.elapsed = started.elapsed();
+    report
+}
+
+// Game number `game` of a batch seeded with `seed`, as a report of that one game, with
+// the cell X opened on
+fn play_seeded(
+    rules: &Rules,
+    x: &dyn Player,
+    o: &dyn Player,
+    seed: u64,
+    game: u32,
+) -> (SimulationReport, Option<usize>) {
+    let mut rng = GameRng::seeded(RngKind::ChaCha8, game_seed(seed, game));
+    let (mut x_fork, mut o_fork) = (false, false);
+    let mut first_move = None;
+    let (status, moves) = play(
+        rules.new_board(),
+        State::X,
+        rules,
+        x,
+        o,
+        &mut rng,
+        |board, mover, index| {
+            first_move = first_move.or(Some(index));
+            let forked = if mover == State::X {
+                &mut x_fork
+            } else {
+                &mut o_fork
+            };
+            *forked = *forked || ai::has_fork(board, rules, mover);
+        },
+    );
+    let report = SimulationReport {
+        games: 1,
+        x_wins: (status == Status::Won(State::X)) as u32,
+        o_wins: (status == Status::Won(State::O)) as u32,
+        ties: (status == Status::Tie) as u32,
+        x_forks: x_fork as u32,
+        o_forks: o_fork as u32,
+        total_moves: moves as u64,
+        elapsed: Duration::ZERO,
+    };
+    (report, first_move)
+}
+
+// Totals of a batch of games split up by the cell X opened on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpeningReport {
+    pub rows: usize,
+    pub cols: usize,
+    // One report per cell, row by row
+    pub cells: Vec<SimulationReport>,
+    pub elapsed: Duration,
+}
+
+impl OpeningReport {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        OpeningReport {
+            rows,
+            cols,
+            cells: vec![SimulationReport::default(); rows * cols],
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    // Adds a game opened on `first_move`; games without a move aren't counted anywhere
+    pub fn add(&mut self, first_move: Option<usize>, game: SimulationReport) {
+        if let Some(cell) = first_move.and_then(|index| self.cells.get_mut(index)) {
+            *cell = std::mem::take(cell).merge(game);
+        }
+    }
+
+    fn merge(mut self, other: OpeningReport) -> OpeningReport {
+        for (cell, game) in self.cells.iter_mut().zip(other.cells) {
+            *cell = std::mem::take(cell).merge(game);
+        }
+        self
+    }
+
+    // A row for every cell opened on at least once, then one for the totals
+    pub fn rows(&self) -> Vec<ReportRow> {
+        let pairings: Vec<(String, SimulationReport)> = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, report)| report.games > 0)
+            .map(|(index, report)| (index.to_string(), report.clone()))
+            .collect();
+        let mut rows = report_rows(&pairings);
+        if let Some(total) = rows.last_mut() {
+            total.seconds = self.elapsed.as_secs_f64();
+        }
+        rows
+    }
+
+    // X's win rate for each first move, laid out like the board; cells never opened on
+    // show a dash
+    pub fn grid(&self) -> String {
+        let mut grid = String::new();
+        for row in self.cells.chunks(self.cols.max(1)) {
+            let line: Vec<String> = row
+                .iter()
+                .map(|report| {
+                    let rate = (report.games > 0)
+                        .then(|| report.x_wins as f64 / report.games as f64 * 100.0);
+                    rate.map_or(format!("{:>6}", "-"), |rate| format!("{:>5.1}%", rate))
+                })
+                .collect();
+            grid.push_str(&line.join(" "));
+            grid.push('\n');
+        }
+        grid
+    }
+}
+
+// `simulate_between` with the games split up by X's first move. The games are the same
+// as those of `simulate_between` with the same seed.
+pub fn simulate_openings(
+    rules: &Rules,
+    x: &(dyn Player + Sync),
+    o: &(dyn Player + Sync),
+    seed: u64,
+    games: u32,
+) -> OpeningReport {
+    let started = Instant::now();
+    let empty = || OpeningReport::new(rules.rows, rules.cols * rules.layers);
+    let mut report = (0..games)
+        .into_par_iter()
+        .map(|game| {
+            let (report, first_move) = play_seeded(rules, x, o, seed, game);
+            let mut opening = empty();
+            opening.add(first_move, report);
+            opening
+        })
+        .reduce(empty, OpeningReport::merge);
+    report.elapsed = started.elapsed();
+    report
+}
+
+// 95% Wilson score interval of a rate of `successes` in `trials`, None without trials.
+// Unlike the plain normal interval it stays inside 0..1 and is sensible at 0 and 100%.
+pub fn wilson_interval(successes: u32, trials: u32) -> Option<(f64, f64)> {
+    const Z: f64 = 1.96;
+    if trials == 0 {
+        return None;
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let denominator = 1.0 + Z * Z / n;
+    let center = (p + Z * Z / (2.0 * n)) / denominator;
+    let margin = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+    Some(((center - margin).max(0.0), (center + margin).min(1.0)))
+}
+
+// One line of a batch report: a pairing of players, or the totals of all of them.
+// Rates and lengths are None when no games were played.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReportRow {
+    pub pairing: String,
+    pub games: u32,
+    pub x_wins: u32,
+    pub o_wins: u32,
+    pub ties: u32,
+    pub x_forks: u32,
+    pub o_forks: u32,
+    pub x_win_rate: Option<f64>,
+    pub x_win_interval: Option<(f64, f64)>,
+    pub average_length: Option<f64>,
+    pub seconds: f64,
+}
+
+impl ReportRow {
+    pub fn new(pairing: impl Into<String>, report: &SimulationReport) -> Self {
+        ReportRow {
+            pairing: pairing.into(),
+            games: report.games,
+            x_wins: report.x_wins,
+            o_wins: report.o_wins,
+            ties: report.ties,
+            x_forks: report.x_forks,
+            o_forks: report.o_forks,
+            x_win_rate: (report.games > 0).then(|| report.x_wins as f64 / report.games as f64),
+            x_win_interval: wilson_interval(report.x_wins, report.games),
+            average_length: (report.games > 0).then(|| report.average_length()),
+            seconds: report.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+// A row for every pairing, then one for the totals
+pub fn report_rows(pairings: &[(String, SimulationReport)]) -> Vec<ReportRow> {
+    let mut total = SimulationReport::default();
+    let mut rows = Vec::with_capacity(pairings.len() + 1);
+    for (pairing, report) in pairings {
+        rows.push(ReportRow::new(pairing.as_str(), report));
+        let elapsed = total.elapsed + report.elapsed;
+        total = total.merge(report.clone());
+        total.elapsed = elapsed;
+    }
+    rows.push(ReportRow::new("Total", &total));
+    rows
+}
+
+// The rows as an aligned text table under a header, the last row set off as the totals
+pub fn report_table(rows: &[ReportRow]) -> String {
+    titled_table("Pairing", rows)
+}
+
+// `report_table` with `title` over the first column instead of "Pairing"
+pub fn titled_table(title: &str, rows: &[ReportRow]) -> String {
+    let header = [
+        title, "Games", "W/L/T", "X win", "95% CI", "Forks", "Avg len", "Time",
+    ]
+    .map(String::from);
+    let cells: Vec<[String; 8]> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.pairing.clone(),
+                row.games.to_string(),
+                format!("{}/{}/{}", row.x_wins, row.o_wins, row.ties),
+                row.x_win_rate
+                    .map_or("-".to_string(), |rate| format!("{:.1}%", rate * 100.0)),
+                row.x_win_interval.map_or("-".to_string(), |(low, high)| {
+                    format!("{:.1}-{:.1}%", low * 100.0, high * 100.0)
+                }),
+                format!("{}/{}", row.x_forks, row.o_forks),
+                row.average_length
+                    .map_or("-".to_string(), |length| format!("{:.1}", length)),
+                format!("{:.2}s", row.seconds),
+            ]
+        })
+        .collect();
+    aligned_table(&header, &cells, true)
+}
+
+// Lines up `cells` in columns under `header`, the first column to the left and the others
+// to the right. With `totals` the last line is set off like the header.
+fn aligned_table<const N: usize>(
+    header: &[String; N],
+    cells: &[[String; N]],
+    totals: bool,
+) -> String {
+    let mut widths = header.clone().map(|title| title.len());
+    for line in cells {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let format_line = |line: &[String; N]| {
+        let mut text = format!("{:<w$}", line[0], w = widths[0]);
+        for (cell, &width) in line.iter().zip(&widths).skip(1) {
+            text.push_str(&format!("  {:>w$}", cell, w = width));
+        }
+        text.push('\n');
+        text
+    };
+    let rule = "-".repeat(widths.iter().sum::<usize>() + 2 * (N - 1)) + "\n";
+    let mut table = format_line(header) + &rule;
+    for (number, line) in cells.iter().enumerate() {
+        if totals && number + 1 == cells.len() && number > 0 {
+            table.push_str(&rule);
+        }
+        table.push_str(&format_line(line));
+    }
+    table
+}
+
+// The opponents every difficulty is measured against. Unlike the CPUs they never change,
+// so their results only move when the CPUs do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    // Any legal move
+    Random,
+    // Completes its own line, else cuts the opponent's, else sets up a win; never looks
+    // further ahead
+    Greedy,
+}
+
+const GREEDY: Weights = Weights {
+    win: 100,
+    block: 10,
+    fork: 1,
+    threat: 1,
+    block_fork: 0,
+    center: 0,
+    corner: 0,
+};
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reference::Random => write!(f, "random"),
+            Reference::Greedy => write!(f, "greedy"),
+        }
+    }
+}
+
+impl Player for Reference {
+    fn choose_move(
+        &self,
+        board: &Board,
+        rules: &Rules,
+        mark: State,
+        mut rng: &mut dyn RngCore,
+    ) -> Option<MoveDecision> {
+        match self {
+            Reference::Random => {
+                ai::choose_move(board, rules, mark, Difficulty::Easy.into(), &mut rng)
+            }
+            Reference::Greedy => ai::choose_weighted(board, rules, mark, &GREEDY, &mut rng),
+        }
+    }
+}
+
+// Rating the random reference is pinned to, the others are rated relative to it
+pub const RANDOM_RATING: f64 = 1000.0;
+
+// One player's games against one opponent, counted from the player's side
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Standing {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub total_moves: u64,
+}
+
+impl Standing {
+    // `report` of games the player played as X, or as O
+    fn of(report: &SimulationReport, side: State) -> Self {
+        let (wins, losses) = match side {
+            State::O => (report.o_wins, report.x_wins),
+            _ => (report.x_wins, report.o_wins),
+        };
+        Standing {
+            games: report.games,
+            wins,
+            draws: report.ties,
+            losses,
+            total_moves: report.total_moves,
+        }
+    }
+
+    fn merge(self, other: Standing) -> Standing {
+        Standing {
+            games: self.games + other.games,
+            wins: self.wins + other.wins,
+            draws: self.draws + other.draws,
+            losses: self.losses + other.losses,
+            total_moves: self.total_moves + other.total_moves,
+        }
+    }
+
+    // Share of the games, 0 without games
+    pub fn rate(&self, count: u32) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            count as f64 / self.games as f64
+        }
+    }
+
+    pub fn average_length(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.total_moves as f64 / self.games as f64
+        }
+    }
+
+    // Elo-style rating of a player scoring this against an opponent rated `opponent`,
+    // a draw counting half. All wins or all losses are taken as half a game short of
+    // it, so the rating stays finite and grows with the number of games.
+    pub fn performance(&self, opponent: f64) -> f64 {
+        if self.games == 0 {
+            return opponent;
+        }
+        let games = self.games as f64;
+        let score = (self.wins as f64 + self.draws as f64 / 2.0) / games;
+        let score = score.clamp(0.5 / games, 1.0 - 0.5 / games);
+        opponent + 400.0 * (score / (1.0 - score)).log10()
+    }
+}
+
+// A CPU's results against both references and the rating they add up to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub cpu: Cpu,
+    pub random: Standing,
+    pub greedy: Standing,
+    // The mean of its performances against the two
+    pub rating: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationReport {
+    pub cpus: Vec<Calibration>,
+    // Greedy against random, which rates greedy
+    pub greedy: Standing,
+    pub greedy_rating: f64,
+    pub elapsed: Duration,
+}
+
+impl CalibrationReport {
+    pub fn get(&self, difficulty: Difficulty) -> Option<&Calibration> {
+        self.cpus
+            .iter()
+            .find(|row| row.cpu.difficulty == difficulty)
+    }
+
+    // One line per CPU with its win, draw and loss percentages against each reference
+    pub fn table(&self) -> String {
+        let header = [
+            "CPU",
+            "vs random W/D/L",
+            "vs greedy W/D/L",
+            "Avg len",
+            "Rating",
+        ]
+        .map(String::from);
+        let percentages = |standing: &Standing| {
+            format!(
+                "{:.1}/{:.1}/{:.1}%",
+                standing.rate(standing.wins) * 100.0,
+                standing.rate(standing.draws) * 100.0,
+                standing.rate(standing.losses) * 100.0
+            )
+        };
+        let cells: Vec<[String; 5]> = self
+            .cpus
+            .iter()
+            .map(|row| {
+                let both = row.random.merge(row.greedy);
+                [
+                    row.cpu.to_string(),
+                    percentages(&row.random),
+                    percentages(&row.greedy),
+                    format!("{:.1}", both.average_length()),
+                    format!("{:.0}", row.rating),
+                ]
+            })
+            .collect();
+        format!(
+            "{}Random is rated {:.0}, greedy {:.0} from {} games against random ({:.2}s)\n",
+            aligned_table(&header, &cells, false),
+            RANDOM_RATING,
+            self.greedy_rating,
+            self.greedy.games,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+// `player` against `opponent` in `games` seeded games, half of them (rounded up) as X
+pub fn match_up(
+    rules: &Rules,
+    player: &(dyn Player + Sync),
+    opponent: &(dyn Player + Sync),
+    seed: u64,
+    games: u32,
+) -> Standing {
+    let as_x = games.div_ceil(2);
+    let x = simulate_between(rules, player, opponent, seed, as_x);
+    // Another master seed, so the games as O don't replay the openings of those as X
+    let o = simulate_between(rules, opponent, player, !seed, games - as_x);
+    Standing::of(&x, State::X).merge(Standing::of(&o, State::O))
+}
+
+// Plays every CPU `games` games against each reference and rates it. The games only
+// depend on the seed, so a change in the table is a change in the CPUs.
+pub fn calibrate(rules: &Rules, cpus: &[Cpu], seed: u64, games: u32) -> CalibrationReport {
+    let started = Instant::now();
+    let (random, greedy) = (Reference::Random, Reference::Greedy);
+    let greedy_standing = match_up(rules, &greedy, &random, seed, games);
+    let greedy_rating = greedy_standing.performance(RANDOM_RATING);
+    let cpus = cpus
+        .iter()
+        .map(|cpu| {
+            let against_random = match_up(rules, cpu, &random, seed, games);
+            let against_greedy = match_up(rules, cpu, &greedy, seed, games);
+            Calibration {
+                cpu: *cpu,
+                random: against_random,
+                greedy: against_greedy,
+                rating: (against_random.performance(RANDOM_RATING)
+                    + against_greedy.performance(greedy_rating))
+                    / 2.0,
+            }
+        })
+        .collect();
+    CalibrationReport {
+        cpus,
+        greedy: greedy_standing,
+        greedy_rating,
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(x: Difficulty, o: Difficulty, seed: u64) -> SimulationConfig {
+        SimulationConfig {
+            rules: Rules::default(),
+            x: x.into(),
+            o: o.into(),
+            seed,
+        }
+    }
+
+    // The report of `games` games played on `threads` threads, without the time taken
+    fn simulate_on(threads: usize, config: &SimulationConfig, games: u32) -> SimulationReport {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let report = pool.install(|| simulate_many(config, games));
+        SimulationReport {
+            elapsed: Duration::ZERO,
+            ..report
+        }
+    }
+
+    #[test]
+    fn totals_depend_on_the_seed_not_the_threads() {
+        let config = config(Difficulty::Easy, Difficulty::Medium, 17);
+        let report = simulate_on(1, &config, 200);
+        assert_eq!(simulate_on(4, &config, 200), report);
+        assert_eq!(report.games, 200);
+        assert_eq!(report.x_wins + report.o_wins + report.ties, 200);
+        assert!((5.0..=9.0).contains(&report.average_length()));
+    }
+
+    #[test]
+    fn other_seeds_play_other_games() {
+        let first = simulate_on(2, &config(Difficulty::Easy, Difficulty::Easy, 1), 100);
+        let second = simulate_on(2, &config(Difficulty::Easy, Difficulty::Easy, 2), 100);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn game_seeds_are_spread_out() {
+        let seeds: Vec<u64> = (0..100).map(|game| game_seed(0, game)).collect();
+        for (i, seed) in seeds.iter().enumerate() {
+            assert!(!seeds[i + 1..].contains(seed));
+        }
+        assert_ne!(game_seed(1, 0), game_seed(0, 1));
+    }
+
+    fn report(x_wins: u32, o_wins: u32, ties: u32, millis: u64) -> SimulationReport {
+        let games = x_wins + o_wins + ties;
+        SimulationReport {
+            games,
+            x_wins,
+            o_wins,
+            ties,
+            x_forks: x_wins / 2,
+            o_forks: 0,
+            total_moves: games as u64 * 7,
+            elapsed: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn wilson_interval_covers_the_edges() {
+        assert_eq!(wilson_interval(0, 0), None);
+        let (low, high) = wilson_interval(0, 20).unwrap();
+        assert_eq!(low, 0.0);
+        assert!(high > 0.0 && high < 0.2);
+        let (low, high) = wilson_interval(20, 20).unwrap();
+        assert!(low > 0.8 && low < 1.0);
+        assert_eq!(high, 1.0);
+        let (low, high) = wilson_interval(50, 100).unwrap();
+        assert!((low - 0.404).abs() < 0.001 && (high - 0.596).abs() < 0.001);
+    }
+
+    #[test]
+    fn report_table_lines_up_with_totals() {
+        let rows = report_rows(&[
+            ("hard vs easy".to_string(), report(6, 2, 2, 1500)),
+            ("easy vs easy".to_string(), report(1, 1, 0, 250)),
+        ]);
+        assert_eq!(rows.last().unwrap().games, 12);
+        assert_eq!(
+            report_table(&rows),
+            "\
+Pairing       Games  W/L/T  X win      95% CI  Forks  Avg len   Time
+--------------------------------------------------------------------
+hard vs easy     10  6/2/2  60.0%  31.3-83.2%    3/0      7.0  1.50s
+easy vs easy      2  1/1/0  50.0%   9.5-90.5%    0/0      7.0  0.25s
+--------------------------------------------------------------------
+Total            12  7/3/2  58.3%  32.0-80.7%    3/0      7.0  1.75s
+"
+        );
+    }
+
+    #[test]
+    fn empty_and_tied_reports_have_no_nan() {
+        let rows = report_rows(&[
+            ("none".to_string(), SimulationReport::default()),
+            ("ties".to_string(), report(0, 0, 5, 0)),
+        ]);
+        assert_eq!(rows[0].x_win_rate, None);
+        assert_eq!(rows[0].average_length, None);
+        assert_eq!(rows[1].x_win_rate, Some(0.0));
+        let table = report_table(&rows);
+        assert!(
+            !table.contains("NaN") && !table.contains("inf"),
+            "{}",
+            table
+        );
+        assert!(
+            table.contains("none         0  0/0/0      -          -    0/0        -"),
+            "{}",
+            table
+        );
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&rows).unwrap();
+            assert!(json.contains(r#""x_win_rate":null"#), "{}", json);
+        }
+    }
+
+    // One finished game as the simulation reports it
+    fn game(status: Status, moves: u64) -> SimulationReport {
+        SimulationReport {
+            games: 1,
+            x_wins: (status == Status::Won(State::X)) as u32,
+            o_wins: (status == Status::Won(State::O)) as u32,
+            ties: (status == Status::Tie) as u32,
+            total_moves: moves,
+            ..SimulationReport::default()
+        }
+    }
+
+    fn openings(games: &[(Option<usize>, Status, u64)]) -> OpeningReport {
+        let mut report = OpeningReport::new(3, 3);
+        for &(first_move, status, moves) in games {
+            report.add(first_move, game(status, moves));
+        }
+        report
+    }
+
+    #[test]
+    fn openings_are_bucketed_by_first_move() {
+        let report = openings(&[
+            (Some(4), Status::Won(State::X), 5),
+            (Some(4), Status::Won(State::X), 7),
+            (Some(4), Status::Tie, 9),
+            (Some(0), Status::Won(State::O), 6),
+            (Some(0), Status::Won(State::X), 7),
+            // Neither a move nor a cell of the board
+            (None, Status::Tie, 0),
+            (Some(9), Status::Won(State::X), 5),
+        ]);
+        assert_eq!((report.cells[4].games, report.cells[4].x_wins), (3, 2));
+        assert_eq!(report.cells[4].total_moves, 21);
+        assert_eq!((report.cells[0].x_wins, report.cells[0].o_wins), (1, 1));
+        assert_eq!(report.cells.iter().map(|cell| cell.games).sum::<u32>(), 5);
+        assert_eq!(
+            report.grid(),
+            " 50.0%      -      -\n     -  66.7%      -\n     -      -      -\n"
+        );
+        let rows = report.rows();
+        let pairings: Vec<&str> = rows.iter().map(|row| row.pairing.as_str()).collect();
+        assert_eq!(pairings, ["0", "4", "Total"]);
+        assert_eq!((rows[2].games, rows[2].x_wins, rows[2].ties), (5, 3, 1));
+    }
+
+    #[test]
+    fn merged_openings_add_up_cell_by_cell() {
+        let first = openings(&[(Some(2), Status::Won(State::X), 5)]);
+        let second = openings(&[
+            (Some(2), Status::Tie, 9),
+            (Some(8), Status::Won(State::O), 6),
+        ]);
+        let merged = first.merge(second);
+        assert_eq!((merged.cells[2].games, merged.cells[2].ties), (2, 1));
+        assert_eq!(merged.cells[8].o_wins, 1);
+        assert_eq!(
+            merged.grid(),
+            "     -      -  50.0%\n     -      -      -\n     -      -   0.0%\n"
+        );
+    }
+
+    #[test]
+    fn openings_split_the_same_games_as_the_totals() {
+        let rules = Rules::default();
+        let (x, o) = (Cpu::from(Difficulty::Easy), Cpu::from(Difficulty::Medium));
+        let openings = simulate_openings(&rules, &x, &o, 5, 300);
+        let mut total = SimulationReport::default();
+        for cell in &openings.cells {
+            total = total.merge(cell.clone());
+        }
+        let report = simulate_between(&rules, &x, &o, 5, 300);
+        assert_eq!(
+            total,
+            SimulationReport {
+                elapsed: Duration::ZERO,
+                ..report
+            }
+        );
+        assert_eq!(
+            simulate_openings(&rules, &x, &o, 5, 300).cells,
+            openings.cells
+        );
+    }
+
+    #[test]
+    fn greedy_wins_before_it_blocks_and_blocks_before_anything_else() {
+        let rules = Rules::default();
+        let mut board = rules.new_board();
+        for (index, mark) in [(0, State::X), (1, State::X), (3, State::O), (4, State::O)] {
+            board[index] = mark;
+        }
+        let mut rng = GameRng::seeded(RngKind::ChaCha8, 1);
+        let greedy = |board: &Board, mark, rng: &mut GameRng| {
+            Reference::Greedy
+                .choose_move(board, &rules, mark, rng)
+                .unwrap()
+                .index
+        };
+        assert_eq!(greedy(&board, State::X, &mut rng), 2);
+        assert_eq!(greedy(&board, State::O, &mut rng), 5);
+        board[5] = State::X;
+        assert_eq!(greedy(&board, State::O, &mut rng), 2);
+    }
+
+    #[test]
+    fn performance_is_even_at_half_and_finite_at_the_edges() {
+        let standing = |wins, draws, losses| Standing {
+            games: wins + draws + losses,
+            wins,
+            draws,
+            losses,
+            total_moves: 0,
+        };
+        assert_eq!(standing(3, 4, 3).performance(1200.0), 1200.0);
+        assert_eq!(Standing::default().performance(1200.0), 1200.0);
+        let (all, few) = (standing(100, 0, 0), standing(10, 0, 0));
+        assert!(all.performance(1000.0).is_finite());
+        assert!(all.performance(1000.0) > few.performance(1000.0));
+        assert!(standing(0, 0, 10).performance(1000.0) < 1000.0);
+        assert!((standing(3, 0, 1).performance(0.0) - 400.0 * 3f64.log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn match_ups_play_both_sides_and_count_from_the_player() {
+        let rules = Rules::default();
+        let medium = Cpu::from(Difficulty::Medium);
+        let standing = match_up(&rules, &medium, &Reference::Random, 4, 7);
+        assert_eq!(standing.games, 7);
+        assert_eq!(standing.wins + standing.draws + standing.losses, 7);
+        let as_x = simulate_between(&rules, &medium, &Reference::Random, 4, 4);
+        let as_o = simulate_between(&rules, &Reference::Random, &medium, !4, 3);
+        assert_eq!(standing.wins, as_x.x_wins + as_o.o_wins);
+        assert_eq!(standing.losses, as_x.o_wins + as_o.x_wins);
+        assert_eq!(
+            standing,
+            match_up(&rules, &medium, &Reference::Random, 4, 7)
+        );
+    }
+
+    // Guards the difficulties' strength: hard never loses, medium seldom loses to
+    // random, and the ratings come out in order
+    #[test]
+    fn calibration_keeps_the_difficulties_apart() {
+        let cpus = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard].map(Cpu::from);
+        let report = calibrate(&Rules::default(), &cpus, 3, 24);
+        let [easy, medium, hard] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard]
+            .map(|difficulty| report.get(difficulty).unwrap());
+        assert_eq!((hard.random.losses, hard.greedy.losses), (0, 0));
+        assert!(
+            medium.random.rate(medium.random.losses) < 0.15,
+            "{:?}",
+            medium
+        );
+        assert!(easy.rating < medium.rating && medium.rating < hard.rating);
+        assert!(report.greedy_rating > RANDOM_RATING);
+        assert_eq!(report.cpus.len(), 3);
+        assert_eq!(hard.random.games + hard.greedy.games, 48);
+    }
+
+    #[test]
+    fn calibration_table_has_a_line_per_cpu() {
+        let standing = |wins, draws, losses| Standing {
+            games: wins + draws + losses,
+            wins,
+            draws,
+            losses,
+            total_moves: 7 * (wins + draws + losses) as u64,
+        };
+        let report = CalibrationReport {
+            cpus: vec![Calibration {
+                cpu: Difficulty::Hard.into(),
+                random: standing(9, 1, 0),
+                greedy: standing(1, 3, 0),
+                rating: 1612.4,
+            }],
+            greedy: standing(8, 1, 1),
+            greedy_rating: 1381.6,
+            elapsed: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            report.table(),
+            "\
+CPU              vs random W/D/L  vs greedy W/D/L  Avg len  Rating
+------------------------------------------------------------------
+hard (balanced)   90.0/10.0/0.0%   25.0/75.0/0.0%      7.0    1612
+Random is rated 1000, greedy 1382 from 10 games against random (1.50s)
+"
+        );
+    }
+}
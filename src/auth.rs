@@ -0,0 +1,172 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Longest display name, in characters
+pub const MAX_NAME: usize = 32;
+
+// What is kept of a token: only its SHA-256, so the table can't be used to play if it leaks
+pub type TokenHash = [u8; 32];
+
+// Who presented a valid token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub hash: TokenHash,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+struct Registration {
+    name: String,
+    expires: Instant,
+}
+
+// Tokens handed out to players, each valid for `lifetime` after it was issued. The caller
+// passes the time to every call, as with the rate limiter.
+#[derive(Debug, Clone)]
+pub struct Tokens {
+    lifetime: Duration,
+    registered: HashMap<TokenHash, Registration>,
+}
+
+impl Tokens {
+    pub fn new(lifetime: Duration) -> Self {
+        Tokens {
+            lifetime,
+            registered: HashMap::new(),
+        }
+    }
+
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    // A new random token for `name`. It is returned once and never stored.
+    pub fn register(&mut self, name: &str, now: Instant) -> Result<String, String> {
+        let name = check_name(name)?;
+        let mut bytes = [0; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.registered.insert(
+            hash(&token),
+            Registration {
+                name: name.to_string(),
+                expires: now + self.lifetime,
+            },
+        );
+        Ok(token)
+    }
+
+    // Who `token` was issued to, unless it is unknown or has expired. Only hashes are
+    // looked up, so how long that takes says nothing about the tokens themselves.
+    pub fn identify(&self, token: &str, now: Instant) -> Option<Identity> {
+        let hash = hash(token);
+        let registration = self.registered.get(&hash)?;
+        (now < registration.expires).then(|| Identity {
+            hash,
+            name: registration.name.clone(),
+        })
+    }
+
+    pub fn forget_expired(&mut self, now: Instant) {
+        self.registered
+            .retain(|_, registration| now < registration.expires);
+    }
+}
+
+// `name` without the spaces around it, if it is fit to show to other players
+pub fn check_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME {
+        return Err(format!("A name has 1 to {} characters", MAX_NAME));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("A name can't contain control characters".to_string());
+    }
+    Ok(name)
+}
+
+pub fn hash(token: &str) -> TokenHash {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+// Whether two hashes are equal, looking at every byte whatever the first difference, so
+// the time taken gives nothing away
+pub fn same(a: &TokenHash, b: &TokenHash) -> bool {
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn tokens_identify_their_player_until_they_expire() {
+        let now = Instant::now();
+        let mut tokens = Tokens::new(DAY);
+        let token = tokens.register("  Alice ", now).unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        let alice = tokens.identify(&token, now + DAY / 2).unwrap();
+        assert_eq!(alice.name, "Alice");
+        assert_eq!(alice.hash, hash(&token));
+        assert_eq!(tokens.identify(&token, now + DAY), None);
+
+        let other = tokens.register("Alice", now).unwrap();
+        assert_ne!(other, token);
+        assert_eq!(tokens.identify(&token.to_uppercase(), now), None);
+        assert_eq!(tokens.identify("", now), None);
+    }
+
+    #[test]
+    fn only_hashes_are_kept() {
+        let now = Instant::now();
+        let mut tokens = Tokens::new(DAY);
+        let token = tokens.register("Bob", now).unwrap();
+        assert!(!format!("{:?}", tokens).contains(&token));
+        assert!(tokens.registered.contains_key(&hash(&token)));
+    }
+
+    #[test]
+    fn expired_tokens_are_forgotten() {
+        let now = Instant::now();
+        let mut tokens = Tokens::new(DAY);
+        tokens.register("early", now).unwrap();
+        let late = tokens.register("late", now + DAY / 2).unwrap();
+        tokens.forget_expired(now + DAY);
+        assert_eq!(tokens.registered.len(), 1);
+        assert!(tokens.identify(&late, now + DAY).is_some());
+    }
+
+    #[test]
+    fn names_are_checked() {
+        let mut tokens = Tokens::new(DAY);
+        let now = Instant::now();
+        let long = "é".repeat(MAX_NAME + 1);
+        for name in ["", "   ", long.as_str()] {
+            assert_eq!(
+                tokens.register(name, now),
+                Err("A name has 1 to 32 characters".to_string())
+            );
+        }
+        assert!(tokens.register(&"é".repeat(MAX_NAME), now).is_ok());
+        assert_eq!(
+            tokens.register("Eve\u{1b}[31m", now),
+            Err("A name can't contain control characters".to_string())
+        );
+        assert_eq!(tokens.registered.len(), 1);
+    }
+
+    #[test]
+    fn hashes_compare_by_every_byte() {
+        let a = hash("a");
+        let mut b = a;
+        assert!(same(&a, &b));
+        b[31] ^= 1;
+        assert!(!same(&a, &b));
+        assert!(!same(&a, &hash("b")));
+    }
+}
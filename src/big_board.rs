@@ -0,0 +1,292 @@
+use crate::board::{Board, State};
+use crate::color::{self, Emphasis};
+use crossterm::terminal;
+
+// Size of a cell's block in characters
+pub const CELL_WIDTH: usize = 5;
+pub const CELL_HEIGHT: usize = 3;
+
+// Between the cells, and between the layers of a cube
+const SEPARATOR: char = '#';
+const LAYER_GAP: &str = "   ";
+
+const X: [&str; CELL_HEIGHT] = [" \\ / ", "  X  ", " / \\ "];
+const O: [&str; CELL_HEIGHT] = [" .-. ", "|   |", " '-' "];
+
+// The digits 1 to 9 of numerical mode as on a seven-segment display
+const DIGITS: [[&str; CELL_HEIGHT]; 9] = [
+    ["   ", "  |", "  |"],
+    [" _ ", " _|", "|_ "],
+    [" _ ", " _|", " _|"],
+    ["   ", "|_|", "  |"],
+    [" _ ", "|_ ", " _|"],
+    [" _ ", "|_ ", "|_|"],
+    [" _ ", "  |", "  |"],
+    [" _ ", "|_|", "|_|"],
+    [" _ ", "|_|", " _|"],
+];
+
+// What a cell shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellView {
+    pub owner: State,
+    pub digit: Option<u8>,
+    pub emphasis: Emphasis,
+    // Printed small in the corner of an empty cell, e.g. the number to type for it
+    pub label: Option<usize>,
+}
+
+// Characters across a board drawn big
+pub fn width(board: &Board) -> usize {
+    let layer = board.cols() * (CELL_WIDTH + 1) - 1;
+    board.layers() * layer + (board.layers() - 1) * LAYER_GAP.len()
+}
+
+// Whether the board drawn big fits the terminal; when the width is unknown, e.g. with
+// output going to a file, it does
+pub fn fits(board: &Board) -> bool {
+    terminal::size().map_or(true, |(columns, _)| width(board) <= columns as usize)
+}
+
+// The board with every cell as a block of ASCII art, `view` telling what each index
+// shows. Layers of a cube go side by side, `column_numbers` heads the columns with their
+// number as gravity mode takes them. Layers and columns are numbered from `first`.
+pub fn render(
+    board: &Board,
+    view: impl Fn(usize) -> CellView,
+    colors: bool,
+    column_numbers: bool,
+    first: usize,
+) -> String {
+    let (rows, cols, layers) = (board.rows(), board.cols(), board.layers());
+    let layer_width = cols * (CELL_WIDTH + 1) - 1;
+    let mut out = String::new();
+    if layers > 1 {
+        let labels: Vec<String> = (0..layers)
+            .map(|layer| {
+                format!(
+                    "{:<w$}",
+                    format!("layer {}", layer + first),
+                    w = layer_width
+                )
+            })
+            .collect();
+        out.push_str(labels.join(LAYER_GAP).trim_end());
+        out.push('\n');
+    }
+    if column_numbers {
+        let numbers: Vec<String> = (0..cols)
+            .map(|col| format!("{:^w$}", col + first, w = CELL_WIDTH))
+            .collect();
+        out.push_str(numbers.join(" ").trim_end());
+        out.push('\n');
+    }
+    let separator: String = (0..layers)
+        .map(|_| SEPARATOR.to_string().repeat(layer_width))
+        .collect::<Vec<_>>()
+        .join(LAYER_GAP);
+    for row in 0..rows {
+        if row > 0 {
+            out.push_str(&separator);
+            out.push('\n');
+        }
+        for line in 0..CELL_HEIGHT {
+            let mut text = String::new();
+            for layer in 0..layers {
+                if layer > 0 {
+                    text.push_str(LAYER_GAP);
+                }
+                for col in 0..cols {
+                    if col > 0 {
+                        text.push(SEPARATOR);
+                    }
+                    let cell = view((layer * rows + row) * cols + col);
+                    let block = block_line(cell, line);
+                    if colors {
+                        text.push_str(&color::cell(&block, cell.owner, cell.emphasis, CELL_WIDTH));
+                    } else {
+                        text.push_str(&block);
+                    }
+                }
+            }
+            out.push_str(text.trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// One line of a cell's block, always CELL_WIDTH characters
+fn block_line(cell: CellView, line: usize) -> String {
+    let mut block = match (cell.owner, cell.digit) {
+        (_, Some(digit @ 1..=9)) => format!(" {} ", DIGITS[digit as usize - 1][line]),
+        (_, Some(digit)) => format!("{:^w$}", digit, w = CELL_WIDTH),
+        (State::X, None) => X[line].to_string(),
+        (State::O, None) => O[line].to_string(),
+        (State::Empty, None) => match cell.label {
+            Some(label) if line == 0 => format!("{:<w$}", label, w = CELL_WIDTH),
+            _ => " ".repeat(CELL_WIDTH),
+        },
+    };
+    // The same marks as the normal board, in the top right corner
+    let marker = match cell.emphasis {
+        Emphasis::None => None,
+        Emphasis::Latest => Some('*'),
+        Emphasis::Tentative => Some('?'),
+    };
+    if let Some(marker) = marker.filter(|_| line == 0) {
+        block.pop();
+        block.push(marker);
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What the game shows: empty cells labelled from 1, `latest` marked
+    fn view(board: &Board, latest: Option<usize>) -> impl Fn(usize) -> CellView + '_ {
+        move |index| CellView {
+            owner: board[index],
+            digit: board.digit(index),
+            emphasis: if latest == Some(index) {
+                Emphasis::Latest
+            } else {
+                Emphasis::None
+            },
+            label: (board[index] == State::Empty).then_some(index + 1),
+        }
+    }
+
+    #[test]
+    fn a_classic_board() {
+        let board: Board = "XO..X...O".parse().unwrap();
+        let drawn = render(&board, view(&board, Some(8)), false, false, 1);
+        assert_eq!(
+            drawn,
+            concat!(
+                " \\ / # .-. #3\n",
+                "  X  #|   |#\n",
+                " / \\ # '-' #\n",
+                "#################\n",
+                "4    # \\ / #6\n",
+                "     #  X  #\n",
+                "     # / \\ #\n",
+                "#################\n",
+                "7    #8    # .-.*\n",
+                "     #     #|   |\n",
+                "     #     # '-'\n",
+            )
+        );
+    }
+
+    #[test]
+    fn a_gravity_board_with_column_numbers() {
+        let mut board = Board::new(4, 4, 1);
+        board[12] = State::X;
+        board[13] = State::O;
+        let drawn = render(&board, view(&board, None), false, true, 0);
+        assert_eq!(width(&board), 23);
+        assert_eq!(
+            drawn,
+            concat!(
+                "  0     1     2     3\n",
+                "1    #2    #3    #4\n",
+                "     #     #     #\n",
+                "     #     #     #\n",
+                "#######################\n",
+                "5    #6    #7    #8\n",
+                "     #     #     #\n",
+                "     #     #     #\n",
+                "#######################\n",
+                "9    #10   #11   #12\n",
+                "     #     #     #\n",
+                "     #     #     #\n",
+                "#######################\n",
+                " \\ / # .-. #15   #16\n",
+                "  X  #|   |#     #\n",
+                " / \\ # '-' #     #\n",
+            )
+        );
+    }
+
+    #[test]
+    fn a_cube_side_by_side() {
+        let mut board = Board::new(3, 3, 3);
+        board[13] = State::X;
+        let drawn = render(
+            &board,
+            |index| CellView {
+                label: None,
+                ..view(&board, None)(index)
+            },
+            false,
+            false,
+            1,
+        );
+        assert_eq!(width(&board), 3 * 17 + 2 * 3);
+        assert_eq!(
+            drawn,
+            concat!(
+                "layer 1             layer 2             layer 3\n",
+                "     #     #             #     #             #     #\n",
+                "     #     #             #     #             #     #\n",
+                "     #     #             #     #             #     #\n",
+                "#################   #################   #################\n",
+                "     #     #             # \\ / #             #     #\n",
+                "     #     #             #  X  #             #     #\n",
+                "     #     #             # / \\ #             #     #\n",
+                "#################   #################   #################\n",
+                "     #     #             #     #             #     #\n",
+                "     #     #             #     #             #     #\n",
+                "     #     #             #     #             #     #\n",
+            )
+        );
+    }
+
+    #[test]
+    fn numerical_digits() {
+        let mut board = Board::new(3, 3, 1);
+        board.place_digit(0, State::X, 7);
+        board.place_digit(4, State::O, 8);
+        let drawn = render(&board, view(&board, Some(4)), false, false, 1);
+        assert_eq!(
+            drawn,
+            concat!(
+                "  _  #2    #3\n",
+                "   | #     #\n",
+                "   | #     #\n",
+                "#################\n",
+                "4    #  _ *#6\n",
+                "     # |_| #\n",
+                "     # |_| #\n",
+                "#################\n",
+                "7    #8    #9\n",
+                "     #     #\n",
+                "     #     #\n",
+            )
+        );
+    }
+
+    #[test]
+    fn colors_keep_the_layout() {
+        let board: Board = "XO.......".parse().unwrap();
+        let plain = render(&board, view(&board, None), false, false, 1);
+        let colored = render(&board, view(&board, None), true, false, 1);
+        assert!(colored.contains('\x1b'));
+        let mut stripped = String::new();
+        let mut escape = false;
+        for c in colored.chars() {
+            match c {
+                '\x1b' => escape = true,
+                'm' if escape => escape = false,
+                _ if !escape => stripped.push(c),
+                _ => (),
+            }
+        }
+        // Colored lines keep their trailing blanks, which sit inside the color codes
+        let lines: Vec<&str> = stripped.lines().map(str::trim_end).collect();
+        assert_eq!(lines, plain.lines().collect::<Vec<_>>());
+    }
+}
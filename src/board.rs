@@ -0,0 +1,696 @@
+use core::fmt;
+use core::ops::{Deref, Index, IndexMut};
+#[cfg(feature = "std")]
+use core::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer};
+
+// Largest supported board (8x8, or the 3x3x3 cube)
+pub const MAX_CELLS: usize = 64;
+
+// Serialized in lowercase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum State {
+    X,
+    O,
+    Empty,
+}
+
+impl State {
+    pub fn opponent(self) -> State {
+        match self {
+            State::X => State::O,
+            State::O => State::X,
+            State::Empty => State::Empty,
+        }
+    }
+
+    // Numerical mode: X plays the odd digits and O the even ones
+    pub fn owns_digit(self, digit: u8) -> bool {
+        match self {
+            State::X => digit % 2 == 1,
+            State::O => digit.is_multiple_of(2),
+            State::Empty => false,
+        }
+    }
+}
+
+// Line directions as (layer, row, col) steps, only one of each opposite pair
+const DIRECTIONS: [(isize, isize, isize); 13] = [
+    (0, 0, 1),
+    (0, 1, -1),
+    (0, 1, 0),
+    (0, 1, 1),
+    (1, -1, -1),
+    (1, -1, 0),
+    (1, -1, 1),
+    (1, 0, -1),
+    (1, 0, 0),
+    (1, 0, 1),
+    (1, 1, -1),
+    (1, 1, 0),
+    (1, 1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize), serde(try_from = "BoardRepr"))]
+pub struct Board {
+    cells: [State; MAX_CELLS],
+    // Digits placed in numerical mode, the cell state records who placed them
+    digits: [Option<u8>; MAX_CELLS],
+    rows: usize,
+    cols: usize,
+    layers: usize,
+}
+
+impl Board {
+    pub fn new(rows: usize, cols: usize, layers: usize) -> Self {
+        assert!(rows * cols * layers <= MAX_CELLS, "board too large");
+        Board {
+            cells: [State::Empty; MAX_CELLS],
+            digits: [None; MAX_CELLS],
+            rows,
+            cols,
+            layers,
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers
+    }
+
+    pub fn size(&self) -> usize {
+        self.rows * self.cols * self.layers
+    }
+
+    pub fn cells(&self) -> &[State] {
+        &self.cells[..self.size()]
+    }
+
+    // Base 3 number of the cells, a different one for every board of up to 40 cells
+    pub fn key(&self) -> u64 {
+        self.cells().iter().fold(0, |key, &cell| {
+            key * 3
+                + match cell {
+                    State::Empty => 0,
+                    State::X => 1,
+                    State::O => 2,
+                }
+        })
+    }
+
+    pub fn count(&self, state: State) -> usize {
+        self.cells().iter().filter(|&&v| v == state).count()
+    }
+
+    // X always moves first, so O is to move once the counts differ
+    pub fn to_move(&self) -> State {
+        if self.count(State::X) > self.count(State::O) {
+            State::O
+        } else {
+            State::X
+        }
+    }
+
+    // First cell past the end of the board that holds a mark or digit. Boards built
+    // through the API never have one, others (hand-made, deserialized) might.
+    pub fn stray_cell(&self) -> Option<usize> {
+        (self.size()..MAX_CELLS)
+            .find(|&index| self.cells[index] != State::Empty || self.digits[index].is_some())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.cells().iter().all(|&v| v != State::Empty)
+    }
+
+    // All runs of `len` cells in a straight line, including 3D diagonals
+    pub fn lines(&self, len: usize) -> Lines {
+        Lines {
+            rows: self.rows,
+            cols: self.cols,
+            layers: self.layers,
+            len,
+            index: 0,
+            dir: 0,
+        }
+    }
+
+    pub fn has_line(&self, state: State, len: usize) -> bool {
+        self.find_line(state, len).is_some()
+    }
+
+    // The first run of `len` cells all held by `state`
+    pub fn find_line(&self, state: State, len: usize) -> Option<Line> {
+        self.lines(len)
+            .find(|line| line.cells().all(|i| self.cells[i] == state))
+    }
+
+    pub fn digit(&self, index: usize) -> Option<u8> {
+        self.digits[..self.size()][index]
+    }
+
+    pub fn place_digit(&mut self, index: usize, owner: State, digit: u8) {
+        self[index] = owner;
+        self.digits[index] = Some(digit);
+    }
+
+    pub fn digit_used(&self, digit: u8) -> bool {
+        self.digits[..self.size()].contains(&Some(digit))
+    }
+
+    // A completely filled line whose digits add up to `target`, whoever placed them
+    pub fn has_sum_line(&self, len: usize, target: u32) -> bool {
+        self.find_sum_line(len, target).is_some()
+    }
+
+    // The first run of `len` cells whose digits add up to `target`
+    pub fn find_sum_line(&self, len: usize, target: u32) -> Option<Line> {
+        self.lines(len).find(|line| {
+            let mut sum = 0;
+            for i in line.cells() {
+                match self.digits[i] {
+                    Some(digit) => sum += digit as u32,
+                    None => return false,
+                }
+            }
+            sum == target
+        })
+    }
+
+    // Rebuild the board with the given shape, taking cell (layer, row, col) from `source`
+    fn remap(
+        &self,
+        rows: usize,
+        cols: usize,
+        source: impl Fn(usize, usize) -> (usize, usize),
+    ) -> Board {
+        let mut board = Board::new(rows, cols, self.layers);
+        for layer in 0..self.layers {
+            for row in 0..rows {
+                for col in 0..cols {
+                    let (from_row, from_col) = source(row, col);
+                    let from = (layer * self.rows + from_row) * self.cols + from_col;
+                    let to = (layer * rows + row) * cols + col;
+                    board.cells[to] = self.cells[from];
+                    board.digits[to] = self.digits[from];
+                }
+            }
+        }
+        board
+    }
+
+    // Quarter turn clockwise, each layer of a cube is turned on its own
+    pub fn rotate90(&self) -> Board {
+        let rows = self.rows;
+        self.remap(self.cols, self.rows, |row, col| (rows - 1 - col, row))
+    }
+
+    // Flip left to right
+    pub fn mirror_horizontal(&self) -> Board {
+        let cols = self.cols;
+        self.remap(self.rows, self.cols, |row, col| (row, cols - 1 - col))
+    }
+
+    // Flip top to bottom
+    pub fn mirror_vertical(&self) -> Board {
+        let rows = self.rows;
+        self.remap(self.rows, self.cols, |row, col| (rows - 1 - row, col))
+    }
+
+    // The 8 symmetries of the square: four rotations, then the same of the mirror image
+    pub fn transforms(&self) -> impl Iterator<Item = Board> {
+        let mut boards = [*self; 8];
+        boards[4] = self.mirror_horizontal();
+        for i in [1, 2, 3, 5, 6, 7] {
+            boards[i] = boards[i - 1].rotate90();
+        }
+        boards.into_iter()
+    }
+
+    // Where every cell goes under each of the 8 `transforms`, in the same order: cell `i`
+    // has index `maps[t][i]` on the board of transform `t`. Moves mapped this way stay in
+    // step with the boards they were played on.
+    pub fn cell_transforms(&self) -> [[u8; MAX_CELLS]; 8] {
+        // Each cell carries its own index through the transforms as its digit
+        let mut labelled = Board::new(self.rows, self.cols, self.layers);
+        for (index, digit) in labelled.digits[..self.size()].iter_mut().enumerate() {
+            *digit = Some(index as u8);
+        }
+        let mut maps = [[0; MAX_CELLS]; 8];
+        for (map, board) in maps.iter_mut().zip(labelled.transforms()) {
+            for (to, digit) in board.digits[..board.size()].iter().enumerate() {
+                if let Some(from) = digit {
+                    map[*from as usize] = to as u8;
+                }
+            }
+        }
+        maps
+    }
+
+    // The lexicographically smallest of the 8 symmetries, equal for all of them
+    pub fn canonical(&self) -> Board {
+        self.transforms()
+            .min_by(|a, b| {
+                (a.rows, a.cols, a.cells(), &a.digits[..a.size()]).cmp(&(
+                    b.rows,
+                    b.cols,
+                    b.cells(),
+                    &b.digits[..b.size()],
+                ))
+            })
+            .unwrap_or(*self)
+    }
+
+    // Lowest empty cell of a column (row 0 is the top of the board)
+    pub fn drop_target(&self, col: usize) -> Option<usize> {
+        (0..self.rows)
+            .rev()
+            .map(|row| row * self.cols + col)
+            .find(|&i| self.cells[i] == State::Empty)
+    }
+}
+
+// Serialized form of a board, only the cells in use
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct BoardRepr {
+    rows: usize,
+    cols: usize,
+    layers: usize,
+    cells: Vec<State>,
+    #[serde(default)]
+    digits: Vec<Option<u8>>,
+}
+
+// Binary formats such as bincode read every field in order, so only self-describing
+// ones leave out the digits of a board without any
+#[cfg(feature = "serde")]
+impl Serialize for Board {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = BoardRepr::from(*self);
+        let skip_digits = repr.digits.is_empty() && serializer.is_human_readable();
+        let mut board = serializer.serialize_struct("BoardRepr", 5)?;
+        board.serialize_field("rows", &repr.rows)?;
+        board.serialize_field("cols", &repr.cols)?;
+        board.serialize_field("layers", &repr.layers)?;
+        board.serialize_field("cells", &repr.cells)?;
+        if skip_digits {
+            board.skip_field("digits")?;
+        } else {
+            board.serialize_field("digits", &repr.digits)?;
+        }
+        board.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Board> for BoardRepr {
+    fn from(board: Board) -> Self {
+        let digits = &board.digits[..board.size()];
+        BoardRepr {
+            rows: board.rows,
+            cols: board.cols,
+            layers: board.layers,
+            cells: board.cells().to_vec(),
+            digits: if digits.iter().any(Option::is_some) {
+                digits.to_vec()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BoardRepr> for Board {
+    type Error = String;
+
+    fn try_from(repr: BoardRepr) -> Result<Board, String> {
+        let size = repr
+            .rows
+            .checked_mul(repr.cols)
+            .and_then(|area| area.checked_mul(repr.layers))
+            .unwrap_or(0);
+        if size == 0 || size > MAX_CELLS || repr.cells.len() != size {
+            return Err(format!(
+                "Board of {} cells doesn't match its size",
+                repr.cells.len()
+            ));
+        }
+        if !repr.digits.is_empty() && repr.digits.len() != size {
+            return Err("Board digits don't match its size".to_string());
+        }
+        let mut board = Board::new(repr.rows, repr.cols, repr.layers);
+        board.cells[..size].copy_from_slice(&repr.cells);
+        board.digits[..repr.digits.len()].copy_from_slice(&repr.digits);
+        Ok(board)
+    }
+}
+
+// Compact form such as "X...O....", one character per cell row by row. The alternate
+// form `{:#}` splits the rows with '|' and the layers with '/', such as "X..|.O.|...".
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let area = self.rows * self.cols;
+        for (i, &val) in self.cells().iter().enumerate() {
+            if f.alternate() && i > 0 && i % area == 0 {
+                write!(f, "/")?;
+            } else if f.alternate() && i > 0 && i % self.cols == 0 {
+                write!(f, "|")?;
+            }
+            match (val, self.digits[i]) {
+                (_, Some(digit)) => write!(f, "{}", digit)?,
+                (State::X, None) => write!(f, "X")?,
+                (State::O, None) => write!(f, "O")?,
+                (State::Empty, None) => write!(f, ".")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Parses the compact form back: a square board (or 27 cells for the cube),
+// ignoring whitespace and '/' or '|' row separators
+#[cfg(feature = "std")]
+impl FromStr for Board {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Board, String> {
+        let marks: Vec<char> = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '/' && *c != '|')
+            .collect();
+        let (side, layers) = match marks.len() {
+            27 => (3, 3),
+            len => match (2..=8).find(|side| side * side == len) {
+                Some(side) => (side, 1),
+                None => return Err(format!("A position can't have {} cells", len)),
+            },
+        };
+        let mut board = Board::new(side, side, layers);
+        for (i, c) in marks.into_iter().enumerate() {
+            board.cells[i] = match c {
+                'X' | 'x' => State::X,
+                'O' | 'o' => State::O,
+                '.' | '-' | '_' => State::Empty,
+                _ => return Err(format!("Unexpected character '{}' in position", c)),
+            };
+        }
+        Ok(board)
+    }
+}
+
+impl Index<usize> for Board {
+    type Output = State;
+
+    fn index(&self, index: usize) -> &State {
+        &self.cells()[index]
+    }
+}
+
+impl IndexMut<usize> for Board {
+    fn index_mut(&mut self, index: usize) -> &mut State {
+        let len = self.size();
+        &mut self.cells[..len][index]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub start: usize,
+    pub step: usize,
+    pub len: usize,
+}
+
+impl Line {
+    pub fn cells(self) -> impl Iterator<Item = usize> {
+        (0..self.len).map(move |i| self.start + i * self.step)
+    }
+}
+
+pub struct Lines {
+    rows: usize,
+    cols: usize,
+    layers: usize,
+    len: usize,
+    index: usize,
+    dir: usize,
+}
+
+impl Iterator for Lines {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        let area = self.rows * self.cols;
+        let total = area * self.layers;
+        let span = self.len as isize - 1;
+
+        while self.index < total && self.len > 0 {
+            if self.dir == DIRECTIONS.len() {
+                self.dir = 0;
+                self.index += 1;
+                continue;
+            }
+            let (dl, dr, dc) = DIRECTIONS[self.dir];
+            self.dir += 1;
+
+            let layer = (self.index / area) as isize;
+            let row = (self.index % area / self.cols) as isize;
+            let col = (self.index % self.cols) as isize;
+            let (end_layer, end_row, end_col) =
+                (layer + dl * span, row + dr * span, col + dc * span);
+            if end_layer < 0
+                || end_layer >= self.layers as isize
+                || end_row < 0
+                || end_row >= self.rows as isize
+                || end_col < 0
+                || end_col >= self.cols as isize
+            {
+                continue;
+            }
+
+            let step = dl * area as isize + dr * self.cols as isize + dc;
+            return Some(Line {
+                start: self.index,
+                step: step as usize,
+                len: self.len,
+            });
+        }
+        None
+    }
+}
+
+// Cell indexes in a fixed array, so generating moves never allocates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveList {
+    indexes: [usize; MAX_CELLS],
+    len: usize,
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            indexes: [0; MAX_CELLS],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, index: usize) {
+        self.indexes[self.len] = index;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn extend_from_slice(&mut self, indexes: &[usize]) {
+        for &index in indexes {
+            self.push(index);
+        }
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.indexes[..self.len]
+    }
+}
+
+impl FromIterator<usize> for MoveList {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut list = MoveList::new();
+        for index in iter {
+            list.push(index);
+        }
+        list
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = usize;
+    type IntoIter = core::iter::Take<core::array::IntoIter<usize, MAX_CELLS>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.indexes.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a usize;
+    type IntoIter = core::slice::Iter<'a, usize>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Tiebreak, XorShift};
+
+    // A board of the given shape with each cell X, O or empty at random
+    fn random_board(rows: usize, cols: usize, layers: usize, seed: u64) -> Board {
+        let mut rng = XorShift::new(seed);
+        let mut board = Board::new(rows, cols, layers);
+        for i in 0..board.size() {
+            board[i] = [State::X, State::O, State::Empty][rng.below(3)];
+        }
+        board
+    }
+
+    #[test]
+    fn four_quarter_turns_are_the_identity() {
+        for (rows, cols, layers) in [(3, 3, 1), (4, 6, 1), (3, 3, 3)] {
+            let board = random_board(rows, cols, layers, 5);
+            let turned = board.rotate90();
+            assert_eq!((turned.rows(), turned.cols()), (cols, rows));
+            assert_ne!(turned, board);
+            assert_eq!(turned.rotate90().rotate90().rotate90(), board);
+        }
+    }
+
+    #[test]
+    fn mirrors_undo_themselves() {
+        let board = random_board(4, 5, 1, 9);
+        assert_eq!(board.mirror_horizontal().mirror_horizontal(), board);
+        assert_eq!(board.mirror_vertical().mirror_vertical(), board);
+        // A half turn is both mirrors
+        assert_eq!(
+            board.mirror_horizontal().mirror_vertical(),
+            board.rotate90().rotate90()
+        );
+    }
+
+    #[test]
+    fn canonical_form_is_shared_by_all_transforms() {
+        for seed in 1..20 {
+            let board = random_board(3, 3, 1, seed);
+            let canonical = board.canonical();
+            for transformed in board.transforms() {
+                assert_eq!(transformed.canonical(), canonical);
+                assert!(transformed.cells() >= canonical.cells());
+            }
+        }
+    }
+
+    #[test]
+    fn cell_transforms_move_marks_like_the_boards() {
+        for (rows, cols, layers) in [(3, 3, 1), (4, 6, 1), (3, 3, 3)] {
+            let board = random_board(rows, cols, layers, 11);
+            let maps = board.cell_transforms();
+            for (map, transformed) in maps.iter().zip(board.transforms()) {
+                for index in 0..board.size() {
+                    assert_eq!(transformed[map[index] as usize], board[index]);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parsed_boards_print_back_the_same() {
+        let pieces = [
+            'X', 'O', 'x', 'o', '.', '/', '|', ' ', '\n', '3', 'é', '\u{0}',
+        ];
+        let mut rng = XorShift::new(7);
+        let mut parsed = 0;
+        for _ in 0..50_000 {
+            let text: std::string::String = (0..rng.below(30))
+                .map(|_| pieces[rng.below(pieces.len())])
+                .collect();
+            if let Ok(board) = text.parse::<Board>() {
+                let again: Board = std::format!("{}", board).parse().unwrap();
+                assert_eq!(again, board, "{:?}", text);
+                let again: Board = std::format!("{:#}", board).parse().unwrap();
+                assert_eq!(again, board, "{:?}", text);
+                parsed += 1;
+            }
+        }
+        assert!(parsed > 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn alternate_form_splits_rows_and_layers() {
+        let board: Board = "XXO.O...X".parse().unwrap();
+        assert_eq!(std::format!("{:#}", board), "XXO|.O.|..X");
+        assert_eq!(std::format!("{:#}", board).parse::<Board>(), Ok(board));
+        let mut cube = Board::new(3, 3, 3);
+        cube[13] = State::X;
+        assert_eq!(
+            std::format!("{:#}", cube),
+            "...|...|.../...|.X.|.../...|...|..."
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn boards_round_trip_in_json_and_bincode() {
+        let mut board = Board::new(3, 3, 1);
+        board[4] = State::X;
+        let json = serde_json::to_string(&board).unwrap();
+        assert_eq!(
+            json,
+            r#"{"rows":3,"cols":3,"layers":1,"cells":["empty","empty","empty","empty","x","empty","empty","empty","empty"]}"#
+        );
+        assert_eq!(serde_json::from_str::<Board>(&json).unwrap(), board);
+        let bytes = bincode::serialize(&board).unwrap();
+        assert_eq!(bincode::deserialize::<Board>(&bytes).unwrap(), board);
+
+        board.place_digit(0, State::O, 6);
+        let json = serde_json::to_string(&board).unwrap();
+        assert!(json.ends_with(r#""digits":[6,null,null,null,null,null,null,null,null]}"#));
+        assert_eq!(serde_json::from_str::<Board>(&json).unwrap(), board);
+        let bytes = bincode::serialize(&board).unwrap();
+        assert_eq!(bincode::deserialize::<Board>(&bytes).unwrap(), board);
+
+        let short = r#"{"rows":3,"cols":3,"layers":1,"cells":["x"]}"#;
+        assert!(serde_json::from_str::<Board>(short).is_err());
+    }
+}
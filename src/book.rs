@@ -0,0 +1,372 @@
+use crate::ai::{MoveDecision, Reason, WIN};
+use crate::board::{Board, MoveList, State};
+use crate::rules::{Rules, Variant};
+use bincode::Options;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// First bytes of every book file
+pub const BOOK_MAGIC: &[u8; 4] = b"TTTB";
+
+// Bumped on every incompatible change of the book format
+pub const BOOK_VERSION: u32 = 1;
+
+// Largest board whose cells still fit a u64 key, three states a cell
+const MAX_BOOK_CELLS: usize = 40;
+
+// Perfect replies in the first positions of a game, found by full search ahead of time so
+// the CPU can play them at once. Symmetric positions share one entry: positions and the
+// positions the best moves lead to are kept by the key of their canonical form.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Book {
+    rules: Rules,
+    plies: usize,
+    replies: HashMap<u64, Vec<u64>>,
+}
+
+impl Book {
+    // Solves every position up to `plies` moves into a game. `progress` is told each ply
+    // as it starts and how many positions it has.
+    pub fn build(
+        rules: Rules,
+        plies: usize,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<Book, String> {
+        rules.validate()?;
+        if rules.variant != Variant::Classic || rules.layers > 1 {
+            return Err("Opening books are for classic flat boards".to_string());
+        }
+        if rules.cells() > MAX_BOOK_CELLS {
+            return Err(format!(
+                "Opening books are for boards of at most {} cells",
+                MAX_BOOK_CELLS
+            ));
+        }
+        let mut solver = Solver {
+            rules,
+            values: HashMap::new(),
+        };
+        let mut replies = HashMap::new();
+        let mut positions = vec![rules.new_board()];
+        for ply in 0..plies {
+            progress(ply, positions.len());
+            let mark = if ply % 2 == 0 { State::X } else { State::O };
+            let solved: Vec<(Board, Vec<u64>)> = positions
+                .iter()
+                .filter(|board| !finished(board, &rules))
+                .map(|board| (*board, solver.best_replies(board, mark)))
+                .collect();
+            // Every reply of the other side is a position the next ply may have to answer
+            let mut seen = HashSet::new();
+            positions = solved
+                .iter()
+                .flat_map(|(board, _)| {
+                    let board = *board;
+                    rules
+                        .legal_moves(&board)
+                        .into_iter()
+                        .map(move |index| child(&board, index, mark))
+                })
+                .filter(|next| seen.insert(next.key()))
+                .collect();
+            replies.extend(solved.into_iter().map(|(board, best)| (board.key(), best)));
+        }
+        Ok(Book {
+            rules,
+            plies,
+            replies,
+        })
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    // Positions with known replies
+    pub fn positions(&self) -> usize {
+        self.replies.len()
+    }
+
+    // The best moves in `board` for `mark`, empty if the book doesn't have it
+    pub fn moves(&self, board: &Board, rules: &Rules, mark: State) -> MoveList {
+        let mut moves = MoveList::new();
+        if *rules != self.rules {
+            return moves;
+        }
+        if let Some(best) = self.replies.get(&board.canonical().key()) {
+            for index in rules.legal_moves(board) {
+                if best.contains(&child(board, index, mark).key()) {
+                    moves.push(index);
+                }
+            }
+        }
+        moves
+    }
+
+    // One of the best moves at random, so book games differ
+    pub fn choose_move(
+        &self,
+        board: &Board,
+        rules: &Rules,
+        mark: State,
+        rng: &mut dyn RngCore,
+    ) -> Option<MoveDecision> {
+        let moves = self.moves(board, rules, mark);
+        if moves.is_empty() {
+            return None;
+        }
+        Some(MoveDecision {
+            index: moves[rng.gen_range(0..moves.len())],
+            reason: Reason::Book,
+            search: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = BOOK_MAGIC.to_vec();
+        bytes.extend_from_slice(&BOOK_VERSION.to_le_bytes());
+        bincode::DefaultOptions::new()
+            .serialize_into(&mut bytes, self)
+            .map_err(|err| format!("Can't encode the book: {}", err))?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        let body = bytes
+            .strip_prefix(BOOK_MAGIC)
+            .ok_or("not an opening book")?;
+        let (version, body) = match body.split_first_chunk::<4>() {
+            Some((version, body)) => (u32::from_le_bytes(*version), body),
+            None => return Err("corrupt book: missing version".to_string()),
+        };
+        if version != BOOK_VERSION {
+            return Err(format!(
+                "unsupported book version {} (this build reads version {})",
+                version, BOOK_VERSION
+            ));
+        }
+        // The limit stops a damaged length from allocating more than the file holds
+        let book: Book = bincode::DefaultOptions::new()
+            .with_limit(body.len() as u64)
+            .reject_trailing_bytes()
+            .deserialize(body)
+            .map_err(|err| format!("corrupt book: {}", err))?;
+        book.rules
+            .validate()
+            .map_err(|err| format!("invalid book: {}", err))?;
+        if book.rules.cells() > MAX_BOOK_CELLS {
+            return Err("invalid book: the board is too large".to_string());
+        }
+        Ok(book)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        fs::write(path, self.to_bytes()?).map_err(|err| err.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Book, String> {
+        Book::from_bytes(&fs::read(path).map_err(|err| err.to_string())?)
+    }
+}
+
+impl fmt::Display for Book {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} positions, {} plies deep",
+            self.replies.len(),
+            self.plies
+        )
+    }
+}
+
+impl fmt::Debug for Book {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Book")
+            .field("rules", &self.rules)
+            .field("plies", &self.plies)
+            .field("positions", &self.replies.len())
+            .finish()
+    }
+}
+
+// Full search that remembers the value of every position it met, so the many positions of
+// a book share the work of their common continuations. Values are those of `ai::evaluate`.
+struct Solver {
+    rules: Rules,
+    values: HashMap<u64, i32>,
+}
+
+impl Solver {
+    fn value(&mut self, board: &Board, to_move: State) -> i32 {
+        // Only the previous mover can have just completed a line
+        if board.has_line(to_move.opponent(), self.rules.win_len) {
+            return -WIN;
+        }
+        let moves = self.rules.legal_moves(board);
+        if moves.is_empty() {
+            return 0;
+        }
+        let key = board.canonical().key();
+        if let Some(&value) = self.values.get(&key) {
+            return value;
+        }
+        let mut best = -WIN - 1;
+        for index in moves {
+            let mut next = *board;
+            next[index] = to_move;
+            // One ply further from the end than the position it leads to
+            let value = self.value(&next, to_move.opponent());
+            best = best.max(-value + value.signum());
+            if best == WIN - 1 {
+                break;
+            }
+        }
+        self.values.insert(key, best);
+        best
+    }
+
+    // The keys of the positions the best moves in `board` lead to
+    fn best_replies(&mut self, board: &Board, mark: State) -> Vec<u64> {
+        let mut best = Vec::new();
+        let mut best_value = i32::MIN;
+        for index in self.rules.legal_moves(board) {
+            let next = child(board, index, mark);
+            let value = -self.value(&next, mark.opponent());
+            if value > best_value {
+                best_value = value;
+                best.clear();
+            }
+            if value == best_value && !best.contains(&next.key()) {
+                best.push(next.key());
+            }
+        }
+        best
+    }
+}
+
+// The canonical form of `board` after `mark` plays `index`
+fn child(board: &Board, index: usize, mark: State) -> Board {
+    let mut child = *board;
+    child[index] = mark;
+    child.canonical()
+}
+
+fn finished(board: &Board, rules: &Rules) -> bool {
+    board.has_line(State::X, rules.win_len)
+        || board.has_line(State::O, rules.win_len)
+        || rules.legal_moves(board).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{self, Difficulty};
+    use crate::game::{Game, Move};
+    use crate::rng::{GameRng, RngKind};
+    use crate::settings::Settings;
+    use std::sync::Arc;
+
+    // Every position reached in the first `plies` moves, symmetric ones included
+    fn positions(rules: &Rules, plies: usize) -> Vec<(Board, State)> {
+        let mut found = vec![(rules.new_board(), State::X)];
+        let mut last = found.clone();
+        for _ in 0..plies {
+            let mut next = Vec::new();
+            for (board, mark) in last.iter().filter(|(board, _)| !finished(board, rules)) {
+                for index in rules.legal_moves(board) {
+                    let mut child = *board;
+                    child[index] = *mark;
+                    next.push((child, mark.opponent()));
+                }
+            }
+            found.extend(&next);
+            last = next;
+        }
+        found
+    }
+
+    #[test]
+    fn book_moves_agree_with_search() {
+        let rules = Rules::default();
+        let book = Book::build(rules, 3, &mut |_, _| {}).unwrap();
+        for (board, mark) in positions(&rules, 2) {
+            let moves = rules.legal_moves(&board);
+            let values: Vec<i32> = moves
+                .iter()
+                .map(|&index| {
+                    let mut next = board;
+                    next[index] = mark;
+                    -ai::evaluate(&next, &rules, mark.opponent())
+                })
+                .collect();
+            let best = *values.iter().max().unwrap();
+            let searched: Vec<usize> = moves
+                .iter()
+                .zip(&values)
+                .filter(|(_, &value)| value == best)
+                .map(|(&index, _)| index)
+                .collect();
+            assert_eq!(
+                book.moves(&board, &rules, mark).to_vec(),
+                searched,
+                "{}",
+                board
+            );
+        }
+    }
+
+    #[test]
+    fn book_is_only_for_its_rules_and_plies() {
+        let rules = Rules::default();
+        let book = Book::build(rules, 2, &mut |_, _| {}).unwrap();
+        let mut board = rules.new_board();
+        board[4] = State::X;
+        assert!(!book.moves(&board, &rules, State::O).is_empty());
+        board[0] = State::O;
+        assert!(book.moves(&board, &rules, State::X).is_empty());
+        let gravity = Rules {
+            variant: Variant::Gravity,
+            ..rules
+        };
+        assert!(book
+            .moves(&rules.new_board(), &gravity, State::X)
+            .is_empty());
+    }
+
+    fn at(index: usize) -> Move {
+        Move {
+            index,
+            mark: None,
+            digit: None,
+        }
+    }
+
+    #[test]
+    fn book_hits_skip_the_search() {
+        let rules = Rules::default();
+        let settings = Settings {
+            difficulty: Difficulty::Hard,
+            ..Settings::default()
+        };
+        let mut game = Game::with_rng(rules, settings, GameRng::seeded(RngKind::ChaCha8, 3));
+        game.set_book(Arc::new(Book::build(rules, 2, &mut |_, _| {}).unwrap()));
+        game.new_round();
+        game.submit(at(4)).unwrap();
+        let decision = game.last_decision().unwrap();
+        assert_eq!(decision.reason, Reason::Book);
+        assert_eq!(decision.search.map_or(0, |search| search.nodes), 0);
+
+        // Past the book's plies the CPU searches again
+        let board = game.board().unwrap();
+        let free = (0..9).find(|&index| board[index] == State::Empty).unwrap();
+        game.submit(at(free)).unwrap();
+        let decision = game.last_decision().unwrap();
+        assert_ne!(decision.reason, Reason::Book);
+        assert!(decision.search.unwrap().nodes > 0);
+    }
+}
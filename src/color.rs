@@ -0,0 +1,49 @@
+use crate::board::State;
+use crossterm::style::{Attribute, Color, Stylize};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+// Whether stdout is a terminal that understands ANSI colors, NO_COLOR turns them off
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal() && supports_ansi()
+    })
+}
+
+// Older Windows consoles need virtual terminal processing switched on first
+#[cfg(windows)]
+fn supports_ansi() -> bool {
+    crossterm::ansi_support::supports_ansi()
+}
+
+#[cfg(not(windows))]
+fn supports_ansi() -> bool {
+    env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+// How a cell stands out from the rest of the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emphasis {
+    None,
+    // The opponent's latest move
+    Latest,
+    // A move waiting for confirmation
+    Tentative,
+}
+
+// A board cell padded to `width` and colored by its owner
+pub fn cell(text: &str, owner: State, emphasis: Emphasis, width: usize) -> String {
+    let padded = format!("{:width$}", text, width = width);
+    let styled = match owner {
+        State::X => padded.with(Color::Red),
+        State::O => padded.with(Color::Blue),
+        State::Empty => padded.with(Color::DarkGrey),
+    };
+    match emphasis {
+        Emphasis::None => styled.to_string(),
+        Emphasis::Latest => styled.attribute(Attribute::Bold).to_string(),
+        Emphasis::Tentative => styled.attribute(Attribute::Reverse).to_string(),
+    }
+}
@@ -0,0 +1,225 @@
+use crate::ai::{self, WIN};
+use crate::board::{Board, State};
+use crate::position::Position;
+use crate::rules::Rules;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How long `go` may think: plies and milliseconds, both unlimited when not given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Limits {
+    depth: Option<usize>,
+    movetime: Option<u64>,
+}
+
+// A search running on its own thread, so `stop` can still be read meanwhile
+struct Thinking {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+struct Engine<W> {
+    rules: Rules,
+    board: Board,
+    to_move: State,
+    output: Arc<Mutex<W>>,
+    thinking: Option<Thinking>,
+}
+
+// Speaks a UCI-like protocol: reads commands from `input` until `quit` or its end and
+// answers on `output`. Unknown commands are ignored, as UCI engines do.
+//
+//   newgame                           empty 3x3 board, X to move
+//   position startpos|<board> [moves <index>...]
+//   go [depth N] [movetime MS]        info lines per depth, then bestmove <index>
+//   stop                              bestmove from the deepest finished search
+//   isready                           readyok
+//   quit
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> io::Result<()> {
+    let mut engine = Engine {
+        rules: Rules::default(),
+        board: Rules::default().new_board(),
+        to_move: State::X,
+        output: Arc::new(Mutex::new(output)),
+        thinking: None,
+    };
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["uci"] => engine.send(&["id name tic-tac-toe-rs", "uciok"])?,
+            ["isready"] => engine.send(&["readyok"])?,
+            ["newgame" | "ucinewgame"] => {
+                engine.stop();
+                engine.rules = Rules::default();
+                engine.board = engine.rules.new_board();
+                engine.to_move = State::X;
+            }
+            ["position", args @ ..] => {
+                engine.stop();
+                if let Err(err) = engine.set_position(args) {
+                    engine.send(&[&format!("info string {}", err)])?;
+                }
+            }
+            ["go", args @ ..] => {
+                engine.stop();
+                engine.go(parse_limits(args));
+            }
+            ["stop"] => engine.stop(),
+            ["quit"] => break,
+            _ => (),
+        }
+    }
+    engine.stop();
+    Ok(())
+}
+
+fn parse_limits(args: &[&str]) -> Limits {
+    let mut limits = Limits::default();
+    for pair in args.windows(2) {
+        match pair {
+            ["depth", depth] => limits.depth = depth.parse().ok(),
+            ["movetime", ms] => limits.movetime = ms.parse().ok(),
+            _ => (),
+        }
+    }
+    limits
+}
+
+impl<W: Write + Send + 'static> Engine<W> {
+    fn send(&self, lines: &[&str]) -> io::Result<()> {
+        send(&self.output, lines)
+    }
+
+    // A board such as X...O.... (or startpos), then moves made from it in turn
+    fn set_position(&mut self, args: &[&str]) -> Result<(), String> {
+        let (mut board, moves) = match args {
+            ["startpos", moves @ ..] => (Rules::default().new_board(), moves),
+            [board, moves @ ..] => (board.parse::<Board>()?, moves),
+            [] => return Err("position needs a board".to_string()),
+        };
+        let rules = Rules {
+            rows: board.rows(),
+            cols: board.cols(),
+            layers: board.layers(),
+            win_len: board.rows().min(board.cols()),
+            ..Rules::default()
+        };
+        rules.validate()?;
+        let mut to_move = board.to_move();
+        Position::new(board, to_move, &rules)
+            .validate(&rules)
+            .map_err(|err| err.to_string())?;
+        let moves = match moves {
+            ["moves", moves @ ..] => moves,
+            [] => &[],
+            _ => return Err("Expected moves after the board".to_string()),
+        };
+        for text in moves {
+            let index: usize = text
+                .parse()
+                .map_err(|_| format!("{} is not a move", text))?;
+            if rules.winner(&board).is_some() || !rules.legal_moves(&board).contains(&index) {
+                return Err(format!("Move {} is not legal", index));
+            }
+            board[index] = to_move;
+            to_move = to_move.opponent();
+        }
+        (self.rules, self.board, self.to_move) = (rules, board, to_move);
+        Ok(())
+    }
+
+    // Starts searching deeper and deeper, reporting each depth
+    fn go(&mut self, limits: Limits) {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (rules, board, to_move) = (self.rules, self.board, self.to_move);
+        let output = Arc::clone(&self.output);
+        let flag = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let deadline = limits
+                .movetime
+                .map(|ms| Instant::now() + Duration::from_millis(ms));
+            let mut should_stop = || {
+                flag.load(Ordering::Relaxed) || deadline.is_some_and(|end| Instant::now() >= end)
+            };
+            // Output errors can't be reported anywhere, the reader is gone
+            let _ = think(&board, &rules, to_move, limits, &mut should_stop, &output);
+        });
+        self.thinking = Some(Thinking { stop, handle });
+    }
+
+    // Ends the running search, if any, once it has sent its bestmove
+    fn stop(&mut self) {
+        if let Some(thinking) = self.thinking.take() {
+            thinking.stop.store(true, Ordering::Relaxed);
+            let _ = thinking.handle.join();
+        }
+    }
+}
+
+fn think<W: Write>(
+    board: &Board,
+    rules: &Rules,
+    to_move: State,
+    limits: Limits,
+    should_stop: &mut dyn FnMut() -> bool,
+    output: &Mutex<W>,
+) -> io::Result<()> {
+    let moves = rules.legal_moves(board);
+    if moves.is_empty() || rules.winner(board).is_some() {
+        return send(output, &["bestmove none"]);
+    }
+    // Deeper than the empty cells finds nothing new
+    let open = board.count(State::Empty);
+    let max_depth = limits.depth.unwrap_or(open).clamp(1, open);
+    let started = Instant::now();
+    let mut best = moves[0];
+    let mut nodes = 0;
+    for depth in 1..=max_depth {
+        let result = match ai::search_to_depth(board, rules, to_move, depth, should_stop) {
+            Some(result) => result,
+            None => break,
+        };
+        nodes += result.nodes;
+        best = result.pv.first().copied().unwrap_or(best);
+        let pv: Vec<String> = result.pv.iter().map(|index| index.to_string()).collect();
+        let info = format!(
+            "info depth {} nodes {} time {} score {} pv {}",
+            depth,
+            nodes,
+            started.elapsed().as_millis(),
+            score_text(result.score),
+            pv.join(" ")
+        );
+        send(output, &[&info])?;
+        // A forced result within reach stays the same however deep the search goes
+        if ai::is_decisive(result.score) {
+            break;
+        }
+    }
+    send(output, &[&format!("bestmove {}", best)])
+}
+
+// UCI's form of a score: `mate N` in own moves, negative when losing, or `cp` and the
+// estimate
+fn score_text(score: i32) -> String {
+    if !ai::is_decisive(score) {
+        return format!("cp {}", score);
+    }
+    let moves = (WIN - score.abs() + 1) / 2;
+    format!("mate {}", if score > 0 { moves } else { -moves })
+}
+
+fn send<W: Write>(output: &Mutex<W>, lines: &[&str]) -> io::Result<()> {
+    // A panicked search thread leaves the writer usable, keep going with it
+    let mut output = output
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for line in lines {
+        writeln!(output, "{}", line)?;
+    }
+    output.flush()
+}
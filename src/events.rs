@@ -0,0 +1,72 @@
+use crate::board::State;
+use crate::game::{Ending, Status};
+use std::fmt;
+use std::time::Duration;
+
+// Something that happened in a session, in the order it happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    RoundStart {
+        round: u32,
+    },
+    Move {
+        mark: State,
+        index: usize,
+        digit: Option<u8>,
+        human: bool,
+        // Played for a player who left their turn idle
+        auto: bool,
+        // Since the round started
+        elapsed: Duration,
+    },
+    RoundEnd {
+        status: Status,
+        // Set when the players ended the round by resigning or agreeing to a draw
+        ending: Option<Ending>,
+    },
+    Score {
+        player: u16,
+        cpu: u16,
+        tie: u16,
+    },
+    // A match was decided, or drawn when neither side has more wins
+    MatchEnd {
+        player: u16,
+        cpu: u16,
+        tiebreak_rounds: u16,
+    },
+    SessionEnd,
+}
+
+pub trait Observer {
+    fn on_event(&mut self, event: &Event);
+}
+
+// Observers attached to a game. They belong to the live session, so clones
+// of the game (AI lookahead, snapshots) start without any.
+#[derive(Default)]
+pub struct Observers(Vec<Box<dyn Observer + Send>>);
+
+impl Observers {
+    pub fn add(&mut self, observer: Box<dyn Observer + Send>) {
+        self.0.push(observer);
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        for observer in &mut self.0 {
+            observer.on_event(&event);
+        }
+    }
+}
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
@@ -0,0 +1,649 @@
+use crate::board::{Board, Line, State};
+use crate::game::Status;
+use crate::position::Position;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+#[cfg(feature = "image-export")]
+use tiny_skia::{Color, LineCap, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+// Size and colors of an exported board, colors in any form SVG takes; PNG export only
+// reads #rgb and #rrggbb
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    // Side of a cell in pixels
+    pub cell: u32,
+    pub background: String,
+    pub grid: String,
+    pub x: String,
+    pub o: String,
+    // Behind the cells of the highlighted line
+    pub highlight: String,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            cell: 100,
+            background: "#ffffff".to_string(),
+            grid: "#333333".to_string(),
+            x: "#d62828".to_string(),
+            o: "#1d4ed8".to_string(),
+            highlight: "#f4d35e".to_string(),
+        }
+    }
+}
+
+// Widest and tallest PNG drawn, far more than any board needs and small enough to allocate
+#[cfg(feature = "image-export")]
+pub const MAX_PNG_SIDE: u32 = 16384;
+
+// Where everything goes in a picture of a board, the layers of a cube side by side
+struct Layout {
+    rows: usize,
+    cols: usize,
+    layers: usize,
+    cell: usize,
+    pad: usize,
+    gap: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Layout {
+    fn of(board: &Board, style: &Style) -> Self {
+        let (rows, cols, layers) = (board.rows(), board.cols(), board.layers());
+        let cell = style.cell as usize;
+        let (pad, gap) = (cell / 10, cell / 2);
+        Layout {
+            rows,
+            cols,
+            layers,
+            cell,
+            pad,
+            gap,
+            width: layers * cols * cell + (layers - 1) * gap + 2 * pad,
+            height: rows * cell + 2 * pad,
+        }
+    }
+
+    // Top left corner of a cell
+    fn corner(&self, index: usize) -> (usize, usize) {
+        let (rows, cols) = (self.rows, self.cols);
+        let (layer, row, col) = (index / (rows * cols), index / cols % rows, index % cols);
+        (
+            self.left(layer) + col * self.cell,
+            self.pad + row * self.cell,
+        )
+    }
+
+    fn left(&self, layer: usize) -> usize {
+        self.pad + layer * (self.cols * self.cell + self.gap)
+    }
+
+    // Start and end of every line between cells
+    fn grid(&self) -> Vec<((usize, usize), (usize, usize))> {
+        let mut lines = Vec::new();
+        let bottom = self.pad + self.rows * self.cell;
+        for layer in 0..self.layers {
+            let left = self.left(layer);
+            let right = left + self.cols * self.cell;
+            for col in 1..self.cols {
+                let x = left + col * self.cell;
+                lines.push(((x, self.pad), (x, bottom)));
+            }
+            for row in 1..self.rows {
+                let y = self.pad + row * self.cell;
+                lines.push(((left, y), (right, y)));
+            }
+        }
+        lines
+    }
+
+    fn grid_width(&self) -> usize {
+        (self.cell / 20).max(1)
+    }
+
+    fn mark_width(&self) -> usize {
+        (self.cell / 10).max(1)
+    }
+
+    // The two strokes of an X in the cell at `corner`
+    fn cross(&self, (x, y): (usize, usize)) -> [((usize, usize), (usize, usize)); 2] {
+        let (near, far) = (self.cell / 4, self.cell - self.cell / 4);
+        [
+            ((x + near, y + near), (x + far, y + far)),
+            ((x + far, y + near), (x + near, y + far)),
+        ]
+    }
+
+    fn circle_radius(&self) -> usize {
+        self.cell * 3 / 10
+    }
+}
+
+// A standalone SVG image of `position`. The cells of `line`, such as the winning one,
+// are highlighted.
+pub fn svg(position: &Position, line: Option<Line>, style: &Style) -> String {
+    let board = &position.board;
+    let layout = Layout::of(board, style);
+    let (width, height, cell) = (layout.width, layout.height, layout.cell);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = width,
+        h = height
+    );
+    let _ = writeln!(
+        svg,
+        "  <rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        width,
+        height,
+        escape(&style.background)
+    );
+    for index in line.into_iter().flat_map(Line::cells) {
+        let (x, y) = layout.corner(index);
+        let _ = writeln!(
+            svg,
+            "  <rect class=\"highlight\" x=\"{}\" y=\"{}\" width=\"{c}\" height=\"{c}\" fill=\"{}\"/>",
+            x,
+            y,
+            escape(&style.highlight),
+            c = cell
+        );
+    }
+    for (from, to) in layout.grid() {
+        let _ = writeln!(
+            svg,
+            "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\"/>",
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            escape(&style.grid),
+            layout.grid_width()
+        );
+    }
+    for (index, &state) in board.cells().iter().enumerate() {
+        let color = match state {
+            State::X => escape(&style.x),
+            State::O => escape(&style.o),
+            State::Empty => continue,
+        };
+        let (x, y) = layout.corner(index);
+        if let Some(digit) = board.digit(index) {
+            let _ = writeln!(
+                svg,
+                "  <text class=\"digit\" x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"sans-serif\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\">{}</text>",
+                x + cell / 2,
+                y + cell / 2,
+                cell * 6 / 10,
+                color,
+                digit
+            );
+        } else if state == State::X {
+            let [(a, b), (c, d)] = layout.cross((x, y));
+            let _ = writeln!(
+                svg,
+                "  <path class=\"x\" d=\"M{} {} L{} {} M{} {} L{} {}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\" fill=\"none\"/>",
+                a.0,
+                a.1,
+                b.0,
+                b.1,
+                c.0,
+                c.1,
+                d.0,
+                d.1,
+                color,
+                layout.mark_width()
+            );
+        } else {
+            let _ = writeln!(
+                svg,
+                "  <circle class=\"o\" cx=\"{}\" cy=\"{}\" r=\"{}\" stroke=\"{}\" stroke-width=\"{}\" fill=\"none\"/>",
+                x + cell / 2,
+                y + cell / 2,
+                layout.circle_radius(),
+                color,
+                layout.mark_width()
+            );
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// A small self-contained HTML page showing the SVG and how the round stands
+pub fn html(position: &Position, line: Option<Line>, style: &Style) -> String {
+    let caption = match position.status {
+        Status::Won(mark) => format!("{:?} wins", mark),
+        Status::Tie => "Tie".to_string(),
+        Status::InProgress => format!("{:?} to move", position.to_move),
+        Status::Invalid => "Invalid: both sides have a line".to_string(),
+    };
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\"/>\n<title>Tic-tac-toe: {caption}</title>\n</head>\n<body style=\"font-family: sans-serif; text-align: center\">\n{}<p>{caption}</p>\n</body>\n</html>\n",
+        svg(position, line, style),
+        caption = caption
+    )
+}
+
+// The picture `svg` draws as the bytes of a PNG file. Without fonts, the digits of
+// numerical mode are drawn like on a seven-segment display.
+#[cfg(feature = "image-export")]
+pub fn png(position: &Position, line: Option<Line>, style: &Style) -> Result<Vec<u8>, String> {
+    let board = &position.board;
+    let layout = Layout::of(board, style);
+    let cell = layout.cell as f32;
+    let (width, height) = match (u32::try_from(layout.width), u32::try_from(layout.height)) {
+        (Ok(width), Ok(height)) if width <= MAX_PNG_SIDE && height <= MAX_PNG_SIDE => {
+            (width, height)
+        }
+        _ => {
+            return Err(format!(
+                "The image would be {}x{} pixels, more than {} on a side",
+                layout.width, layout.height, MAX_PNG_SIDE
+            ))
+        }
+    };
+    let mut pixmap = Pixmap::new(width, height).ok_or("The image would be empty")?;
+    pixmap.fill(parse_color(&style.background)?);
+
+    let mut paint = Paint::default();
+    paint.set_color(parse_color(&style.highlight)?);
+    for index in line.into_iter().flat_map(Line::cells) {
+        let (x, y) = layout.corner(index);
+        if let Some(rect) = Rect::from_xywh(x as f32, y as f32, cell, cell) {
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+    }
+
+    let mut path = PathBuilder::new();
+    for (from, to) in layout.grid() {
+        segment(&mut path, from, to);
+    }
+    paint.set_color(parse_color(&style.grid)?);
+    stroke(&mut pixmap, path, &paint, layout.grid_width());
+
+    for mark in [State::X, State::O] {
+        let mut path = PathBuilder::new();
+        for index in (0..board.size()).filter(|&index| board[index] == mark) {
+            let corner = layout.corner(index);
+            if let Some(digit) = board.digit(index) {
+                seven_segments(&mut path, corner, layout.cell, digit);
+            } else if mark == State::X {
+                for (from, to) in layout.cross(corner) {
+                    segment(&mut path, from, to);
+                }
+            } else {
+                path.push_circle(
+                    corner.0 as f32 + cell / 2.0,
+                    corner.1 as f32 + cell / 2.0,
+                    layout.circle_radius() as f32,
+                );
+            }
+        }
+        let color = if mark == State::X { &style.x } else { &style.o };
+        paint.set_color(parse_color(color)?);
+        stroke(&mut pixmap, path, &paint, layout.mark_width());
+    }
+    pixmap.encode_png().map_err(|err| err.to_string())
+}
+
+// Writes `position` to `path` as SVG, HTML or, with the image-export feature, PNG, going
+// by the file's extension
+pub fn export(
+    path: impl AsRef<Path>,
+    position: &Position,
+    line: Option<Line>,
+    style: &Style,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    let bytes = match extension.as_deref() {
+        Some("svg") => svg(position, line, style).into_bytes(),
+        Some("html" | "htm") => html(position, line, style).into_bytes(),
+        #[cfg(feature = "image-export")]
+        Some("png") => png(position, line, style)?,
+        #[cfg(not(feature = "image-export"))]
+        Some("png") => return Err("This build can't write PNG, export to .svg".to_string()),
+        _ => return Err("Export to a .svg, .html or .png file".to_string()),
+    };
+    fs::write(path, bytes).map_err(|err| format!("Can't write {}: {}", path.display(), err))
+}
+
+// Colors come from the caller, keep them from breaking out of their attribute
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(feature = "image-export")]
+fn segment(path: &mut PathBuilder, from: (usize, usize), to: (usize, usize)) {
+    path.move_to(from.0 as f32, from.1 as f32);
+    path.line_to(to.0 as f32, to.1 as f32);
+}
+
+#[cfg(feature = "image-export")]
+fn stroke(pixmap: &mut Pixmap, path: PathBuilder, paint: &Paint, width: usize) {
+    // An empty builder gives no path, there's nothing to draw then
+    if let Some(path) = path.finish() {
+        let stroke = Stroke {
+            width: width as f32,
+            line_cap: LineCap::Round,
+            ..Stroke::default()
+        };
+        pixmap.stroke_path(&path, paint, &stroke, Transform::identity(), None);
+    }
+}
+
+// Segments lit for each of the digits 1 to 9, bits 0 to 6 for top, top right, bottom
+// right, bottom, bottom left, top left and middle
+#[cfg(feature = "image-export")]
+const SEGMENTS: [u8; 9] = [
+    0b000_0110, 0b101_1011, 0b100_1111, 0b110_0110, 0b110_1101, 0b111_1101, 0b000_0111, 0b111_1111,
+    0b110_1111,
+];
+
+#[cfg(feature = "image-export")]
+fn seven_segments(path: &mut PathBuilder, (x, y): (usize, usize), cell: usize, digit: u8) {
+    let (left, right) = (x + cell * 7 / 20, x + cell * 13 / 20);
+    let (top, middle, bottom) = (y + cell / 4, y + cell / 2, y + cell * 3 / 4);
+    let ends = [
+        ((left, top), (right, top)),
+        ((right, top), (right, middle)),
+        ((right, middle), (right, bottom)),
+        ((left, bottom), (right, bottom)),
+        ((left, middle), (left, bottom)),
+        ((left, top), (left, middle)),
+        ((left, middle), (right, middle)),
+    ];
+    let lit = SEGMENTS[(digit.clamp(1, 9) - 1) as usize];
+    for (bit, (from, to)) in ends.into_iter().enumerate() {
+        if lit & (1 << bit) != 0 {
+            segment(path, from, to);
+        }
+    }
+}
+
+// #rgb or #rrggbb
+#[cfg(feature = "image-export")]
+fn parse_color(text: &str) -> Result<Color, String> {
+    let invalid = || format!("Not a #rrggbb color: {}", text);
+    let hex = text
+        .strip_prefix('#')
+        .filter(|hex| hex.is_ascii())
+        .ok_or_else(invalid)?;
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+    let (r, g, b) = match hex.len() {
+        3 => (
+            channel(&hex[0..1])? * 17,
+            channel(&hex[1..2])? * 17,
+            channel(&hex[2..3])? * 17,
+        ),
+        6 => (
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ),
+        _ => return Err(invalid()),
+    };
+    Ok(Color::from_rgba8(r, g, b, 255))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{Rules, Variant};
+    use std::{env, process};
+
+    fn position(cells: &str, to_move: State, rules: &Rules) -> Position {
+        Position::new(cells.parse().unwrap(), to_move, rules)
+    }
+
+    // Whether every tag is closed in the right order. A '>' may be inside a quoted
+    // attribute, a '<' can't.
+    fn well_formed(xml: &str) -> bool {
+        let mut open = Vec::new();
+        let mut chars = xml.chars();
+        while chars.any(|c| c == '<') {
+            let (mut tag, mut quoted) = (String::new(), false);
+            loop {
+                match chars.next() {
+                    None => return false,
+                    Some('<') => return false,
+                    Some('"') => quoted = !quoted,
+                    Some('>') if !quoted => break,
+                    Some(c) => tag.push(c),
+                }
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                if open.pop().as_deref() != Some(name) {
+                    return false;
+                }
+            } else if !tag.starts_with('!') && !tag.ends_with('/') {
+                open.push(
+                    tag.split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                );
+            }
+        }
+        open.is_empty()
+    }
+
+    #[test]
+    fn svg_draws_each_mark_and_the_winning_line() {
+        let rules = Rules::default();
+        let won = position("XOX.XO.OX", State::O, &rules);
+        let line = won.winning_line(&rules);
+        assert_eq!(line.map(|line| line.cells().count()), Some(3));
+        let svg = svg(&won, line, &Style::default());
+        assert!(well_formed(&svg), "{}", svg);
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"320\" height=\"320\""));
+        assert_eq!(svg.matches("class=\"x\"").count(), 4);
+        assert_eq!(svg.matches("class=\"o\"").count(), 3);
+        assert_eq!(svg.matches("class=\"highlight\"").count(), 3);
+        assert_eq!(svg.matches("<line ").count(), 4);
+        // The highlight is the diagonal, cell 4's corner is the middle
+        assert!(svg.contains("<rect class=\"highlight\" x=\"110\" y=\"110\" width=\"100\""));
+
+        let empty = super::svg(
+            &position(".........", State::X, &rules),
+            None,
+            &Style::default(),
+        );
+        assert!(well_formed(&empty));
+        assert!(!empty.contains("class="));
+    }
+
+    #[test]
+    fn svg_follows_the_style_and_the_board_size() {
+        let style = Style {
+            cell: 20,
+            x: "red\"/><script>".to_string(),
+            ..Style::default()
+        };
+        let rules = Rules::cube();
+        let cells = format!("X{}O", ".".repeat(25));
+        let svg = svg(&position(&cells, State::X, &rules), None, &style);
+        assert!(well_formed(&svg), "{}", svg);
+        assert!(!svg.contains("<script>"));
+        assert!(svg.contains("stroke=\"red&quot;/>&lt;script>\""));
+        // Three 3x3 layers side by side, half a cell apart
+        assert!(svg.contains("width=\"204\" height=\"64\""));
+        assert_eq!(svg.matches("<line ").count(), 12);
+        assert_eq!(
+            (
+                svg.matches("class=\"x\"").count(),
+                svg.matches("class=\"o\"").count()
+            ),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn numerical_boards_show_their_digits() {
+        let rules = Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        };
+        let mut board = rules.new_board();
+        board.place_digit(0, State::X, 5);
+        board.place_digit(4, State::O, 8);
+        let svg = svg(
+            &Position::new(board, State::X, &rules),
+            None,
+            &Style::default(),
+        );
+        assert!(well_formed(&svg));
+        assert_eq!(svg.matches("class=\"digit\"").count(), 2);
+        assert!(svg.contains(">5</text>") && svg.contains(">8</text>"));
+        assert!(!svg.contains("class=\"x\""));
+    }
+
+    #[test]
+    fn html_wraps_the_svg_with_a_caption() {
+        let rules = Rules::default();
+        let tie = position("XOXXOOOXX", State::X, &rules);
+        let html = html(&tie, None, &Style::default());
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>"));
+        assert!(html.contains(&svg(&tie, None, &Style::default())));
+        assert!(html.contains("<title>Tic-tac-toe: Tie</title>"));
+        assert!(well_formed(&html));
+        let playing = super::html(
+            &position("X........", State::O, &rules),
+            None,
+            &Style::default(),
+        );
+        assert!(playing.contains("<p>O to move</p>"));
+    }
+
+    #[test]
+    fn export_goes_by_the_extension() {
+        let rules = Rules::default();
+        let won = position("XXXOO....", State::O, &rules);
+        let line = won.winning_line(&rules);
+        let dir = env::temp_dir();
+        let svg_path = dir.join(format!("ttt-export-{}.SVG", process::id()));
+        export(&svg_path, &won, line, &Style::default()).unwrap();
+        let written = fs::read_to_string(&svg_path).unwrap();
+        fs::remove_file(&svg_path).unwrap();
+        assert_eq!(written, svg(&won, line, &Style::default()));
+
+        let html_path = dir.join(format!("ttt-export-{}.htm", process::id()));
+        export(&html_path, &won, line, &Style::default()).unwrap();
+        assert!(fs::read_to_string(&html_path).unwrap().contains("X wins"));
+        fs::remove_file(&html_path).unwrap();
+
+        let err = export(dir.join("board.txt"), &won, line, &Style::default());
+        assert_eq!(err, Err("Export to a .svg, .html or .png file".to_string()));
+        let missing = dir.join("no-such-dir").join("board.svg");
+        assert!(export(&missing, &won, line, &Style::default())
+            .unwrap_err()
+            .starts_with("Can't write "));
+    }
+
+    // The color of a pixel of a decoded PNG, as #rrggbb
+    #[cfg(feature = "image-export")]
+    fn pixel(image: &Pixmap, x: u32, y: u32) -> String {
+        let color = image.pixel(x, y).unwrap();
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            color.red(),
+            color.green(),
+            color.blue()
+        )
+    }
+
+    #[cfg(feature = "image-export")]
+    #[test]
+    fn png_draws_the_marks_in_their_cells() {
+        let rules = Rules::default();
+        let won = position("XXXOO....", State::O, &rules);
+        let style = Style::default();
+        let bytes = png(&won, won.winning_line(&rules), &style).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG"));
+        let image = Pixmap::decode_png(&bytes).unwrap();
+        assert_eq!((image.width(), image.height()), (320, 320));
+        // The middle of an X is on both strokes, of an O inside the ring
+        assert_eq!(pixel(&image, 60, 60), style.x);
+        assert_eq!(pixel(&image, 60, 35), style.highlight);
+        assert_eq!(pixel(&image, 60, 160), style.background);
+        assert_eq!(pixel(&image, 60 + 30, 160), style.o);
+        assert_eq!(pixel(&image, 260, 260), style.background);
+        assert_eq!(pixel(&image, 110, 260), style.grid);
+
+        let small = Style {
+            cell: 10,
+            ..Style::default()
+        };
+        let image = Pixmap::decode_png(&png(&won, None, &small).unwrap()).unwrap();
+        assert_eq!((image.width(), image.height()), (32, 32));
+    }
+
+    #[cfg(feature = "image-export")]
+    #[test]
+    fn png_refuses_huge_images() {
+        let rules = Rules::default();
+        let won = position("XXXOO....", State::O, &rules);
+        let huge = Style {
+            cell: 30_000,
+            ..Style::default()
+        };
+        assert_eq!(
+            png(&won, None, &huge),
+            Err("The image would be 96000x96000 pixels, more than 16384 on a side".to_string())
+        );
+        let too_wide = Style {
+            cell: u32::MAX,
+            ..Style::default()
+        };
+        assert!(png(&won, None, &too_wide).is_err());
+    }
+
+    #[cfg(feature = "image-export")]
+    #[test]
+    fn png_reads_hex_colors_only() {
+        assert_eq!(parse_color("#fff"), Ok(Color::WHITE));
+        assert_eq!(parse_color("#000000"), Ok(Color::BLACK));
+        for text in ["white", "#ffff", "#gggggg", "#ééé"] {
+            assert_eq!(
+                parse_color(text),
+                Err(format!("Not a #rrggbb color: {}", text))
+            );
+        }
+        let named = Style {
+            x: "red".to_string(),
+            ..Style::default()
+        };
+        let rules = Rules::default();
+        assert!(png(&position("X........", State::O, &rules), None, &named).is_err());
+    }
+
+    #[cfg(not(feature = "image-export"))]
+    #[test]
+    fn png_needs_the_feature() {
+        let rules = Rules::default();
+        let path = env::temp_dir().join("board.png");
+        let err = export(
+            path,
+            &position(".........", State::X, &rules),
+            None,
+            &Style::default(),
+        );
+        assert_eq!(
+            err,
+            Err("This build can't write PNG, export to .svg".to_string())
+        );
+    }
+}
@@ -1,41 +1,744 @@
+use crate::ai::{self, Cpu, Difficulty, MoveDecision, Player, Reason, SearchResult};
+use crate::arena;
+use crate::big_board::{self, CellView};
+use crate::board::{Board, Line, MoveList, State};
+use crate::color::{self, Emphasis};
+use crate::events::{Event, Observer, Observers};
+use crate::export::{self, Style};
+use crate::position::{CellChange, IllegalPosition, Position};
+use crate::rng::GameRng;
+use crate::rules::{Rules, Variant};
+use crate::settings::{Numbering, Settings};
 use rand::Rng;
-use std::{io, usize};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::ops::AddAssign;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-enum PickError {
-    AreaOccupied,
+#[cfg(feature = "serde")]
+use crate::achievements::{Achievement, RoundRecord, Stats};
+#[cfg(feature = "serde")]
+use crate::book::Book;
+#[cfg(feature = "serde")]
+use crate::rng::RngState;
+#[cfg(feature = "serde")]
+use crate::save::SavedGame;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::any::Any;
+#[cfg(feature = "serde")]
+use std::{fs, path::PathBuf};
+
+// Why a move was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickError {
+    // The cell at `index` already holds `occupant`'s mark
+    AreaOccupied { index: usize, occupant: State },
+    ColumnFull,
+    DigitNotYours,
+    DigitUsed,
+    // Moves wait until the offered draw is accepted or declined
+    DrawOffered,
+    // Both sides have a line, the round can't go on
+    InvalidBoard,
     MovesMapNotInitialized,
-    OutOfBounds,
+    NoDrawOffer,
+    NotYourTurn,
+    // `given` is past `max`, the last index there is
+    OutOfBounds { given: usize, max: usize },
+    WrongPhase,
+}
+
+impl PickError {
+    // What to tell the player, with indices counted the way they type them
+    pub fn message(&self, numbering: Numbering) -> String {
+        let first = numbering.first();
+        match *self {
+            PickError::AreaOccupied { index, occupant } => {
+                format!("Cell {} is already taken by {:?}!", index + first, occupant)
+            }
+            PickError::ColumnFull => "That column is already full!".to_string(),
+            PickError::DigitNotYours => "That digit is the other side's!".to_string(),
+            PickError::DigitUsed => "That digit has already been played!".to_string(),
+            PickError::DrawOffered => "A draw offer awaits an answer!".to_string(),
+            PickError::InvalidBoard => "Both sides have a line on this board!".to_string(),
+            PickError::MovesMapNotInitialized => "The game has not started!".to_string(),
+            PickError::NoDrawOffer => "No draw has been offered!".to_string(),
+            PickError::NotYourTurn => "It's not your turn!".to_string(),
+            // Undoes parse_move's wrapping_sub, 0 when counting from 1 shows as 0
+            PickError::OutOfBounds { given, max } => format!(
+                "Invalid index {}, must be between {} and {}!",
+                given.wrapping_add(first),
+                first,
+                max + first
+            ),
+            PickError::WrongPhase => "The round is already over!".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PickError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message(Numbering::ZeroBased))
+    }
+}
+
+impl Error for PickError {}
+
+// Why the game refused something asked of it through its API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameError {
+    Pick(PickError),
+    Position(IllegalPosition),
+    // Rules or a setup the game can't be played with
+    Invalid(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::Pick(err) => write!(f, "{}", err),
+            GameError::Position(err) => write!(f, "{}", err),
+            GameError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
 }
 
+impl Error for GameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GameError::Pick(err) => Some(err),
+            GameError::Position(err) => Some(err),
+            GameError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<PickError> for GameError {
+    fn from(err: PickError) -> Self {
+        GameError::Pick(err)
+    }
+}
+
+impl From<IllegalPosition> for GameError {
+    fn from(err: IllegalPosition) -> Self {
+        GameError::Position(err)
+    }
+}
+
+// A parsed player move; `mark` is only set in wild mode and `digit` in numerical mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub index: usize,
+    pub mark: Option<State>,
+    pub digit: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum CheckResult {
     Win,
     Tie,
     Contine,
+    Invalid,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum State {
-    X,
-    O,
-    Empty,
+// Where the current round stands; a win names the mark of the side that won it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Status {
+    InProgress,
+    Won(State),
+    Tie,
+    // Both sides have a line, which no game can reach: the board came from outside or a
+    // rules bug, and the round can't be scored
+    Invalid,
+}
+
+// A round the players ended before the board decided it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Ending {
+    // The side with this mark gave up, the other side wins
+    Resigned(State),
+    // A draw was offered and accepted, the round is a tie
+    DrawAgreed,
+}
+
+impl fmt::Display for Ending {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ending::Resigned(mark) => write!(f, "{:?} resigns", mark),
+            Ending::DrawAgreed => write!(f, "draw agreed"),
+        }
+    }
+}
+
+// A round best play has already decided, as `Game::forced_outcome` finds it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forced {
+    // `winner` wins, making `moves` more moves at most
+    Win { winner: State, moves: u32 },
+    // No line can be completed any more
+    DeadDraw,
+}
+
+// What became of a draw offer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawAnswer {
+    // The other player is yet to answer it
+    Pending,
+    Accepted,
+    Declined,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Score {
+    pub player: u16,
+    pub cpu: u16,
+    pub tie: u16,
+}
+
+impl Score {
+    pub fn rounds(&self) -> u32 {
+        self.player as u32 + self.cpu as u32 + self.tie as u32
+    }
+
+    // One aligned row per side and one for ties, with each share of the rounds played
+    pub fn table(&self, player: &str, cpu: &str) -> String {
+        let rows = [(player, self.player), (cpu, self.cpu), ("Tie", self.tie)];
+        let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let count_width = rows
+            .iter()
+            .map(|(_, count)| count.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let rounds = self.rounds();
+        let mut table = String::new();
+        for (label, count) in rows {
+            let share = match rounds {
+                0 => "   -".to_string(),
+                _ => format!("{:3.0}%", count as f64 * 100.0 / rounds as f64),
+            };
+            table.push_str(&format!(
+                "{:<lw$}  {:>cw$}  {}\n",
+                label,
+                count,
+                share,
+                lw = label_width,
+                cw = count_width
+            ));
+        }
+        table
+    }
+
+    // Which of `names` reached `target` round wins, ties never count
+    fn match_winner(&self, target: u16, names: [&'static str; 2]) -> Option<&'static str> {
+        if self.player >= target {
+            Some(names[0])
+        } else if self.cpu >= target {
+            Some(names[1])
+        } else {
+            None
+        }
+    }
+}
+
+// Where the interactive loop reads moves and writes everything it shows
+pub(crate) struct Console<I, W> {
+    pub(crate) input: I,
+    pub(crate) output: W,
+    // ANSI colors for the board, only when writing to a terminal
+    pub(crate) colors: bool,
+    // Reads stdin in place of `input`, so that waiting for a move can time out
+    pub(crate) lines: Option<LineReader>,
+    // Lines typed before the last move was answered are dropped rather than taken as the
+    // next move: the rest of a paste, or a key the terminal sent twice. Needs `lines`,
+    // and only suits a terminal, scripts type all their moves ahead.
+    pub(crate) debounce: bool,
+    // A move was just played, lines typed meanwhile are still to be dropped
+    pub(crate) moved: bool,
+}
+
+impl Console<Box<dyn BufRead>, Box<dyn Write>> {
+    // Stdin and stdout; with `timed` or a terminal for stdin, stdin is read by a
+    // `LineReader`, with `quiet` nothing is shown
+    pub(crate) fn terminal(timed: bool, quiet: bool) -> Self {
+        let debounce = io::stdin().is_terminal();
+        let (input, lines): (Box<dyn BufRead>, _) = if timed || debounce {
+            (Box::new(io::empty()), Some(LineReader::stdin()))
+        } else {
+            (Box::new(io::stdin().lock()), None)
+        };
+        let output: Box<dyn Write> = if quiet {
+            Box::new(io::sink())
+        } else {
+            Box::new(io::stdout().lock())
+        };
+        Console {
+            input,
+            output,
+            colors: !quiet && color::enabled(),
+            lines,
+            debounce,
+            moved: false,
+        }
+    }
+}
+
+// How soon a line has to follow a move to count as typed ahead of its answer
+const TYPED_AHEAD: Duration = Duration::from_millis(20);
+
+// What waiting for a line of input ended with
+enum Typed {
+    Line(String),
+    End,
+    // Nothing was typed in time
+    Idle,
+}
+
+// Reads stdin on its own thread, one line each time one is asked for. Between the
+// requests stdin is left alone, so whatever reads it after the game gets its lines.
+pub(crate) struct LineReader {
+    requests: mpsc::Sender<()>,
+    lines: mpsc::Receiver<Option<String>>,
+    // A line was asked for and hasn't been taken yet
+    pending: bool,
+}
+
+impl LineReader {
+    fn stdin() -> Self {
+        LineReader::spawn(|line| io::stdin().read_line(line))
+    }
+
+    // Reads lines with `read_line` on a thread of its own
+    fn spawn<F>(mut read_line: F) -> Self
+    where
+        F: FnMut(&mut String) -> io::Result<usize> + Send + 'static,
+    {
+        let (requests, asked) = mpsc::channel();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for () in asked {
+                let mut line = String::new();
+                let line = match read_line(&mut line) {
+                    Ok(0) | Err(_) => None,
+                    Ok(_) => Some(line),
+                };
+                let end = line.is_none();
+                if sender.send(line).is_err() || end {
+                    break;
+                }
+            }
+        });
+        LineReader {
+            requests,
+            lines,
+            pending: false,
+        }
+    }
+
+    // The next line, waiting no longer than `timeout` when there is one. A line that
+    // comes too late is the answer to the next call.
+    fn read(&mut self, timeout: Option<Duration>) -> Typed {
+        if !self.pending {
+            if self.requests.send(()).is_err() {
+                return Typed::End;
+            }
+            self.pending = true;
+        }
+        let line = match timeout {
+            Some(timeout) => match self.lines.recv_timeout(timeout) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Typed::Idle,
+                Err(RecvTimeoutError::Disconnected) => None,
+            },
+            None => self.lines.recv().ok().flatten(),
+        };
+        self.pending = false;
+        line.map_or(Typed::End, Typed::Line)
+    }
+}
+
+impl<I: BufRead, W: Write> Console<I, W> {
+    // A line of input, None at end of input; a read error counts as the end too
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        match self.read_line_within(None)? {
+            Typed::Line(line) => Ok(Some(line)),
+            _ => Ok(None),
+        }
+    }
+
+    // A line of input, or Typed::Idle once `idle` passes without one. Only a
+    // `LineReader` can stop waiting, other input is waited for regardless.
+    fn read_line_within(&mut self, idle: Option<Duration>) -> io::Result<Typed> {
+        // Prompts may be buffered, show them before waiting
+        self.output.flush()?;
+        if let Some(lines) = &mut self.lines {
+            if std::mem::take(&mut self.moved) && self.debounce {
+                let mut dropped = 0;
+                // Typed ahead lines are already waiting, a line typed now takes longer
+                while let Typed::Line(_) = lines.read(Some(TYPED_AHEAD)) {
+                    dropped += 1;
+                }
+                if dropped > 0 {
+                    let plural = if dropped == 1 { "" } else { "s" };
+                    writeln!(
+                        self.output,
+                        "Ignored {} line{} typed before your move was answered",
+                        dropped, plural
+                    )?;
+                    self.output.flush()?;
+                }
+            }
+            return Ok(lines.read(idle));
+        }
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => Ok(Typed::End),
+            Ok(_) => Ok(Typed::Line(line)),
+        }
+    }
+
+    // A trimmed, lowercased answer, or None at end of input
+    fn read_answer(&mut self) -> io::Result<Option<String>> {
+        Ok(self.read_line()?.map(|line| line.trim().to_lowercase()))
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        self.player += other.player;
+        self.cpu += other.cpu;
+        self.tie += other.tie;
+    }
+}
+
+// Round results against each opponent, keyed by `Game::opponent`: a record against the
+// easy Cpu says little about one against the hard Cpu
+pub type Scores = BTreeMap<String, Score>;
+
+// One aligned row per opponent with the rounds won, lost and tied against it
+pub fn scores_table(scores: &Scores) -> String {
+    let width = scores
+        .keys()
+        .map(String::len)
+        .chain(["Opponent".len()])
+        .max()
+        .unwrap_or(0);
+    let mut table = format!(
+        "{:<w$}  {:>4}  {:>4}  {:>4}\n",
+        "Opponent",
+        "Won",
+        "Lost",
+        "Tied",
+        w = width
+    );
+    for (opponent, score) in scores {
+        table.push_str(&format!(
+            "{:<w$}  {:>4}  {:>4}  {:>4}\n",
+            opponent,
+            score.player,
+            score.cpu,
+            score.tie,
+            w = width
+        ));
+    }
+    table
+}
+
+// A move of the current round as it was placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayedMove {
+    pub mark: State,
+    pub index: usize,
+    pub digit: Option<u8>,
+    // Played for an idle player
+    pub auto: bool,
+    // When it was placed, since the round started
+    pub elapsed: Duration,
+}
+
+// Why the interactive loop stopped
+pub(crate) enum Handoff {
+    // The player left, the summary is written
+    Ended,
+    // A `game ...` line for the session above the game
+    Command(String),
+}
+
+// Where the interactive loop is within a turn; each step hands over to the next
+enum Step {
+    ReadInput,
+    // The move and whether it was played for the player
+    ApplyPlayerMove(Move, bool),
+    CpuMove,
+    RoundEnd(Outcome),
+    SessionEnd(Handoff),
+}
+
+// A move drawn on the board before it's placed
+#[derive(Debug, Clone, Copy)]
+struct Overlay {
+    index: usize,
+    mark: State,
+    digit: Option<u8>,
+}
+
+// Random games per estimate when a position is too big to solve
+const EVAL_PLAYOUTS: u32 = 200;
+
+// Finished rounds kept for `games`, the oldest are dropped beyond it
+const HISTORY_LEN: usize = 100;
+
+// A finished round as `games` shows it
+#[derive(Debug, Clone, PartialEq)]
+struct FinishedRound {
+    number: u32,
+    board: Board,
+    line: Option<Line>,
+    // As said when it ended, e.g. "You win"
+    result: String,
+    moves: Vec<PlayedMove>,
+    ending: Option<Ending>,
+    times: TurnTimes,
+    // Finished rounds of the session that were the same game up to symmetry, this one included
+    played: u32,
+}
+
+// A finished round's moves and ending, the same for every mirror image and turn of it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CanonicalGame {
+    moves: Vec<(State, usize, Option<u8>)>,
+    ending: Option<Ending>,
+}
+
+impl CanonicalGame {
+    // The smallest of the game's move sequences under the symmetries the rules allow:
+    // turns only keep square boards, and gravity only allows flipping left to right
+    pub fn new(rules: &Rules, moves: &[PlayedMove], ending: Option<Ending>) -> Self {
+        let symmetries: &[usize] = match rules.variant {
+            Variant::Gravity => &[0, 4],
+            _ if rules.rows == rules.cols => &[0, 1, 2, 3, 4, 5, 6, 7],
+            _ => &[0, 2, 4, 6],
+        };
+        let maps = rules.new_board().cell_transforms();
+        let moves = symmetries
+            .iter()
+            .map(|&symmetry| {
+                moves
+                    .iter()
+                    .map(|played| {
+                        let index = maps[symmetry][played.index] as usize;
+                        (played.mark, index, played.digit)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .min()
+            .unwrap_or_default();
+        CanonicalGame { moves, ending }
+    }
+}
+
+// How a finished round went, from the human's side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Outcome {
+    PlayerWin,
+    CpuWin,
+    Tie,
+}
+
+// Who the round is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Phase {
+    AwaitingPlayer,
+    AwaitingCpu,
+    RoundOver(Outcome),
+    // The board has lines of both sides; nobody moves until a new round
+    Invalid,
+}
+
+// Where a best-of match stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MatchPhase {
+    #[default]
+    Scheduled,
+    // The scheduled rounds ended level; this many sudden-death rounds are done
+    TieBreak(u16),
+}
+
+// How a finished match went
+enum MatchOutcome {
+    Won(&'static str),
+    Drawn,
+}
+
+// Everything about a game but its RNG, observers and clocks, to save or send it. The
+// side to move and the phase follow from the position.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GameState {
+    pub rules: Rules,
+    pub settings: Settings,
+    // The session's score split by opponent
+    pub scores: Scores,
+    pub match_score: Score,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub match_phase: MatchPhase,
+    pub human_mark: State,
+    pub cpu_opens: bool,
+    // The current round, None before the first one
+    pub position: Option<Position>,
+}
+
+// How a session went, returned once the player stops
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub score: Score,
+    pub scores: Scores,
+    pub rounds: u32,
+    // Different games among the rounds finished this session, up to symmetry
+    pub distinct_games: usize,
+    pub duration: Duration,
+    pub times: TurnTimes,
+    // Labels of the score's two sides, the player's first
+    pub sides: [&'static str; 2],
+}
+
+// Time each side spent on its moves, from the prompt to the placed mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TurnTimes {
+    pub player: Duration,
+    pub cpu: Duration,
+}
+
+impl TurnTimes {
+    // Each side's time after its label, e.g. "You 4.2s, Cpu 1.0s"
+    pub fn line(&self, player: &str, cpu: &str) -> String {
+        format!(
+            "{} {}, {} {}",
+            player,
+            seconds(self.player),
+            cpu,
+            seconds(self.cpu)
+        )
+    }
 }
 
-#[derive(Debug)]
-struct Score {
-    player: u16,
-    cpu: u16,
-    tie: u16,
+// A duration in seconds with one decimal, e.g. "4.2s"
+pub fn seconds(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
 }
 
-#[derive(Debug)]
-pub struct Game {
-    moves_map: Option<[State; 9]>,
+// `R` makes every random choice of the CPU, seed it for reproducible games
+#[derive(Debug, Clone)]
+pub struct Game<R = GameRng> {
+    moves_map: Option<Board>,
     score: Score,
+    // `score` split by who the rounds were played against
+    scores: Scores,
+    // Score of the current first-to-N match, `score` keeps the whole session
+    match_score: Score,
+    match_phase: MatchPhase,
+    rules: Rules,
+    settings: Settings,
+    cpu_opens: bool,
+    // The CPU plays the other mark
+    human_mark: State,
+    // Mark of whoever moved last, the winner in wild and numerical mode
+    last_mover: Option<State>,
+    // Set when the round ended by resignation or agreement, the board is left as it was
+    ending: Option<Ending>,
+    // Mark of the side whose draw offer awaits the other side's answer
+    draw_offer: Option<State>,
+    // The forced outcome last offered to end the round with, not offered again once declined
+    fast_finish_offer: Option<Forced>,
+    // Computed on demand, cleared by every board change
+    status: Cell<Option<Status>>,
+    // Snapshot from before the latest move
+    previous: Option<Position>,
+    // The moves of the round so far
+    round_moves: Vec<PlayedMove>,
+    // When the round started, its moves are timed from it
+    round_started: Instant,
+    // The latest finished rounds, oldest first
+    history: VecDeque<FinishedRound>,
+    // How often each game was played this session, up to symmetry
+    played_games: HashMap<CanonicalGame, u32>,
+    // Why the CPU made its latest move
+    last_decision: Option<MoveDecision>,
+    // Plays instead of the built-in CPU in classic and gravity mode
+    player: Option<Arc<dyn Player + Send + Sync>>,
+    phase: Phase,
+    // The round's instructions were shown, later prompts only show the board and status
+    instructed: bool,
+    round_times: TurnTimes,
+    session_times: TurnTimes,
+    // Mark of the side to move, the human or the CPU
+    turn: State,
+    observers: Observers,
+    rng: R,
+    // Name of the session slot holding the game, shown before every prompt
+    label: Option<String>,
+    // Path of the picture saved of every won round, {round} standing for its number
+    snapshot_on_win: Option<String>,
+    // Where `pause` saves the game, deleted again once a round ends
+    #[cfg(feature = "serde")]
+    autosave: Option<PathBuf>,
+    // Openings the hard CPU plays without searching
+    #[cfg(feature = "serde")]
+    book: Option<Arc<Book>>,
+    // Where unlocked achievements are kept, read again at every round end so that
+    // several games can share it
+    #[cfg(feature = "serde")]
+    stats_file: Option<PathBuf>,
+    // Each mover of the round and the board after its move, for the achievements
+    #[cfg(feature = "serde")]
+    round_boards: Vec<(State, Board)>,
+    // Unlocked by the latest round and not yet announced
+    #[cfg(feature = "serde")]
+    unlocked: Vec<Achievement>,
+    // A move of the round was played for the player, so it earns no achievements
+    #[cfg(feature = "serde")]
+    auto_played: bool,
+    // Parent of the per-turn spans of the current round
+    #[cfg(feature = "tracing")]
+    round_span: tracing::Span,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Self {
+        Game::with_rules(Rules::default())
+    }
+
+    pub fn with_rules(rules: Rules) -> Self {
+        Game::with_settings(rules, Settings::default())
+    }
+
+    pub fn with_settings(rules: Rules, settings: Settings) -> Self {
+        Game::with_rng(rules, settings, GameRng::from_entropy())
+    }
+}
+
+impl<R: Rng + 'static> Game<R> {
+    pub fn with_rng(rules: Rules, settings: Settings, rng: R) -> Self {
         Game {
             moves_map: None,
             score: Score {
@@ -43,188 +746,5483 @@ impl Game {
                 cpu: 0,
                 tie: 0,
             },
+            scores: Scores::new(),
+            match_score: Score::default(),
+            match_phase: MatchPhase::Scheduled,
+            rules,
+            settings,
+            cpu_opens: false,
+            human_mark: State::X,
+            last_mover: None,
+            ending: None,
+            draw_offer: None,
+            fast_finish_offer: None,
+            status: Cell::new(None),
+            previous: None,
+            round_moves: Vec::new(),
+            round_started: Instant::now(),
+            history: VecDeque::new(),
+            played_games: HashMap::new(),
+            last_decision: None,
+            player: None,
+            phase: Phase::AwaitingPlayer,
+            instructed: false,
+            round_times: TurnTimes::default(),
+            session_times: TurnTimes::default(),
+            turn: State::X,
+            observers: Observers::default(),
+            rng,
+            label: None,
+            snapshot_on_win: None,
+            #[cfg(feature = "serde")]
+            autosave: None,
+            #[cfg(feature = "serde")]
+            book: None,
+            #[cfg(feature = "serde")]
+            stats_file: None,
+            #[cfg(feature = "serde")]
+            round_boards: Vec::new(),
+            #[cfg(feature = "serde")]
+            unlocked: Vec::new(),
+            #[cfg(feature = "serde")]
+            auto_played: false,
+            #[cfg(feature = "tracing")]
+            round_span: tracing::Span::none(),
         }
     }
 
-    pub fn start(&mut self) {
-        // Initialize the moves_map with an empty board
-        self.moves_map = Some([State::Empty; 9]);
+    // A game continuing from a saved state, with `rng` for the CPU's choices from here on
+    pub fn from_state(state: GameState, rng: R) -> Result<Self, GameError> {
+        state.rules.validate().map_err(GameError::Invalid)?;
+        if state.human_mark == State::Empty {
+            return Err(GameError::Invalid("The player has no mark".to_string()));
+        }
+        let mut game = Game::with_rng(state.rules, state.settings, rng);
+        for score in state.scores.values() {
+            game.score += *score;
+        }
+        game.scores = state.scores;
+        game.match_score = state.match_score;
+        game.match_phase = state.match_phase;
+        game.human_mark = state.human_mark;
+        game.cpu_opens = state.cpu_opens;
+        if let Some(position) = state.position {
+            position.validate(&state.rules)?;
+            game.set_position(position);
+        }
+        Ok(game)
+    }
 
-        loop {
-            println!("Choose index(0 to 8):");
-            self.print_info();
-            let mut input = String::new();
-            io::stdin()
-                .read_line(&mut input)
-                .expect("Failed to read line");
-
-            // Convert the input to an integer, trimming the newline
-            let number: usize = input.trim().parse().expect("Please enter a valid number");
-            println!("You entered: {}", number);
-            match self.pick_player(number) {
-                Ok(()) => match self.check(State::X) {
-                    CheckResult::Win => {
-                        println!("** You win! **");
-                        self.increase_score(1);
-                        self.reset();
-                        continue;
-                    }
-                    CheckResult::Tie => {
-                        println!("** Tie! **");
-                        self.increase_score(0);
-                        self.reset();
-                        continue;
-                    }
-                    CheckResult::Contine => {
-                        println!("** Cpu turn **");
-                    }
-                },
-                Err(PickError::AreaOccupied) => {
-                    println!("That area is already occupied!");
-                    continue;
-                }
-                Err(PickError::OutOfBounds) => {
-                    println!("Invalid index!\nMust be between 0 and 8");
-                    continue;
-                }
-                Err(PickError::MovesMapNotInitialized) => println!("The game has not started!"),
+    // Who is ahead: solved exactly when the search is quick enough, otherwise estimated
+    // from random playouts. None when the round is over or the variant can't be searched.
+    fn eval_line(&mut self) -> Option<String> {
+        let map = self.moves_map?;
+        let searchable = matches!(self.rules.variant, Variant::Classic | Variant::Gravity);
+        if !searchable || self.status() != Status::InProgress {
+            return None;
+        }
+        let human = self.human_mark;
+        let (you, cpu) = if self.settings.two_players {
+            (("x wins", "x"), ("o wins", "o"))
+        } else {
+            (("you win", "you"), ("cpu wins", "cpu"))
+        };
+        if map.count(State::Empty) <= ai::MAX_SEARCH_CELLS {
+            let score = ai::evaluate(&map, &self.rules, self.turn);
+            let moves = (ai::WIN - score.abs() + 1) / 2;
+            let verdict = match score {
+                0 => "draw".to_string(),
+                s if (s > 0) == (self.turn == human) => format!("{} in {}", you.0, moves),
+                _ => format!("{} in {}", cpu.0, moves),
             };
-            self.pick_cpu();
-            match self.check(State::O) {
-                CheckResult::Win => {
-                    println!("** Cpu wins! **");
-                    self.increase_score(2);
-                    self.reset();
-                    continue;
-                }
-                CheckResult::Tie => {
-                    println!("** Tie! **");
-                    self.increase_score(0);
-                    self.reset();
-                    continue;
-                }
-                CheckResult::Contine => {
-                    println!("** Your turn **");
-                }
+            return Some(format!("eval: {} (best play)", verdict));
+        }
+
+        let (mut human_wins, mut cpu_wins, mut tie) = (0, 0, 0);
+        for _ in 0..EVAL_PLAYOUTS {
+            let (status, _) = arena::play_out_from(
+                map,
+                self.turn,
+                &self.rules,
+                Cpu::default(),
+                Cpu::default(),
+                &mut self.rng,
+            );
+            match status {
+                Status::Won(mark) if mark == human => human_wins += 1,
+                Status::Won(_) => cpu_wins += 1,
+                _ => tie += 1,
             }
         }
+        let percent = |count: u32| count * 100 / EVAL_PLAYOUTS;
+        Some(format!(
+            "playouts: {} {}% | tie {}% | {} {}%",
+            you.1,
+            percent(human_wins),
+            percent(tie),
+            cpu.1,
+            percent(cpu_wins)
+        ))
     }
 
-    fn increase_score(&mut self, turn: u8) {
-        match turn {
-            0 => self.score.tie += 1,
-            1 => self.score.player += 1,
-            2 => self.score.cpu += 1,
-            _ => (),
-        }
+    // Receive every session event, e.g. to write a log
+    pub fn add_observer(&mut self, observer: Box<dyn Observer + Send>) {
+        self.observers.add(observer);
     }
 
-    fn reset(&mut self) {
-        self.moves_map = Some([State::Empty; 9]);
+    // Let `pause` save the game to `path`
+    #[cfg(feature = "serde")]
+    pub fn set_autosave(&mut self, path: PathBuf) {
+        self.autosave = Some(path);
     }
 
-    fn is_full(&self) -> bool {
-        match self.moves_map {
-            Some(moves) => moves.iter().all(|&v| v != State::Empty),
-            None => false,
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
+    }
+
+    // Export every won round's final board to `template`, see `export::export`
+    pub fn set_snapshot_on_win(&mut self, template: String) {
+        self.snapshot_on_win = Some(template);
+    }
+
+    // Track achievements in `path`
+    #[cfg(feature = "serde")]
+    pub fn set_stats_file(&mut self, path: PathBuf) {
+        self.stats_file = Some(path);
+    }
+
+    // Replace the built-in CPU, e.g. with a learned one
+    pub fn set_player(&mut self, player: Arc<dyn Player + Send + Sync>) {
+        self.player = Some(player);
+    }
+
+    // Let the hard CPU play the book's moves while the game is still in it
+    #[cfg(feature = "serde")]
+    pub fn set_book(&mut self, book: Arc<Book>) {
+        self.book = Some(book);
+    }
+
+    fn start_round(&mut self) {
+        let round = self.score.rounds() + 1;
+        #[cfg(feature = "tracing")]
+        {
+            self.round_span = tracing::info_span!("round", round);
         }
+        self.observers.emit(Event::RoundStart { round });
     }
 
-    fn print_info(&self) {
-        match &self.moves_map {
-            Some(moves) => {
-                for (i, &val) in moves.iter().enumerate() {
-                    let symbol = match val {
-                        State::X => "X",
-                        State::O => "O",
-                        State::Empty => ".",
-                    };
-                    print!("{:3}", symbol);
-                    if (i + 1) % 3 == 0 {
-                        println!();
-                    }
-                }
+    // Plays on stdin and stdout until the player stops
+    pub fn start(&mut self) -> SessionSummary {
+        let started = Instant::now();
+        let mut console = Console::terminal(self.settings.auto_play.is_some(), false);
+        if let Err(err) = self.play(&mut console) {
+            // Output piped into a closed reader (such as `head`) just ends the session
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("Can't write to the terminal: {}", err);
             }
-            None => println!("No moves yet!"),
-        };
-        println!("{:?}", &self.score)
+        }
+        self.summary(started)
     }
 
-    fn pick_cpu(&mut self) {
-        loop {
-            let mut rng = rand::thread_rng();
-            let index: usize = rng.gen_range(0..=8);
-            if self.is_full() {
-                return;
-            }
+    // The interactive loop reading moves from `input` and writing everything to `output`;
+    // a write error ends the session and is returned
+    pub fn start_with<I: BufRead, W: Write>(
+        &mut self,
+        input: I,
+        output: W,
+    ) -> io::Result<SessionSummary> {
+        let started = Instant::now();
+        self.play(&mut Console {
+            input,
+            output,
+            colors: false,
+            lines: None,
+            debounce: false,
+            moved: false,
+        })?;
+        Ok(self.summary(started))
+    }
 
-            if let Some(map) = &mut self.moves_map {
-                if map[index] == State::Empty {
-                    map[index] = State::O;
-                    break;
-                }
-            }
+    fn summary(&self, started: Instant) -> SessionSummary {
+        SessionSummary {
+            score: self.score,
+            scores: self.scores.clone(),
+            rounds: self.score.rounds(),
+            distinct_games: self.distinct_games(),
+            duration: started.elapsed(),
+            times: self.session_times,
+            sides: self.side_names(),
         }
     }
 
-    fn pick_player(&mut self, index: usize) -> Result<(), PickError> {
-        match index {
-            0..=8 => {
-                if let Some(map) = &mut self.moves_map {
-                    if map[index] == State::Empty {
-                        map[index] = State::X;
-                        Ok(())
-                    } else {
-                        Err(PickError::AreaOccupied) // Fail, already occupied
-                    }
-                } else {
-                    Err(PickError::MovesMapNotInitialized) // Fail, moves_map is None
-                }
-            }
-            _ => Err(PickError::OutOfBounds),
+    fn play<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<()> {
+        if self.begin(console)? {
+            self.run(console, false)?;
         }
+        Ok(())
     }
 
-    fn check(&mut self, state: State) -> CheckResult {
-        if let Some(map) = self.moves_map {
-            let (mut ptr1, mut ptr2, mut ptr3) = (0, 3, 6);
+    // Sets up the first round; false if the session already ended. A resumed game goes
+    // on with its round, others start on an empty board.
+    pub(crate) fn begin<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<bool> {
+        if self.moves_map.is_none() || self.status() != Status::InProgress {
+            self.reset();
+        }
+        self.start_round();
+        if self.phase == Phase::AwaitingCpu {
+            return self.cpu_turn(console);
+        }
+        Ok(true)
+    }
 
-            // Scan map from left to right
-            for _ in 0..=2 {
-                if map[ptr1] == state && map[ptr2] == state && map[ptr3] == state {
-                    return CheckResult::Win;
+    // The interactive loop after `begin`. With `slots`, `game ...` lines are handed back
+    // for the session holding the game to handle.
+    pub(crate) fn run<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+        slots: bool,
+    ) -> io::Result<Handoff> {
+        // The player's clock runs from the first prompt of a turn until a move is placed
+        let mut turn_started = None;
+        let mut step = Step::ReadInput;
+        loop {
+            step = match step {
+                Step::ReadInput => {
+                    turn_started.get_or_insert_with(Instant::now);
+                    self.read_input(console, slots)?
                 }
-                ptr1 += 1;
-                ptr2 += 1;
-                ptr3 += 1;
-            }
+                Step::ApplyPlayerMove(player_move, auto) => {
+                    self.apply_player_move(console, player_move, auto, &mut turn_started)?
+                }
+                Step::CpuMove => self.cpu_move(console)?,
+                Step::RoundEnd(outcome) => self.round_end(console, outcome)?,
+                Step::SessionEnd(handoff) => return Ok(handoff),
+            };
+        }
+    }
 
-            // Scan map from top to bottom
-            (ptr1, ptr2, ptr3) = (0, 1, 2);
-            for _ in 0..=2 {
-                if map[ptr1] == state && map[ptr2] == state && map[ptr3] == state {
-                    return CheckResult::Win;
+    // Prompts for and reads one line, handling commands; a move goes on to be played
+    fn read_input<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+        slots: bool,
+    ) -> io::Result<Step> {
+        // A batch of quiet rounds only explains the input once
+        let repeat = self.settings.summary_only && self.score.rounds() > 0;
+        if !self.instructed && !repeat {
+            self.print_instructions(console)?;
+            self.instructed = true;
+        }
+        if self.settings.summary_only {
+            self.print_compact(console)?;
+        } else {
+            self.write_board(console, None)?;
+            self.print_status(console)?;
+        }
+        if self.settings.show_eval {
+            if let Some(line) = self.eval_line() {
+                writeln!(console.output, "{}", line)?;
+            }
+        }
+        // Only classic and gravity mode have a move to suggest
+        let idle = self
+            .settings
+            .auto_play
+            .filter(|_| matches!(self.rules.variant, Variant::Classic | Variant::Gravity));
+        let wait = idle.map(|seconds| Duration::from_secs(seconds.into()));
+        let (input, auto) = match console.read_line_within(wait)? {
+            Typed::Line(input) => (input, false),
+            // End of input (or a broken input) finishes the session like declining a rematch
+            Typed::End => {
+                // Leaving isn't pausing, the round's checkpoint goes
+                #[cfg(feature = "serde")]
+                if let Some(path) = &self.autosave {
+                    let _ = fs::remove_file(path);
                 }
-                ptr1 += 3;
-                ptr2 += 3;
-                ptr3 += 3;
+                writeln!(console.output)?;
+                self.print_summary(console)?;
+                return Ok(Step::SessionEnd(Handoff::Ended));
             }
-
-            // Scan map diagonally(both sides)
-            (ptr1, ptr2, ptr3) = (0, 4, 8);
-            if map[ptr1] == state && map[ptr2] == state && map[ptr3] == state {
-                return CheckResult::Win;
+            Typed::Idle => match self.auto_play_move() {
+                Some(input) => {
+                    writeln!(
+                        console.output,
+                        "No move for {}s, playing one for you",
+                        idle.unwrap_or_default()
+                    )?;
+                    (input, true)
+                }
+                None => return Ok(Step::ReadInput),
+            },
+        };
+        if slots && input.split_whitespace().next() == Some("game") {
+            return Ok(Step::SessionEnd(Handoff::Command(input.trim().to_string())));
+        }
+        if input.trim() == "swap" {
+            self.swap_sides(console)?;
+            // X opens numerical games, so there the Cpu may now be the one to open
+            let untouched = self
+                .moves_map
+                .is_some_and(|map| map.cells().iter().all(|&v| v == State::Empty));
+            if self.cpu_opens && untouched {
+                writeln!(console.output, "** Cpu opens **")?;
+                self.pick_cpu();
             }
-            (ptr1, ptr2, ptr3) = (2, 4, 6);
-            if map[ptr1] == state && map[ptr2] == state && map[ptr3] == state {
-                return CheckResult::Win;
+            return Ok(Step::ReadInput);
+        }
+        if let Some(toggle) = input.trim().strip_prefix("confirm") {
+            match toggle.trim() {
+                "on" => self.settings.confirm_moves = true,
+                "off" => self.settings.confirm_moves = false,
+                _ => {
+                    writeln!(console.output, "Use confirm on or confirm off")?;
+                    return Ok(Step::ReadInput);
+                }
+            }
+            let state = if self.settings.confirm_moves {
+                "on"
+            } else {
+                "off"
+            };
+            writeln!(console.output, "Move confirmation is {}", state)?;
+            return Ok(Step::ReadInput);
+        }
+        if let Some(numbering) = input.trim().strip_prefix("numbering") {
+            match numbering.trim().parse() {
+                Ok(numbering) => {
+                    self.settings.numbering = numbering;
+                    self.instructed = false;
+                    writeln!(console.output, "Cells are numbered {}", numbering)?;
+                }
+                Err(_) => writeln!(
+                    console.output,
+                    "Use numbering zero-based or numbering one-based"
+                )?,
+            }
+            return Ok(Step::ReadInput);
+        }
+        if input.trim() == "share" {
+            match self.snapshot().encode() {
+                Some(code) => writeln!(console.output, "Position code: {}", code)?,
+                None => writeln!(console.output, "Only classic 3x3 positions can be shared")?,
+            }
+            return Ok(Step::ReadInput);
+        }
+        if let Some(path) = input.trim().strip_prefix("export ") {
+            self.export(console, path.trim())?;
+            return Ok(Step::ReadInput);
+        }
+        if let Some(code) = input.trim().strip_prefix("load ") {
+            let loaded = Position::decode(code)
+                .and_then(|position| self.load_position(position).map_err(|err| err.to_string()));
+            return match loaded {
+                Ok(()) => {
+                    writeln!(console.output, "** Position loaded **")?;
+                    if self.phase == Phase::AwaitingCpu {
+                        Ok(Step::CpuMove)
+                    } else {
+                        Ok(Step::ReadInput)
+                    }
+                }
+                Err(err) => {
+                    writeln!(console.output, "{}", err)?;
+                    Ok(Step::ReadInput)
+                }
+            };
+        }
+        if input.trim() == "pause" {
+            if self.pause(console)? {
+                return Ok(Step::SessionEnd(Handoff::Ended));
             }
+            return Ok(Step::ReadInput);
+        }
+        if let Some(args) = input.trim().strip_prefix("games") {
+            self.browse_history(console, args)?;
+            return Ok(Step::ReadInput);
+        }
+        if input.trim() == "achievements" {
+            self.print_achievements(console)?;
+            return Ok(Step::ReadInput);
+        }
+        if input.trim() == "score" {
+            self.print_score(console)?;
+            return Ok(Step::ReadInput);
+        }
+        if input.trim() == "eval" {
+            self.settings.show_eval = !self.settings.show_eval;
+            let state = if self.settings.show_eval { "on" } else { "off" };
+            writeln!(console.output, "Evaluation is {}", state)?;
+            return Ok(Step::ReadInput);
+        }
+        if input.trim() == "resign" {
+            return self.resign_at(console);
+        }
+        if matches!(input.trim(), "offer draw" | "draw") {
+            return self.offer_draw_at(console);
+        }
+        if let answer @ ("accept" | "decline") = input.trim() {
+            return self.answer_draw_at(console, answer == "accept");
+        }
 
-            // Tie
-            if self.is_full() {
-                return CheckResult::Tie;
+        let player_move = match parse_move(&input, &self.rules, self.settings.numbering) {
+            Some(parsed) => parsed,
+            None if self.several_moves(&input) => {
+                writeln!(console.output, "One move per line, please, none was played")?;
+                return Ok(Step::ReadInput);
+            }
+            None => {
+                let named = cell_name(input.trim()).is_some() && !names_cells(&self.rules);
+                let hint = match self.rules.variant {
+                    Variant::Gravity if named => "Please enter a column number",
+                    _ if named && self.rules.layers > 1 => {
+                        "Names like center are for 3x3 boards, please enter layer,row,col"
+                    }
+                    _ if named => "Names like center are for 3x3 boards, please enter an index",
+                    Variant::Wild => "Please enter an index followed by x or o",
+                    Variant::Numerical => "Please enter a digit and an index, like 5@4",
+                    _ => "Please enter a valid number",
+                };
+                writeln!(console.output, "{}", hint)?;
+                return Ok(Step::ReadInput);
+            }
+        };
+        if auto {
+            writeln!(console.output, "Played for you: {} (auto)", input.trim())?;
+        } else {
+            if !self.settings.summary_only {
+                writeln!(console.output, "You entered: {}", input.trim())?;
+            }
+            if self.settings.confirm_moves && !self.confirm_move(console, player_move)? {
+                writeln!(console.output, "Move discarded")?;
+                return Ok(Step::ReadInput);
             }
         }
-        return CheckResult::Contine;
+        Ok(Step::ApplyPlayerMove(player_move, auto))
+    }
+
+    // Whether a line that isn't a move holds several, like `4 5`: rather than play the
+    // first and drop the rest, none of them is played
+    fn several_moves(&self, input: &str) -> bool {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        words.len() > 1
+            && words
+                .iter()
+                .all(|word| parse_move(word, &self.rules, self.settings.numbering).is_some())
+    }
+
+    // `resign` at the prompt: the player at the keyboard gives up the round
+    fn resign_at<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<Step> {
+        let mark = self.mover();
+        if let Err(err) = self.end_round(Ending::Resigned(mark)) {
+            writeln!(console.output, "{}", err)?;
+            return Ok(Step::ReadInput);
+        }
+        if self.settings.two_players {
+            writeln!(console.output, "** {:?} resigns **", mark)?;
+        } else {
+            writeln!(console.output, "** You resign **")?;
+        }
+        Ok(self.step_after_ending())
+    }
+
+    // `offer draw` at the prompt, which the CPU answers at once
+    fn offer_draw_at<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<Step> {
+        match self.propose_draw() {
+            Ok(DrawAnswer::Pending) => writeln!(
+                console.output,
+                "{:?} offers a draw, {:?} may accept or decline",
+                self.mover(),
+                self.mover().opponent()
+            )?,
+            Ok(DrawAnswer::Accepted) => writeln!(console.output, "** Cpu accepts the draw **")?,
+            Ok(DrawAnswer::Declined) => writeln!(console.output, "Cpu declines the draw, play on")?,
+            Err(err) => writeln!(console.output, "{}", err)?,
+        }
+        Ok(self.step_after_ending())
+    }
+
+    // `accept` or `decline` at the prompt, answering the other player's draw offer
+    fn answer_draw_at<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+        accept: bool,
+    ) -> io::Result<Step> {
+        match self.reply_to_draw(accept) {
+            Ok(DrawAnswer::Accepted) => writeln!(console.output, "** Draw agreed **")?,
+            Ok(_) => writeln!(
+                console.output,
+                "Draw declined, {:?} to move",
+                self.whose_turn()
+            )?,
+            Err(err) => writeln!(console.output, "{}", err)?,
+        }
+        Ok(self.step_after_ending())
+    }
+
+    // The round's end if the players just ended it, otherwise the next prompt
+    fn step_after_ending(&self) -> Step {
+        match self.phase {
+            Phase::RoundOver(outcome) => Step::RoundEnd(outcome),
+            _ => Step::ReadInput,
+        }
+    }
+
+    // Places the player's move; a refused move asks again, with the turn's clock running on
+    fn apply_player_move<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+        player_move: Move,
+        auto: bool,
+        turn_started: &mut Option<Instant>,
+    ) -> io::Result<Step> {
+        let mover = self.mover();
+        if let Err(err) = self.pick_player(player_move, auto) {
+            match err {
+                PickError::DigitNotYours => match mover {
+                    State::O => writeln!(console.output, "You can only play even digits!")?,
+                    _ => writeln!(console.output, "You can only play odd digits!")?,
+                },
+                // Points out the taken cell on a small board
+                PickError::AreaOccupied { index, .. } => {
+                    writeln!(console.output, "{}", err.message(self.settings.numbering))?;
+                    if let Some(moves) = &self.moves_map {
+                        write!(console.output, "{}", pointing_at(moves, index))?;
+                    }
+                }
+                _ => writeln!(console.output, "{}", err.message(self.settings.numbering))?,
+            }
+            return Ok(Step::ReadInput);
+        }
+        console.moved = true;
+        let elapsed = turn_started
+            .take()
+            .map_or(Duration::ZERO, |started| started.elapsed());
+        self.round_times.player += elapsed;
+        self.session_times.player += elapsed;
+        if !self.settings.summary_only {
+            if self.settings.two_players {
+                writeln!(console.output, "{:?} took {}", mover, seconds(elapsed))?;
+            } else {
+                writeln!(console.output, "You took {}", seconds(elapsed))?;
+            }
+        }
+        match self.check(mover) {
+            CheckResult::Win if mover == self.human_mark => Ok(Step::RoundEnd(Outcome::PlayerWin)),
+            CheckResult::Win => Ok(Step::RoundEnd(Outcome::CpuWin)),
+            CheckResult::Tie => Ok(Step::RoundEnd(Outcome::Tie)),
+            // Two players take turns at the prompt
+            CheckResult::Contine if self.settings.two_players => {
+                if let Some(step) = self.offer_fast_finish(console)? {
+                    return Ok(step);
+                }
+                if !self.settings.summary_only {
+                    writeln!(console.output, "** {:?} to move **", self.turn)?;
+                }
+                Ok(Step::ReadInput)
+            }
+            CheckResult::Contine => {
+                if let Some(step) = self.offer_fast_finish(console)? {
+                    return Ok(step);
+                }
+                if !self.settings.summary_only {
+                    writeln!(console.output, "** Cpu turn **")?;
+                }
+                Ok(Step::CpuMove)
+            }
+            CheckResult::Invalid => self.invalid_round(console),
+        }
+    }
+
+    // Lets the CPU move and reports it
+    fn cpu_move<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<Step> {
+        let elapsed = self.pick_cpu();
+        if !self.settings.summary_only {
+            writeln!(console.output, "Cpu took {}", seconds(elapsed))?;
+        }
+        self.print_explanation(console, elapsed)?;
+        match self.check(self.human_mark.opponent()) {
+            CheckResult::Win => Ok(Step::RoundEnd(Outcome::CpuWin)),
+            CheckResult::Tie => Ok(Step::RoundEnd(Outcome::Tie)),
+            CheckResult::Contine => {
+                if let Some(step) = self.offer_fast_finish(console)? {
+                    return Ok(step);
+                }
+                if !self.settings.summary_only {
+                    writeln!(console.output, "** Your turn **")?;
+                }
+                Ok(Step::ReadInput)
+            }
+            CheckResult::Invalid => self.invalid_round(console),
+        }
+    }
+
+    // With `fast_finish`, offers to end a round best play has decided: the side that can't
+    // avoid losing may concede, a round nobody can win may be called a draw. The Cpu
+    // concedes a lost round whenever the player wants it ended. Declined, the same offer
+    // isn't made again this round.
+    fn offer_fast_finish<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<Option<Step>> {
+        if !self.settings.fast_finish {
+            return Ok(None);
+        }
+        let forced = match self.forced_outcome() {
+            Some(forced) if self.fast_finish_offer != Some(forced) => forced,
+            _ => return Ok(None),
+        };
+        self.fast_finish_offer = Some(forced);
+        let (question, ending, banner) = match forced {
+            Forced::Win { winner, moves } => {
+                let decided = format!("{:?} wins in {} with best play", winner, moves);
+                let loser = winner.opponent();
+                if self.settings.two_players {
+                    let banner = format!("** {:?} concedes **", loser);
+                    let question = format!("{}, does {:?} concede? (y/n)", decided, loser);
+                    (question, Ending::Resigned(loser), banner)
+                } else if loser == self.human_mark {
+                    let question = format!("{}, concede? (y/n)", decided);
+                    let banner = "** You concede **".to_string();
+                    (question, Ending::Resigned(loser), banner)
+                } else {
+                    let question = format!("{}, end the round now? (y/n)", decided);
+                    let banner = "** Cpu concedes **".to_string();
+                    (question, Ending::Resigned(loser), banner)
+                }
+            }
+            Forced::DeadDraw => (
+                "Nobody can win any more, call it a draw? (y/n)".to_string(),
+                Ending::DrawAgreed,
+                "** Draw agreed **".to_string(),
+            ),
+        };
+        if !self.ask_yes_no(console, &question)? {
+            writeln!(console.output, "Play on")?;
+            return Ok(None);
+        }
+        if let Err(err) = self.end_round(ending) {
+            writeln!(console.output, "{}", err)?;
+            return Ok(None);
+        }
+        writeln!(console.output, "{}", banner)?;
+        Ok(Some(self.step_after_ending()))
+    }
+
+    // A board with lines of both sides can't be scored either way: says so and ends the
+    // session rather than count a made-up result
+    fn invalid_round<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<Step> {
+        writeln!(
+            console.output,
+            "{}\nThe round can't be scored, ending the session",
+            PickError::InvalidBoard
+        )?;
+        Ok(Step::SessionEnd(Handoff::Ended))
+    }
+
+    // Announces and records the finished round, then sets up the next if there is one
+    fn round_end<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+        outcome: Outcome,
+    ) -> io::Result<Step> {
+        match (self.status(), outcome) {
+            (Status::Won(mark), _) if self.settings.two_players => {
+                writeln!(console.output, "** {:?} wins! **", mark)?
+            }
+            (_, Outcome::PlayerWin) => writeln!(console.output, "** You win! **")?,
+            (_, Outcome::CpuWin) => writeln!(console.output, "** Cpu wins! **")?,
+            (_, Outcome::Tie) => writeln!(console.output, "** Tie! **")?,
+        }
+        self.record_outcome(outcome);
+        let repeated = self.history.back().filter(|round| round.played > 1);
+        if let Some(round) = repeated.filter(|_| !self.settings.summary_only) {
+            writeln!(
+                console.output,
+                "You've played this exact game {} times",
+                round.played
+            )?;
+        }
+        if self.rematch(console)? {
+            Ok(Step::ReadInput)
+        } else {
+            Ok(Step::SessionEnd(Handoff::Ended))
+        }
+    }
+
+    // The CPU's move outside the loop, settling the round if it ended; false once the
+    // session is over
+    fn cpu_turn<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<bool> {
+        let next = match self.cpu_move(console)? {
+            Step::RoundEnd(outcome) => self.round_end(console, outcome)?,
+            next => next,
+        };
+        Ok(!matches!(next, Step::SessionEnd(_)))
+    }
+
+    // Everything a finished round counts towards: the scores, the history, observers,
+    // achievements and the snapshot of a win
+    fn record_outcome(&mut self, outcome: Outcome) {
+        let opponent = self.scores.entry(self.opponent()).or_default();
+        for score in [&mut self.score, &mut self.match_score, opponent] {
+            match outcome {
+                Outcome::Tie => score.tie += 1,
+                Outcome::PlayerWin => score.player += 1,
+                Outcome::CpuWin => score.cpu += 1,
+            }
+        }
+        let game = CanonicalGame::new(&self.rules, &self.round_moves, self.ending);
+        *self.played_games.entry(game).or_default() += 1;
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.finished_round());
+        self.observers.emit(Event::RoundEnd {
+            status: self.status(),
+            ending: self.ending,
+        });
+        self.observers.emit(Event::Score {
+            player: self.score.player,
+            cpu: self.score.cpu,
+            tie: self.score.tie,
+        });
+        // A paused round was resumed and played out, there's nothing left to resume
+        #[cfg(feature = "serde")]
+        if let Some(path) = &self.autosave {
+            let _ = fs::remove_file(path);
+        }
+        #[cfg(feature = "serde")]
+        self.record_achievements();
+        let won = matches!(self.status(), Status::Won(_));
+        if let Some(template) = self.snapshot_on_win.as_ref().filter(|_| won) {
+            let path = template.replace("{round}", &self.score.rounds().to_string());
+            let line = self.winning_line();
+            let exported = export::export(&path, &self.snapshot(), line, &Style::default());
+            if let Err(err) = exported {
+                eprintln!("Warning: snapshot not saved: {}", err);
+            }
+        }
+    }
+
+    // Counts the finished round towards the achievements; two players earn none, nor do
+    // rounds ended by resigning or agreeing to a draw
+    #[cfg(feature = "serde")]
+    fn record_achievements(&mut self) {
+        let path = match &self.stats_file {
+            Some(path)
+                if !self.settings.two_players && !self.auto_played && self.ending.is_none() =>
+            {
+                path
+            }
+            _ => return,
+        };
+        // Only the built-in CPU's strength is known, and only where it plays by it
+        let searchable = matches!(self.rules.variant, Variant::Classic | Variant::Gravity);
+        let round = RoundRecord {
+            rules: self.rules,
+            human_mark: self.human_mark,
+            difficulty: (searchable && self.player.is_none()).then_some(self.settings.difficulty),
+            boards: self.round_boards.clone(),
+            status: self.status(),
+        };
+        let saved = Stats::load(path).and_then(|mut stats| {
+            let unlocked = stats.record(&round);
+            stats.save(path).map(|_| unlocked)
+        });
+        match saved {
+            Ok(unlocked) => self.unlocked.extend(unlocked),
+            Err(err) => eprintln!("Warning: achievements not saved: {}", err),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn print_achievements<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<()> {
+        let stats = match &self.stats_file {
+            Some(path) => match Stats::load(path) {
+                Ok(stats) => stats,
+                Err(err) => return writeln!(console.output, "{}", err),
+            },
+            None => return writeln!(console.output, "Achievements aren't tracked"),
+        };
+        for achievement in Achievement::ALL {
+            match stats.unlocked.get(&achievement) {
+                // Only the day of the RFC 3339 timestamp
+                Some(date) => write!(console.output, "[x] {} ", &date[..date.len().min(10)])?,
+                None => write!(console.output, "[ ] {:10} ", "")?,
+            }
+            writeln!(
+                console.output,
+                "{}: {}",
+                achievement.title(),
+                achievement.description()
+            )?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn print_achievements<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<()> {
+        writeln!(
+            console.output,
+            "Achievements need a build with the serde feature"
+        )
+    }
+
+    // The state of the CPU's RNG if it is a `GameRng` that shows it, for a resumed game to
+    // make the same random choices
+    #[cfg(feature = "serde")]
+    fn rng_state(&self) -> Option<RngState> {
+        let rng: &dyn Any = &self.rng;
+        rng.downcast_ref::<GameRng>()?.state()
+    }
+
+    // Saves the round in progress after every move, so a crash loses at most the move
+    // being made; the next launch offers it like a paused game. A failed save turns
+    // saving off for the session.
+    #[cfg(feature = "serde")]
+    fn checkpoint(&mut self) {
+        let path = match &self.autosave {
+            Some(path) if self.status() == Status::InProgress => path,
+            _ => return,
+        };
+        let saved = SavedGame::new(self.state(), self.rng_state());
+        if let Err(err) = saved.save(path) {
+            eprintln!("Warning: {}, the game won't be saved", err);
+            self.autosave = None;
+        }
+    }
+
+    // Saves the game mid-round and ends the session; false if it couldn't be saved
+    #[cfg(feature = "serde")]
+    fn pause<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<bool> {
+        let path = match &self.autosave {
+            Some(path) => path.clone(),
+            None => {
+                writeln!(console.output, "There is nowhere to save the game")?;
+                return Ok(false);
+            }
+        };
+        let saved = SavedGame::new(self.state(), self.rng_state());
+        if let Err(err) = saved.save(&path) {
+            writeln!(console.output, "{}", err)?;
+            return Ok(false);
+        }
+        writeln!(console.output, "** Game paused, start again to resume **")?;
+        self.print_summary(console)?;
+        Ok(true)
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn pause<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<bool> {
+        writeln!(
+            console.output,
+            "Pausing needs a build with the serde feature"
+        )?;
+        Ok(false)
+    }
+
+    // Hand the keyboard over: the human takes the CPU's mark and score and vice versa
+    fn swap_sides<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<()> {
+        let round_started = match &self.moves_map {
+            Some(map) => map.cells().iter().any(|&v| v != State::Empty),
+            None => false,
+        };
+        if round_started {
+            return writeln!(console.output, "Sides can only be swapped between rounds!");
+        }
+        if self.settings.two_players {
+            return writeln!(console.output, "Both sides are played here already!");
+        }
+
+        // Whoever was to move still is, now with the other mark. X opens in numerical
+        // mode though, so there the opening move goes with it.
+        self.human_mark = self.human_mark.opponent();
+        if self.rules.variant == Variant::Numerical {
+            self.cpu_opens = !self.cpu_opens;
+            self.phase = if self.turn == self.human_mark {
+                Phase::AwaitingPlayer
+            } else {
+                Phase::AwaitingCpu
+            };
+        } else {
+            self.turn = self.turn.opponent();
+        }
+        self.instructed = false;
+        let scores = self.scores.values_mut();
+        for score in [&mut self.score, &mut self.match_score]
+            .into_iter()
+            .chain(scores)
+        {
+            std::mem::swap(&mut score.player, &mut score.cpu);
+        }
+        writeln!(
+            console.output,
+            "** Sides swapped: you play {:?}, the Cpu plays {:?} **",
+            self.human_mark,
+            self.human_mark.opponent()
+        )
+    }
+
+    // Ask whether to play another round and set it up; false ends the session
+    fn rematch<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<bool> {
+        if !self.settings.summary_only {
+            write!(console.output, "{}", self.round_summary(console.colors))?;
+        }
+        #[cfg(feature = "serde")]
+        for achievement in self.unlocked.drain(..) {
+            writeln!(
+                console.output,
+                "** Achievement unlocked: {} ({}) **",
+                achievement.title(),
+                achievement.description()
+            )?;
+        }
+        if let Some(rounds) = self.settings.rounds {
+            // A batch of rounds follows on without asking until it's done
+            if self.score.rounds() >= rounds.into() {
+                self.print_summary(console)?;
+                return Ok(false);
+            }
+        } else if let Some(target) = self.settings.first_to {
+            // Rounds of an undecided match follow each other without asking
+            if let Some(winner) = self.match_score.match_winner(target, self.side_names()) {
+                writeln!(
+                    console.output,
+                    "** {} won the match {}-{}! **",
+                    winner,
+                    self.match_score.player.max(self.match_score.cpu),
+                    self.match_score.player.min(self.match_score.cpu)
+                )?;
+                if !self.end_match(console)? {
+                    return Ok(false);
+                }
+            }
+        } else if let Some(rounds) = self.settings.best_of {
+            // Rounds of an undecided match follow each other without asking
+            match self.advance_match(rounds) {
+                Some(outcome) => {
+                    let played = self.tiebreak_rounds();
+                    let after = match played {
+                        0 => String::new(),
+                        1 => " after 1 sudden-death round".to_string(),
+                        _ => format!(" after {} sudden-death rounds", played),
+                    };
+                    let (high, low) = (
+                        self.match_score.player.max(self.match_score.cpu),
+                        self.match_score.player.min(self.match_score.cpu),
+                    );
+                    match outcome {
+                        MatchOutcome::Won(winner) => writeln!(
+                            console.output,
+                            "** {} won the match {}-{}{}! **",
+                            winner, high, low, after
+                        )?,
+                        MatchOutcome::Drawn => writeln!(
+                            console.output,
+                            "** The match is drawn {}-{}{} **",
+                            high, low, after
+                        )?,
+                    }
+                    if !self.end_match(console)? {
+                        return Ok(false);
+                    }
+                }
+                None => {
+                    if let MatchPhase::TieBreak(played) = self.match_phase {
+                        writeln!(
+                            console.output,
+                            "** Sudden death, round {}: the next decisive round wins the match **",
+                            played + 1
+                        )?;
+                    }
+                }
+            }
+        } else if !self.settings.auto_rematch {
+            loop {
+                writeln!(
+                    console.output,
+                    "Play again? (y/n, swap to change sides, or export <file> to keep the board)"
+                )?;
+                let answer = console.read_line()?;
+                if let Some(path) = answer
+                    .as_deref()
+                    .and_then(|answer| answer.trim().strip_prefix("export "))
+                {
+                    self.export(console, path.trim())?;
+                    continue;
+                }
+                if let Some(args) = answer
+                    .as_deref()
+                    .and_then(|answer| answer.trim().strip_prefix("games"))
+                {
+                    self.browse_history(console, args)?;
+                    continue;
+                }
+                match answer.map(|answer| answer.trim().to_lowercase()).as_deref() {
+                    Some("y" | "yes") => break,
+                    Some("swap") => {
+                        // The old board is still shown, reset it so the swap is allowed
+                        self.reset();
+                        self.swap_sides(console)?;
+                        break;
+                    }
+                    Some("n" | "no") | None => {
+                        self.print_summary(console)?;
+                        return Ok(false);
+                    }
+                    Some(_) => writeln!(console.output, "Please answer y or n")?,
+                }
+            }
+        }
+
+        // Sudden death is fair only if the sides take turns opening
+        if self.settings.alternate_opener || self.match_phase != MatchPhase::Scheduled {
+            self.cpu_opens = !self.cpu_opens;
+        }
+        self.reset();
+        self.start_round();
+        if self.phase == Phase::AwaitingCpu {
+            writeln!(console.output, "** Cpu opens **")?;
+            let elapsed = self.pick_cpu();
+            self.print_explanation(console, elapsed)?;
+        }
+        Ok(true)
+    }
+
+    // `games` lists the finished rounds, `games show <n>` shows one again and
+    // `games replay <n>` goes through it move by move
+    fn browse_history<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+        args: &str,
+    ) -> io::Result<()> {
+        let mut words = args.split_whitespace();
+        let (command, number) = (words.next(), words.next());
+        if command.is_none() {
+            if self.history.is_empty() {
+                return writeln!(console.output, "No finished rounds yet");
+            }
+            for round in &self.history {
+                let repeat = match round.played {
+                    0 | 1 => String::new(),
+                    played => format!(" (game played {} times)", played),
+                };
+                writeln!(
+                    console.output,
+                    "Round {}: {} in {} moves{}",
+                    round.number,
+                    round.result,
+                    round.moves.len(),
+                    repeat
+                )?;
+            }
+            let rounds: u32 = self.played_games.values().sum();
+            return writeln!(
+                console.output,
+                "Distinct games: {} of {} rounds (mirror images and turns count as one)",
+                self.distinct_games(),
+                rounds
+            );
+        }
+        let round = match number.and_then(|number| number.parse::<u32>().ok()) {
+            Some(number) => self.history.iter().find(|round| round.number == number),
+            None => {
+                return writeln!(
+                    console.output,
+                    "Use games, games show <n> or games replay <n>"
+                )
+            }
+        };
+        let round = match (round, self.history.front()) {
+            (Some(round), _) => round,
+            (None, Some(oldest)) if oldest.number > 1 => {
+                return writeln!(
+                    console.output,
+                    "Only rounds {} to {} are kept",
+                    oldest.number,
+                    self.score.rounds()
+                )
+            }
+            _ => {
+                return writeln!(
+                    console.output,
+                    "There is no round {}",
+                    number.unwrap_or_default()
+                )
+            }
+        };
+        match command {
+            Some("show") => {
+                writeln!(console.output, "== Round {} ==", round.number)?;
+                write!(
+                    console.output,
+                    "{}",
+                    self.describe_round(round, console.colors)
+                )
+            }
+            Some("replay") => {
+                let mut board = self.rules.new_board();
+                for (number, &played) in round.moves.iter().enumerate() {
+                    match played.digit {
+                        Some(digit) => board.place_digit(played.index, played.mark, digit),
+                        None => board[played.index] = played.mark,
+                    }
+                    writeln!(
+                        console.output,
+                        "{}. {} ({})",
+                        number + 1,
+                        self.move_text(played),
+                        seconds(played.elapsed)
+                    )?;
+                    let out = &mut console.output;
+                    self.draw_board(&board, out, console.colors, None, &[played.index])?;
+                }
+                writeln!(console.output, "Result: {}", round.result)
+            }
+            _ => writeln!(
+                console.output,
+                "Use games, games show <n> or games replay <n>"
+            ),
+        }
+    }
+
+    // Moves a best-of-`rounds` match on after a round, into and through sudden death when
+    // it's level; the outcome once it's decided
+    fn advance_match(&mut self, rounds: u16) -> Option<MatchOutcome> {
+        let score = self.match_score;
+        let [player, cpu] = self.side_names();
+        let leader = match score.player.cmp(&score.cpu) {
+            Ordering::Greater => Some(player),
+            Ordering::Less => Some(cpu),
+            Ordering::Equal => None,
+        };
+        match self.match_phase {
+            MatchPhase::Scheduled => {
+                let left = rounds.saturating_sub(score.player + score.cpu + score.tie);
+                match leader {
+                    // Out of reach even if the other side won every round left
+                    Some(leader) if score.player.abs_diff(score.cpu) > left => {
+                        Some(MatchOutcome::Won(leader))
+                    }
+                    Some(_) => None,
+                    None if left > 0 => None,
+                    None if self.settings.sudden_death.is_some() => {
+                        self.match_phase = MatchPhase::TieBreak(0);
+                        None
+                    }
+                    None => Some(MatchOutcome::Drawn),
+                }
+            }
+            MatchPhase::TieBreak(played) => {
+                let played = played + 1;
+                self.match_phase = MatchPhase::TieBreak(played);
+                match leader {
+                    Some(leader) => Some(MatchOutcome::Won(leader)),
+                    None if self.settings.sudden_death.is_none_or(|max| played >= max) => {
+                        Some(MatchOutcome::Drawn)
+                    }
+                    None => None,
+                }
+            }
+        }
+    }
+
+    fn tiebreak_rounds(&self) -> u16 {
+        match self.match_phase {
+            MatchPhase::TieBreak(played) => played,
+            MatchPhase::Scheduled => 0,
+        }
+    }
+
+    // Shows the final match score and offers a new match; false ends the session
+    fn end_match<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<bool> {
+        let [player, cpu] = self.side_names();
+        write!(console.output, "{}", self.match_score.table(player, cpu))?;
+        self.observers.emit(Event::MatchEnd {
+            player: self.match_score.player,
+            cpu: self.match_score.cpu,
+            tiebreak_rounds: self.tiebreak_rounds(),
+        });
+        if self.settings.auto_rematch || !self.ask_yes_no(console, "Start a new match? (y/n)")? {
+            self.print_summary(console)?;
+            return Ok(false);
+        }
+        self.match_score = Score::default();
+        self.match_phase = MatchPhase::Scheduled;
+        Ok(true)
+    }
+
+    // The board as an SVG, HTML or PNG file, with the winning line of a won round
+    fn export<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+        path: &str,
+    ) -> io::Result<()> {
+        let exported = export::export(
+            path,
+            &self.snapshot(),
+            self.winning_line(),
+            &Style::default(),
+        );
+        match exported {
+            Ok(()) => writeln!(console.output, "Board exported to {}", path),
+            Err(err) => writeln!(console.output, "{}", err),
+        }
+    }
+
+    // Re-asks until the answer is yes or no; end of input counts as no
+    fn ask_yes_no<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+        question: &str,
+    ) -> io::Result<bool> {
+        loop {
+            writeln!(console.output, "{}", question)?;
+            match console.read_answer()?.as_deref() {
+                Some("y" | "yes") => return Ok(true),
+                Some("n" | "no") | None => return Ok(false),
+                Some(_) => writeln!(console.output, "Please answer y or n")?,
+            }
+        }
+    }
+
+    // The caller prints the numbers from the returned SessionSummary
+    fn print_summary<I: BufRead, W: Write>(
+        &mut self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<()> {
+        self.observers.emit(Event::SessionEnd);
+        writeln!(console.output, "** Thanks for playing! **")?;
+        console.output.flush()
+    }
+
+    fn reset(&mut self) {
+        self.moves_map = Some(self.rules.new_board());
+        #[cfg(feature = "serde")]
+        {
+            self.round_boards.clear();
+            self.auto_played = false;
+        }
+        self.last_mover = None;
+        self.ending = None;
+        self.draw_offer = None;
+        self.fast_finish_offer = None;
+        self.status.set(None);
+        self.previous = None;
+        self.round_moves.clear();
+        self.round_started = Instant::now();
+        self.round_times = TurnTimes::default();
+        self.instructed = false;
+        self.turn = if self.cpu_opens {
+            self.human_mark.opponent()
+        } else {
+            self.human_mark
+        };
+        self.phase = self.current_phase();
+    }
+
+    // Step by step play for front-ends without stdin, such as the GUI: start a round,
+    // then submit the player's moves; the CPU answers within `submit`
+    pub fn new_round(&mut self) {
+        if self.moves_map.is_some() && self.settings.alternate_opener {
+            self.cpu_opens = !self.cpu_opens;
+        }
+        self.reset();
+        self.start_round();
+        if self.phase == Phase::AwaitingCpu {
+            self.pick_cpu();
+        }
+    }
+
+    pub fn submit(&mut self, player_move: Move) -> Result<Phase, PickError> {
+        self.pick_player(player_move, false)?;
+        if self.phase == Phase::AwaitingCpu {
+            self.pick_cpu();
+        }
+        self.settle();
+        Ok(self.phase)
+    }
+
+    // The player at the keyboard gives up; the round counts as the other side's win
+    pub fn resign(&mut self) -> Result<Phase, PickError> {
+        self.end_round(Ending::Resigned(self.mover()))?;
+        self.settle();
+        Ok(self.phase)
+    }
+
+    // The player at the keyboard offers a draw. The CPU answers at once, a second player
+    // later with `answer_draw`; moves are refused until then.
+    pub fn offer_draw(&mut self) -> Result<DrawAnswer, PickError> {
+        let answer = self.propose_draw()?;
+        self.settle();
+        Ok(answer)
+    }
+
+    // The other player's answer to the pending draw offer
+    pub fn answer_draw(&mut self, accept: bool) -> Result<Phase, PickError> {
+        self.reply_to_draw(accept)?;
+        self.settle();
+        Ok(self.phase)
+    }
+
+    // Records the round if it just ended outside `submit`
+    fn settle(&mut self) {
+        if let Phase::RoundOver(outcome) = self.phase {
+            self.record_outcome(outcome);
+        }
+    }
+
+    // The players can only end a round that is being played
+    fn check_in_progress(&self) -> Result<(), PickError> {
+        if self.moves_map.is_none() {
+            return Err(PickError::MovesMapNotInitialized);
+        }
+        match self.phase {
+            Phase::RoundOver(_) => Err(PickError::WrongPhase),
+            Phase::Invalid => Err(PickError::InvalidBoard),
+            _ => Ok(()),
+        }
+    }
+
+    // Ends the round in progress as the players agreed, leaving the board as it is. A
+    // pending draw offer is answered first.
+    fn end_round(&mut self, ending: Ending) -> Result<(), PickError> {
+        self.check_in_progress()?;
+        if self.draw_offer.is_some() {
+            return Err(PickError::DrawOffered);
+        }
+        self.ending = Some(ending);
+        self.status.set(None);
+        self.phase = self.current_phase();
+        Ok(())
+    }
+
+    // A draw offered by the player at the keyboard: left pending for a second player,
+    // answered right away by the CPU
+    fn propose_draw(&mut self) -> Result<DrawAnswer, PickError> {
+        self.check_in_progress()?;
+        if self.draw_offer.is_some() {
+            return Err(PickError::DrawOffered);
+        }
+        if self.settings.two_players {
+            self.draw_offer = Some(self.mover());
+            return Ok(DrawAnswer::Pending);
+        }
+        if self.cpu_accepts_draw() {
+            self.end_round(Ending::DrawAgreed)?;
+            Ok(DrawAnswer::Accepted)
+        } else {
+            Ok(DrawAnswer::Declined)
+        }
+    }
+
+    fn reply_to_draw(&mut self, accept: bool) -> Result<DrawAnswer, PickError> {
+        self.check_in_progress()?;
+        if self.draw_offer.take().is_none() {
+            return Err(PickError::NoDrawOffer);
+        }
+        if accept {
+            self.end_round(Ending::DrawAgreed)?;
+            Ok(DrawAnswer::Accepted)
+        } else {
+            Ok(DrawAnswer::Declined)
+        }
+    }
+
+    // The CPU only takes a draw that its evaluation says it can't better, a position
+    // lost or drawn for it. Variants it can't search are always played on.
+    fn cpu_accepts_draw(&self) -> bool {
+        let map = match self.moves_map {
+            Some(map) => map,
+            None => return false,
+        };
+        if !matches!(self.rules.variant, Variant::Classic | Variant::Gravity) {
+            return false;
+        }
+        // Scores are for the side to move
+        let score = if map.count(State::Empty) <= ai::MAX_SEARCH_CELLS {
+            ai::evaluate(&map, &self.rules, self.turn)
+        } else {
+            let depth = self.settings.depth.unwrap_or(ai::DEFAULT_DEPTH);
+            match ai::search_to_depth(&map, &self.rules, self.turn, depth, &mut || false) {
+                Some(search) => search.score,
+                None => return false,
+            }
+        };
+        let cpu_score = if self.turn == self.human_mark {
+            -score
+        } else {
+            score
+        };
+        cpu_score <= 0
+    }
+
+    // The result best play leads to from here, when the exact evaluator can tell: a win
+    // either way or a draw nobody can avoid. None while the round is open, over, or too
+    // big to search to the end.
+    pub fn forced_outcome(&self) -> Option<Forced> {
+        let map = self.moves_map?;
+        if self.status() != Status::InProgress
+            || !matches!(self.rules.variant, Variant::Classic | Variant::Gravity)
+        {
+            return None;
+        }
+        if self.rules.dead_draw(&map) {
+            return Some(Forced::DeadDraw);
+        }
+        if map.count(State::Empty) > ai::MAX_SEARCH_CELLS {
+            return None;
+        }
+        // Scores are for the side to move
+        let score = ai::evaluate(&map, &self.rules, self.turn);
+        if !ai::is_decisive(score) {
+            return None;
+        }
+        let winner = if score > 0 {
+            self.turn
+        } else {
+            self.turn.opponent()
+        };
+        // Count only the winner's own moves, as `ai::describe` does
+        let plies = (ai::WIN - score.abs()) as u32;
+        Some(Forced::Win {
+            winner,
+            moves: plies.div_ceil(2),
+        })
+    }
+
+    // Games of the rounds finished this session, those the same up to symmetry counted once
+    pub fn distinct_games(&self) -> usize {
+        self.played_games.len()
+    }
+
+    // The side whose draw offer is waiting for an answer
+    pub fn draw_offer(&self) -> Option<State> {
+        self.draw_offer
+    }
+
+    // How the players ended the round, None while it's played or if the board decided it
+    pub fn ending(&self) -> Option<Ending> {
+        self.ending
+    }
+
+    // The current round's board, None before the first round
+    pub fn board(&self) -> Option<&Board> {
+        self.moves_map.as_ref()
+    }
+
+    // The completed line of a won round
+    pub fn winning_line(&self) -> Option<Line> {
+        self.moves_map?;
+        self.snapshot().winning_line(&self.rules)
+    }
+
+    // Continue from a shared position; the sides keep their marks
+    pub fn load_position(&mut self, position: Position) -> Result<(), GameError> {
+        if self.rules != Rules::default() {
+            return Err(GameError::Invalid(
+                "Position codes are for classic 3x3 games".to_string(),
+            ));
+        }
+        position.validate(&self.rules)?;
+        if position.status != Status::InProgress {
+            return Err(GameError::Invalid(
+                "The round in that position is already over".to_string(),
+            ));
+        }
+        self.set_position(position);
+        Ok(())
+    }
+
+    // Puts a checked position on the board, as if the side not to move had just moved
+    fn set_position(&mut self, position: Position) {
+        let board = position.board;
+        self.moves_map = Some(board);
+        self.last_mover =
+            (board.count(State::Empty) < board.size()).then(|| position.to_move.opponent());
+        self.ending = None;
+        self.draw_offer = None;
+        self.status.set(None);
+        self.previous = None;
+        self.turn = position.to_move;
+        self.phase = self.current_phase();
+    }
+
+    pub fn state(&self) -> GameState {
+        GameState {
+            rules: self.rules,
+            settings: self.settings,
+            scores: self.scores.clone(),
+            match_score: self.match_score,
+            match_phase: self.match_phase,
+            human_mark: self.human_mark,
+            cpu_opens: self.cpu_opens,
+            position: self.moves_map.map(|_| self.snapshot()),
+        }
+    }
+
+    pub fn score(&self) -> Score {
+        self.score
+    }
+
+    pub fn scores(&self) -> &Scores {
+        &self.scores
+    }
+
+    // Who the rounds are played against, as `scores` keys them. Changing the difficulty
+    // or personality starts a new key, the old one keeps its rounds.
+    pub fn opponent(&self) -> String {
+        if self.settings.two_players {
+            return "two players".to_string();
+        }
+        match &self.player {
+            Some(player) => player.to_string(),
+            None => format!("cpu {}", self.settings.cpu()),
+        }
+    }
+
+    pub fn human_mark(&self) -> State {
+        self.human_mark
+    }
+
+    // The player's mark and whether the Cpu opens, set before the first round. X opens
+    // every numerical game, so there the opener follows from the marks.
+    pub fn set_sides(&mut self, human_mark: State, cpu_opens: bool) -> Result<(), GameError> {
+        if human_mark == State::Empty {
+            return Err(GameError::Invalid("The player has no mark".to_string()));
+        }
+        if self.moves_map.is_some() {
+            return Err(GameError::Invalid(
+                "Sides can only be picked before the first round".to_string(),
+            ));
+        }
+        self.human_mark = human_mark;
+        self.cpu_opens = match self.rules.variant {
+            Variant::Numerical => human_mark == State::O,
+            _ => cpu_opens,
+        };
+        Ok(())
+    }
+
+    // Mark the keyboard plays now: always the player's, or either side with two players
+    fn mover(&self) -> State {
+        if self.settings.two_players {
+            self.turn
+        } else {
+            self.human_mark
+        }
+    }
+
+    // Labels of the score's two sides
+    pub(crate) fn side_names(&self) -> [&'static str; 2] {
+        if self.settings.two_players {
+            ["X", "O"]
+        } else {
+            ["You", "Cpu"]
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.settings.difficulty = difficulty;
+    }
+
+    pub fn match_phase(&self) -> MatchPhase {
+        self.match_phase
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn whose_turn(&self) -> State {
+        self.turn
+    }
+
+    // Thinking time of the current round, or of the one just finished
+    pub fn round_times(&self) -> TurnTimes {
+        self.round_times
+    }
+
+    pub fn session_times(&self) -> TurnTimes {
+        self.session_times
+    }
+
+    // Highest accepted input: a cell index, or a column in gravity mode
+    fn max_input(&self) -> usize {
+        match self.rules.variant {
+            Variant::Classic | Variant::Wild | Variant::Numerical => self.rules.cells() - 1,
+            Variant::Gravity => self.rules.cols - 1,
+        }
+    }
+
+    // Cells a mark can be placed on this turn
+    fn legal_moves(&self) -> MoveList {
+        match &self.moves_map {
+            Some(map) => self.rules.legal_moves(map),
+            None => MoveList::new(),
+        }
+    }
+
+    // How to enter a move, shown once a round
+    fn print_instructions<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+    ) -> io::Result<()> {
+        let first = self.settings.numbering.first();
+        match self.rules.variant {
+            Variant::Classic if self.rules.layers > 1 => writeln!(
+                console.output,
+                "Choose index({} to {}) or layer,row,col:",
+                first,
+                self.max_input() + first
+            )?,
+            Variant::Classic if names_cells(&self.rules) => writeln!(
+                console.output,
+                "Choose index({} to {}) or a name like top-left:",
+                first,
+                self.max_input() + first
+            )?,
+            Variant::Classic => {
+                let last = self.max_input() + first;
+                writeln!(console.output, "Choose index({} to {}):", first, last)?
+            }
+            Variant::Gravity => {
+                let last = self.max_input() + first;
+                writeln!(console.output, "Choose column({} to {}):", first, last)?
+            }
+            Variant::Wild => writeln!(
+                console.output,
+                "Choose index({} to {}) and mark, like 4x or 4o:",
+                first,
+                self.max_input() + first
+            )?,
+            // Both parities are typed here, the status line tells whose digits are left
+            Variant::Numerical if self.settings.two_players => writeln!(
+                console.output,
+                "Choose a digit of yours and index({} to {}), like 5@4:",
+                first,
+                self.max_input() + first
+            )?,
+            Variant::Numerical => writeln!(
+                console.output,
+                "Choose {} digit and index({} to {}), like {}@4:",
+                if self.mover() == State::X {
+                    "an odd"
+                } else {
+                    "an even"
+                },
+                first,
+                self.max_input() + first,
+                if self.mover() == State::X { 5 } else { 4 }
+            )?,
+        }
+        Ok(())
+    }
+
+    // One line on the turn: whose it is, the score so far and what the round is
+    fn print_status<I: BufRead, W: Write>(&self, console: &mut Console<I, W>) -> io::Result<()> {
+        let [player, cpu] = self.side_names();
+        let mut parts = vec![
+            format!("Round {}", self.score.rounds() + 1),
+            format!(
+                "{} {}, {} {}, Tie {}",
+                player, self.score.player, cpu, self.score.cpu, self.score.tie
+            ),
+        ];
+        if self.settings.two_players {
+            parts.push(format!("{:?} to move", self.turn));
+        } else {
+            parts.push(format!("You play {:?}", self.human_mark));
+            if let Variant::Classic | Variant::Gravity = self.rules.variant {
+                match &self.player {
+                    Some(player) => parts.push(format!("Cpu: {}", player)),
+                    None => parts.push(format!("Cpu: {}", self.settings.cpu())),
+                }
+            }
+        }
+        if let (Some(map), Variant::Numerical) = (&self.moves_map, self.rules.variant) {
+            let digits: Vec<String> = (1..=9)
+                .filter(|&digit| self.mover().owns_digit(digit) && !map.digit_used(digit))
+                .map(|digit| digit.to_string())
+                .collect();
+            parts.push(format!("Your digits: {}", digits.join(" ")));
+        }
+        // The record against this opponent, once it differs from the whole session's
+        if self.scores.len() > 1 {
+            let score = self
+                .scores
+                .get(&self.opponent())
+                .copied()
+                .unwrap_or_default();
+            parts.push(format!(
+                "vs {}: {}-{}-{}",
+                self.opponent(),
+                score.player,
+                score.cpu,
+                score.tie
+            ));
+        }
+        if let MatchPhase::TieBreak(played) = self.match_phase {
+            parts.push(format!("Sudden death round {}", played + 1));
+        }
+        if let Some(mark) = self.draw_offer {
+            parts.push(format!("{:?} offers a draw: accept or decline", mark));
+        }
+        if let Some(label) = &self.label {
+            write!(console.output, "[{}] ", label)?;
+        }
+        writeln!(console.output, "{}", parts.join(" | "))
+    }
+
+    // The prompt of --summary-only: the round, who moves and the board on one line
+    fn print_compact<I: BufRead, W: Write>(&self, console: &mut Console<I, W>) -> io::Result<()> {
+        if let Some(label) = &self.label {
+            write!(console.output, "[{}] ", label)?;
+        }
+        let board = self.moves_map.unwrap_or_else(|| self.rules.new_board());
+        writeln!(
+            console.output,
+            "Round {}, {:?} to move: {:#}",
+            self.score.rounds() + 1,
+            self.mover(),
+            board
+        )
+    }
+
+    // The full score table on `score`, then the rounds against each opponent
+    fn print_score<I: BufRead, W: Write>(&self, console: &mut Console<I, W>) -> io::Result<()> {
+        let [player, cpu] = self.side_names();
+        write!(console.output, "{}", self.score.table(player, cpu))?;
+        if !self.scores.is_empty() {
+            write!(
+                console.output,
+                "By opponent:\n{}",
+                scores_table(&self.scores)
+            )?;
+        }
+        Ok(())
+    }
+
+    // Draws the board, with `overlay` shown in its cell as a tentative move
+    fn write_board<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+        overlay: Option<Overlay>,
+    ) -> io::Result<()> {
+        // Mark the other side's latest move so it's easy to spot
+        let highlight: Vec<usize> = match self.last_mover {
+            Some(mark) if mark != self.mover() => self
+                .last_changes()
+                .iter()
+                .map(|change| change.index)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let out = &mut console.output;
+        match &self.moves_map {
+            Some(moves) => self.draw_board(moves, out, console.colors, overlay, &highlight),
+            None => writeln!(out, "No moves yet!"),
+        }
+    }
+
+    // `moves` with the cells of `highlight` marked
+    fn draw_board(
+        &self,
+        moves: &Board,
+        out: &mut impl Write,
+        colors: bool,
+        overlay: Option<Overlay>,
+        highlight: &[usize],
+    ) -> io::Result<()> {
+        let view = |index: usize| {
+            let (owner, digit) = match overlay {
+                Some(overlay) if overlay.index == index => (overlay.mark, overlay.digit),
+                _ => (moves[index], moves.digit(index)),
+            };
+            let emphasis = if overlay.is_some_and(|overlay| overlay.index == index) {
+                Emphasis::Tentative
+            } else if highlight.contains(&index) {
+                Emphasis::Latest
+            } else {
+                Emphasis::None
+            };
+            CellView {
+                owner,
+                digit,
+                emphasis,
+                // Gravity mode takes columns, numbered above the board instead
+                label: (self.rules.variant != Variant::Gravity)
+                    .then_some(index + self.settings.numbering.first()),
+            }
+        };
+        // Too wide a board for the terminal is better drawn small than wrapped
+        if self.settings.big_board && big_board::fits(moves) {
+            let gravity = self.rules.variant == Variant::Gravity;
+            let first = self.settings.numbering.first();
+            return write!(
+                out,
+                "{}",
+                big_board::render(moves, view, colors, gravity, first)
+            );
+        }
+        if self.rules.variant == Variant::Gravity {
+            for col in 0..moves.cols() {
+                write!(out, "{:3}", col + self.settings.numbering.first())?;
+            }
+            writeln!(out)?;
+        }
+        if moves.layers() > 1 {
+            for layer in 0..moves.layers() {
+                write!(
+                    out,
+                    "{:<w$}",
+                    format!("layer {}", layer + self.settings.numbering.first()),
+                    w = moves.cols() * 3 + 3
+                )?;
+            }
+            writeln!(out)?;
+        }
+        // Layers are drawn side by side, one board row per line
+        let area = moves.rows() * moves.cols();
+        for row in 0..moves.rows() {
+            for layer in 0..moves.layers() {
+                for col in 0..moves.cols() {
+                    let CellView {
+                        owner,
+                        digit,
+                        emphasis,
+                        ..
+                    } = view(layer * area + row * moves.cols() + col);
+                    let mut symbol = match (owner, digit) {
+                        (_, Some(digit)) => digit.to_string(),
+                        (State::X, None) => "X".to_string(),
+                        (State::O, None) => "O".to_string(),
+                        (State::Empty, None) => ".".to_string(),
+                    };
+                    match emphasis {
+                        Emphasis::Tentative => symbol.push('?'),
+                        Emphasis::Latest => symbol.push('*'),
+                        Emphasis::None => (),
+                    }
+                    let cell = if colors {
+                        color::cell(&symbol, owner, emphasis, 3)
+                    } else {
+                        format!("{:3}", symbol)
+                    };
+                    write!(out, "{}", cell)?;
+                }
+                if layer + 1 < moves.layers() {
+                    write!(out, "   ")?;
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // Shows the move on the board and asks before placing it; moves that pick_player
+    // will reject anyway are let through so it can say why
+    fn confirm_move<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+        player_move: Move,
+    ) -> io::Result<bool> {
+        let map = match &self.moves_map {
+            Some(map) if player_move.index <= self.max_input() => map,
+            _ => return Ok(true),
+        };
+        let index = match self.rules.variant {
+            Variant::Gravity => match map.drop_target(player_move.index) {
+                Some(index) => index,
+                None => return Ok(true),
+            },
+            _ => player_move.index,
+        };
+        if map[index] != State::Empty {
+            return Ok(true);
+        }
+        let overlay = Overlay {
+            index,
+            mark: player_move.mark.unwrap_or(self.mover()),
+            digit: player_move.digit,
+        };
+        self.write_board(console, Some(overlay))?;
+        let what = match overlay.digit {
+            Some(digit) => digit.to_string(),
+            None => format!("{:?}", overlay.mark),
+        };
+        let shown = index + self.settings.numbering.first();
+        self.ask_yes_no(console, &format!("Place {} at {}? (y/n)", what, shown))
+    }
+
+    fn print_explanation<I: BufRead, W: Write>(
+        &self,
+        console: &mut Console<I, W>,
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        let decision = match self.last_decision {
+            Some(decision) if self.settings.explain => decision,
+            _ => return Ok(()),
+        };
+        writeln!(
+            console.output,
+            "Cpu {}",
+            explain(decision, self.settings.numbering)
+        )?;
+        if let Some(search) = decision.search {
+            writeln!(
+                console.output,
+                "  {}",
+                search_info(&search, elapsed, self.settings.numbering)
+            )?;
+        }
+        Ok(())
+    }
+
+    // Why the CPU made its latest move, None before it has moved
+    pub fn last_decision(&self) -> Option<MoveDecision> {
+        self.last_decision
+    }
+
+    // Lets the CPU move and adds the time it took to the totals
+    fn pick_cpu(&mut self) -> Duration {
+        let started = Instant::now();
+        self.place_cpu_move();
+        let elapsed = started.elapsed();
+        self.round_times.cpu += elapsed;
+        self.session_times.cpu += elapsed;
+        elapsed
+    }
+
+    fn place_cpu_move(&mut self) {
+        let moves = self.legal_moves();
+        if self.phase != Phase::AwaitingCpu || moves.is_empty() {
+            return;
+        }
+
+        let before = self.snapshot();
+        let cpu_mark = self.human_mark.opponent();
+        // Wild and numerical mode have no strategy yet, the CPU plays at random there
+        let decision = match (self.rules.variant, &self.moves_map) {
+            #[cfg(feature = "serde")]
+            (Variant::Classic, Some(map))
+                if self.player.is_none() && self.settings.difficulty == Difficulty::Hard =>
+            {
+                let book = self.book.as_ref();
+                book.and_then(|book| book.choose_move(map, &self.rules, cpu_mark, &mut self.rng))
+                    .or_else(|| {
+                        ai::choose_move(
+                            map,
+                            &self.rules,
+                            cpu_mark,
+                            self.settings.cpu(),
+                            &mut self.rng,
+                        )
+                    })
+            }
+            (Variant::Classic | Variant::Gravity, Some(map)) => match &self.player {
+                Some(player) => player.choose_move(map, &self.rules, cpu_mark, &mut self.rng),
+                None => ai::choose_move(
+                    map,
+                    &self.rules,
+                    cpu_mark,
+                    self.settings.cpu(),
+                    &mut self.rng,
+                ),
+            },
+            _ => None,
+        };
+        let decision = decision.unwrap_or_else(|| MoveDecision {
+            index: moves[self.rng.gen_range(0..moves.len())],
+            reason: Reason::Random,
+            search: None,
+        });
+        self.last_decision = Some(decision);
+        let index = decision.index;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(parent: &self.round_span, "cpu_turn", index).entered();
+        if self.rules.variant == Variant::Numerical {
+            if let Some(map) = &mut self.moves_map {
+                let digits: Vec<u8> = (1..=9)
+                    .filter(|&digit| cpu_mark.owns_digit(digit) && !map.digit_used(digit))
+                    .collect();
+                if !digits.is_empty() {
+                    map.place_digit(index, cpu_mark, digits[self.rng.gen_range(0..digits.len())]);
+                }
+            }
+            self.moved(cpu_mark, index, before, false);
+            return;
+        }
+        let mark = match self.rules.variant {
+            Variant::Wild if self.rng.gen_bool(0.5) => cpu_mark.opponent(),
+            _ => cpu_mark,
+        };
+        if let Some(map) = &mut self.moves_map {
+            map[index] = mark;
+        }
+        self.moved(cpu_mark, index, before, false);
+    }
+
+    // The phase that the status and the side to move call for
+    fn current_phase(&self) -> Phase {
+        match self.status() {
+            Status::Won(mark) if mark == self.human_mark => Phase::RoundOver(Outcome::PlayerWin),
+            Status::Won(_) => Phase::RoundOver(Outcome::CpuWin),
+            Status::Tie => Phase::RoundOver(Outcome::Tie),
+            Status::InProgress if self.turn == self.human_mark || self.settings.two_players => {
+                Phase::AwaitingPlayer
+            }
+            Status::InProgress => Phase::AwaitingCpu,
+            Status::Invalid => Phase::Invalid,
+        }
+    }
+
+    // Bookkeeping after any move was applied to the board
+    fn moved(&mut self, mover: State, index: usize, before: Position, auto: bool) {
+        self.last_mover = Some(mover);
+        self.previous = Some(before);
+        #[cfg(feature = "serde")]
+        if let Some(map) = self.moves_map {
+            self.round_boards.push((mover, map));
+        }
+        #[cfg(feature = "serde")]
+        {
+            self.auto_played |= auto;
+        }
+        self.status.set(None);
+        self.turn = mover.opponent();
+        self.phase = self.current_phase();
+        let (mark, digit) = self
+            .moves_map
+            .map_or((mover, None), |map| (map[index], map.digit(index)));
+        let elapsed = self.round_started.elapsed();
+        self.round_moves.push(PlayedMove {
+            mark,
+            index,
+            digit,
+            auto,
+            elapsed,
+        });
+        #[cfg(feature = "serde")]
+        self.checkpoint();
+        self.observers.emit(Event::Move {
+            mark,
+            index,
+            digit,
+            human: mover == self.human_mark || self.settings.two_players,
+            auto,
+            elapsed,
+        });
+    }
+
+    // The move the best CPU would make for the side to move, typed as the player would
+    // type it; None in wild and numerical mode, which have no such suggestion
+    fn auto_play_move(&mut self) -> Option<String> {
+        let map = self.moves_map?;
+        let cpu = Cpu {
+            difficulty: Difficulty::Hard,
+            personality: self.settings.personality,
+            depth: self.settings.depth,
+        };
+        let first = self.settings.numbering.first();
+        match self.rules.variant {
+            Variant::Classic => {
+                let decision =
+                    ai::choose_move(&map, &self.rules, self.mover(), cpu, &mut self.rng)?;
+                Some((decision.index + first).to_string())
+            }
+            Variant::Gravity => {
+                let decision =
+                    ai::choose_move(&map, &self.rules, self.mover(), cpu, &mut self.rng)?;
+                Some((decision.index % self.rules.cols + first).to_string())
+            }
+            Variant::Wild | Variant::Numerical => None,
+        }
+    }
+
+    // The moves of the current round, first to last. A resumed round starts with none.
+    pub fn round_moves(&self) -> &[PlayedMove] {
+        &self.round_moves
+    }
+
+    // The round just finished in one block: the final board with the winning line
+    // marked, the result, the moves, the time it took and the score
+    pub fn round_summary(&self, colors: bool) -> String {
+        let [player, cpu] = self.side_names();
+        format!(
+            "== Round {} ==\n{}{}",
+            self.score.rounds(),
+            self.describe_round(&self.finished_round(), colors),
+            self.score.table(player, cpu)
+        )
+    }
+
+    // What is kept of the round that just ended
+    fn finished_round(&self) -> FinishedRound {
+        let result = match self.status() {
+            Status::Won(mark) if self.settings.two_players => format!("{:?} wins", mark),
+            Status::Won(mark) if mark == self.human_mark => "You win".to_string(),
+            Status::Won(_) => "Cpu wins".to_string(),
+            Status::Tie => "Tie".to_string(),
+            Status::InProgress => "Unfinished".to_string(),
+            Status::Invalid => "Invalid".to_string(),
+        };
+        FinishedRound {
+            number: self.score.rounds(),
+            board: self.moves_map.unwrap_or_else(|| self.rules.new_board()),
+            line: self.winning_line(),
+            result,
+            moves: self.round_moves.clone(),
+            ending: self.ending,
+            times: self.round_times,
+            played: self
+                .played_games
+                .get(&CanonicalGame::new(
+                    &self.rules,
+                    &self.round_moves,
+                    self.ending,
+                ))
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+
+    // A move written the way it is typed
+    fn move_text(&self, played: PlayedMove) -> String {
+        let first = self.settings.numbering.first();
+        let cell = match (self.rules.variant, played.digit) {
+            (Variant::Gravity, _) => (played.index % self.rules.cols + first).to_string(),
+            (_, Some(digit)) => format!("{}@{}", digit, played.index + first),
+            _ => (played.index + first).to_string(),
+        };
+        let auto = if played.auto { " (auto)" } else { "" };
+        format!("{:?} {}{}", played.mark, cell, auto)
+    }
+
+    // The final board, result, moves and time of `round`
+    fn describe_round(&self, round: &FinishedRound, colors: bool) -> String {
+        let line: Vec<usize> = round
+            .line
+            .map_or_else(Vec::new, |line| line.cells().collect());
+        let mut board = Vec::new();
+        let drawn = self.draw_board(&round.board, &mut board, colors, None, &line);
+        debug_assert!(drawn.is_ok(), "writing to a Vec can't fail");
+        let mut moves: Vec<String> = round
+            .moves
+            .iter()
+            .enumerate()
+            .map(|(number, &played)| {
+                let text = self.move_text(played);
+                format!("{}. {} ({})", number + 1, text, seconds(played.elapsed))
+            })
+            .collect();
+        // The transcript ends with how the players ended it, e.g. "O resigns"
+        if let Some(ending) = round.ending {
+            moves.push(ending.to_string());
+        }
+        let [player, cpu] = self.side_names();
+        format!(
+            "{}Result: {}\nMoves: {}\nTime this round: {}\n",
+            String::from_utf8_lossy(&board),
+            round.result,
+            moves.join("  "),
+            round.times.line(player, cpu)
+        )
+    }
+
+    // Cells changed by the latest move, e.g. to highlight or animate it
+    pub fn last_changes(&self) -> Vec<CellChange> {
+        match &self.previous {
+            Some(previous) => previous.diff(&self.snapshot()),
+            None => Vec::new(),
+        }
+    }
+
+    // `auto` marks a move played for an idle player
+    fn pick_player(&mut self, player_move: Move, auto: bool) -> Result<(), PickError> {
+        if self.moves_map.is_some() {
+            match self.phase {
+                Phase::RoundOver(_) => return Err(PickError::WrongPhase),
+                Phase::Invalid => return Err(PickError::InvalidBoard),
+                _ => (),
+            }
+            if self.turn != self.mover() {
+                return Err(PickError::NotYourTurn);
+            }
+            if self.draw_offer.is_some() {
+                return Err(PickError::DrawOffered);
+            }
+        }
+        if player_move.index > self.max_input() {
+            return Err(PickError::OutOfBounds {
+                given: player_move.index,
+                max: self.max_input(),
+            });
+        }
+        let variant = self.rules.variant;
+        let mover = self.mover();
+        let mark = player_move.mark.unwrap_or(mover);
+        let before = self.snapshot();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            parent: &self.round_span,
+            "player_turn",
+            index = player_move.index
+        )
+        .entered();
+        if let Some(map) = &mut self.moves_map {
+            // In gravity mode the input is a column and the mark drops to its landing cell
+            let index = match variant {
+                Variant::Gravity => map
+                    .drop_target(player_move.index)
+                    .ok_or(PickError::ColumnFull)?,
+                _ => player_move.index,
+            };
+            if map[index] == State::Empty {
+                match player_move.digit {
+                    Some(digit) if !mark.owns_digit(digit) => return Err(PickError::DigitNotYours),
+                    Some(digit) if map.digit_used(digit) => return Err(PickError::DigitUsed),
+                    Some(digit) => map.place_digit(index, mark, digit),
+                    None => map[index] = mark,
+                }
+                self.moved(mover, index, before, auto);
+                Ok(())
+            } else {
+                // Fail, already occupied
+                Err(PickError::AreaOccupied {
+                    index,
+                    occupant: map[index],
+                })
+            }
+        } else {
+            Err(PickError::MovesMapNotInitialized) // Fail, moves_map is None
+        }
+    }
+
+    // Copy of the current round for history, diffing and replays
+    pub fn snapshot(&self) -> Position {
+        Position {
+            board: self.moves_map.unwrap_or_else(|| self.rules.new_board()),
+            to_move: self.whose_turn(),
+            status: self.status(),
+        }
+    }
+
+    // State of the round, computed once per board change
+    pub fn status(&self) -> Status {
+        if let Some(status) = self.status.get() {
+            return status;
+        }
+        let status = self.compute_status();
+        self.status.set(Some(status));
+        status
+    }
+
+    fn compute_status(&self) -> Status {
+        let map = match &self.moves_map {
+            Some(map) => map,
+            None => return Status::InProgress,
+        };
+        match self.ending {
+            Some(Ending::Resigned(mark)) => return Status::Won(mark.opponent()),
+            Some(Ending::DrawAgreed) => return Status::Tie,
+            None => (),
+        }
+        if self.rules.both_won(map) {
+            return Status::Invalid;
+        }
+        let win_len = self.rules.win_len;
+        let winner = match self.rules.variant {
+            // Any completed line counts for the mover, whichever mark it is made of
+            Variant::Wild if self.rules.winner(map).is_some() => self.last_mover,
+            Variant::Numerical if map.has_sum_line(win_len, 15) => self.last_mover,
+            Variant::Wild | Variant::Numerical => None,
+            _ => self.rules.winner(map),
+        };
+        match winner {
+            Some(mark) => Status::Won(mark),
+            None if map.is_full() => Status::Tie,
+            None => Status::InProgress,
+        }
+    }
+
+    // Called right after `state`'s owner moved, so a Win belongs to the mover
+    fn check(&self, state: State) -> CheckResult {
+        match self.status() {
+            Status::Won(mark) if mark == state => CheckResult::Win,
+            Status::Tie => CheckResult::Tie,
+            Status::Invalid => CheckResult::Invalid,
+            _ => CheckResult::Contine,
+        }
+    }
+}
+
+// One line on a CPU move, worded for the player it moved against
+// Cells are named as `numbering` has them
+pub fn explain(decision: MoveDecision, numbering: Numbering) -> String {
+    let index = decision.index + numbering.first();
+    match decision.reason {
+        Reason::Random => format!("played {} at random", index),
+        Reason::Win => format!("took winning move at {}", index),
+        Reason::Block { line } => {
+            let cells: Vec<String> = line
+                .cells()
+                .map(|cell| (cell + numbering.first()).to_string())
+                .collect();
+            format!("blocked your line {}", cells.join("-"))
+        }
+        Reason::Fork => format!("created a fork at {}", index),
+        Reason::BlockFork => format!("took {} to stop your fork", index),
+        Reason::Threat => format!("threatened a line at {}", index),
+        Reason::Center => format!("took the center at {}", index),
+        Reason::Corner => format!("took the corner at {}", index),
+        Reason::Learned => format!("played {}, its best learned move", index),
+        Reason::Book => format!("played {}, a perfect opening from the book", index),
+        Reason::Search { score, plies } => {
+            let outlook = match score {
+                0 => format!("draw in {}", plies),
+                s if s > 0 => format!("win in {}", plies.div_ceil(2)),
+                _ => format!("loss in {}", plies / 2),
+            };
+            format!("played {}, best by search ({})", index, outlook)
+        }
+        Reason::Lookahead { score, depth } => {
+            let outlook = match score {
+                s if ai::is_decisive(s) && s > 0 => ", a forced win",
+                s if ai::is_decisive(s) => ", a forced loss",
+                _ => "",
+            };
+            format!("played {}, best {} plies ahead{}", index, depth, outlook)
+        }
+    }
+}
+
+// What a search did, as "depth 6, 1.2k nodes, 3 ms, pv 4 0 8 2"
+// Cells are named as `numbering` has them
+pub fn search_info(search: &SearchResult, elapsed: Duration, numbering: Numbering) -> String {
+    let pv: Vec<String> = search
+        .pv
+        .iter()
+        .map(|index| (index + numbering.first()).to_string())
+        .collect();
+    format!(
+        "depth {}, {} nodes, {} ms, pv {}",
+        search.depth,
+        count(search.nodes),
+        elapsed.as_millis(),
+        pv.join(" ")
+    )
+}
+
+// `n` shortened to three significant places at most, as 950, 1.2k or 34M
+fn count(n: u64) -> String {
+    match n {
+        0..1_000 => n.to_string(),
+        1_000..10_000 => format!("{:.1}k", n as f64 / 1e3),
+        10_000..1_000_000 => format!("{}k", n / 1_000),
+        1_000_000..10_000_000 => format!("{:.1}M", n as f64 / 1e6),
+        _ => format!("{}M", n / 1_000_000),
+    }
+}
+
+// First digit (zero) of the decimal digit blocks of Unicode that keyboards commonly type:
+// Arabic-Indic, Eastern Arabic-Indic, NKo, the Indic scripts, Thai, Lao, Tibetan, Myanmar,
+// Khmer, Mongolian and fullwidth
+const DIGIT_ZEROS: [u32; 20] = [
+    0x0660, 0x06f0, 0x07c0, 0x0966, 0x09e6, 0x0a66, 0x0ae6, 0x0b66, 0x0be6, 0x0c66, 0x0ce6, 0x0d66,
+    0x0de6, 0x0e50, 0x0ed0, 0x0f20, 0x1040, 0x17e0, 0x1810, 0xff10,
+];
+
+// `input` with the digits of other scripts, even mixed in one number, as ASCII digits
+fn ascii_digits(input: &str) -> String {
+    input
+        .chars()
+        .map(|c| {
+            DIGIT_ZEROS
+                .iter()
+                .find_map(|&zero| (c as u32).checked_sub(zero).filter(|&digit| digit < 10))
+                .and_then(|digit| char::from_digit(digit, 10))
+                .unwrap_or(c)
+        })
+        .collect()
+}
+
+// `moves` drawn small with `index` in brackets, the cell a refused move wanted
+fn pointing_at(moves: &Board, index: usize) -> String {
+    let area = moves.rows() * moves.cols();
+    let mut text = String::new();
+    for row in 0..moves.rows() {
+        let mut line = String::new();
+        for layer in 0..moves.layers() {
+            if layer > 0 {
+                line.push_str("  ");
+            }
+            for col in 0..moves.cols() {
+                let cell = layer * area + row * moves.cols() + col;
+                let symbol = match (moves[cell], moves.digit(cell)) {
+                    (_, Some(digit)) => digit.to_string(),
+                    (State::X, None) => "X".to_string(),
+                    (State::O, None) => "O".to_string(),
+                    (State::Empty, None) => ".".to_string(),
+                };
+                if cell == index {
+                    line.push_str(&format!("[{}]", symbol));
+                } else {
+                    line.push_str(&format!(" {} ", symbol));
+                }
+            }
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+// Parse a move such as "4", "1,2,0" on a cube, "4x" / "4o" in wild mode
+// where the mark is chosen per move, or "5@4" / "5 at 4" in numerical mode. Indices and
+// coordinates count from `numbering`'s first number.
+pub fn parse_move(input: &str, rules: &Rules, numbering: Numbering) -> Option<Move> {
+    let first = numbering.first();
+    // Windows consoles end lines with \r\n and may leave stray carriage returns inside
+    let input = ascii_digits(&input.replace('\r', ""));
+    let mut input = input.trim();
+    let mut mark = None;
+    let mut digit = None;
+    match rules.variant {
+        Variant::Wild => {
+            mark = match input.chars().last()?.to_ascii_lowercase() {
+                'x' => Some(State::X),
+                'o' => Some(State::O),
+                _ => return None,
+            };
+            input = input[..input.len() - 1].trim();
+        }
+        Variant::Numerical => {
+            let (value, cell) = input.split_once('@').or_else(|| input.split_once(" at "))?;
+            digit = match value.trim().parse::<u8>().ok()? {
+                value @ 1..=9 => Some(value),
+                _ => return None,
+            };
+            input = cell.trim();
+        }
+        _ => (),
+    }
+
+    if rules.layers > 1 && input.contains(',') {
+        let mut coords = input.split(',').map(|part| {
+            let coord = part.trim().parse::<usize>().ok()?;
+            coord.checked_sub(first)
+        });
+        let (layer, row, col) = match (coords.next(), coords.next(), coords.next(), coords.next()) {
+            (Some(Some(layer)), Some(Some(row)), Some(Some(col)), None) => (layer, row, col),
+            _ => return None,
+        };
+        if layer >= rules.layers || row >= rules.rows || col >= rules.cols {
+            return None;
+        }
+        let index = (layer * rules.rows + row) * rules.cols + col;
+        return Some(Move { index, mark, digit });
+    }
+    let index = match input.parse::<usize>() {
+        // 0 when counting from 1 becomes an index past the end, out of bounds like one
+        Ok(index) => index.wrapping_sub(first),
+        Err(_) if names_cells(rules) => cell_name(input)?,
+        Err(_) => return None,
+    };
+    Some(Move { index, mark, digit })
+}
+
+// Whether cells can be given by name: only on one 3x3 grid, and not in gravity mode
+// where the input is a column
+fn names_cells(rules: &Rules) -> bool {
+    (rules.rows, rules.cols, rules.layers) == (3, 3, 1) && rules.variant != Variant::Gravity
+}
+
+// The index of a cell of a 3x3 board by its name, such as "top-left", "Top Left" or "tl"
+fn cell_name(name: &str) -> Option<usize> {
+    let name = name.to_lowercase().replace([' ', '_'], "-");
+    let index = match name.as_str() {
+        "top-left" | "tl" => 0,
+        "top" | "t" => 1,
+        "top-right" | "tr" => 2,
+        "left" | "l" => 3,
+        "center" | "centre" | "middle" | "c" => 4,
+        "right" | "r" => 5,
+        "bottom-left" | "bl" => 6,
+        "bottom" | "b" => 7,
+        "bottom-right" | "br" => 8,
+        _ => return None,
+    };
+    Some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::RngKind;
+    use rand::rngs::mock::StepRng;
+    use std::sync::Mutex;
+
+    fn seeded(rules: Rules, settings: Settings) -> Game {
+        Game::with_rng(rules, settings, GameRng::seeded(RngKind::ChaCha8, 7))
+    }
+
+    fn gravity(rows: usize, cols: usize) -> Game {
+        let mut game = Game::with_rules(Rules::gravity(rows, cols));
+        game.reset();
+        game
+    }
+
+    fn at(index: usize, mark: State) -> Move {
+        Move {
+            index,
+            mark: Some(mark),
+            digit: None,
+        }
+    }
+
+    #[test]
+    fn gravity_drops_stack_up_a_column() {
+        let mut game = gravity(6, 7);
+        for mark in [State::X, State::O, State::X] {
+            play_as(&mut game, mark, at(3, mark));
+        }
+        let board = game.moves_map.unwrap();
+        assert_eq!(
+            (board[38], board[31], board[24]),
+            (State::X, State::O, State::X)
+        );
+        assert_eq!(board[17], State::Empty);
+    }
+
+    #[test]
+    fn gravity_refuses_a_full_column() {
+        let mut game = gravity(6, 7);
+        for mark in [State::X, State::O].repeat(3) {
+            play_as(&mut game, mark, at(0, mark));
+        }
+        assert!(matches!(
+            try_as(&mut game, State::X, at(0, State::X)),
+            Err(PickError::ColumnFull)
+        ));
+        assert!(matches!(
+            try_as(&mut game, State::X, at(7, State::X)),
+            Err(PickError::OutOfBounds { given: 7, max: 6 })
+        ));
+        assert!(try_as(&mut game, State::X, at(1, State::X)).is_ok());
+    }
+
+    // Play `played` as `side`, the way two players would share the keyboard
+    fn try_as<R: Rng + 'static>(
+        game: &mut Game<R>,
+        side: State,
+        played: Move,
+    ) -> Result<(), PickError> {
+        game.human_mark = side;
+        game.turn = side;
+        game.pick_player(played, false)
+    }
+
+    fn play_as<R: Rng + 'static>(game: &mut Game<R>, side: State, played: Move) {
+        try_as(game, side, played).unwrap();
+    }
+
+    fn wild_rules() -> Rules {
+        Rules {
+            variant: Variant::Wild,
+            ..Rules::default()
+        }
+    }
+
+    fn wild_game() -> Game {
+        let mut game = Game::with_rules(wild_rules());
+        game.reset();
+        game
+    }
+
+    #[test]
+    fn wild_moves_carry_their_mark() {
+        let rules = wild_rules();
+        let parsed = parse_move("4x", &rules, Numbering::ZeroBased).unwrap();
+        assert_eq!((parsed.index, parsed.mark), (4, Some(State::X)));
+        let parsed = parse_move(" 4 O ", &rules, Numbering::ZeroBased).unwrap();
+        assert_eq!((parsed.index, parsed.mark), (4, Some(State::O)));
+        assert!(parse_move("4", &rules, Numbering::ZeroBased).is_none());
+        assert!(parse_move("4z", &rules, Numbering::ZeroBased).is_none());
+    }
+
+    #[test]
+    fn wild_line_belongs_to_whoever_completes_it() {
+        let mut game = wild_game();
+        for (index, mark) in [(0, State::O), (4, State::X), (1, State::O), (8, State::X)] {
+            play_as(&mut game, State::X, at(index, mark));
+        }
+        // X's side finishes a row of Os
+        play_as(&mut game, State::X, at(2, State::O));
+        assert!(matches!(game.check(State::X), CheckResult::Win));
+    }
+
+    #[test]
+    fn wild_second_side_wins_with_the_first_sides_mark() {
+        let mut game = wild_game();
+        for (index, mark) in [
+            (0, State::O),
+            (3, State::X),
+            (8, State::O),
+            (4, State::X),
+            (2, State::O),
+        ] {
+            play_as(&mut game, State::X, at(index, mark));
+        }
+        play_as(&mut game, State::O, at(5, State::X));
+        assert!(matches!(game.check(State::O), CheckResult::Win));
+    }
+
+    #[test]
+    fn wild_cpu_plays_both_marks_to_a_finish() {
+        let mut game = wild_game();
+        let finished = |result| matches!(result, CheckResult::Win | CheckResult::Tie);
+        for _ in 0..5 {
+            let free = game.legal_moves()[0];
+            game.pick_player(at(free, State::X), false).unwrap();
+            if finished(game.check(State::X)) {
+                return;
+            }
+            game.pick_cpu();
+            if finished(game.check(State::O)) {
+                return;
+            }
+        }
+        panic!("the round never finished");
+    }
+
+    #[test]
+    fn cube_moves_take_layer_row_and_column() {
+        let rules = Rules::cube();
+        let index =
+            |input| parse_move(input, &rules, Numbering::ZeroBased).map(|parsed| parsed.index);
+        assert_eq!(index("1,2,0"), Some(15));
+        assert_eq!(index("2, 2, 2"), Some(26));
+        assert_eq!(index("26"), Some(26));
+        assert_eq!(index("3,0,0"), None);
+        assert_eq!(index("0,0"), None);
+    }
+
+    #[test]
+    fn cube_space_diagonal_wins() {
+        let mut game = Game::with_rules(Rules::cube());
+        game.reset();
+        for (index, mark) in [(0, State::X), (1, State::O), (13, State::X), (2, State::O)] {
+            play_as(&mut game, mark, at(index, mark));
+        }
+        play_as(&mut game, State::X, at(26, State::X));
+        assert!(matches!(game.check(State::X), CheckResult::Win));
+    }
+
+    fn digit(value: u8, index: usize) -> Move {
+        Move {
+            index,
+            mark: None,
+            digit: Some(value),
+        }
+    }
+
+    fn numerical_game() -> Game {
+        let mut game = Game::with_rules(Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        });
+        game.reset();
+        game
+    }
+
+    #[test]
+    fn numerical_line_of_both_sides_digits_wins_for_the_mover() {
+        let mut game = numerical_game();
+        let parsed = parse_move("5@4", &game.rules, Numbering::ZeroBased).unwrap();
+        assert_eq!((parsed.digit, parsed.index), (Some(5), 4));
+        play_as(&mut game, State::X, digit(5, 0));
+        play_as(&mut game, State::O, digit(2, 1));
+        play_as(&mut game, State::X, digit(1, 6));
+        // 5 + 2 + 8 across the top, one odd digit of X's
+        play_as(&mut game, State::O, digit(8, 2));
+        assert!(matches!(game.check(State::O), CheckResult::Win));
+    }
+
+    #[test]
+    fn numerical_line_of_the_other_sides_digits_wins_for_the_mover() {
+        let mut game = numerical_game();
+        play_as(&mut game, State::X, digit(7, 8));
+        play_as(&mut game, State::O, digit(4, 1));
+        play_as(&mut game, State::X, digit(1, 3));
+        play_as(&mut game, State::O, digit(6, 2));
+        play_as(&mut game, State::X, digit(5, 0));
+        assert!(matches!(game.check(State::X), CheckResult::Win));
+    }
+
+    #[test]
+    fn numerical_digits_must_be_the_movers_and_unused() {
+        let mut game = numerical_game();
+        assert!(matches!(
+            game.pick_player(digit(2, 0), false),
+            Err(PickError::DigitNotYours)
+        ));
+        play_as(&mut game, State::X, digit(3, 0));
+        play_as(&mut game, State::O, digit(2, 1));
+        assert!(matches!(
+            try_as(&mut game, State::X, digit(3, 4)),
+            Err(PickError::DigitUsed)
+        ));
+    }
+
+    // A game whose CPU always takes the first free cell, so scripts know its replies
+    fn pinned(rules: Rules, settings: Settings) -> Game<StepRng> {
+        Game::with_rng(rules, settings, StepRng::new(0, 0))
+    }
+
+    // Plays a scripted session, returning everything it printed
+    fn session<R: Rng + 'static>(game: &mut Game<R>, input: &str) -> (SessionSummary, String) {
+        let mut output = Vec::new();
+        let summary = game.start_with(input.as_bytes(), &mut output).unwrap();
+        (summary, String::from_utf8(output).unwrap())
+    }
+
+    // X takes the diagonal while the CPU fills the top row from the left
+    const X_WINS: &str = "4\n2\n6\n";
+
+    fn two_player_session(settings: Settings) -> Game<StepRng> {
+        let settings = Settings {
+            two_players: true,
+            ..settings
+        };
+        pinned(Rules::default(), settings)
+    }
+
+    // X takes the top row against O in two-player mode
+    const X_TAKES_THE_TOP_ROW: &str = "0\n3\n1\n4\n2\n";
+
+    #[test]
+    fn rematch_prompt_reasks_until_answered() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, &format!("{}maybe\nn\n", X_WINS));
+        assert_eq!(output.matches("Play again?").count(), 2);
+        assert!(output.contains("Please answer y or n"));
+        assert!(output.contains("Thanks for playing"));
+        assert_eq!(game.score.player, 1);
+    }
+
+    #[test]
+    fn rematch_prompt_takes_end_of_input_as_no() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, X_WINS);
+        assert_eq!(output.matches("Play again?").count(), 1);
+        assert!(output.contains("Thanks for playing"));
+        assert_eq!(game.score.player, 1);
+    }
+
+    #[test]
+    fn rematch_yes_plays_another_round() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        session(&mut game, &format!("{0}y\n{0}n\n", X_WINS));
+        assert_eq!(game.score.player, 2);
+    }
+
+    #[test]
+    fn a_batch_of_rounds_ends_with_the_summary_without_asking() {
+        let settings = Settings {
+            rounds: Some(2),
+            ..Settings::default()
+        };
+        let mut game = two_player_session(settings);
+        let (_, output) = session(&mut game, &X_TAKES_THE_TOP_ROW.repeat(3));
+        assert_eq!(game.score.rounds(), 2);
+        assert!(!output.contains("Play again?"), "{}", output);
+        assert_eq!(output.matches("== Round").count(), 2, "{}", output);
+        assert!(
+            output.ends_with("** Thanks for playing! **\n"),
+            "{}",
+            output
+        );
+
+        let quiet = Settings {
+            summary_only: true,
+            ..settings
+        };
+        let mut game = two_player_session(quiet);
+        let (_, output) = session(&mut game, &X_TAKES_THE_TOP_ROW.repeat(3));
+        assert_eq!(game.score.rounds(), 2);
+        let turns: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("Round 2"))
+            .collect();
+        assert_eq!(
+            turns,
+            [
+                "Round 2, X to move: ...|...|...",
+                "Round 2, O to move: X..|...|...",
+                "Round 2, X to move: X..|O..|...",
+                "Round 2, O to move: XX.|O..|...",
+                "Round 2, X to move: XX.|OO.|...",
+            ]
+        );
+        assert_eq!(output.matches("Choose index").count(), 1, "{}", output);
+        for chatter in ["== Round", "You entered", "took", "to move **", "X* "] {
+            assert!(!output.contains(chatter), "{} in {}", chatter, output);
+        }
+        assert!(output.ends_with("** X wins! **\n** Thanks for playing! **\n"));
+    }
+
+    #[test]
+    fn auto_rematch_never_asks() {
+        let settings = Settings {
+            auto_rematch: true,
+            ..Settings::default()
+        };
+        let mut game = pinned(Rules::default(), settings);
+        let (_, output) = session(&mut game, &X_WINS.repeat(3));
+        assert!(!output.contains("Play again?"));
+        assert_eq!(game.score.player, 3);
+    }
+
+    #[test]
+    fn match_goes_to_the_first_side_reaching_the_target() {
+        let mut game = Game::new();
+        for outcome in [
+            Outcome::PlayerWin,
+            Outcome::Tie,
+            Outcome::CpuWin,
+            Outcome::Tie,
+        ] {
+            game.record_outcome(outcome);
+            assert_eq!(game.match_score.match_winner(2, ["You", "Cpu"]), None);
+        }
+        game.record_outcome(Outcome::CpuWin);
+        assert_eq!(
+            game.match_score.match_winner(2, ["You", "Cpu"]),
+            Some("Cpu")
+        );
+        assert_eq!(game.match_score.tie, 2);
+    }
+
+    // Every cell filled and no line
+    const TIE: &str = "1\n3\n4\n6\n8\n";
+
+    #[test]
+    fn first_to_match_ends_the_session_when_declined() {
+        let settings = Settings {
+            first_to: Some(2),
+            ..Settings::default()
+        };
+        let mut game = pinned(Rules::default(), settings);
+        let (_, output) = session(&mut game, &format!("{0}{1}{0}n\n", X_WINS, TIE));
+        // The tie is tallied but the match goes on without asking
+        assert!(!output.contains("Play again?"));
+        assert_eq!(output.matches("won the match 2-0").count(), 1);
+        assert_eq!(output.matches("Start a new match?").count(), 1);
+        assert_eq!((game.score.player, game.score.tie), (2, 1));
+    }
+
+    #[test]
+    fn new_match_keeps_the_session_score() {
+        let settings = Settings {
+            first_to: Some(1),
+            ..Settings::default()
+        };
+        let mut game = pinned(Rules::default(), settings);
+        let (_, output) = session(&mut game, &format!("{0}y\n{0}n\n", X_WINS));
+        assert_eq!(output.matches("won the match 1-0").count(), 2);
+        assert_eq!(game.score.player, 2);
+    }
+
+    #[test]
+    fn swap_is_refused_mid_round() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, "4\nswap\n");
+        assert!(output.contains("Sides can only be swapped between rounds!"));
+        assert_eq!(game.human_mark, State::X);
+    }
+
+    #[test]
+    fn swap_before_the_first_move_changes_marks_but_not_the_opener() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, "swap\n");
+        assert!(output.contains("Sides swapped: you play O, the Cpu plays X"));
+        assert_eq!(game.human_mark, State::O);
+        let board = game.moves_map.unwrap();
+        assert!(board.cells().iter().all(|&cell| cell == State::Empty));
+    }
+
+    #[test]
+    fn swap_exchanges_the_score_buckets() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        session(&mut game, &format!("{}swap\n", X_WINS));
+        assert_eq!((game.score.player, game.score.cpu), (0, 1));
+        assert_eq!(game.human_mark, State::O);
+    }
+
+    #[test]
+    fn numerical_swap_hands_over_the_opening_move() {
+        let rules = numerical_game().rules;
+        let mut game = pinned(rules, Settings::default());
+        let (_, output) = session(&mut game, "swap\n");
+        assert!(output.contains("Sides swapped"));
+        assert_eq!(game.human_mark, State::O);
+        let board = game.moves_map.unwrap();
+        let marks = |mark| board.cells().iter().filter(|&&cell| cell == mark).count();
+        assert_eq!((marks(State::X), marks(State::O)), (1, 0));
+    }
+
+    #[test]
+    fn status_is_never_served_stale() {
+        let mut game = Game::new();
+        game.reset();
+        let moves = [(0, State::X), (3, State::O), (1, State::X), (4, State::O)];
+        for (index, mark) in moves {
+            assert_eq!(game.status(), Status::InProgress);
+            play_as(&mut game, mark, at(index, mark));
+        }
+        assert_eq!(game.status(), Status::InProgress);
+        play_as(&mut game, State::X, at(2, State::X));
+        assert_eq!(game.status(), Status::Won(State::X));
+
+        // A new board read right after a cached win
+        game.reset();
+        assert_eq!(game.status(), Status::InProgress);
+        for (index, mark) in moves {
+            play_as(&mut game, mark, at(index, mark));
+        }
+        play_as(&mut game, State::O, at(5, State::O));
+        assert_eq!(game.status(), Status::Won(State::O));
+    }
+
+    #[test]
+    fn status_follows_round_endings() {
+        let mut game = Game::new();
+        game.reset();
+        // X O X / X O O / O X X, no line
+        for (index, mark) in [
+            (0, State::X),
+            (1, State::O),
+            (2, State::X),
+            (4, State::O),
+            (3, State::X),
+            (5, State::O),
+            (7, State::X),
+            (6, State::O),
+        ] {
+            play_as(&mut game, mark, at(index, mark));
+            assert_eq!(game.status(), Status::InProgress);
+        }
+        play_as(&mut game, State::X, at(8, State::X));
+        assert_eq!(game.status(), Status::Tie);
+        game.reset();
+        assert_eq!(game.status(), Status::InProgress);
+    }
+
+    #[test]
+    fn status_needs_only_a_shared_borrow() {
+        fn read(game: &Game) -> Status {
+            game.status()
+        }
+        let game = Game::new();
+        assert_eq!(read(&game), read(&game));
+    }
+
+    #[test]
+    fn playing_on_a_clone_leaves_the_original_alone() {
+        let mut game = Game::new();
+        game.reset();
+        play_as(&mut game, State::X, at(4, State::X));
+        let before = game.snapshot();
+        let mut copy = game.clone();
+        for (index, side) in [(0, State::O), (3, State::X), (8, State::O), (5, State::X)] {
+            play_as(&mut copy, side, at(index, side));
+        }
+        assert_eq!(copy.status(), Status::Won(State::X));
+        assert_eq!(game.snapshot(), before);
+        assert_eq!(game.status(), Status::InProgress);
+    }
+
+    #[test]
+    fn clones_draw_the_same_cpu_moves() {
+        let mut game = seeded(Rules::default(), Settings::default());
+        game.new_round();
+        let mut copy = game.clone();
+        game.submit(at(0, State::X)).unwrap();
+        copy.submit(at(0, State::X)).unwrap();
+        assert_eq!(game.snapshot(), copy.snapshot());
+    }
+
+    #[test]
+    fn snapshots_key_sets() {
+        let mut game = Game::new();
+        game.reset();
+        let mut seen = std::collections::HashSet::new();
+        seen.insert(game.snapshot());
+        play_as(&mut game, State::X, at(4, State::X));
+        seen.insert(game.snapshot());
+        let snapshot = game.snapshot();
+        assert!(seen.contains(&snapshot));
+        assert_eq!(
+            (snapshot.to_move, snapshot.status),
+            (State::O, Status::InProgress)
+        );
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn last_changes_are_the_latest_move() {
+        let mut game = Game::new();
+        game.reset();
+        assert!(game.last_changes().is_empty());
+        play_as(&mut game, State::X, at(4, State::X));
+        play_as(&mut game, State::O, at(0, State::O));
+        let change = CellChange {
+            index: 0,
+            from: State::Empty,
+            to: State::O,
+        };
+        assert_eq!(game.last_changes(), [change]);
+        // A fresh board has no move to show
+        game.reset();
+        assert!(game.last_changes().is_empty());
+    }
+
+    #[test]
+    fn last_changes_show_the_cpus_reply() {
+        let mut game = Game::new();
+        game.reset();
+        game.pick_player(at(4, State::X), false).unwrap();
+        game.pick_cpu();
+        let changes = game.last_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].from, changes[0].to), (State::Empty, State::O));
+        assert_eq!(game.moves_map.unwrap()[changes[0].index], State::O);
+    }
+
+    // Names of the spans opened while `f` runs, in order
+    #[cfg(feature = "tracing")]
+    fn spans_opened(f: impl FnOnce()) -> Vec<&'static str> {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+
+        struct Capture(Arc<Mutex<Vec<&'static str>>>);
+        impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Capture {
+            fn on_new_span(
+                &self,
+                attrs: &tracing::span::Attributes,
+                _: &tracing::span::Id,
+                _: Context<S>,
+            ) {
+                self.0.lock().unwrap().push(attrs.metadata().name());
+            }
+        }
+
+        let names = Arc::default();
+        let subscriber = tracing_subscriber::registry().with(Capture(Arc::clone(&names)));
+        tracing::subscriber::with_default(subscriber, f);
+        let names = names.lock().unwrap().clone();
+        names
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn round_opens_the_player_and_cpu_turn_spans() {
+        let mut game = Game::new();
+        let names = spans_opened(|| {
+            game.reset();
+            game.start_round();
+            game.pick_player(at(4, State::X), false).unwrap();
+            game.pick_cpu();
+        });
+        assert_eq!(names, ["round", "player_turn", "cpu_turn"]);
+    }
+
+    #[test]
+    fn windows_line_endings_are_ignored() {
+        let parsed = |input, rules: &Rules| {
+            parse_move(input, rules, Numbering::ZeroBased)
+                .map(|played| (played.index, played.mark, played.digit))
+        };
+        let classic = Rules::default();
+        assert_eq!(parsed("4\r\n", &classic), Some((4, None, None)));
+        assert_eq!(parsed("\r4\r", &classic), Some((4, None, None)));
+        assert_eq!(
+            parsed("1,\r2,0\r\n", &Rules::cube()),
+            Some((15, None, None))
+        );
+        assert_eq!(
+            parsed("4x\r\n", &wild_rules()),
+            Some((4, Some(State::X), None))
+        );
+        let numerical = numerical_game().rules;
+        assert_eq!(parsed("5@\r4\r\n", &numerical), Some((4, None, Some(5))));
+        assert_eq!(parsed("\r\n", &classic), None);
+    }
+
+    #[test]
+    fn step_rng_pins_the_random_cpu() {
+        // Always the lowest draw, so always the first free cell
+        let mut game = Game::with_rng(Rules::default(), Settings::default(), StepRng::new(0, 0));
+        game.reset();
+        for index in [4, 8] {
+            game.pick_player(at(index, State::X), false).unwrap();
+            game.pick_cpu();
+        }
+        let board = game.moves_map.unwrap();
+        assert_eq!((board[0], board[1]), (State::O, State::O));
+        assert_eq!(
+            board
+                .cells()
+                .iter()
+                .filter(|&&cell| cell == State::O)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn same_seed_same_cpu_moves() {
+        let play = |seed| {
+            let rng = GameRng::seeded(RngKind::ChaCha8, seed);
+            let mut game = Game::with_rng(Rules::default(), Settings::default(), rng);
+            game.reset();
+            let mut boards = Vec::new();
+            while game.status() == Status::InProgress {
+                let free = game.legal_moves()[0];
+                game.pick_player(at(free, State::X), false).unwrap();
+                game.pick_cpu();
+                boards.push(game.moves_map.unwrap());
+            }
+            boards
+        };
+        assert_eq!(play(3), play(3));
+        assert_ne!((1..6).map(play).collect::<Vec<_>>(), vec![play(1); 5]);
+    }
+
+    #[test]
+    fn scripted_session_through_a_cursor() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let input = io::Cursor::new(format!("{}y\n{}n\n", X_WINS, TIE).into_bytes());
+        let mut output = io::Cursor::new(Vec::new());
+        game.start_with(input, &mut output).unwrap();
+        let output = String::from_utf8(output.into_inner()).unwrap();
+        assert_eq!(
+            (game.score.player, game.score.cpu, game.score.tie),
+            (1, 0, 1)
+        );
+        assert!(output.contains("** You win! **"));
+        assert!(output.contains("** Tie! **"), "{}", output);
+    }
+
+    #[test]
+    fn sessions_read_windows_input_and_print_no_escapes() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (summary, output) = session(&mut game, &X_WINS.replace('\n', "\r\n"));
+        assert_eq!(summary.score.player, 1);
+        assert!(!output.contains('\x1b'));
+    }
+
+    // Refuses every write, like a closed pipe
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failing_output_ends_the_session() {
+        let settings = Settings {
+            auto_rematch: true,
+            ..Settings::default()
+        };
+        let mut game = pinned(Rules::default(), settings);
+        let input = X_WINS.repeat(100);
+        let err = game.start_with(input.as_bytes(), Closed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(game.score.player + game.score.cpu + game.score.tie, 0);
+    }
+
+    #[test]
+    fn session_summary_reports_how_it_went() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (summary, _) = session(&mut game, &format!("{0}y\n{0}y\n{1}n\n", X_WINS, TIE));
+        assert_eq!(summary.score, game.score);
+        assert_eq!(summary.rounds, 3);
+        assert_eq!((summary.score.player, summary.score.tie), (2, 1));
+    }
+
+    #[test]
+    fn session_without_rounds_has_an_empty_summary() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (summary, _) = session(&mut game, "");
+        assert_eq!(summary.rounds, 0);
+        assert_eq!(summary.score, Score::default());
+    }
+
+    // Text made of pieces that mean something to the parsers, and some that don't
+    fn noise(rng: &mut crate::ai::XorShift, pieces: &[&str]) -> String {
+        use crate::ai::Tiebreak;
+        (0..rng.below(8))
+            .map(|_| pieces[rng.below(pieces.len())])
+            .collect()
+    }
+
+    #[test]
+    fn parse_move_takes_any_text_and_prints_back() {
+        let pieces = [
+            "0",
+            "1",
+            "4",
+            "9",
+            "26",
+            "99999999999999999999",
+            ",",
+            "@",
+            " at ",
+            "x",
+            "O",
+            " ",
+            "\r",
+            "\n",
+            "tl",
+            "center",
+            "-",
+            "é",
+            "٣",
+            "\u{0}",
+            "🙂",
+        ];
+        let rules = [
+            Rules::default(),
+            Rules::gravity(6, 7),
+            Rules::cube(),
+            wild_rules(),
+            numerical_game().rules,
+        ];
+        let mut rng = crate::ai::XorShift::new(125);
+        for _ in 0..20_000 {
+            let input = noise(&mut rng, &pieces);
+            for rules in &rules {
+                let parsed = match parse_move(&input, rules, Numbering::ZeroBased) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+                if input.contains(',') {
+                    assert!(parsed.index < rules.cells(), "{:?}", input);
+                }
+                assert_eq!(parsed.mark.is_some(), rules.variant == Variant::Wild);
+                assert!(parsed.digit.is_none_or(|digit| (1..=9).contains(&digit)));
+                // Written back as a plain index it reads the same
+                let text = match (parsed.mark, parsed.digit) {
+                    (Some(mark), _) => format!("{}{:?}", parsed.index, mark),
+                    (_, Some(digit)) => format!("{}@{}", digit, parsed.index),
+                    _ => parsed.index.to_string(),
+                };
+                assert_eq!(
+                    parse_move(&text, rules, Numbering::ZeroBased),
+                    Some(parsed),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
+
+    // Plays each index in turn, letting the CPU answer, and returns the phase after the last
+    fn play_through(game: &mut Game<StepRng>, moves: &str) -> Phase {
+        for index in moves.lines() {
+            game.pick_player(at(index.parse().unwrap(), State::X), false)
+                .unwrap();
+            game.pick_cpu();
+        }
+        game.phase()
+    }
+
+    #[test]
+    fn moves_need_a_round() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        assert!(matches!(
+            game.pick_player(at(4, State::X), false),
+            Err(PickError::MovesMapNotInitialized)
+        ));
+        game.reset();
+        assert_eq!(game.phase(), Phase::AwaitingPlayer);
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_refused() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        game.pick_player(at(4, State::X), false).unwrap();
+        assert_eq!(game.phase(), Phase::AwaitingCpu);
+        assert!(matches!(
+            game.pick_player(at(8, State::X), false),
+            Err(PickError::NotYourTurn)
+        ));
+        game.pick_cpu();
+        assert_eq!(game.moves_map.unwrap()[0], State::O);
+        assert_eq!(game.phase(), Phase::AwaitingPlayer);
+        assert_eq!(
+            play_through(&mut game, "8\n6\n"),
+            Phase::RoundOver(Outcome::CpuWin)
+        );
+        assert!(matches!(
+            game.pick_player(at(3, State::X), false),
+            Err(PickError::WrongPhase)
+        ));
+        game.reset();
+        assert_eq!(game.phase(), Phase::AwaitingPlayer);
+    }
+
+    #[test]
+    fn boards_where_both_sides_won_are_refused_and_never_scored() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        let board: Board = "XXXOOO...".parse().unwrap();
+        let both = Position::new(board, State::X, &Rules::default());
+        assert_eq!(
+            game.load_position(both),
+            Err(GameError::Position(IllegalPosition::BothWon))
+        );
+        // Only a rules bug could still put one on the board
+        game.set_position(both);
+        assert_eq!(game.status(), Status::Invalid);
+        assert_eq!(game.phase(), Phase::Invalid);
+        assert!(matches!(
+            game.pick_player(at(6, State::X), false),
+            Err(PickError::InvalidBoard)
+        ));
+        assert_eq!(game.resign(), Err(PickError::InvalidBoard));
+        assert_eq!(game.score(), Score::default());
+    }
+
+    #[test]
+    fn loaded_position_with_the_cpu_to_move_awaits_it() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let board: Board = "X........".parse().unwrap();
+        game.load_position(Position::new(board, State::O, &Rules::default()))
+            .unwrap();
+        assert_eq!(game.phase(), Phase::AwaitingCpu);
+        assert!(matches!(
+            game.pick_player(at(4, State::X), false),
+            Err(PickError::NotYourTurn)
+        ));
+    }
+
+    #[test]
+    fn every_round_result_ends_the_round() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        assert_eq!(
+            play_through(&mut game, X_WINS),
+            Phase::RoundOver(Outcome::PlayerWin)
+        );
+        game.reset();
+        assert_eq!(play_through(&mut game, TIE), Phase::RoundOver(Outcome::Tie));
+        assert!(matches!(
+            game.pick_player(at(0, State::X), false),
+            Err(PickError::WrongPhase)
+        ));
+    }
+
+    #[test]
+    fn turns_alternate_with_the_cpu() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        assert_eq!(game.whose_turn(), State::X);
+        game.pick_player(at(4, State::X), false).unwrap();
+        assert_eq!(game.whose_turn(), State::O);
+        game.pick_cpu();
+        assert_eq!(game.whose_turn(), State::X);
+        let board = game.moves_map.unwrap();
+        assert_eq!((board[4], board[0]), (State::X, State::O));
+    }
+
+    #[test]
+    fn second_move_in_a_row_is_refused() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        game.pick_player(at(4, State::X), false).unwrap();
+        assert!(matches!(
+            game.pick_player(at(0, State::X), false),
+            Err(PickError::NotYourTurn)
+        ));
+        assert_eq!(game.moves_map.unwrap().count(State::X), 1);
+        assert_eq!(game.whose_turn(), State::O);
+    }
+
+    #[test]
+    fn alternating_opener_sets_the_turn_each_round() {
+        let settings = Settings {
+            alternate_opener: true,
+            ..Settings::default()
+        };
+        let mut game = pinned(Rules::default(), settings);
+        session(&mut game, &format!("{}y\n", X_WINS));
+        // The Cpu opened the second round and left it to the player
+        assert_eq!(game.moves_map.unwrap().count(State::O), 1);
+        assert_eq!(game.whose_turn(), State::X);
+        assert_eq!(game.phase(), Phase::AwaitingPlayer);
+    }
+
+    #[test]
+    fn empty_score_table_has_no_shares() {
+        let table = Score::default().table("You", "CPU");
+        assert_eq!(table, "You  0     -\nCPU  0     -\nTie  0     -\n");
+    }
+
+    #[test]
+    fn score_table_aligns_counts_and_shares() {
+        let score = Score {
+            player: 10,
+            cpu: 3,
+            tie: 7,
+        };
+        let table = score.table("Alice", "CPU");
+        assert_eq!(table, "Alice  10   50%\nCPU     3   15%\nTie     7   35%\n");
+    }
+
+    #[test]
+    fn session_score_is_printed_as_a_table() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, &format!("{}y\n", X_WINS));
+        assert!(
+            output.contains("You  1  100%\nCpu  0    0%\nTie  0    0%\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn two_players_score_is_labelled_by_mark() {
+        let mut game = two_player_session(Settings::default());
+        let (summary, output) = session(&mut game, &format!("{}y\n", X_TAKES_THE_TOP_ROW));
+        assert!(
+            output.contains("X    1  100%\nO    0    0%\nTie  0    0%\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Time this round: X "), "{}", output);
+        assert_eq!(summary.sides, ["X", "O"]);
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (summary, _) = session(&mut game, "4\n");
+        assert_eq!(summary.sides, ["You", "Cpu"]);
+    }
+
+    #[test]
+    fn eval_is_exact_on_small_boards() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        assert_eq!(game.eval_line().as_deref(), Some("eval: draw (best play)"));
+        play_through(&mut game, "4\n2\n");
+        assert_eq!(
+            game.eval_line().as_deref(),
+            Some("eval: you win in 1 (best play)")
+        );
+        game.pick_player(at(6, State::X), false).unwrap();
+        assert_eq!(game.eval_line(), None);
+    }
+
+    #[test]
+    fn eval_plays_out_large_boards_from_the_seed() {
+        let rules = Rules {
+            rows: 4,
+            cols: 4,
+            ..Rules::default()
+        };
+        let line = |seed| {
+            let rng = GameRng::seeded(RngKind::ChaCha8, seed);
+            let mut game = Game::with_rng(rules, Settings::default(), rng);
+            game.reset();
+            game.eval_line().unwrap()
+        };
+        let first = line(7);
+        assert_eq!(first, line(7));
+        let shares: Vec<u32> = first
+            .strip_prefix("playouts: you ")
+            .unwrap()
+            .split(['%', '|'])
+            .filter_map(|part| part.trim().rsplit(' ').next()?.parse().ok())
+            .collect();
+        assert_eq!(shares.len(), 3, "{}", first);
+        let total: u32 = shares.iter().sum();
+        assert!((98..=100).contains(&total), "{}", first);
+    }
+
+    #[test]
+    fn eval_is_off_until_asked_for() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, "4\n");
+        assert!(!output.contains("eval:"), "{}", output);
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, "eval\n4\n");
+        assert!(output.contains("Evaluation is on"), "{}", output);
+        assert!(output.contains("eval: "), "{}", output);
+    }
+
+    #[test]
+    fn durations_print_in_tenths_of_seconds() {
+        assert_eq!(seconds(Duration::ZERO), "0.0s");
+        assert_eq!(seconds(Duration::from_millis(4249)), "4.2s");
+        assert_eq!(seconds(Duration::from_millis(61_050)), "61.0s");
+        let times = TurnTimes {
+            player: Duration::from_millis(1500),
+            cpu: Duration::from_millis(200),
+        };
+        assert_eq!(times.line("You", "Cpu"), "You 1.5s, Cpu 0.2s");
+        assert_eq!(times.line("X", "O"), "X 1.5s, O 0.2s");
+    }
+
+    #[test]
+    fn round_times_restart_and_session_times_add_up() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        play_through(&mut game, "4\n8\n6\n");
+        let round = game.round_times();
+        assert_eq!(game.session_times(), round);
+        assert!(round.cpu > Duration::ZERO);
+        game.reset();
+        assert_eq!(game.round_times(), TurnTimes::default());
+        play_through(&mut game, "4\n");
+        assert_eq!(game.session_times().cpu, round.cpu + game.round_times().cpu);
+    }
+
+    #[test]
+    fn every_turn_reports_its_time() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (summary, output) = session(&mut game, "4\n");
+        assert!(output.contains("You took "), "{}", output);
+        assert!(output.contains("Cpu took "), "{}", output);
+        assert_eq!(summary.times, game.session_times());
+        let mut game = two_player_session(Settings::default());
+        let (_, output) = session(&mut game, "4\n0\n");
+        assert!(
+            output.contains("X took ") && output.contains("O took "),
+            "{}",
+            output
+        );
+    }
+
+    fn confirming(settings: Settings) -> Settings {
+        Settings {
+            confirm_moves: true,
+            ..settings
+        }
+    }
+
+    #[test]
+    fn a_confirmed_move_is_placed() {
+        let mut game = pinned(Rules::default(), confirming(Settings::default()));
+        let (_, output) = session(&mut game, "4\ny\n");
+        // The move is shown on the board, marked as tentative, before it is asked about
+        assert!(
+            output.contains(concat!(
+                "You entered: 4\n",
+                ".  .  .  \n",
+                ".  X? .  \n",
+                ".  .  .  \n",
+                "Place X at 4? (y/n)\n",
+            )),
+            "{}",
+            output
+        );
+        let board = game.moves_map.unwrap();
+        assert_eq!((board[4], board.count(State::O)), (State::X, 1));
+    }
+
+    #[test]
+    fn a_declined_move_asks_again_without_using_the_turn() {
+        let mut game = pinned(Rules::default(), confirming(Settings::default()));
+        let (_, output) = session(&mut game, "4\nmaybe\nn\n0\ny\n");
+        assert!(output.contains("Please answer y or n\n"), "{}", output);
+        assert!(output.contains("Move discarded\n"), "{}", output);
+        assert!(output.contains("Place X at 0? (y/n)\n"), "{}", output);
+        let board = game.moves_map.unwrap();
+        assert_eq!(board[4], State::Empty);
+        assert_eq!(board[0], State::X);
+        assert_eq!((board.count(State::X), board.count(State::O)), (1, 1));
+    }
+
+    #[test]
+    fn end_of_input_at_the_confirmation_places_nothing() {
+        let mut game = pinned(Rules::default(), confirming(Settings::default()));
+        let (summary, output) = session(&mut game, "4\n");
+        assert!(output.contains("Place X at 4? (y/n)\n"), "{}", output);
+        assert!(output.contains("Move discarded\n"), "{}", output);
+        assert!(output.contains("** Thanks for playing! **"), "{}", output);
+        assert_eq!(game.moves_map.unwrap().count(State::Empty), 9);
+        assert_eq!(summary.rounds, 0);
+    }
+
+    #[test]
+    fn confirmation_is_switched_mid_session() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let input = "confirm on\n4\ny\nconfirm off\n8\nconfirm maybe\n";
+        let (_, output) = session(&mut game, input);
+        assert!(output.contains("Move confirmation is on\n"), "{}", output);
+        assert!(output.contains("Move confirmation is off\n"), "{}", output);
+        assert!(
+            output.contains("Use confirm on or confirm off\n"),
+            "{}",
+            output
+        );
+        // Only the move made while it was on was asked about
+        assert_eq!(output.matches("? (y/n)").count(), 1, "{}", output);
+        let board = game.moves_map.unwrap();
+        assert_eq!((board[4], board[8]), (State::X, State::X));
+        assert!(!game.settings.confirm_moves);
+    }
+
+    #[test]
+    fn explanations_read_as_sentences() {
+        let decision = |index, reason| MoveDecision {
+            index,
+            reason,
+            search: None,
+        };
+        let diagonal = crate::board::Line {
+            start: 0,
+            step: 4,
+            len: 3,
+        };
+        assert_eq!(
+            explain(decision(6, Reason::Win), Numbering::ZeroBased),
+            "took winning move at 6"
+        );
+        assert_eq!(
+            explain(
+                decision(8, Reason::Block { line: diagonal }),
+                Numbering::ZeroBased
+            ),
+            "blocked your line 0-4-8"
+        );
+        assert_eq!(
+            explain(
+                decision(8, Reason::Block { line: diagonal }),
+                Numbering::OneBased
+            ),
+            "blocked your line 1-5-9"
+        );
+        assert_eq!(
+            explain(decision(2, Reason::Fork), Numbering::ZeroBased),
+            "created a fork at 2"
+        );
+        let search = Reason::Search { score: 0, plies: 4 };
+        assert_eq!(
+            explain(decision(4, search), Numbering::ZeroBased),
+            "played 4, best by search (draw in 4)"
+        );
+    }
+
+    // A game after the player won a round and two moves into the next
+    #[cfg(feature = "serde")]
+    const STATE_FIXTURE: &str = include_str!("../tests/fixtures/game-state.json");
+
+    #[cfg(feature = "serde")]
+    fn saved_state() -> GameState {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.new_round();
+        for index in [4, 2, 6] {
+            game.submit(at(index, State::X)).unwrap();
+        }
+        game.new_round();
+        game.submit(at(4, State::X)).unwrap();
+        game.state()
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn states_and_scores_read_well_in_json() {
+        let marks = [State::X, State::O, State::Empty];
+        assert_eq!(
+            serde_json::to_string(&marks).unwrap(),
+            r#"["x","o","empty"]"#
+        );
+        let score = Score {
+            player: 3,
+            cpu: 1,
+            tie: 0,
+        };
+        let json = serde_json::to_string(&score).unwrap();
+        assert_eq!(json, r#"{"player":3,"cpu":1,"tie":0}"#);
+        assert_eq!(serde_json::from_str::<Score>(&json).unwrap(), score);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn game_state_round_trips_in_json_and_bincode() {
+        let state = saved_state();
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<GameState>(&json).unwrap(), state);
+        let bytes = bincode::serialize(&state).unwrap();
+        assert_eq!(bincode::deserialize::<GameState>(&bytes).unwrap(), state);
+        let restored = Game::from_state(state.clone(), GameRng::seeded(RngKind::ChaCha8, 1));
+        assert_eq!(restored.unwrap().state(), state);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn game_state_format_is_unchanged() {
+        let state = saved_state();
+        assert_eq!(
+            serde_json::from_str::<GameState>(STATE_FIXTURE).unwrap(),
+            state
+        );
+        assert_eq!(
+            serde_json::to_string_pretty(&state).unwrap() + "\n",
+            STATE_FIXTURE
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    fn autosave_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ttt-{}-{}.json", name, std::process::id()))
+    }
+
+    // The first free cell of a game's board
+    #[cfg(feature = "serde")]
+    fn free_cell(game: &Game) -> usize {
+        let board = game.board().unwrap();
+        (0..9).find(|&index| board[index] == State::Empty).unwrap()
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn paused_game_resumes_with_the_same_cpu() {
+        let path = autosave_path("pause");
+        let mut paused = seeded(Rules::default(), Settings::default());
+        paused.set_autosave(path.clone());
+        let (_, output) = session(&mut paused, "4\npause\n");
+        assert!(output.contains("Game paused"), "{}", output);
+        let saved = SavedGame::load(&path);
+        fs::remove_file(&path).unwrap();
+        let saved = saved.unwrap();
+        assert!(saved.rng_state.is_some());
+        let mut resumed = Game::from_state(saved.state.clone(), saved.rng()).unwrap();
+        assert_eq!(resumed.board(), paused.board());
+        assert_eq!(resumed.whose_turn(), State::X);
+
+        // The same game played straight through
+        let next = free_cell(&resumed);
+        let mut straight = seeded(Rules::default(), Settings::default());
+        session(&mut straight, &format!("4\n{}\n", next));
+        session(&mut resumed, &format!("{}\n", next));
+        assert_eq!(resumed.board(), straight.board());
+        assert_eq!(resumed.board().unwrap().count(State::O), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn checkpoint_keeps_the_rng_where_it_was() {
+        let path = autosave_path("checkpoint");
+        let mut game = seeded(Rules::default(), Settings::default());
+        game.set_autosave(path.clone());
+        game.new_round();
+        game.submit(at(0, State::X)).unwrap();
+        let saved = SavedGame::load(&path);
+        fs::remove_file(&path).unwrap();
+        let saved = saved.unwrap();
+        assert!(saved.rng_state.is_some());
+        let mut resumed = Game::from_state(saved.state.clone(), saved.rng()).unwrap();
+        let next = free_cell(&game);
+        game.submit(at(next, State::X)).unwrap();
+        resumed.submit(at(next, State::X)).unwrap();
+        assert_eq!(resumed.board(), game.board());
+        // Saving drew nothing from the RNG, the seeded game plays as if it weren't saved
+        let mut unsaved = seeded(Rules::default(), Settings::default());
+        unsaved.new_round();
+        unsaved.submit(at(0, State::X)).unwrap();
+        unsaved.submit(at(next, State::X)).unwrap();
+        assert_eq!(unsaved.board(), game.board());
+    }
+
+    // Keeps every event of a game for the test to look at
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<Event>>>);
+
+    impl Observer for Events {
+        fn on_event(&mut self, event: &Event) {
+            self.0.lock().unwrap().push(*event);
+        }
+    }
+
+    impl Events {
+        fn match_ends(&self) -> Vec<Event> {
+            let events = self.0.lock().unwrap();
+            events
+                .iter()
+                .filter(|event| matches!(event, Event::MatchEnd { .. }))
+                .copied()
+                .collect()
+        }
+
+        // The mark of the first move of round `round`
+        fn opener(&self, round: u32) -> Option<State> {
+            let events = self.0.lock().unwrap();
+            let start = events
+                .iter()
+                .position(|event| *event == Event::RoundStart { round })?;
+            events[start..].iter().find_map(|event| match event {
+                Event::Move { mark, .. } => Some(*mark),
+                _ => None,
+            })
+        }
+    }
+
+    // O takes the middle row
+    const O_WINS: &str = "0\n3\n1\n4\n8\n5\n";
+
+    // A full board without a line, whoever opens
+    const FULL_TIE: &str = "0\n4\n8\n2\n6\n3\n5\n7\n1\n";
+
+    fn best_of_two(sudden_death: Option<u16>) -> (Game<StepRng>, Events) {
+        let mut game = two_player_session(Settings {
+            best_of: Some(2),
+            sudden_death,
+            ..Settings::default()
+        });
+        let events = Events::default();
+        game.add_observer(Box::new(events.clone()));
+        (game, events)
+    }
+
+    #[test]
+    fn a_level_match_goes_to_sudden_death() {
+        let (mut game, events) = best_of_two(Some(3));
+        let input = format!("{}{}", X_TAKES_THE_TOP_ROW, O_WINS);
+        let (_, output) = session(&mut game, &input);
+        assert_eq!(game.match_phase(), MatchPhase::TieBreak(0));
+        assert!(
+            output.contains("** Sudden death, round 1: the next decisive round wins the match **"),
+            "{}",
+            output
+        );
+        assert!(!output.contains("won the match"), "{}", output);
+        assert!(!output.contains("drawn"), "{}", output);
+        assert!(events.match_ends().is_empty());
+    }
+
+    #[test]
+    fn the_first_decisive_tiebreak_round_wins_the_match() {
+        let (mut game, events) = best_of_two(Some(3));
+        let input = format!(
+            "{}{}{}{}n\n",
+            X_TAKES_THE_TOP_ROW, O_WINS, FULL_TIE, X_TAKES_THE_TOP_ROW
+        );
+        let (summary, output) = session(&mut game, &input);
+        assert_eq!(summary.rounds, 4);
+        // Sudden-death rounds take turns opening, O first
+        assert_eq!(events.opener(3), Some(State::O));
+        assert_eq!(events.opener(4), Some(State::X));
+        assert!(
+            output.contains("** Sudden death, round 2: the next decisive round wins the match **"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("** X won the match 2-1 after 2 sudden-death rounds! **"),
+            "{}",
+            output
+        );
+        assert_eq!(
+            events.match_ends(),
+            [Event::MatchEnd {
+                player: 2,
+                cpu: 1,
+                tiebreak_rounds: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn sudden_death_stops_drawn_at_its_cap() {
+        let (mut game, events) = best_of_two(Some(2));
+        let input = format!(
+            "{}{}{}{}n\n",
+            X_TAKES_THE_TOP_ROW, O_WINS, FULL_TIE, FULL_TIE
+        );
+        let (summary, output) = session(&mut game, &input);
+        assert_eq!(summary.rounds, 4);
+        assert_eq!(output.matches("** Sudden death, round").count(), 2);
+        assert!(
+            output.contains("** The match is drawn 1-1 after 2 sudden-death rounds **"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Start a new match?"), "{}", output);
+        assert_eq!(
+            events.match_ends(),
+            [Event::MatchEnd {
+                player: 1,
+                cpu: 1,
+                tiebreak_rounds: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn without_sudden_death_a_level_match_is_drawn() {
+        let (mut game, events) = best_of_two(None);
+        let input = format!("{}{}n\n", X_TAKES_THE_TOP_ROW, O_WINS);
+        let (_, output) = session(&mut game, &input);
+        assert!(!output.contains("Sudden death"), "{}", output);
+        assert!(
+            output.contains("** The match is drawn 1-1 **"),
+            "{}",
+            output
+        );
+        assert_eq!(
+            events.match_ends(),
+            [Event::MatchEnd {
+                player: 1,
+                cpu: 1,
+                tiebreak_rounds: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn won_rounds_are_snapshotted() {
+        let dir = std::env::temp_dir();
+        let template = dir.join(format!("ttt-snapshot-{}-{{round}}.svg", std::process::id()));
+        let mut game = two_player_session(Settings::default());
+        game.set_snapshot_on_win(template.to_string_lossy().into_owned());
+        session(
+            &mut game,
+            &format!("{}y\n{}n\n", X_TAKES_THE_TOP_ROW, FULL_TIE),
+        );
+        let round = |n: u32| {
+            template
+                .to_string_lossy()
+                .replace("{round}", &n.to_string())
+        };
+        let picture = std::fs::read_to_string(round(1)).unwrap();
+        std::fs::remove_file(round(1)).unwrap();
+        assert_eq!(picture.matches("class=\"highlight\"").count(), 3);
+        assert_eq!(picture.matches("class=\"x\"").count(), 3);
+        // The tied round has no picture
+        assert!(std::fs::metadata(round(2)).is_err());
+    }
+
+    #[test]
+    fn parse_move_reads_digits_of_other_scripts() {
+        let rules = Rules::default();
+        let plain = |index| Move {
+            index,
+            mark: None,
+            digit: None,
+        };
+        // Arabic-Indic, Eastern Arabic-Indic and Devanagari four
+        for four in ["٤", "۴", "४", " ٤\r"] {
+            assert_eq!(
+                parse_move(four, &rules, Numbering::ZeroBased),
+                Some(plain(4)),
+                "{}",
+                four
+            );
+        }
+        assert_eq!(parse_move("٩", &rules, Numbering::OneBased), Some(plain(8)));
+        assert_eq!(
+            parse_move("٤x", &wild_rules(), Numbering::ZeroBased),
+            Some(at(4, State::X))
+        );
+        let numerical = Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        };
+        assert_eq!(
+            parse_move("٥@۴", &numerical, Numbering::ZeroBased),
+            Some(digit(5, 4))
+        );
+        let cube = Rules::cube();
+        assert_eq!(
+            parse_move("١,٢,٠", &cube, Numbering::ZeroBased),
+            Some(plain(15))
+        );
+        assert_eq!(
+            parse_move("१,२,०", &cube, Numbering::ZeroBased),
+            Some(plain(15))
+        );
+
+        // A number mixing scripts still counts as long as each character is a digit
+        let big = Rules {
+            rows: 5,
+            cols: 5,
+            win_len: 4,
+            ..Rules::default()
+        };
+        assert_eq!(
+            parse_move("١۲", &big, Numbering::ZeroBased),
+            Some(plain(12))
+        );
+        assert_eq!(
+            parse_move("2४", &big, Numbering::ZeroBased),
+            Some(plain(24))
+        );
+        for not_digits in ["٤.٥", "٤a", "-٤", "Ⅳ", "４٤x"] {
+            assert_eq!(
+                parse_move(not_digits, &rules, Numbering::ZeroBased),
+                None,
+                "{}",
+                not_digits
+            );
+        }
+        // Fullwidth digits as typed on CJK keyboards
+        assert_eq!(
+            parse_move("４", &rules, Numbering::ZeroBased),
+            Some(plain(4))
+        );
+    }
+
+    #[test]
+    fn every_cell_name_and_alias_is_a_move() {
+        let names = [
+            ("top-left", "tl", 0),
+            ("top", "t", 1),
+            ("top-right", "tr", 2),
+            ("left", "l", 3),
+            ("center", "c", 4),
+            ("right", "r", 5),
+            ("bottom-left", "bl", 6),
+            ("bottom", "b", 7),
+            ("bottom-right", "br", 8),
+        ];
+        let rules = Rules::default();
+        let plain = |index| Move {
+            index,
+            mark: None,
+            digit: None,
+        };
+        for numbering in [Numbering::ZeroBased, Numbering::OneBased] {
+            for (name, alias, index) in names {
+                let spaced = name.replace('-', " ").to_uppercase();
+                let underscored = name.replace('-', "_");
+                for input in [name, alias, &alias.to_uppercase(), &spaced, &underscored] {
+                    assert_eq!(
+                        parse_move(input, &rules, numbering),
+                        Some(plain(index)),
+                        "{}",
+                        input
+                    );
+                }
+            }
+        }
+        for center in ["centre", "middle", "Middle"] {
+            assert_eq!(
+                parse_move(center, &rules, Numbering::ZeroBased),
+                Some(plain(4))
+            );
+        }
+        assert_eq!(
+            parse_move("top right o", &wild_rules(), Numbering::ZeroBased),
+            Some(at(2, State::O))
+        );
+        let numerical = Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        };
+        assert_eq!(
+            parse_move("5 at center", &numerical, Numbering::ZeroBased),
+            Some(digit(5, 4))
+        );
+        for unknown in ["top-center", "topleft", "lt", "centr", "upper-left"] {
+            assert_eq!(
+                parse_move(unknown, &rules, Numbering::ZeroBased),
+                None,
+                "{}",
+                unknown
+            );
+        }
+    }
+
+    #[test]
+    fn cell_names_are_refused_off_the_3x3_grid() {
+        let big = Rules {
+            rows: 4,
+            cols: 4,
+            ..Rules::default()
+        };
+        let boards = [
+            (
+                big,
+                "Names like center are for 3x3 boards, please enter an index",
+            ),
+            (Rules::gravity(3, 3), "Please enter a column number"),
+            (
+                Rules::cube(),
+                "Names like center are for 3x3 boards, please enter layer,row,col",
+            ),
+        ];
+        for (rules, hint) in boards {
+            for name in ["center", "tl", "Bottom Right"] {
+                assert_eq!(parse_move(name, &rules, Numbering::ZeroBased), None);
+            }
+            let mut game = seeded(
+                rules,
+                Settings {
+                    two_players: true,
+                    ..Settings::default()
+                },
+            );
+            let (_, output) = session(&mut game, "center\n");
+            assert!(output.contains(hint), "{}", output);
+            assert!(!output.contains("a name like top-left"));
+        }
+        let mut game = two_player_session(Settings::default());
+        let (_, output) = session(&mut game, "center\n");
+        assert!(output.contains("Choose index(0 to 8) or a name like top-left:"));
+        assert!(output.contains("You entered: center"));
+    }
+
+    fn numbered_session(rules: Rules, numbering: Numbering, input: &str) -> (Game, String) {
+        let mut game = seeded(
+            rules,
+            Settings {
+                two_players: true,
+                numbering,
+                ..Settings::default()
+            },
+        );
+        let (_, output) = session(&mut game, input);
+        (game, output)
+    }
+
+    #[test]
+    fn numbering_sets_the_range_of_cells() {
+        let rules = Rules::default();
+        let (game, output) = numbered_session(rules, Numbering::OneBased, "0\n10\n1\n9\n");
+        assert!(output.contains("Choose index(1 to 9) or a name like top-left:"));
+        assert!(output.contains("Invalid index 0, must be between 1 and 9!"));
+        assert!(output.contains("Invalid index 10, must be between 1 and 9!"));
+        let board = game.board().unwrap();
+        assert_eq!((board[0], board[8]), (State::X, State::O));
+
+        let (game, output) = numbered_session(rules, Numbering::ZeroBased, "9\n0\n8\n");
+        assert!(output.contains("Choose index(0 to 8) or a name like top-left:"));
+        assert!(output.contains("Invalid index 9, must be between 0 and 8!"));
+        let board = game.board().unwrap();
+        assert_eq!((board[0], board[8]), (State::X, State::O));
+
+        let (_, output) = numbered_session(Rules::gravity(3, 4), Numbering::OneBased, "5\n4\n");
+        assert!(output.contains("Choose column(1 to 4):"));
+        assert!(output.contains("Invalid index 5, must be between 1 and 4!"));
+
+        let (game, _) = numbered_session(Rules::cube(), Numbering::OneBased, "1,1,1\n3,3,3\n");
+        let board = game.board().unwrap();
+        assert_eq!((board[0], board[26]), (State::X, State::O));
+    }
+
+    #[test]
+    fn numbering_switches_mid_round() {
+        let (game, output) = numbered_session(
+            Rules::default(),
+            Numbering::ZeroBased,
+            "numbering one-based\n9\nnumbering 0\n0\nnumbering both\n",
+        );
+        assert!(output.contains("Cells are numbered one-based"));
+        assert!(output.contains("Cells are numbered zero-based"));
+        assert!(output.contains("Use numbering zero-based or numbering one-based"));
+        assert_eq!(game.settings().numbering, Numbering::ZeroBased);
+        let board = game.board().unwrap();
+        assert_eq!((board[8], board[0]), (State::X, State::O));
+    }
+
+    // Lets a blocked reader see the end of input once a move was played for the player
+    struct EndAfterAutoMove(Option<mpsc::Sender<()>>);
+
+    impl Observer for EndAfterAutoMove {
+        fn on_event(&mut self, event: &Event) {
+            if let Event::Move { auto: true, .. } = event {
+                self.0 = None;
+            }
+        }
+    }
+
+    #[test]
+    fn idle_players_get_the_hint_move_played_for_them() {
+        let mut game = seeded(
+            Rules::default(),
+            Settings {
+                auto_play: Some(1),
+                ..Settings::default()
+            },
+        );
+        let events = Events::default();
+        game.add_observer(Box::new(events.clone()));
+        let (release, released) = mpsc::channel();
+        game.add_observer(Box::new(EndAfterAutoMove(Some(release))));
+        // Nothing is typed until the input ends
+        let reader = LineReader::spawn(move |_| {
+            let _ = released.recv();
+            Ok(0)
+        });
+        let mut output = Vec::new();
+        game.play(&mut Console {
+            input: io::empty(),
+            output: &mut output,
+            colors: false,
+            lines: Some(reader),
+            debounce: false,
+            moved: false,
+        })
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("No move for 1s, playing one for you\n"),
+            "{}",
+            output
+        );
+        let moves: Vec<Event> = events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, Event::Move { .. }))
+            .copied()
+            .collect();
+        let index = match moves[..] {
+            [Event::Move {
+                mark: State::X,
+                index,
+                human: true,
+                auto: true,
+                ..
+            }, Event::Move {
+                mark: State::O,
+                human: false,
+                auto: false,
+                ..
+            }] => index,
+            _ => panic!("{:?}", moves),
+        };
+        // Labelled as played for the player, and the round goes on with the Cpu's reply
+        let played = format!("Played for you: {} (auto)", index);
+        assert!(output.contains(&played), "{}", output);
+        assert!(game.round_moves()[0].auto);
+        assert!(!game.round_moves()[1].auto);
+        assert_eq!(game.board().unwrap()[index], State::X);
+        assert_eq!(game.status(), Status::InProgress);
+    }
+
+    // Gives the sides and the moves fixed times, so the round's summary can be compared
+    // whole: move n was played n * 1.5s into the round
+    fn fix_times<R: Rng + 'static>(game: &mut Game<R>) {
+        game.round_times = TurnTimes {
+            player: Duration::from_millis(4500),
+            cpu: Duration::from_millis(3000),
+        };
+        for (number, played) in game.round_moves.iter_mut().enumerate() {
+            played.elapsed = Duration::from_millis(1500 * (number as u64 + 1));
+        }
+    }
+
+    #[test]
+    fn round_summary_of_a_won_round() {
+        let mut game = two_player_session(Settings::default());
+        session(&mut game, &format!("{}n\n", X_TAKES_THE_TOP_ROW));
+        fix_times(&mut game);
+        assert_eq!(
+            game.round_summary(false),
+            concat!(
+                "== Round 1 ==\n",
+                "X* X* X* \n",
+                "O  O  .  \n",
+                ".  .  .  \n",
+                "Result: X wins\n",
+                "Moves: 1. X 0 (1.5s)  2. O 3 (3.0s)  3. X 1 (4.5s)  4. O 4 (6.0s)  5. X 2 (7.5s)\n",
+                "Time this round: X 4.5s, O 3.0s\n",
+                "X    1  100%\n",
+                "O    0    0%\n",
+                "Tie  0    0%\n",
+            )
+        );
+    }
+
+    #[test]
+    fn round_summary_against_the_cpu() {
+        let mut game = pinned(
+            Rules::default(),
+            Settings {
+                numbering: Numbering::OneBased,
+                ..Settings::default()
+            },
+        );
+        session(&mut game, "5\n3\n7\nn\n");
+        fix_times(&mut game);
+        assert_eq!(
+            game.round_summary(false),
+            concat!(
+                "== Round 1 ==\n",
+                "O  O  X* \n",
+                ".  X* .  \n",
+                "X* .  .  \n",
+                "Result: You win\n",
+                "Moves: 1. X 5 (1.5s)  2. O 1 (3.0s)  3. X 3 (4.5s)  4. O 2 (6.0s)  5. X 7 (7.5s)\n",
+                "Time this round: You 4.5s, Cpu 3.0s\n",
+                "You  1  100%\n",
+                "Cpu  0    0%\n",
+                "Tie  0    0%\n",
+            )
+        );
+    }
+
+    #[test]
+    fn round_summary_of_a_numerical_round() {
+        let rules = Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        };
+        let mut game = pinned(
+            rules,
+            Settings {
+                two_players: true,
+                ..Settings::default()
+            },
+        );
+        session(&mut game, "1@0\n2@4\n5@1\n4@3\n9@2\nn\n");
+        fix_times(&mut game);
+        assert_eq!(
+            game.round_summary(false),
+            concat!(
+                "== Round 1 ==\n",
+                "1* 5* 9* \n",
+                "4  2  .  \n",
+                ".  .  .  \n",
+                "Result: X wins\n",
+                "Moves: 1. X 1@0 (1.5s)  2. O 2@4 (3.0s)  3. X 5@1 (4.5s)  4. O 4@3 (6.0s)  5. X 9@2 (7.5s)\n",
+                "Time this round: X 4.5s, O 3.0s\n",
+                "X    1  100%\n",
+                "O    0    0%\n",
+                "Tie  0    0%\n",
+            )
+        );
+    }
+
+    #[test]
+    fn round_summary_comes_before_the_rematch_prompt() {
+        let mut game = two_player_session(Settings::default());
+        let (_, output) = session(&mut game, &format!("{}n\n", X_TAKES_THE_TOP_ROW));
+        let summary = output.find("== Round 1 ==").unwrap();
+        assert!(output[summary..].contains("Result: X wins\nMoves: 1. X 0 ("));
+        assert!(summary < output.find("Play again?").unwrap());
+    }
+
+    #[test]
+    fn games_lists_and_shows_finished_rounds() {
+        let mut game = two_player_session(Settings::default());
+        let input = format!(
+            "games\n{}y\n{}games\ngames show 2\nn\n",
+            X_TAKES_THE_TOP_ROW, FULL_TIE
+        );
+        let (_, output) = session(&mut game, &input);
+        assert!(output.contains("No finished rounds yet\n"), "{}", output);
+        assert!(
+            output.contains(concat!(
+                "Round 1: X wins in 5 moves\n",
+                "Round 2: Tie in 9 moves\n",
+                "Distinct games: 2 of 2 rounds (mirror images and turns count as one)\n",
+            )),
+            "{}",
+            output
+        );
+        let output = without_times(&output);
+        let shown = output.split("== Round 2 ==\n").nth(2).unwrap();
+        assert!(
+            shown.starts_with(concat!(
+                "X  X  O  \n",
+                "O  O  X  \n",
+                "X  O  X  \n",
+                "Result: Tie\n",
+                "Moves: 1. X 0 (#s)  2. O",
+            )),
+            "{}",
+            shown
+        );
+    }
+
+    #[test]
+    fn games_replay_goes_through_a_round_move_by_move() {
+        let mut game = two_player_session(Settings::default());
+        let (_, output) = session(
+            &mut game,
+            &format!("{}games replay 1\nn\n", X_TAKES_THE_TOP_ROW),
+        );
+        // Between the prompt it was asked at and the same prompt again
+        let output = without_times(&output);
+        let moves = output.split("Play again?").nth(1).unwrap();
+        assert!(moves.contains("1. X 0 (#s)\n"), "{}", moves);
+        assert!(moves.contains("5. X 2 (#s)\n"), "{}", moves);
+        assert!(moves.contains("X  X  X* \n"), "{}", moves);
+        assert!(moves.contains("Result: X wins\n"), "{}", moves);
+    }
+
+    #[test]
+    fn games_refuses_rounds_it_does_not_have() {
+        let mut game = two_player_session(Settings::default());
+        let input = format!(
+            "{}games show 0\ngames show 2\ngames replay 9\ngames show two\ngames list\nn\n",
+            X_TAKES_THE_TOP_ROW
+        );
+        let (_, output) = session(&mut game, &input);
+        assert!(output.contains("There is no round 0\n"), "{}", output);
+        assert!(output.contains("There is no round 2\n"), "{}", output);
+        assert!(output.contains("There is no round 9\n"), "{}", output);
+        assert_eq!(
+            output
+                .matches("Use games, games show <n> or games replay <n>\n")
+                .count(),
+            2,
+            "{}",
+            output
+        );
+        // Only the summary at the end of the round
+        assert_eq!(output.matches("== Round").count(), 1, "{}", output);
+    }
+
+    #[test]
+    fn games_keeps_only_the_latest_rounds() {
+        let mut game = two_player_session(Settings {
+            auto_rematch: true,
+            ..Settings::default()
+        });
+        let input = format!(
+            "{}games show 1\ngames show 2\ngames\n",
+            X_TAKES_THE_TOP_ROW.repeat(HISTORY_LEN + 1)
+        );
+        let (summary, output) = session(&mut game, &input);
+        assert_eq!(summary.rounds, HISTORY_LEN as u32 + 1);
+        assert_eq!(game.history.len(), HISTORY_LEN);
+        assert!(
+            output.contains("Only rounds 2 to 101 are kept\n"),
+            "{}",
+            output
+        );
+        let listed = output.rsplit("== Round 2 ==\n").next().unwrap();
+        assert!(
+            listed.contains("\nRound 2: X wins in 5 moves (game played 2 times)\n"),
+            "{}",
+            listed
+        );
+        assert!(!listed.contains("\nRound 1: "), "{}", listed);
+        // Rounds dropped from the list still count as games played
+        assert!(
+            listed.contains("Distinct games: 1 of 101 rounds"),
+            "{}",
+            listed
+        );
+    }
+
+    // Each move is followed by a crash, and the game is reloaded from its checkpoint
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_crash_loses_at_most_the_move_being_made() {
+        let path = autosave_path("crash");
+        let mut game = seeded(
+            Rules::default(),
+            Settings {
+                two_players: true,
+                ..Settings::default()
+            },
+        );
+        game.new_round();
+        game.set_autosave(path.clone());
+        for (index, mark) in [(4, State::X), (0, State::O), (8, State::X), (2, State::O)] {
+            game.submit(at(index, mark)).unwrap();
+            let board = game.board().copied();
+            drop(game);
+            let saved = SavedGame::load(&path).unwrap();
+            game = Game::from_state(saved.state.clone(), saved.rng()).unwrap();
+            game.set_autosave(path.clone());
+            assert_eq!(game.board().copied(), board);
+        }
+        assert_eq!(game.whose_turn(), State::X);
+        // A move that is refused leaves the checkpoint as it was
+        let before = fs::read_to_string(&path).unwrap();
+        assert!(game.submit(at(4, State::X)).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        // A finished round has nothing to resume
+        game.submit(at(1, State::X)).unwrap();
+        game.submit(at(6, State::O)).unwrap();
+        game.submit(at(7, State::X)).unwrap();
+        assert_eq!(game.status(), Status::Won(State::X));
+        assert!(fs::metadata(&path).is_err());
+    }
+
+    #[test]
+    fn pick_errors_say_what_the_cli_prints() {
+        let cases = [
+            (
+                PickError::AreaOccupied {
+                    index: 2,
+                    occupant: State::O,
+                },
+                "Cell 2 is already taken by O!",
+            ),
+            (PickError::ColumnFull, "That column is already full!"),
+            (PickError::DigitNotYours, "That digit is the other side's!"),
+            (PickError::DigitUsed, "That digit has already been played!"),
+            (PickError::DrawOffered, "A draw offer awaits an answer!"),
+            (
+                PickError::MovesMapNotInitialized,
+                "The game has not started!",
+            ),
+            (PickError::NoDrawOffer, "No draw has been offered!"),
+            (PickError::NotYourTurn, "It's not your turn!"),
+            (
+                PickError::OutOfBounds { given: 12, max: 8 },
+                "Invalid index 12, must be between 0 and 8!",
+            ),
+            (PickError::WrongPhase, "The round is already over!"),
+        ];
+        for (err, message) in cases {
+            assert_eq!(err.to_string(), message);
+            assert!(err.source().is_none());
+            // Wrapped, the message stays and the error is its source
+            let wrapped = GameError::from(err);
+            assert_eq!(wrapped.to_string(), message);
+            let source = wrapped.source().unwrap();
+            assert_eq!(source.downcast_ref::<PickError>(), Some(&err));
+        }
+    }
+
+    // A two-player game with its first round begun
+    fn two_player_round() -> Game<StepRng> {
+        let mut game = two_player_session(Settings::default());
+        game.new_round();
+        game
+    }
+
+    #[test]
+    fn refusals_say_which_cell_and_whose_mark() {
+        let mut game = two_player_round();
+        game.submit(at(4, State::X)).unwrap();
+        assert_eq!(
+            game.submit(at(4, State::O)),
+            Err(PickError::AreaOccupied {
+                index: 4,
+                occupant: State::X
+            })
+        );
+        game.submit(at(0, State::O)).unwrap();
+        assert_eq!(
+            game.submit(at(0, State::X)),
+            Err(PickError::AreaOccupied {
+                index: 0,
+                occupant: State::O
+            })
+        );
+        assert_eq!(
+            game.submit(at(20, State::X)),
+            Err(PickError::OutOfBounds { given: 20, max: 8 })
+        );
+        // Shown the way the player counts
+        let err = PickError::OutOfBounds { given: 20, max: 8 };
+        assert_eq!(
+            err.message(Numbering::OneBased),
+            "Invalid index 21, must be between 1 and 9!"
+        );
+        let err = PickError::AreaOccupied {
+            index: 0,
+            occupant: State::O,
+        };
+        assert_eq!(
+            err.message(Numbering::OneBased),
+            "Cell 1 is already taken by O!"
+        );
+        assert_eq!(
+            pointing_at(game.board().unwrap(), 0),
+            "[O] .  .\n .  X  .\n .  .  .\n"
+        );
+    }
+
+    #[test]
+    fn game_errors_keep_their_source() {
+        let mut game = two_player_round();
+        game.submit(at(4, State::X)).unwrap();
+        let err = game
+            .submit(at(4, State::O))
+            .map_err(GameError::from)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Cell 4 is already taken by X!");
+        assert_eq!(
+            err.source().unwrap().downcast_ref(),
+            Some(&PickError::AreaOccupied {
+                index: 4,
+                occupant: State::X
+            })
+        );
+
+        // A position that can't come up in a game
+        let board: Board = "XXX......".parse().unwrap();
+        let err = game
+            .load_position(Position::new(board, State::X, &Rules::default()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Illegal position: 3 X against 0 O");
+        let source = err.source().unwrap().downcast_ref::<IllegalPosition>();
+        assert_eq!(
+            source,
+            Some(&IllegalPosition::CountImbalance { x: 3, o: 0 })
+        );
+
+        // A setup the game refuses has nothing under it
+        let err = gravity(6, 7)
+            .load_position(Position::new(
+                Board::new(3, 3, 1),
+                State::X,
+                &Rules::default(),
+            ))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Position codes are for classic 3x3 games");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn errors_convert_with_the_question_mark() {
+        fn play(game: &mut Game<StepRng>, index: usize) -> Result<(), Box<dyn Error>> {
+            let mark = game.whose_turn();
+            game.submit(at(index, mark))?;
+            Ok(())
+        }
+        let mut game = two_player_round();
+        play(&mut game, 0).unwrap();
+        let err = play(&mut game, 9).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid index 9, must be between 0 and 8!");
+        assert!(err.downcast_ref::<PickError>().is_some());
+    }
+
+    // Rounds against a CPU that plays the first free cell: a win, a loss and a tie
+    const OUTCOMES: [(&[usize], Outcome); 3] = [
+        (&[0, 4, 8], Outcome::PlayerWin),
+        (&[3, 4, 8], Outcome::CpuWin),
+        (&[1, 3, 4, 6, 8], Outcome::Tie),
+    ];
+
+    fn play_round<R: Rng + 'static>(game: &mut Game<R>, moves: &[usize]) -> Status {
+        game.new_round();
+        for &index in moves {
+            game.submit(at(index, State::X)).unwrap();
+        }
+        game.status()
+    }
+
+    #[test]
+    fn each_outcome_counts_once() {
+        for (moves, outcome) in OUTCOMES {
+            let mut game = pinned(Rules::default(), Settings::default());
+            let status = play_round(&mut game, moves);
+            assert_ne!(status, Status::InProgress);
+            let (player, cpu, tie) = match outcome {
+                Outcome::PlayerWin => (1, 0, 0),
+                Outcome::CpuWin => (0, 1, 0),
+                Outcome::Tie => (0, 0, 1),
+            };
+            assert_eq!(game.score(), Score { player, cpu, tie }, "{:?}", outcome);
+        }
+    }
+
+    #[test]
+    fn outcomes_add_up_over_a_session() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        for (moves, _) in OUTCOMES.iter().chain(&OUTCOMES[..1]) {
+            play_round(&mut game, moves);
+        }
+        let score = game.score();
+        assert_eq!((score.player, score.cpu, score.tie), (2, 1, 1));
+        assert_eq!(score.rounds(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn wins_build_a_streak_that_others_break() {
+        let path = autosave_path("streak");
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.set_stats_file(path.clone());
+        let mut streaks = Vec::new();
+        for (moves, _) in [
+            OUTCOMES[0],
+            OUTCOMES[0],
+            OUTCOMES[2],
+            OUTCOMES[0],
+            OUTCOMES[1],
+        ] {
+            play_round(&mut game, moves);
+            streaks.push(Stats::load(&path).unwrap().streak);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(streaks, [1, 2, 0, 1, 0]);
+    }
+
+    #[test]
+    fn refused_moves_keep_the_cpu_waiting() {
+        let mut game = seeded(Rules::default(), Settings::default());
+        let (summary, output) = session(&mut game, "4\n4\nnonsense\n");
+        // Only the first move was answered
+        assert_eq!(game.board().unwrap().count(State::Empty), 7);
+        assert!(
+            output.contains("Cell 4 is already taken by X!"),
+            "{}",
+            output
+        );
+        assert_eq!(summary.rounds, 0);
+    }
+
+    #[test]
+    fn a_cpu_win_ends_the_round_like_a_player_win() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let mut output = Vec::new();
+        let summary = game
+            .start_with("3\n4\n8\nn\n".as_bytes(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(game.status(), Status::Won(State::O));
+        assert_eq!(output.matches("Play again?").count(), 1);
+        assert!(output.contains("Thanks for playing"));
+        assert_eq!((summary.rounds, summary.score.cpu), (1, 1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn leaving_mid_round_drops_the_checkpoint() {
+        let path = autosave_path("leave");
+        let mut game = two_player_session(Settings::default());
+        game.set_autosave(path.clone());
+        let (summary, output) = session(&mut game, "4\n0\n");
+        assert!(fs::metadata(&path).is_err());
+        assert!(output.contains("Thanks for playing"));
+        assert_eq!(summary.rounds, 0);
+    }
+
+    // The output with every time such as "1.5s" read as "#s", so it can be compared whole
+    fn without_times(output: &str) -> String {
+        output
+            .split_inclusive([' ', '\n', '('])
+            .map(|word| {
+                let time = word.trim_end_matches([' ', '\n', ')', ',']);
+                let seconds = time.strip_suffix('s').map(str::parse::<f64>);
+                if let Some(Ok(_)) = seconds {
+                    word.replacen(time, "#s", 1)
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scripted_round_prints_in_order() {
+        let mut game = two_player_session(Settings::default());
+        let (_, output) = session(&mut game, "0\n3\n3\n1\n4\n2\nn\n");
+        assert_eq!(
+            without_times(&output),
+            concat!(
+                "Choose index(0 to 8) or a name like top-left:\n",
+                ".  .  .  \n",
+                ".  .  .  \n",
+                ".  .  .  \n",
+                "Round 1 | X 0, O 0, Tie 0 | X to move\n",
+                "You entered: 0\n",
+                "X took #s\n",
+                "** O to move **\n",
+                "X* .  .  \n",
+                ".  .  .  \n",
+                ".  .  .  \n",
+                "Round 1 | X 0, O 0, Tie 0 | O to move\n",
+                "You entered: 3\n",
+                "O took #s\n",
+                "** X to move **\n",
+                "X  .  .  \n",
+                "O* .  .  \n",
+                ".  .  .  \n",
+                "Round 1 | X 0, O 0, Tie 0 | X to move\n",
+                "You entered: 3\n",
+                "Cell 3 is already taken by O!\n",
+                " X  .  .\n",
+                "[O] .  .\n",
+                " .  .  .\n",
+                "X  .  .  \n",
+                "O* .  .  \n",
+                ".  .  .  \n",
+                "Round 1 | X 0, O 0, Tie 0 | X to move\n",
+                "You entered: 1\n",
+                "X took #s\n",
+                "** O to move **\n",
+                "X  X* .  \n",
+                "O  .  .  \n",
+                ".  .  .  \n",
+                "Round 1 | X 0, O 0, Tie 0 | O to move\n",
+                "You entered: 4\n",
+                "O took #s\n",
+                "** X to move **\n",
+                "X  X  .  \n",
+                "O  O* .  \n",
+                ".  .  .  \n",
+                "Round 1 | X 0, O 0, Tie 0 | X to move\n",
+                "You entered: 2\n",
+                "X took #s\n",
+                "** X wins! **\n",
+                "== Round 1 ==\n",
+                "X* X* X* \n",
+                "O  O  .  \n",
+                ".  .  .  \n",
+                "Result: X wins\n",
+                "Moves: 1. X 0 (#s)  2. O 3 (#s)  3. X 1 (#s)  4. O 4 (#s)  5. X 2 (#s)\n",
+                "Time this round: X #s, O #s\n",
+                "X    1  100%\n",
+                "O    0    0%\n",
+                "Tie  0    0%\n",
+                "Play again? (y/n, swap to change sides, or export <file> to keep the board)\n",
+                "** Thanks for playing! **\n",
+            )
+        );
+    }
+
+    #[test]
+    fn search_info_names_cells_as_numbered() {
+        let mut pv = MoveList::new();
+        pv.extend_from_slice(&[4, 0, 8, 2]);
+        let search = SearchResult {
+            score: 0,
+            pv,
+            nodes: 1234,
+            depth: 6,
+        };
+        let elapsed = Duration::from_millis(3);
+        assert_eq!(
+            search_info(&search, elapsed, Numbering::ZeroBased),
+            "depth 6, 1.2k nodes, 3 ms, pv 4 0 8 2"
+        );
+        assert_eq!(
+            search_info(&search, elapsed, Numbering::OneBased),
+            "depth 6, 1.2k nodes, 3 ms, pv 5 1 9 3"
+        );
+    }
+
+    #[test]
+    fn resigning_scores_a_loss_and_ends_the_transcript() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (summary, output) = session(&mut game, "4\nresign\nn\n");
+        assert!(
+            output.contains("** You resign **\n** Cpu wins! **"),
+            "{}",
+            output
+        );
+        assert_eq!(
+            summary.score,
+            Score {
+                player: 0,
+                cpu: 1,
+                tie: 0
+            }
+        );
+        assert_eq!(game.status(), Status::Won(State::O));
+        assert_eq!(game.ending(), Some(Ending::Resigned(State::X)));
+        assert_eq!(game.winning_line(), None);
+        let round = without_times(&game.round_summary(false));
+        assert!(round.contains("Result: Cpu wins\n"), "{}", round);
+        assert!(
+            round.contains("Moves: 1. X 4 (#s)  2. O 0 (#s)  X resigns\n"),
+            "{}",
+            round
+        );
+    }
+
+    #[test]
+    fn two_players_resign_the_side_to_move() {
+        let mut game = two_player_session(Settings::default());
+        game.new_round();
+        game.submit(at(4, State::X)).unwrap();
+        assert_eq!(game.resign(), Ok(Phase::RoundOver(Outcome::PlayerWin)));
+        assert_eq!(
+            game.score(),
+            Score {
+                player: 1,
+                cpu: 0,
+                tie: 0
+            }
+        );
+        assert_eq!(game.resign(), Err(PickError::WrongPhase));
+        assert_eq!(game.offer_draw(), Err(PickError::WrongPhase));
+    }
+
+    #[test]
+    fn a_draw_offer_holds_up_moves_until_answered() {
+        let mut game = two_player_session(Settings::default());
+        let input = "0\noffer draw\n4\nresign\ndecline\n4\ndraw\naccept\nn\n";
+        let (summary, output) = session(&mut game, input);
+        assert!(
+            output.contains("O offers a draw, X may accept or decline"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("O offers a draw: accept or decline"),
+            "{}",
+            output
+        );
+        assert_eq!(
+            output.matches("A draw offer awaits an answer!").count(),
+            2,
+            "{}",
+            output
+        );
+        assert!(output.contains("Draw declined, O to move"), "{}", output);
+        assert!(
+            output.contains("** Draw agreed **\n** Tie! **"),
+            "{}",
+            output
+        );
+        assert_eq!(
+            summary.score,
+            Score {
+                player: 0,
+                cpu: 0,
+                tie: 1
+            }
+        );
+        // Resigning waits for the answer like a move, the offer was still there
+        assert_eq!(game.ending(), Some(Ending::DrawAgreed));
+        let round = without_times(&game.round_summary(false));
+        assert!(
+            round.contains("Moves: 1. X 0 (#s)  2. O 4 (#s)  draw agreed\n"),
+            "{}",
+            round
+        );
+    }
+
+    #[test]
+    fn answers_need_an_offer() {
+        let mut game = two_player_session(Settings::default());
+        game.new_round();
+        assert_eq!(game.answer_draw(true), Err(PickError::NoDrawOffer));
+        assert_eq!(game.offer_draw(), Ok(DrawAnswer::Pending));
+        assert_eq!(game.draw_offer(), Some(State::X));
+        assert_eq!(game.offer_draw(), Err(PickError::DrawOffered));
+        assert_eq!(game.submit(at(4, State::X)), Err(PickError::DrawOffered));
+        assert_eq!(game.answer_draw(false), Ok(Phase::AwaitingPlayer));
+        assert_eq!(game.draw_offer(), None);
+        assert_eq!(game.score(), Score::default());
+    }
+
+    #[test]
+    fn the_cpu_takes_a_draw_only_when_it_cant_do_better() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let load = |game: &mut Game<StepRng>, board: &str| {
+            let board: Board = board.parse().unwrap();
+            game.load_position(Position::new(board, State::X, &Rules::default()))
+                .unwrap();
+        };
+        // O threatens 2 and 6, X can't stop both
+        load(&mut game, "OO.OXX.X.");
+        assert_eq!(game.offer_draw(), Ok(DrawAnswer::Declined));
+        assert_eq!(game.phase(), Phase::AwaitingPlayer);
+        assert_eq!(game.score(), Score::default());
+        // X wins at 2
+        load(&mut game, "XX.OO....");
+        assert_eq!(game.offer_draw(), Ok(DrawAnswer::Accepted));
+        assert_eq!(game.phase(), Phase::RoundOver(Outcome::Tie));
+        assert_eq!(
+            game.score(),
+            Score {
+                player: 0,
+                cpu: 0,
+                tie: 1
+            }
+        );
+
+        let mut game = pinned(Rules::default(), Settings::default());
+        let (_, output) = session(&mut game, "offer draw\nn\n");
+        assert!(
+            output.contains("** Cpu accepts the draw **\n** Tie! **"),
+            "{}",
+            output
+        );
+    }
+
+    // Too big to solve, the CPU answers from its lookahead
+    #[test]
+    fn the_cpu_answers_draw_offers_on_big_gravity_boards() {
+        let mut game = pinned(Rules::gravity(6, 7), Settings::default());
+        game.new_round();
+        let started = Instant::now();
+        assert!(game.offer_draw().is_ok());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    fn played(moves: &[(State, usize)]) -> Vec<PlayedMove> {
+        moves
+            .iter()
+            .map(|&(mark, index)| PlayedMove {
+                mark,
+                index,
+                digit: None,
+                auto: false,
+                elapsed: Duration::ZERO,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mirrored_games_are_the_same_canonical_game() {
+        let rules = Rules::default();
+        let (x, o) = (State::X, State::O);
+        let game = CanonicalGame::new(&rules, &played(&[(x, 4), (o, 0), (x, 8), (o, 2)]), None);
+        // Left to right, then turned a quarter
+        let mirrored = played(&[(x, 4), (o, 2), (x, 6), (o, 0)]);
+        let turned = played(&[(x, 4), (o, 2), (x, 6), (o, 8)]);
+        assert_eq!(CanonicalGame::new(&rules, &mirrored, None), game);
+        assert_eq!(CanonicalGame::new(&rules, &turned, None), game);
+        // The same cells in another order, or ended differently, are another game
+        let reordered = played(&[(x, 4), (o, 2), (x, 8), (o, 0)]);
+        assert_ne!(CanonicalGame::new(&rules, &reordered, None), game);
+        let resigned = Some(Ending::Resigned(State::X));
+        assert_ne!(CanonicalGame::new(&rules, &mirrored, resigned), game);
+    }
+
+    #[test]
+    fn big_gravity_boards_are_evaluated_by_playouts() {
+        let mut game = pinned(Rules::gravity(6, 7), Settings::default());
+        game.new_round();
+        let line = game.eval_line().unwrap();
+        assert!(line.starts_with("playouts: "), "{}", line);
+    }
+
+    // A 6x7 gravity board has only 7 moves but far too many cells to solve
+    #[test]
+    fn big_gravity_boards_get_a_hint_without_a_full_search() {
+        let mut game = pinned(Rules::gravity(6, 7), Settings::default());
+        game.new_round();
+        let started = Instant::now();
+        assert!(game.auto_play_move().is_some());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn gravity_games_are_only_mirrored() {
+        let rules = Rules::gravity(6, 7);
+        let (x, o) = (State::X, State::O);
+        let game = CanonicalGame::new(&rules, &played(&[(x, 35), (o, 36)]), None);
+        let mirrored = played(&[(x, 41), (o, 40)]);
+        assert_eq!(CanonicalGame::new(&rules, &mirrored, None), game);
+        // Flipped upside down the marks would hang from the top row
+        let flipped = played(&[(x, 6), (o, 5)]);
+        assert_ne!(CanonicalGame::new(&rules, &flipped, None), game);
+    }
+
+    #[test]
+    fn repeated_games_are_flagged_and_counted_once() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        let input = format!("{0}y\n{0}y\n{1}games\nn\n", X_WINS, TIE);
+        let (summary, output) = session(&mut game, &input);
+        assert_eq!(
+            output
+                .matches("You've played this exact game 2 times")
+                .count(),
+            1,
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(concat!(
+                "Round 1: You win in 5 moves\n",
+                "Round 2: You win in 5 moves (game played 2 times)\n",
+                "Round 3: Tie in 9 moves\n",
+                "Distinct games: 2 of 3 rounds (mirror images and turns count as one)\n",
+            )),
+            "{}",
+            output
+        );
+        assert_eq!((summary.rounds, summary.distinct_games), (3, 2));
+    }
+
+    #[test]
+    fn scores_are_kept_apart_by_opponent() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        game.record_outcome(Outcome::PlayerWin);
+        game.set_difficulty(Difficulty::Hard);
+        game.reset();
+        game.record_outcome(Outcome::Tie);
+        // Back to the easy Cpu, which still has its round
+        game.set_difficulty(Difficulty::Easy);
+        game.reset();
+        game.record_outcome(Outcome::CpuWin);
+        let score = |player, cpu, tie| Score { player, cpu, tie };
+        assert_eq!(
+            *game.scores(),
+            Scores::from([
+                ("cpu easy (balanced)".to_string(), score(1, 1, 0)),
+                ("cpu hard (balanced)".to_string(), score(0, 0, 1)),
+            ])
+        );
+        assert_eq!(game.score(), score(1, 1, 1));
+        let (_, output) = session(&mut game, "score\n");
+        assert!(
+            output.contains("vs cpu easy (balanced): 1-1-0"),
+            "{}",
+            output
+        );
+        assert!(output.contains("By opponent:"), "{}", output);
+        assert!(
+            output.contains("cpu hard (balanced)     0     0     1"),
+            "{}",
+            output
+        );
+        let two_players = pinned(
+            Rules::default(),
+            Settings {
+                two_players: true,
+                ..Settings::default()
+            },
+        );
+        assert_eq!(two_players.opponent(), "two players");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn resumed_games_add_up_their_scores() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.reset();
+        game.record_outcome(Outcome::PlayerWin);
+        game.set_difficulty(Difficulty::Medium);
+        game.reset();
+        game.record_outcome(Outcome::CpuWin);
+        let resumed = Game::from_state(game.state(), GameRng::seeded(RngKind::ChaCha8, 1)).unwrap();
+        assert_eq!(resumed.score(), game.score());
+        assert_eq!(resumed.scores(), game.scores());
+    }
+
+    // Plays the first free cell of its list
+    #[derive(Debug)]
+    struct Scripted(Vec<usize>);
+
+    impl fmt::Display for Scripted {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "scripted")
+        }
+    }
+
+    impl Player for Scripted {
+        fn choose_move(
+            &self,
+            board: &Board,
+            _: &Rules,
+            _: State,
+            _: &mut dyn rand::RngCore,
+        ) -> Option<MoveDecision> {
+            let index = self.0.iter().copied().find(|&i| board[i] == State::Empty)?;
+            Some(MoveDecision {
+                index,
+                reason: Reason::Random,
+                search: None,
+            })
+        }
+    }
+
+    #[test]
+    fn either_side_can_play_x_and_open() {
+        const LINE: [usize; 3] = [0, 1, 2];
+        const NO_LINE: [usize; 3] = [3, 4, 8];
+        for human_mark in [State::X, State::O] {
+            for cpu_opens in [false, true] {
+                for human_wins in [true, false] {
+                    let case = format!(
+                        "{:?}, cpu opens {}, you win {}",
+                        human_mark, cpu_opens, human_wins
+                    );
+                    let (human, cpu) = if human_wins {
+                        (LINE, NO_LINE)
+                    } else {
+                        (NO_LINE, LINE)
+                    };
+                    let mut game = pinned(Rules::default(), Settings::default());
+                    game.set_sides(human_mark, cpu_opens).unwrap();
+                    game.set_player(Arc::new(Scripted(cpu.to_vec())));
+                    let events = Events::default();
+                    game.add_observer(Box::new(events.clone()));
+                    let input: String = human.iter().map(|cell| format!("{}\n", cell)).collect();
+                    let (summary, output) = session(&mut game, &input);
+
+                    let (banner, score) = if human_wins {
+                        (
+                            "** You win! **",
+                            Score {
+                                player: 1,
+                                cpu: 0,
+                                tie: 0,
+                            },
+                        )
+                    } else {
+                        (
+                            "** Cpu wins! **",
+                            Score {
+                                player: 0,
+                                cpu: 1,
+                                tie: 0,
+                            },
+                        )
+                    };
+                    assert!(output.contains(banner), "{}\n{}", case, output);
+                    let you_play = format!("You play {:?}", human_mark);
+                    assert!(output.contains(&you_play), "{}\n{}", case, output);
+                    assert_eq!(summary.score, score, "{}", case);
+                    assert_eq!(summary.scores["scripted"], score, "{}", case);
+                    let round = game.history.back().unwrap();
+                    let opener = if cpu_opens {
+                        human_mark.opponent()
+                    } else {
+                        human_mark
+                    };
+                    assert_eq!(round.moves[0].mark, opener, "{}", case);
+                    for played in &round.moves {
+                        let yours = human.contains(&played.index);
+                        let mark = if yours {
+                            human_mark
+                        } else {
+                            human_mark.opponent()
+                        };
+                        assert_eq!(played.mark, mark, "{}", case);
+                        assert_eq!(round.board[played.index], mark, "{}", case);
+                    }
+                    for event in events.0.lock().unwrap().iter() {
+                        match *event {
+                            Event::Move {
+                                index,
+                                human: by_human,
+                                ..
+                            } => {
+                                assert_eq!(by_human, human.contains(&index), "{}", case)
+                            }
+                            Event::RoundEnd { status, .. } => {
+                                let winner = if human_wins {
+                                    human_mark
+                                } else {
+                                    human_mark.opponent()
+                                };
+                                assert_eq!(status, Status::Won(winner), "{}", case)
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sides_are_picked_before_the_first_round() {
+        let numerical = Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        };
+        let mut game = pinned(numerical, Settings::default());
+        // X opens numerical games whatever was asked
+        game.set_sides(State::O, false).unwrap();
+        game.reset();
+        assert_eq!(
+            (game.whose_turn(), game.phase()),
+            (State::X, Phase::AwaitingCpu)
+        );
+        assert!(game.set_sides(State::X, false).is_err());
+        let mut game = pinned(Rules::default(), Settings::default());
+        assert!(game.set_sides(State::Empty, false).is_err());
+    }
+
+    #[test]
+    fn lines_typed_before_a_move_was_answered_are_dropped() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.set_player(Arc::new(Scripted(vec![2, 6])));
+        // A paste of 4 and 0, then 8 once the Cpu answered
+        let mut typed = VecDeque::from([(0, "4\n"), (0, "0\n"), (300, "8\n")]);
+        let reader = LineReader::spawn(move |line| match typed.pop_front() {
+            Some((wait, typed)) => {
+                thread::sleep(Duration::from_millis(wait));
+                line.push_str(typed);
+                Ok(typed.len())
+            }
+            None => Ok(0),
+        });
+        let mut output = Vec::new();
+        game.play(&mut Console {
+            input: io::empty(),
+            output: &mut output,
+            colors: false,
+            lines: Some(reader),
+            debounce: true,
+            moved: false,
+        })
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("Ignored 1 line typed before your move was answered"),
+            "{}",
+            output
+        );
+        let board = game.board().unwrap();
+        assert_eq!((board[4], board[8]), (State::X, State::X), "{}", output);
+        assert_eq!((board[2], board[6]), (State::O, State::O), "{}", output);
+        assert_eq!(board[0], State::Empty, "{}", output);
+    }
+
+    #[test]
+    fn several_moves_on_one_line_play_none() {
+        let mut game = pinned(Rules::default(), Settings::default());
+        game.set_player(Arc::new(Scripted(vec![2, 6])));
+        let (_, output) = session(&mut game, "4 0\n8\n");
+        assert!(
+            output.contains("One move per line, please, none was played"),
+            "{}",
+            output
+        );
+        let board = game.board().unwrap();
+        assert_eq!((board[4], board[0]), (State::Empty, State::Empty));
+        assert_eq!((board[8], board[2]), (State::X, State::O));
+
+        // A move may still be written with spaces in it
+        let wild = seeded(wild_rules(), Settings::default());
+        assert!(!wild.several_moves(" 4 x \n"));
+        assert!(wild.several_moves("4x 5o\n"));
+    }
+
+    // A session against the Scripted CPU with `fast_finish` on
+    fn fast_finish_session(
+        human_mark: State,
+        cpu_opens: bool,
+        cpu: &[usize],
+        input: &str,
+    ) -> (Game<StepRng>, SessionSummary, String) {
+        let settings = Settings {
+            fast_finish: true,
+            ..Settings::default()
+        };
+        let mut game = pinned(Rules::default(), settings);
+        game.set_sides(human_mark, cpu_opens).unwrap();
+        game.set_player(Arc::new(Scripted(cpu.to_vec())));
+        let (summary, output) = session(&mut game, input);
+        (game, summary, output)
+    }
+
+    #[test]
+    fn a_won_round_can_be_ended_once_best_play_decides_it() {
+        // O's reply next to X's corner loses
+        let (game, summary, output) = fast_finish_session(State::X, false, &[1], "0\ny\n");
+        assert!(
+            output.contains("X wins in 3 with best play, end the round now? (y/n)\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("** Cpu concedes **"), "{}", output);
+        assert_eq!(summary.score.player, 1);
+        assert_eq!(game.ending(), Some(Ending::Resigned(State::O)));
+        assert_eq!(game.board().unwrap().count(State::Empty), 7);
+    }
+
+    #[test]
+    fn a_lost_round_can_be_conceded() {
+        // The Cpu opens in the corner and the player answers next to it
+        let (game, summary, output) = fast_finish_session(State::O, true, &[0], "1\ny\n");
+        assert!(
+            output.contains("X wins in 3 with best play, concede? (y/n)\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("** You concede **"), "{}", output);
+        assert_eq!(summary.score.cpu, 1);
+        assert_eq!(game.ending(), Some(Ending::Resigned(State::O)));
+        assert_eq!(game.board().unwrap().count(State::Empty), 7);
+    }
+
+    #[test]
+    fn a_dead_draw_is_offered_once_and_may_be_played_out() {
+        let question = "Nobody can win any more, call it a draw? (y/n)";
+        let (game, summary, output) =
+            fast_finish_session(State::X, false, &[4, 2, 3, 7], "0\n1\n6\n5\nn\n8\n");
+        assert_eq!(output.matches(question).count(), 1, "{}", output);
+        // Offered once the Cpu's 7 blocked the last open line
+        let offered = output.find(question).unwrap();
+        assert!(output[..offered].contains("You entered: 5"), "{}", output);
+        assert!(output[offered..].starts_with(&format!("{}\nPlay on\n", question)));
+        assert!(output[offered..].contains("You entered: 8"), "{}", output);
+        assert_eq!(summary.score.tie, 1);
+        assert_eq!(game.ending(), None);
+        assert!(game.board().unwrap().is_full());
+
+        // Accepted, it ends the round right there
+        let (game, summary, output) =
+            fast_finish_session(State::X, false, &[4, 2, 3, 7], "0\n1\n6\n5\ny\n");
+        assert!(output.contains("** Draw agreed **"), "{}", output);
+        assert_eq!(summary.score.tie, 1);
+        assert_eq!(game.ending(), Some(Ending::DrawAgreed));
+        assert_eq!(game.board().unwrap().count(State::Empty), 1);
+    }
+
+    #[test]
+    fn moves_are_timed_from_the_start_of_their_round() {
+        let mut game = two_player_round();
+        let events = Events::default();
+        game.add_observer(Box::new(events.clone()));
+        game.submit(at(4, State::X)).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        game.submit(at(0, State::O)).unwrap();
+        let [first, second] = game.round_moves() else {
+            panic!("{:?}", game.round_moves());
+        };
+        assert!(second.elapsed >= first.elapsed + Duration::from_millis(20));
+        let elapsed: Vec<Duration> = events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                Event::Move { elapsed, .. } => Some(*elapsed),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(elapsed, [first.elapsed, second.elapsed]);
+    }
+
+    #[test]
+    fn only_decided_rounds_have_a_forced_outcome() {
+        let mut game = two_player_round();
+        assert_eq!(game.forced_outcome(), None);
+        for (mark, index) in [(State::X, 0), (State::O, 4), (State::X, 1), (State::O, 2)] {
+            game.submit(at(index, mark)).unwrap();
+        }
+        // O threatens 6, which X has to block
+        assert_eq!(game.forced_outcome(), None);
+        for (mark, index) in [(State::X, 6), (State::O, 3), (State::X, 5), (State::O, 7)] {
+            game.submit(at(index, mark)).unwrap();
+        }
+        assert_eq!(game.forced_outcome(), Some(Forced::DeadDraw));
+        game.submit(at(8, State::X)).unwrap();
+        assert_eq!(game.forced_outcome(), None);
     }
 }
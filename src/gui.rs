@@ -0,0 +1,121 @@
+use crate::ai::Difficulty;
+use crate::board::State;
+use crate::game::{Game, Move, Outcome, Phase};
+use crate::rules::Variant;
+use eframe::egui;
+
+// Side of a board cell in points
+const CELL: f32 = 80.0;
+
+struct App {
+    game: Game,
+}
+
+impl App {
+    fn side_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Score");
+        ui.monospace(self.game.score().table("You", "Cpu"));
+        ui.separator();
+        let mut difficulty = self.game.settings().difficulty;
+        egui::ComboBox::from_label("Difficulty")
+            .selected_text(difficulty.to_string())
+            .show_ui(ui, |ui| {
+                for option in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+                    ui.selectable_value(&mut difficulty, option, option.to_string());
+                }
+            });
+        self.game.set_difficulty(difficulty);
+        if ui.button("New round").clicked() {
+            self.game.new_round();
+        }
+    }
+
+    fn board(&mut self, ui: &mut egui::Ui) {
+        let status = match self.game.phase() {
+            Phase::AwaitingPlayer => format!("Your move ({:?})", self.game.human_mark()),
+            Phase::AwaitingCpu => "Cpu is thinking".to_string(),
+            Phase::RoundOver(Outcome::PlayerWin) => "You win!".to_string(),
+            Phase::RoundOver(Outcome::CpuWin) => "Cpu wins!".to_string(),
+            Phase::RoundOver(Outcome::Tie) => "Tie!".to_string(),
+            Phase::Invalid => "Both sides have a line, start a new round".to_string(),
+        };
+        ui.heading(status);
+
+        let board = match self.game.board() {
+            Some(board) => *board,
+            None => return,
+        };
+        let winning: Vec<usize> = self
+            .game
+            .winning_line()
+            .map_or(Vec::new(), |line| line.cells().collect());
+        let gravity = self.game.rules().variant == Variant::Gravity;
+        let your_turn = self.game.phase() == Phase::AwaitingPlayer;
+        let mut clicked = None;
+        egui::Grid::new("board").spacing([4.0, 4.0]).show(ui, |ui| {
+            for row in 0..board.rows() {
+                for col in 0..board.cols() {
+                    let index = row * board.cols() + col;
+                    let text = match board[index] {
+                        State::X => "X",
+                        State::O => "O",
+                        State::Empty => "",
+                    };
+                    // In gravity mode a click picks the column
+                    let (input, open) = if gravity {
+                        (col, board.drop_target(col).is_some())
+                    } else {
+                        (index, board[index] == State::Empty)
+                    };
+                    let mut button = egui::Button::new(egui::RichText::new(text).size(CELL / 2.0))
+                        .min_size(egui::vec2(CELL, CELL));
+                    if winning.contains(&index) {
+                        button = button.fill(egui::Color32::DARK_GREEN);
+                    }
+                    if ui.add_enabled(your_turn && open, button).clicked() {
+                        clicked = Some(input);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = clicked {
+            // Clicks are only enabled on legal moves, so this can't fail
+            let _ = self.game.submit(Move {
+                index,
+                mark: None,
+                digit: None,
+            });
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::SidePanel::right("score").show(ctx, |ui| self.side_panel(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.board(ui));
+    }
+}
+
+// Opens the window and plays until it's closed
+pub fn run(mut game: Game) -> Result<(), String> {
+    let rules = game.rules();
+    if rules.layers > 1 || !matches!(rules.variant, Variant::Classic | Variant::Gravity) {
+        return Err("The window only plays classic and gravity boards".to_string());
+    }
+    game.new_round();
+    let size = [
+        rules.cols as f32 * (CELL + 4.0) + 220.0,
+        rules.rows as f32 * (CELL + 4.0) + 80.0,
+    ];
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size(size),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Tic-tac-toe",
+        options,
+        Box::new(|_| Ok(Box::new(App { game }))),
+    )
+    .map_err(|err| format!("Can't open the window: {}", err))
+}
@@ -0,0 +1,365 @@
+use crate::board::State;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use std::sync::mpsc;
+use std::thread;
+use tokio::sync::oneshot;
+
+// Upgrades the schema by one version: `STEPS[i]` takes a database of version i to i + 1,
+// the version being SQLite's user_version, 0 for a new file
+const STEPS: &[&str] = &["CREATE TABLE matches (
+        id INTEGER PRIMARY KEY,
+        code TEXT NOT NULL,
+        x TEXT,
+        o TEXT,
+        winner TEXT,
+        moves TEXT NOT NULL,
+        started_ms INTEGER NOT NULL,
+        ended_ms INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL
+    );
+    CREATE INDEX matches_by_end ON matches (ended_ms);"];
+
+const COLUMNS: &str = "code, x, o, winner, moves, started_ms, ended_ms, duration_ms";
+
+// A finished match as it is kept
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Played {
+    // The join code it was played under, which a later match may get again
+    pub code: String,
+    // Names of the players of X and O, None for one who gave none
+    pub x: Option<String>,
+    pub o: Option<String>,
+    // None for a tie
+    pub winner: Option<State>,
+    pub moves: Vec<usize>,
+    // Milliseconds since the Unix epoch
+    pub started_ms: u64,
+    pub ended_ms: u64,
+    pub duration_ms: u64,
+}
+
+// How one player did against another
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Record {
+    pub wins: u32,
+    pub losses: u32,
+    pub ties: u32,
+}
+
+type Reply<T> = oneshot::Sender<Result<T, String>>;
+
+enum Request {
+    Save(Played),
+    Recent {
+        limit: usize,
+        reply: Reply<Vec<Played>>,
+    },
+    Between {
+        player: String,
+        opponent: String,
+        limit: usize,
+        reply: Reply<(Record, Vec<Played>)>,
+    },
+}
+
+// Where finished matches go: to an SQLite database on a thread of its own, so a slow disk
+// never holds up a move, or nowhere by default
+#[derive(Debug, Clone, Default)]
+pub struct Archive {
+    requests: Option<mpsc::Sender<Request>>,
+}
+
+impl Archive {
+    // The database at `path`, ":memory:" for one that lasts as long as the process, its
+    // schema brought up to date first
+    pub fn open(path: &str) -> Result<Self, String> {
+        let mut connection =
+            Connection::open(path).map_err(|err| format!("Can't open {}: {}", path, err))?;
+        migrate(&mut connection).map_err(|err| format!("Can't upgrade {}: {}", path, err))?;
+        let (requests, received) = mpsc::channel();
+        thread::Builder::new()
+            .name("history".to_string())
+            .spawn(move || serve(&connection, received))
+            .map_err(|err| format!("Can't start writing to {}: {}", path, err))?;
+        Ok(Archive {
+            requests: Some(requests),
+        })
+    }
+
+    // Queues `played` to be written without waiting for it. A match that can't be kept is
+    // only logged, the server goes on without it.
+    pub fn save(&self, played: Played) {
+        if let Some(requests) = &self.requests {
+            if requests.send(Request::Save(played)).is_err() {
+                eprintln!("The match history stopped, a finished match was not kept");
+            }
+        }
+    }
+
+    // The latest `limit` matches, newest first
+    pub async fn recent(&self, limit: usize) -> Result<Vec<Played>, String> {
+        self.ask(|reply| Request::Recent { limit, reply }).await
+    }
+
+    // How `player` did against `opponent` in every match between them, and the latest
+    // `limit` of those matches
+    pub async fn between(
+        &self,
+        player: &str,
+        opponent: &str,
+        limit: usize,
+    ) -> Result<(Record, Vec<Played>), String> {
+        self.ask(|reply| Request::Between {
+            player: player.to_string(),
+            opponent: opponent.to_string(),
+            limit,
+            reply,
+        })
+        .await
+    }
+
+    async fn ask<T>(&self, request: impl FnOnce(Reply<T>) -> Request) -> Result<T, String> {
+        let stopped = || "The match history stopped".to_string();
+        let requests = self
+            .requests
+            .as_ref()
+            .ok_or("This server keeps no match history")?;
+        let (reply, answer) = oneshot::channel();
+        requests.send(request(reply)).map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())?
+    }
+}
+
+// Applies the steps past the database's version, all of them or none
+fn migrate(connection: &mut Connection) -> Result<(), String> {
+    let version: u32 = connection
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|err| err.to_string())?;
+    let version = version as usize;
+    if version > STEPS.len() {
+        return Err(format!(
+            "it was written by a newer version (schema v{}, this build reads up to v{})",
+            version,
+            STEPS.len()
+        ));
+    }
+    upgrade(connection, version).map_err(|err| err.to_string())
+}
+
+fn upgrade(connection: &mut Connection, version: usize) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    for step in &STEPS[version..] {
+        transaction.execute_batch(step)?;
+    }
+    transaction.execute_batch(&format!("PRAGMA user_version = {}", STEPS.len()))?;
+    transaction.commit()
+}
+
+// Answers requests until every `Archive` is dropped
+fn serve(connection: &Connection, requests: mpsc::Receiver<Request>) {
+    let failed = |err: rusqlite::Error| err.to_string();
+    for request in requests {
+        match request {
+            Request::Save(played) => {
+                if let Err(err) = insert(connection, &played) {
+                    eprintln!("Can't keep match {}: {}", played.code, err);
+                }
+            }
+            // The asker may have gone away, nobody is left to tell
+            Request::Recent { limit, reply } => {
+                let _ = reply.send(recent(connection, limit).map_err(failed));
+            }
+            Request::Between {
+                player,
+                opponent,
+                limit,
+                reply,
+            } => {
+                let found = between(connection, &player, &opponent, limit);
+                let _ = reply.send(found.map_err(failed));
+            }
+        }
+    }
+}
+
+fn insert(connection: &Connection, played: &Played) -> rusqlite::Result<()> {
+    let moves: Vec<String> = played.moves.iter().map(usize::to_string).collect();
+    connection.execute(
+        &format!(
+            "INSERT INTO matches ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            COLUMNS
+        ),
+        params![
+            played.code,
+            played.x,
+            played.o,
+            played
+                .winner
+                .map(|mark| format!("{:?}", mark).to_lowercase()),
+            moves.join(" "),
+            played.started_ms as i64,
+            played.ended_ms as i64,
+            played.duration_ms as i64,
+        ],
+    )?;
+    Ok(())
+}
+
+fn played(row: &Row) -> rusqlite::Result<Played> {
+    let winner: Option<String> = row.get(3)?;
+    let moves: String = row.get(4)?;
+    Ok(Played {
+        code: row.get(0)?,
+        x: row.get(1)?,
+        o: row.get(2)?,
+        winner: match winner.as_deref() {
+            Some("x") => Some(State::X),
+            Some("o") => Some(State::O),
+            _ => None,
+        },
+        moves: moves
+            .split_whitespace()
+            .filter_map(|index| index.parse().ok())
+            .collect(),
+        started_ms: row.get::<_, i64>(5)? as u64,
+        ended_ms: row.get::<_, i64>(6)? as u64,
+        duration_ms: row.get::<_, i64>(7)? as u64,
+    })
+}
+
+fn recent(connection: &Connection, limit: usize) -> rusqlite::Result<Vec<Played>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT {} FROM matches ORDER BY ended_ms DESC, id DESC LIMIT ?1",
+        COLUMNS
+    ))?;
+    let rows = statement.query_map([limit as i64], played)?;
+    rows.collect()
+}
+
+fn between(
+    connection: &Connection,
+    player: &str,
+    opponent: &str,
+    limit: usize,
+) -> rusqlite::Result<(Record, Vec<Played>)> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT {} FROM matches WHERE (x = ?1 AND o = ?2) OR (x = ?2 AND o = ?1)
+        ORDER BY ended_ms DESC, id DESC",
+        COLUMNS
+    ))?;
+    let matches = statement
+        .query_map([player, opponent], played)?
+        .collect::<rusqlite::Result<Vec<Played>>>()?;
+    let mut record = Record::default();
+    for played in &matches {
+        let mark = if played.x.as_deref() == Some(player) {
+            State::X
+        } else {
+            State::O
+        };
+        match played.winner {
+            None => record.ties += 1,
+            Some(winner) if winner == mark => record.wins += 1,
+            Some(_) => record.losses += 1,
+        }
+    }
+    Ok((record, matches.into_iter().take(limit).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn played(code: &str, x: &str, o: &str, winner: Option<State>, ended_ms: u64) -> Played {
+        Played {
+            code: code.to_string(),
+            x: Some(x.to_string()),
+            o: Some(o.to_string()),
+            winner,
+            moves: vec![4, 0, 8],
+            started_ms: ended_ms - 1500,
+            ended_ms,
+            duration_ms: 1500,
+        }
+    }
+
+    #[test]
+    fn the_schema_is_brought_up_to_date_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+        let version: u32 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, STEPS.len());
+        insert(&connection, &played("ABCDEF", "Ann", "Bob", None, 2000)).unwrap();
+
+        connection
+            .execute_batch("PRAGMA user_version = 99")
+            .unwrap();
+        assert_eq!(
+            migrate(&mut connection),
+            Err(
+                "it was written by a newer version (schema v99, this build reads up to v1)"
+                    .to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn saved_matches_come_back_newest_first() {
+        let archive = Archive::open(":memory:").unwrap();
+        let first = played("AAAAAA", "Ann", "Bob", Some(State::X), 2000);
+        let second = Played {
+            x: None,
+            ..played("BBBBBB", "Ann", "Bob", None, 3000)
+        };
+        let third = played("CCCCCC", "Carol", "Ann", Some(State::O), 4000);
+        for played in [&first, &second, &third] {
+            archive.save(played.clone());
+        }
+        assert_eq!(archive.recent(2).await.unwrap(), vec![third, second]);
+        assert_eq!(archive.recent(10).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn head_to_head_counts_from_either_seat() {
+        let archive = Archive::open(":memory:").unwrap();
+        for played in [
+            played("AAAAAA", "Ann", "Bob", Some(State::X), 2000),
+            played("BBBBBB", "Bob", "Ann", Some(State::X), 3000),
+            played("CCCCCC", "Bob", "Ann", Some(State::O), 4000),
+            played("DDDDDD", "Ann", "Bob", None, 5000),
+            played("EEEEEE", "Ann", "Carol", Some(State::X), 6000),
+        ] {
+            archive.save(played);
+        }
+        let (record, latest) = archive.between("Ann", "Bob", 2).await.unwrap();
+        assert_eq!(
+            record,
+            Record {
+                wins: 2,
+                losses: 1,
+                ties: 1
+            }
+        );
+        let codes: Vec<&str> = latest.iter().map(|played| played.code.as_str()).collect();
+        assert_eq!(codes, ["DDDDDD", "CCCCCC"]);
+        let (record, _) = archive.between("Bob", "Ann", 10).await.unwrap();
+        assert_eq!((record.wins, record.losses), (1, 2));
+        let (record, latest) = archive.between("Bob", "Carol", 10).await.unwrap();
+        assert_eq!((record, latest.len()), (Record::default(), 0));
+    }
+
+    #[tokio::test]
+    async fn without_a_database_nothing_is_kept() {
+        let archive = Archive::default();
+        archive.save(played("AAAAAA", "Ann", "Bob", None, 2000));
+        assert_eq!(
+            archive.recent(10).await,
+            Err("This server keeps no match history".to_string())
+        );
+        assert!(Archive::open("/nonexistent/dir/history.db").is_err());
+    }
+}
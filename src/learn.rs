@@ -0,0 +1,259 @@
+use crate::ai::{MoveDecision, Player, Reason};
+use crate::board::{Board, MoveList, State};
+use crate::rules::{Rules, Variant};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+// Largest board whose cells still fit a u64 key, three states a cell
+const MAX_LEARN_CELLS: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrainingConfig {
+    pub episodes: u32,
+    // How far each update moves a value towards its target
+    pub learning_rate: f32,
+    // Weight of values further down the game against an immediate result
+    pub discount: f32,
+    // Chance of a random move while training, so unseen positions get tried
+    pub exploration: f64,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            episodes: 100_000,
+            learning_rate: 0.2,
+            discount: 0.95,
+            exploration: 0.1,
+        }
+    }
+}
+
+// A tabular Q-learning player. Moves are valued through the position they lead to, for
+// the side that made them, and symmetric positions share one entry. Saved as a `Model`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Agent {
+    rules: Rules,
+    values: HashMap<u64, f32>,
+}
+
+impl Agent {
+    pub fn new(rules: Rules) -> Result<Agent, String> {
+        rules.validate()?;
+        if rules.variant != Variant::Classic || rules.layers > 1 {
+            return Err("The learning CPU only plays classic flat boards".to_string());
+        }
+        if rules.cells() > MAX_LEARN_CELLS {
+            return Err(format!(
+                "The learning CPU plays boards of at most {} cells",
+                MAX_LEARN_CELLS
+            ));
+        }
+        Ok(Agent {
+            rules,
+            values: HashMap::new(),
+        })
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    // Positions with a learned value
+    pub fn states(&self) -> usize {
+        self.values.len()
+    }
+
+    // Base 3 number of the canonical board, the same for all its symmetries
+    fn key(board: &Board) -> u64 {
+        board.canonical().key()
+    }
+
+    fn value(&self, board: &Board) -> f32 {
+        self.values.get(&Agent::key(board)).copied().unwrap_or(0.0)
+    }
+
+    // The moves leading to the highest valued positions for `mark`, and that value
+    fn best_moves(&self, board: &Board, mark: State) -> (f32, MoveList) {
+        let mut best = MoveList::new();
+        let mut best_value = f32::MIN;
+        for index in self.rules.legal_moves(board) {
+            let mut after = *board;
+            after[index] = mark;
+            let value = self.value(&after);
+            if value > best_value {
+                best_value = value;
+                best.clear();
+            }
+            if value == best_value {
+                best.push(index);
+            }
+        }
+        (best_value, best)
+    }
+
+    fn update(&mut self, board: &Board, target: f32, learning_rate: f32) {
+        let value = self.values.entry(Agent::key(board)).or_insert(0.0);
+        *value += learning_rate * (target - *value);
+    }
+
+    // One game against itself. Each side's previous position is pulled towards the
+    // opposite of the best the other side can reach from it.
+    fn train_episode(&mut self, config: &TrainingConfig, rng: &mut impl Rng) {
+        let mut board = self.rules.new_board();
+        let mut mark = State::X;
+        let mut previous: Option<Board> = None;
+        loop {
+            let (best_value, best) = self.best_moves(&board, mark);
+            if best.is_empty() {
+                return;
+            }
+            if let Some(previous) = previous {
+                let target = -config.discount * best_value;
+                self.update(&previous, target, config.learning_rate);
+            }
+            let index = if rng.gen_bool(config.exploration) {
+                let moves = self.rules.legal_moves(&board);
+                moves[rng.gen_range(0..moves.len())]
+            } else {
+                best[rng.gen_range(0..best.len())]
+            };
+            board[index] = mark;
+            if board.has_line(mark, self.rules.win_len) {
+                self.update(&board, 1.0, config.learning_rate);
+                return;
+            }
+            if self.rules.legal_moves(&board).is_empty() {
+                self.update(&board, 0.0, config.learning_rate);
+                return;
+            }
+            previous = Some(board);
+            mark = mark.opponent();
+        }
+    }
+}
+
+// Trains a new agent by self-play
+pub fn train(rules: Rules, config: &TrainingConfig, rng: &mut impl Rng) -> Result<Agent, String> {
+    let mut agent = Agent::new(rules)?;
+    for _ in 0..config.episodes {
+        agent.train_episode(config, rng);
+    }
+    Ok(agent)
+}
+
+impl Player for Agent {
+    fn choose_move(
+        &self,
+        board: &Board,
+        rules: &Rules,
+        mark: State,
+        rng: &mut dyn RngCore,
+    ) -> Option<MoveDecision> {
+        // A table learned on other rules is no use, play whatever is legal
+        let (_, best) = if *rules == self.rules {
+            self.best_moves(board, mark)
+        } else {
+            (0.0, rules.legal_moves(board))
+        };
+        if best.is_empty() {
+            return None;
+        }
+        Some(MoveDecision {
+            index: best[rng.gen_range(0..best.len())],
+            reason: Reason::Learned,
+            search: None,
+        })
+    }
+}
+
+impl fmt::Display for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "learned ({} positions)", self.values.len())
+    }
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Agent")
+            .field("rules", &self.rules)
+            .field("states", &self.values.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Cpu, Difficulty};
+    use crate::arena::play_between;
+    use crate::game::Status;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn trained(episodes: u32) -> Agent {
+        let config = TrainingConfig {
+            episodes,
+            ..TrainingConfig::default()
+        };
+        train(Rules::default(), &config, &mut StdRng::seed_from_u64(3)).unwrap()
+    }
+
+    #[test]
+    fn trained_agent_beats_the_random_cpu() {
+        let agent = trained(20_000);
+        let random = Cpu::from(Difficulty::Easy);
+        let mut rng = StdRng::seed_from_u64(9);
+        let (mut wins, mut losses) = (0, 0);
+        for game in 0..400 {
+            let (agent_mark, status) = if game % 2 == 0 {
+                (
+                    State::X,
+                    play_between(&Rules::default(), &agent, &random, &mut rng).0,
+                )
+            } else {
+                (
+                    State::O,
+                    play_between(&Rules::default(), &random, &agent, &mut rng).0,
+                )
+            };
+            match status {
+                Status::Won(mark) if mark == agent_mark => wins += 1,
+                Status::Won(_) => losses += 1,
+                _ => (),
+            }
+        }
+        assert!(
+            wins * 100 >= (wins + losses) * 85,
+            "{} wins, {} losses",
+            wins,
+            losses
+        );
+    }
+
+    #[test]
+    fn symmetric_positions_share_values() {
+        let agent = trained(2_000);
+        // 765 positions can come up on a 3x3 board up to symmetry
+        assert!(
+            agent.states() > 0 && agent.states() <= 765,
+            "{}",
+            agent.states()
+        );
+    }
+
+    #[test]
+    fn only_classic_flat_boards_can_be_learned() {
+        assert!(Agent::new(Rules::default()).is_ok());
+        assert!(Agent::new(Rules::gravity(6, 7)).is_err());
+        assert!(Agent::new(Rules::cube()).is_err());
+        let huge = Rules {
+            rows: 7,
+            cols: 7,
+            ..Rules::default()
+        };
+        assert!(Agent::new(huge).is_err());
+    }
+}
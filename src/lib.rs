@@ -0,0 +1,63 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+// Runtime code reports failures instead of panicking, tests may unwrap
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+#[cfg(feature = "serde")]
+pub mod achievements;
+pub mod ai;
+#[cfg(feature = "std")]
+pub mod arena;
+#[cfg(feature = "server")]
+pub mod auth;
+#[cfg(feature = "std")]
+pub mod big_board;
+pub mod board;
+#[cfg(feature = "serde")]
+pub mod book;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "std")]
+pub mod engine;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod game;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(feature = "server")]
+pub mod history;
+#[cfg(feature = "serde")]
+pub mod learn;
+#[cfg(feature = "server")]
+pub mod metrics;
+#[cfg(feature = "serde")]
+pub mod migrations;
+#[cfg(feature = "serde")]
+pub mod model;
+#[cfg(feature = "std")]
+pub mod position;
+#[cfg(feature = "std")]
+pub mod rate_limit;
+#[cfg(feature = "std")]
+pub mod referee;
+#[cfg(feature = "serde")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod rng;
+pub mod rules;
+#[cfg(feature = "serde")]
+pub mod save;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "std")]
+pub mod session;
+#[cfg(feature = "std")]
+pub mod session_log;
+#[cfg(feature = "std")]
+pub mod settings;
+#[cfg(feature = "std")]
+pub mod timestamp;
+#[cfg(feature = "std")]
+pub mod tree;
@@ -1,5 +1,1758 @@
-mod game;
+// Runtime code reports failures instead of panicking, tests may unwrap
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
 
-fn main() {
-    game::Game::new().start();
+use clap::{Args, Parser, Subcommand};
+use std::io::{self, IsTerminal};
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+#[cfg(feature = "server")]
+use std::num::NonZeroU32;
+use std::num::NonZeroUsize;
+use std::process::{self, ExitCode};
+#[cfg(feature = "serde")]
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fs, panic, thread};
+use tic_tac_toe_rs::ai::{self, Cpu, Difficulty, Personality, Player};
+use tic_tac_toe_rs::arena::{self, SimulationConfig};
+use tic_tac_toe_rs::board::{Board, State};
+use tic_tac_toe_rs::engine;
+use tic_tac_toe_rs::export::{self, Style};
+use tic_tac_toe_rs::game::{self, Game, Score, Scores, SessionSummary, Status};
+use tic_tac_toe_rs::position::Position;
+#[cfg(feature = "server")]
+use tic_tac_toe_rs::rate_limit::Limit;
+use tic_tac_toe_rs::referee::{self, EngineMatch, EngineProcess};
+#[cfg(feature = "serde")]
+use tic_tac_toe_rs::replay::{Pacing, Playback, Replay, ReplayRecorder, SystemClock};
+use tic_tac_toe_rs::rng::GameRng;
+use tic_tac_toe_rs::rules::{Rules, Variant};
+#[cfg(feature = "server")]
+use tic_tac_toe_rs::server::{self, ServerConfig};
+use tic_tac_toe_rs::session::Session;
+use tic_tac_toe_rs::session_log::FileLog;
+use tic_tac_toe_rs::settings::{Numbering, Settings};
+use tic_tac_toe_rs::tree;
+#[cfg(feature = "serde")]
+use tic_tac_toe_rs::{
+    book::Book,
+    learn::{self, TrainingConfig},
+    model::Model,
+    save::{self, SavedGame},
+};
+
+#[derive(Parser)]
+#[command(
+    name = "tic-tac-toe",
+    version,
+    about = "Tic-tac-toe against the computer",
+    after_help = "Exit codes: a single round played from a script (input that isn't a terminal) \
+exits with 0 if you won (X with --two-players), 1 if the Cpu (O) won and 2 for a tie. Other \
+sessions exit with 0. Usage and I/O errors exit with 3."
+)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // Without a subcommand the flags are those of `play`
+    #[command(flatten)]
+    play: PlayArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play against the CPU (the default)
+    Play(PlayArgs),
+    /// Step through a recorded .ttt file, or watch it play back
+    #[cfg(feature = "serde")]
+    Replay(ReplayArgs),
+    /// Show the value of every move in a position
+    Analyze(PositionArgs),
+    /// Solve a position and print the best line
+    Solve(PositionArgs),
+    /// Export the game tree of a position in Graphviz format
+    Tree(TreeArgs),
+    /// Draw a position as an SVG image or HTML page
+    Render(RenderArgs),
+    /// Let two CPUs play each other
+    Arena(ArenaArgs),
+    /// Let two CPUs play each other and compare how each first move does
+    Openings(OpeningsArgs),
+    /// Measure every difficulty against random and greedy reference players
+    Calibrate(CalibrateArgs),
+    /// Time the search and random playouts
+    Bench(BenchArgs),
+    /// Answer engine protocol commands on stdin, for other front-ends
+    #[command(long_flag = "engine")]
+    Engine,
+    /// Serve games against the CPU over an HTTP API
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+    /// Teach a CPU by playing against itself
+    #[cfg(feature = "serde")]
+    Train(TrainArgs),
+    /// Make opening books for the hard CPU
+    #[cfg(feature = "serde")]
+    Book(BookArgs),
+}
+
+// Flags shared by the subcommands that set up games of their own
+#[derive(Args, Clone)]
+struct CommonArgs {
+    /// Seed for the CPU's random choices, giving the same games on every platform
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Board size as ROWSxCOLS, e.g. 6x7
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(usize, usize)>,
+    /// Marks in a row needed to win
+    #[arg(long)]
+    win: Option<usize>,
+    /// CPU strength: easy, medium or hard
+    #[arg(long, default_value_t = Difficulty::Easy)]
+    difficulty: Difficulty,
+    /// CPU style below perfect play: balanced, aggressive or defensive
+    #[arg(long, default_value_t = Personality::Balanced)]
+    personality: Personality,
+    /// Moves the hard CPU looks ahead on boards too big to search to the end [default: 4]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    depth: Option<u16>,
+}
+
+impl CommonArgs {
+    fn rules(&self, variant: Variant, cube: bool) -> Result<Rules, String> {
+        let (rows, cols) = self.size.unwrap_or((3, 3));
+        let mut rules = match variant {
+            _ if cube => Rules {
+                variant,
+                ..Rules::cube()
+            },
+            Variant::Gravity => Rules::gravity(rows, cols),
+            _ => Rules {
+                rows,
+                cols,
+                win_len: rows.min(cols),
+                variant,
+                ..Rules::default()
+            },
+        };
+        if let Some(win_len) = self.win {
+            rules.win_len = win_len;
+        }
+        rules.validate()?;
+        Ok(rules)
+    }
+}
+
+#[derive(Args, Clone)]
+struct PlayArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Marks fall to the bottom of the chosen column
+    #[arg(long)]
+    gravity: bool,
+    /// Either side may place either mark
+    #[arg(long, conflicts_with_all = ["gravity", "numerical"])]
+    wild: bool,
+    /// Play on a 3x3x3 cube
+    #[arg(long, conflicts_with_all = ["gravity", "size"])]
+    cube: bool,
+    /// Odd digits against even digits, a line summing to 15 wins
+    #[arg(long, conflicts_with = "gravity")]
+    numerical: bool,
+    /// Start the next round without asking
+    #[arg(long)]
+    auto_rematch: bool,
+    /// Take turns opening each round
+    #[arg(long)]
+    alternate_opener: bool,
+    /// Show who is ahead after every move (toggle in game with `eval`)
+    #[arg(long)]
+    eval: bool,
+    /// Ask before placing each move (toggle in game with `confirm on|off`)
+    #[arg(long)]
+    confirm: bool,
+    /// Say why the CPU made each move
+    #[arg(long)]
+    explain: bool,
+    /// Offer to end a round as soon as best play decides it
+    #[arg(long)]
+    fast_finish: bool,
+    /// Number cells from 0 or from 1: zero-based or one-based (switch in game with `numbering`)
+    #[arg(long, default_value_t = Numbering::ZeroBased)]
+    numbering: Numbering,
+    /// Draw the board large, e.g. for a projector (normal size if the terminal is too narrow)
+    #[arg(long)]
+    big: bool,
+    /// Play the suggested move for you after this many seconds without one
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u32).range(1..))]
+    auto_play: Option<u32>,
+    /// Both sides play from the keyboard, taking turns
+    #[arg(long)]
+    two_players: bool,
+    /// Mark you play: x or o, the Cpu plays the other
+    #[arg(long, default_value = "x", value_parser = parse_side, conflicts_with = "two_players")]
+    mark: State,
+    /// Let the Cpu make the first move (X always opens numerical games)
+    #[arg(long, conflicts_with = "two_players")]
+    cpu_first: bool,
+    /// Play a match to this many won rounds
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    first_to: Option<u16>,
+    /// Play a match of this many rounds
+    #[arg(long, conflicts_with = "first_to", value_parser = clap::value_parser!(u16).range(1..))]
+    best_of: Option<u16>,
+    /// Settle a level best-of match with up to this many sudden-death rounds
+    #[arg(long, requires = "best_of", value_parser = clap::value_parser!(u16).range(1..))]
+    sudden_death: Option<u16>,
+    /// Play this many rounds without asking for a rematch, then show the summary and quit
+    #[arg(
+        long,
+        conflicts_with_all = ["first_to", "best_of"],
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    rounds: Option<u16>,
+    /// Show each turn as one line with the board, and only the summary at the end
+    #[arg(long, requires = "rounds")]
+    summary_only: bool,
+    /// Print span timings to stderr (needs the tracing feature)
+    #[arg(long)]
+    trace: bool,
+    /// Save every round as a .ttt replay in this directory
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "DIR")]
+    record: Option<String>,
+    /// Append session events as JSON lines to this file
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<String>,
+    /// Save a picture of every won round; {round} in PATH becomes the round number and
+    /// the extension picks .svg, .html or .png (needs the image-export feature)
+    #[arg(long, value_name = "PATH")]
+    snapshot_on_win: Option<String>,
+    /// Play in a desktop window instead of the terminal
+    #[cfg(feature = "gui")]
+    #[arg(long)]
+    gui: bool,
+    /// Play against a CPU trained with `train`
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "PATH")]
+    model: Option<String>,
+    /// Opening book made with `book build` for the hard CPU
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "PATH", conflicts_with = "model")]
+    book: Option<String>,
+    /// Threads the hard CPU searches on, one per core by default
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+    /// Show nothing but errors, the exit code tells who won a scripted round
+    #[arg(short, long, conflicts_with = "auto_play")]
+    quiet: bool,
+}
+
+#[derive(Args)]
+struct PositionArgs {
+    /// Position such as X...O....
+    #[arg(
+        value_name = "POSITION",
+        required_unless_present = "code",
+        conflicts_with = "code"
+    )]
+    board: Option<String>,
+    /// Code of a shared 3x3 position, as printed by `share` in the game
+    #[arg(long = "position", value_name = "CODE")]
+    code: Option<String>,
+    /// Marks in a row needed to win
+    #[arg(long)]
+    win: Option<usize>,
+}
+
+impl PositionArgs {
+    // The board, the side to move (X first unless a code says otherwise) and the rules
+    fn position(&self) -> Result<(Board, State, Rules), String> {
+        let (board, to_move) = match (&self.board, &self.code) {
+            (_, Some(code)) => {
+                Position::decode(code).map(|position| (position.board, position.to_move))?
+            }
+            (Some(board), None) => {
+                let board = board.parse::<Board>()?;
+                (board, board.to_move())
+            }
+            (None, None) => return Err("No position given".to_string()),
+        };
+        let rules = position_rules(&board, self.win)?;
+        Position::new(board, to_move, &rules)
+            .validate(&rules)
+            .map_err(|err| err.to_string())?;
+        Ok((board, to_move, rules))
+    }
+}
+
+#[derive(Args)]
+struct TreeArgs {
+    /// Position to start from, the empty board by default
+    #[arg(long)]
+    position: Option<String>,
+    #[arg(long, default_value_t = 2)]
+    depth: usize,
+    #[arg(long, default_value_t = 1000)]
+    max_nodes: usize,
+    /// Marks in a row needed to win
+    #[arg(long)]
+    win: Option<usize>,
+    /// Write the graph to a file instead of stdout
+    #[arg(long)]
+    out: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Args)]
+struct ReplayArgs {
+    file: String,
+    /// Play the moves back one by one instead of printing them all at once
+    #[arg(long)]
+    watch: bool,
+    /// Pause between the moves when watching, e.g. 1s or 250ms
+    #[arg(long, requires = "watch", default_value = "1s", value_parser = parse_delay)]
+    delay: Duration,
+    /// When watching, take as long over each move as the players did instead of --delay
+    #[arg(long, requires = "watch")]
+    realtime: bool,
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    #[command(flatten)]
+    position: PositionArgs,
+    /// Write to a .svg, .html or .png file instead of SVG to stdout
+    #[arg(long)]
+    out: Option<String>,
+    /// Side of a cell in pixels
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    cell: u32,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    addr: SocketAddr,
+    /// Minutes a game may go unused before it is dropped
+    #[arg(long, default_value_t = 30)]
+    idle_minutes: u64,
+    /// Moves each client may make per second, in bursts of as many
+    #[arg(long, default_value = "5")]
+    moves_per_second: NonZeroU32,
+    /// Games each client may start per minute, in bursts of as many
+    #[arg(long, default_value = "10")]
+    games_per_minute: NonZeroU32,
+    /// Refused requests in a row after which a client's games are dropped
+    #[arg(long, default_value_t = 50)]
+    abuse_threshold: u32,
+    /// Days a player's token from POST /players stays valid
+    #[arg(long, default_value_t = 30)]
+    token_days: u64,
+    /// Refuse games started without a token instead of tying them to the client's address
+    #[arg(long)]
+    require_auth: bool,
+    /// Matches against other people each client may play at once
+    #[arg(long, default_value_t = 3)]
+    matches_per_client: usize,
+    /// Keep finished matches in this SQLite database, served at GET /history
+    #[arg(long, value_name = "FILE")]
+    history: Option<String>,
+    /// Let pages from this origin use the API, e.g. http://localhost:3000 while working on
+    /// the dashboard
+    #[arg(long)]
+    cors_origin: Option<String>,
+}
+
+#[derive(Args)]
+struct ArenaArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Number of games to play
+    #[arg(long, default_value_t = 100)]
+    games: u32,
+    /// Strength of X, the shared difficulty by default
+    #[arg(long)]
+    x: Option<Difficulty>,
+    /// Strength of O, the shared difficulty by default
+    #[arg(long)]
+    o: Option<Difficulty>,
+    /// Style of X, the shared personality by default
+    #[arg(long)]
+    x_personality: Option<Personality>,
+    /// Style of O, the shared personality by default
+    #[arg(long)]
+    o_personality: Option<Personality>,
+    /// Let a model saved by `train` play instead of one CPU
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "PATH")]
+    model: Option<String>,
+    /// Side the model plays: x or o
+    #[cfg(feature = "serde")]
+    #[arg(long, default_value = "x", value_parser = parse_side)]
+    model_side: State,
+    /// Also play as many games with the sides swapped
+    #[arg(long)]
+    both_sides: bool,
+    /// Command starting an engine to play X instead of a CPU, e.g. "tic-tac-toe engine"
+    #[arg(long, value_name = "COMMAND", requires = "engine_o")]
+    engine_x: Option<String>,
+    /// Command starting an engine to play O
+    #[arg(long, value_name = "COMMAND", requires = "engine_x")]
+    engine_o: Option<String>,
+    /// Milliseconds an engine may think per move before it forfeits
+    #[arg(long, default_value_t = 100)]
+    movetime: u64,
+    /// Also write the report as JSON to this file
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "PATH")]
+    report_json: Option<String>,
+    /// Threads to play on, one per core by default
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+}
+
+#[derive(Args)]
+struct OpeningsArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Number of games to play in each pairing
+    #[arg(long, default_value_t = 10_000)]
+    games: u32,
+    /// Strength of the first CPU, the shared difficulty by default
+    #[arg(long)]
+    a: Option<Difficulty>,
+    /// Strength of the second CPU, the shared difficulty by default
+    #[arg(long)]
+    b: Option<Difficulty>,
+    /// Threads to play on, one per core by default
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+}
+
+#[derive(Args)]
+struct CalibrateArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Number of games each difficulty plays against each reference, half of them as X
+    #[arg(long, default_value_t = 200)]
+    games: u32,
+    /// Threads to play on, one per core by default
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Args)]
+struct TrainArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Number of games to play against itself
+    #[arg(long, default_value_t = 100_000)]
+    episodes: u32,
+    /// File to save the learned values to
+    #[arg(long, value_name = "PATH")]
+    out: String,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Args)]
+struct BookArgs {
+    #[command(subcommand)]
+    command: BookCommand,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Subcommand)]
+enum BookCommand {
+    /// Solve every position of the first moves and save the best replies
+    Build(BookBuildArgs),
+}
+
+#[cfg(feature = "serde")]
+#[derive(Args)]
+struct BookBuildArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Moves into the game to solve
+    #[arg(long, default_value_t = 3)]
+    plies: usize,
+    /// File to save the book to
+    #[arg(long, value_name = "PATH")]
+    out: String,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// Number of random playouts to time
+    #[arg(long, default_value_t = 10_000)]
+    playouts: u32,
+    /// Threads to play on, one per core by default
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+}
+
+fn parse_side(value: &str) -> Result<State, String> {
+    match value.to_lowercase().as_str() {
+        "x" => Ok(State::X),
+        "o" => Ok(State::O),
+        _ => Err(format!("Unknown side: {} (x or o)", value)),
+    }
+}
+
+// Parse "ROWSxCOLS" such as "6x7"
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    value
+        .split_once('x')
+        .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)))
+        .ok_or(format!("Invalid board size: {}", value))
+}
+
+// A pause like "1s", "1.5s" or "250ms"
+#[cfg(feature = "serde")]
+fn parse_delay(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid delay: {} (use e.g. 1s or 250ms)", value);
+    if let Some(millis) = value.strip_suffix("ms") {
+        millis
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| invalid())
+    } else {
+        value
+            .strip_suffix('s')
+            .and_then(|seconds| seconds.parse().ok())
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(invalid)
+    }
+}
+
+// Classic rules fitting a parsed position
+fn position_rules(board: &Board, win: Option<usize>) -> Result<Rules, String> {
+    let rules = Rules {
+        rows: board.rows(),
+        cols: board.cols(),
+        layers: board.layers(),
+        win_len: win.unwrap_or(board.rows().min(board.cols())),
+        ..Rules::default()
+    };
+    rules.validate()?;
+    Ok(rules)
+}
+
+// Print span timings (busy/idle) to stderr as each span closes
+#[cfg(feature = "tracing")]
+fn install_tracing() {
+    use tracing_subscriber::fmt::format::FmtSpan;
+    tracing_subscriber::fmt()
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+#[cfg(not(feature = "tracing"))]
+fn install_tracing() {}
+
+// Tracing is set up once for the whole run, however many games it plays
+fn start_tracing(args: &PlayArgs) -> Result<(), String> {
+    if args.trace && !cfg!(feature = "tracing") {
+        return Err("--trace needs a build with the tracing feature".to_string());
+    }
+    if args.trace {
+        install_tracing();
+    }
+    Ok(())
+}
+
+// Exit codes, listed in --help. A scripted round exits with who won it.
+const EXIT_PLAYER_WIN: u8 = 0;
+const EXIT_CPU_WIN: u8 = 1;
+const EXIT_TIE: u8 = 2;
+const EXIT_ERROR: u8 = 3;
+
+// The exit code of a finished session: a scripted one playing a single round tells who
+// won it, any other exits with 0
+fn exit_code(summary: &SessionSummary, scripted: bool) -> u8 {
+    if !scripted || summary.rounds != 1 {
+        return EXIT_PLAYER_WIN;
+    }
+    if summary.score.player == 1 {
+        EXIT_PLAYER_WIN
+    } else if summary.score.cpu == 1 {
+        EXIT_CPU_WIN
+    } else {
+        EXIT_TIE
+    }
+}
+
+fn run_play(args: PlayArgs) -> Result<ExitCode, String> {
+    set_search_threads(args.threads)?;
+    start_tracing(&args)?;
+    #[cfg(feature = "gui")]
+    if args.gui {
+        return tic_tac_toe_rs::gui::run(new_game(&args)?).map(|()| ExitCode::SUCCESS);
+    }
+    // Asking to resume would break the silence
+    let resumed = if args.quiet { None } else { resume_paused() };
+    let game = match resumed {
+        Some(game) => attach(&args, game)?,
+        None => new_game(&args)?,
+    };
+    let mut session = session(&args, game);
+    session.set_quiet(args.quiet);
+    let summary = session.start();
+    if !args.quiet {
+        let [player, cpu] = summary.sides;
+        println!("Rounds played: {}", summary.rounds);
+        println!("Distinct games: {}", summary.distinct_games);
+        print!("{}", summary.score.table(player, cpu));
+        if summary.scores.len() > 1 {
+            print!("By opponent:\n{}", game::scores_table(&summary.scores));
+        }
+        println!("Time played: {}s", summary.duration.as_secs());
+        println!("Time on moves: {}", summary.times.line(player, cpu));
+    }
+    let scripted = !io::stdin().is_terminal();
+    Ok(ExitCode::from(exit_code(&summary, scripted)))
+}
+
+// `game` in the first slot of a session, later slots set up by the same flags
+fn session(args: &PlayArgs, game: Game) -> Session {
+    let args = args.clone();
+    Session::new("main", game, move |two_players| {
+        new_game(&PlayArgs {
+            two_players,
+            ..args.clone()
+        })
+    })
+}
+
+// A game set up as the play flags ask, ready to start
+fn new_game(args: &PlayArgs) -> Result<Game, String> {
+    let variant = if args.gravity {
+        Variant::Gravity
+    } else if args.wild {
+        Variant::Wild
+    } else if args.numerical {
+        Variant::Numerical
+    } else {
+        Variant::Classic
+    };
+    let rules = args.common.rules(variant, args.cube)?;
+    let settings = Settings {
+        auto_rematch: args.auto_rematch,
+        alternate_opener: args.alternate_opener,
+        first_to: args.first_to,
+        best_of: args.best_of,
+        sudden_death: args.sudden_death,
+        difficulty: args.common.difficulty,
+        personality: args.common.personality,
+        depth: args.common.depth.map(usize::from),
+        show_eval: args.eval,
+        confirm_moves: args.confirm,
+        explain: args.explain,
+        two_players: args.two_players,
+        big_board: args.big,
+        numbering: args.numbering,
+        auto_play: args.auto_play,
+        fast_finish: args.fast_finish,
+        rounds: args.rounds,
+        summary_only: args.summary_only,
+    };
+    let rng = GameRng::from_seed(args.common.seed);
+    let mut game = Game::with_rng(rules, settings, rng);
+    game.set_sides(args.mark, args.cpu_first)
+        .map_err(|err| err.to_string())?;
+    attach(args, game)
+}
+
+// Sets up what the flags ask for around a new or resumed game: logs, replays, the model
+// and where `pause` saves
+fn attach(args: &PlayArgs, mut game: Game) -> Result<Game, String> {
+    if let Some(path) = &args.log_file {
+        let log = FileLog::create(path).map_err(|err| format!("Can't open {}: {}", path, err))?;
+        game.add_observer(Box::new(log));
+    }
+    if let Some(template) = &args.snapshot_on_win {
+        game.set_snapshot_on_win(template.clone());
+    }
+    #[cfg(feature = "serde")]
+    if let Some(dir) = &args.record {
+        let recorder = ReplayRecorder::new(dir, game.rules(), game.settings().difficulty)?;
+        game.add_observer(Box::new(recorder.with_seed(args.common.seed)));
+    }
+    #[cfg(feature = "serde")]
+    if let Some(path) = &args.model {
+        let model = load_model(path, &game.rules())?;
+        game.set_player(Arc::new(model));
+    }
+    #[cfg(feature = "serde")]
+    if let Some(path) = &args.book {
+        let book = Book::load(path)
+            .and_then(|book| {
+                if book.rules() == game.rules() {
+                    Ok(book)
+                } else {
+                    Err("the book is for other rules".to_string())
+                }
+            })
+            .map_err(|err| format!("Can't load {}: {}", path, err))?;
+        game.set_book(Arc::new(book));
+    }
+    #[cfg(feature = "serde")]
+    if let Some(path) = save::autosave_path() {
+        game.set_autosave(path);
+    }
+    #[cfg(feature = "serde")]
+    if let Some(dir) = save::data_dir() {
+        game.set_stats_file(dir.join("stats.json"));
+    }
+    Ok(game)
+}
+
+// The game paused last session, if there is one and the player wants it back. A broken
+// autosave is only warned about, it's replaced by the next pause.
+#[cfg(feature = "serde")]
+fn resume_paused() -> Option<Game> {
+    let path = save::autosave_path().filter(|path| path.exists())?;
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    let resumed = SavedGame::load(&path).and_then(|saved| {
+        let (date, rng) = (saved.date.clone(), saved.rng());
+        Game::from_state(saved.state, rng)
+            .map(|game| (game, date))
+            .map_err(|err| err.to_string())
+    });
+    let (game, date) = match resumed {
+        Ok(resumed) => resumed,
+        Err(err) => {
+            eprintln!("Warning: ignoring {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let question = format!("Resume the game paused on {}? (y/n)", date);
+    loop {
+        match read_text(&mut Terminal, &question)?.to_lowercase().as_str() {
+            "y" | "yes" => return Some(game),
+            "n" | "no" => return None,
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn resume_paused() -> Option<Game> {
+    None
+}
+
+// Pause between the moves of a watched game
+const WATCH_DELAY: Duration = Duration::from_millis(500);
+
+// Totals of everything played from the menu
+#[derive(Default)]
+struct MenuStats {
+    vs_cpu: Score,
+    two_players: Score,
+    watched: Score,
+    played: Duration,
+    // Rounds played from the keyboard, by opponent
+    scores: Scores,
+}
+
+impl MenuStats {
+    // Counts a session played against the CPU or between two players
+    fn add(&mut self, summary: &SessionSummary, two_players: bool) {
+        if two_players {
+            self.two_players += summary.score;
+        } else {
+            self.vs_cpu += summary.score;
+        }
+        for (opponent, score) in &summary.scores {
+            *self.scores.entry(opponent.clone()).or_default() += *score;
+        }
+        self.played += summary.duration;
+    }
+}
+
+// What the menu reads from and shows on, and where the games started from it are played
+trait MenuIo {
+    // A trimmed line, None at the end of input
+    fn read_line(&mut self) -> Option<String>;
+    fn show(&mut self, line: &str);
+    fn play(&mut self, session: &mut Session) -> SessionSummary;
+}
+
+struct Terminal;
+
+impl MenuIo for Terminal {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+
+    fn show(&mut self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn play(&mut self, session: &mut Session) -> SessionSummary {
+        session.start()
+    }
+}
+
+// Shown when started without arguments on a terminal; every mode comes back here
+fn run_menu(args: PlayArgs) -> Result<(), String> {
+    set_search_threads(args.threads)?;
+    start_tracing(&args)?;
+    let resumed = match resume_paused() {
+        Some(game) => Some(attach(&args, game)?),
+        None => None,
+    };
+    menu(&args, resumed, &mut Terminal)
+}
+
+// The menu, after playing `resumed` if a paused game was taken up again. Games are played
+// in a session like those started with `play`, so `game new` and the other slot commands
+// work the same.
+fn menu(args: &PlayArgs, resumed: Option<Game>, io: &mut impl MenuIo) -> Result<(), String> {
+    let mut stats = MenuStats::default();
+    if let Some(game) = resumed {
+        let two_players = game.settings().two_players;
+        let summary = io.play(&mut session(args, game));
+        stats.add(&summary, two_players);
+    }
+    loop {
+        io.show("");
+        io.show("1. Play vs CPU");
+        io.show("2. Two players");
+        io.show("3. Watch AI vs AI");
+        io.show("4. Replay a file");
+        io.show("5. Statistics");
+        io.show("6. Quit");
+        let result = match read_choice(io, "Choose 1 to 6:", 6) {
+            Some(1) => read_difficulty(io, "Cpu difficulty").map_or(Ok(()), |difficulty| {
+                let mut args = args.clone();
+                args.common.difficulty = difficulty;
+                new_game(&args).map(|game| {
+                    let summary = io.play(&mut session(&args, game));
+                    stats.add(&summary, false);
+                })
+            }),
+            Some(2) => {
+                let mut args = args.clone();
+                args.two_players = true;
+                new_game(&args).map(|game| {
+                    let summary = io.play(&mut session(&args, game));
+                    stats.add(&summary, true);
+                })
+            }
+            Some(3) => match (
+                read_difficulty(io, "X difficulty"),
+                read_difficulty(io, "O difficulty"),
+            ) {
+                (Some(x), Some(o)) => watch(args, x, o).map(|status| {
+                    let (player, cpu, tie) = match status {
+                        Status::Won(State::X) => (1, 0, 0),
+                        Status::Won(_) => (0, 1, 0),
+                        _ => (0, 0, 1),
+                    };
+                    stats.watched += Score { player, cpu, tie };
+                }),
+                _ => Ok(()),
+            },
+            #[cfg(feature = "serde")]
+            Some(4) => read_text(io, "Replay file:").map_or(Ok(()), |path| run_replay(&path, None)),
+            #[cfg(not(feature = "serde"))]
+            Some(4) => Err("Replays need a build with the serde feature".to_string()),
+            Some(5) => {
+                io.show("Against the Cpu:");
+                io.show(stats.vs_cpu.table("You", "Cpu").trim_end());
+                io.show("Two players:");
+                io.show(stats.two_players.table("X", "O").trim_end());
+                io.show("Watched:");
+                io.show(stats.watched.table("X", "O").trim_end());
+                if !stats.scores.is_empty() {
+                    io.show("By opponent:");
+                    io.show(game::scores_table(&stats.scores).trim_end());
+                }
+                io.show(&format!("Time played: {}s", stats.played.as_secs()));
+                Ok(())
+            }
+            _ => return Ok(()),
+        };
+        if let Err(err) = result {
+            io.show(&err);
+        }
+    }
+}
+
+// Asks until a number from 1 to `count` is given; None at the end of input
+fn read_choice(io: &mut impl MenuIo, prompt: &str, count: usize) -> Option<usize> {
+    loop {
+        let answer = read_text(io, prompt)?;
+        match answer.parse::<usize>() {
+            Ok(choice) if (1..=count).contains(&choice) => return Some(choice),
+            _ => io.show(&format!("Please enter a number from 1 to {}", count)),
+        }
+    }
+}
+
+fn read_difficulty(io: &mut impl MenuIo, what: &str) -> Option<Difficulty> {
+    let prompt = format!("{}: 1. easy 2. medium 3. hard", what);
+    let levels = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+    read_choice(io, &prompt, levels.len()).map(|choice| levels[choice - 1])
+}
+
+// A trimmed line after showing `prompt`, None at the end of input
+fn read_text(io: &mut impl MenuIo, prompt: &str) -> Option<String> {
+    io.show(prompt);
+    io.read_line()
+}
+
+// Plays one game between two CPUs, showing every move
+fn watch(args: &PlayArgs, x: Difficulty, o: Difficulty) -> Result<Status, String> {
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let mut rng = GameRng::from_seed(args.common.seed);
+    let mut board = rules.new_board();
+    let mut mark = State::X;
+    loop {
+        let cpu = Cpu {
+            difficulty: if mark == State::X { x } else { o },
+            personality: args.common.personality,
+            depth: args.common.depth.map(usize::from),
+        };
+        let decision = match ai::choose_move(&board, &rules, mark, cpu, &mut rng) {
+            Some(decision) => decision,
+            None => break,
+        };
+        board[decision.index] = mark;
+        println!(
+            "{:?} ({}) {}",
+            mark,
+            cpu,
+            game::explain(decision, Numbering::ZeroBased)
+        );
+        print_board(&board);
+        if board.has_line(mark, rules.win_len) {
+            println!("** {:?} wins! **", mark);
+            return Ok(Status::Won(mark));
+        }
+        mark = mark.opponent();
+        thread::sleep(WATCH_DELAY);
+    }
+    println!("** Tie! **");
+    Ok(Status::Tie)
+}
+
+// One row of cells per line, as in replays
+fn print_board(board: &Board) {
+    let cells = board.to_string();
+    let chars: Vec<char> = cells.chars().collect();
+    for row in chars.chunks(board.cols()) {
+        let row: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+        println!("  {}", row.join("  "));
+    }
+}
+
+// tic-tac-toe tree --position "X...O...." --depth 4 --out tree.dot
+fn run_tree(args: TreeArgs) -> Result<(), String> {
+    let board = match &args.position {
+        Some(position) => position.parse::<Board>()?,
+        None => Rules::default().new_board(),
+    };
+    let rules = position_rules(&board, args.win)?;
+    Position::new(board, board.to_move(), &rules)
+        .validate(&rules)
+        .map_err(|err| err.to_string())?;
+    let dot = tree::export_dot(&board, &rules, args.depth, args.max_nodes);
+    match args.out {
+        Some(path) => fs::write(&path, dot).map_err(|err| format!("Can't write {}: {}", path, err)),
+        None => {
+            print!("{}", dot);
+            Ok(())
+        }
+    }
+}
+
+// tic-tac-toe render "XXXOO...." --out win.svg
+fn run_render(args: RenderArgs) -> Result<(), String> {
+    let (board, to_move, rules) = args.position.position()?;
+    let position = Position::new(board, to_move, &rules);
+    let line = position.winning_line(&rules);
+    let style = Style {
+        cell: args.cell,
+        ..Style::default()
+    };
+    match args.out {
+        Some(path) => export::export(&path, &position, line, &style),
+        None => {
+            print!("{}", export::svg(&position, line, &style));
+            Ok(())
+        }
+    }
+}
+
+// tic-tac-toe solve "XX.OO...."
+fn run_solve(args: PositionArgs) -> Result<(), String> {
+    let (board, to_move, rules) = args.position()?;
+
+    let exact = board.count(State::Empty) <= ai::MAX_SEARCH_CELLS;
+    let (score, pv) = if exact {
+        ai::solve(&board, &rules, to_move)
+    } else {
+        let search = ai::search_to_depth(&board, &rules, to_move, ai::DEFAULT_DEPTH, &mut || false)
+            .ok_or("The search was stopped")?;
+        (search.score, search.pv)
+    };
+    let pv: Vec<String> = pv.iter().map(|index| index.to_string()).collect();
+    println!("Position: {}", board);
+    if !exact {
+        println!(
+            "Too big to solve exactly, searched {} plies ahead",
+            ai::DEFAULT_DEPTH
+        );
+    }
+    if exact || ai::is_decisive(score) {
+        let result = match score {
+            0 => "draws",
+            s if s > 0 => "wins",
+            _ => "loses",
+        };
+        println!(
+            "{:?} to move {} with best play ({})",
+            to_move,
+            result,
+            ai::describe(score, to_move)
+        );
+        println!(
+            "Distance: {} {}",
+            pv.len(),
+            if pv.len() == 1 { "ply" } else { "plies" }
+        );
+    } else {
+        println!("{:?} to move, {}", to_move, estimate_text(score, to_move));
+    }
+    println!("Principal variation: {}", pv.join(" "));
+    Ok(())
+}
+
+// An estimate from a search that stopped short, for the side it scores
+fn estimate_text(score: i32, to_move: State) -> String {
+    format!("estimated {:+} for {:?}", score, to_move)
+}
+
+// tic-tac-toe analyze "X...O...."
+fn run_analyze(args: PositionArgs) -> Result<(), String> {
+    let (board, to_move, rules) = args.position()?;
+    if rules.winner(&board).is_some() {
+        return Err("The game is already over in this position".to_string());
+    }
+
+    println!("Position: {}", board);
+    let exact = board.count(State::Empty) <= ai::MAX_SEARCH_CELLS;
+    if !exact {
+        println!(
+            "Too big to solve exactly, searched {} plies ahead",
+            ai::DEFAULT_DEPTH
+        );
+    }
+    println!("{:?} to move", to_move);
+    let mut moves = Vec::new();
+    for index in rules.legal_moves(&board) {
+        let mut child = board;
+        child[index] = to_move;
+        let score = if exact {
+            ai::evaluate(&child, &rules, to_move.opponent())
+        } else {
+            // The move itself is the first of the plies
+            let depth = ai::DEFAULT_DEPTH - 1;
+            ai::search_to_depth(&child, &rules, to_move.opponent(), depth, &mut || false)
+                .ok_or("The search was stopped")?
+                .score
+        };
+        moves.push((index, -score));
+    }
+    moves.sort_by_key(|&(index, score)| (-score, index));
+    for (index, score) in moves {
+        let outcome = if exact || ai::is_decisive(score) {
+            ai::describe(score, to_move)
+        } else {
+            estimate_text(score, to_move)
+        };
+        println!("{:>3}: {}", index, outcome);
+    }
+    Ok(())
+}
+
+// Sizes rayon's global pool, which the CPU's search runs on, for the rest of the process
+fn set_search_threads(threads: Option<NonZeroUsize>) -> Result<(), String> {
+    match threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.get())
+            .build_global()
+            .map_err(|err| format!("Can't start {} threads: {}", threads, err)),
+        None => Ok(()),
+    }
+}
+
+// Runs `f` on a pool of `threads` threads, or on rayon's default pool (one per core)
+fn in_pool<T: Send>(
+    threads: Option<NonZeroUsize>,
+    f: impl FnOnce() -> T + Send,
+) -> Result<T, String> {
+    match threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.get())
+            .build()
+            .map(|pool| pool.install(f))
+            .map_err(|err| format!("Can't start {} threads: {}", threads, err)),
+        None => Ok(f()),
+    }
+}
+
+// tic-tac-toe arena --x hard --o medium --games 1000
+fn run_arena(args: ArenaArgs) -> Result<(), String> {
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let x = Cpu {
+        difficulty: args.x.unwrap_or(args.common.difficulty),
+        personality: args.x_personality.unwrap_or(args.common.personality),
+        depth: args.common.depth.map(usize::from),
+    };
+    let o = Cpu {
+        difficulty: args.o.unwrap_or(args.common.difficulty),
+        personality: args.o_personality.unwrap_or(args.common.personality),
+        depth: args.common.depth.map(usize::from),
+    };
+    if let (Some(x), Some(o)) = (&args.engine_x, &args.engine_o) {
+        return run_engine_arena(&args, rules, x, o);
+    }
+    let seed = args.common.seed.unwrap_or(0);
+    let (mut x, mut o): (&(dyn Player + Sync), &(dyn Player + Sync)) = (&x, &o);
+    #[cfg(feature = "serde")]
+    let model;
+    #[cfg(feature = "serde")]
+    if let Some(path) = &args.model {
+        model = load_model(path, &rules)?;
+        match args.model_side {
+            State::O => o = &model,
+            _ => x = &model,
+        }
+    }
+    let mut pairings = Vec::new();
+    for _ in 0..if args.both_sides { 2 } else { 1 } {
+        let report = in_pool(args.threads, || {
+            arena::simulate_between(&rules, x, o, seed, args.games)
+        })?;
+        pairings.push((format!("X {} vs O {}", x, o), report));
+        (x, o) = (o, x);
+    }
+    let rows = arena::report_rows(&pairings);
+    print!("{}", arena::report_table(&rows));
+    #[cfg(feature = "serde")]
+    write_report_json(&args, &rows)?;
+    Ok(())
+}
+
+// tic-tac-toe arena --engine-x "tic-tac-toe engine" --engine-o ./other-engine --games 10
+fn run_engine_arena(args: &ArenaArgs, rules: Rules, x: &str, o: &str) -> Result<(), String> {
+    #[cfg(feature = "serde")]
+    if args.model.is_some() {
+        return Err("--model can't play against engines".to_string());
+    }
+    // Positions go out as square boards, which engines read with full rows to win
+    if rules.rows != rules.cols || rules.win_len != rules.rows {
+        return Err("Engines only play square boards won with a full row".to_string());
+    }
+    let config = EngineMatch {
+        rules,
+        games: args.games,
+        movetime: Duration::from_millis(args.movetime),
+    };
+    let (mut x, mut o) = (EngineProcess::spawn(x)?, EngineProcess::spawn(o)?);
+    let mut pairings = Vec::new();
+    let mut forfeits = Vec::new();
+    for _ in 0..if args.both_sides { 2 } else { 1 } {
+        let pairing = format!("X {} vs O {}", x.command(), o.command());
+        let result = referee::play_engines(&config, &mut x, &mut o)?;
+        for (game, side, forfeit) in result.forfeits {
+            forfeits.push(format!(
+                "Game {} of {}: {:?} lost by {}",
+                game, pairing, side, forfeit
+            ));
+        }
+        pairings.push((pairing, result.report));
+        (x, o) = (o, x);
+    }
+    let rows = arena::report_rows(&pairings);
+    print!("{}", arena::report_table(&rows));
+    #[cfg(feature = "serde")]
+    write_report_json(args, &rows)?;
+    for forfeit in forfeits {
+        println!("{}", forfeit);
+    }
+    Ok(())
+}
+
+// Writes the rows given to --report-json, if any
+#[cfg(feature = "serde")]
+fn write_report_json(args: &ArenaArgs, rows: &[arena::ReportRow]) -> Result<(), String> {
+    if let Some(path) = &args.report_json {
+        let json = serde_json::to_string_pretty(rows).map_err(|err| err.to_string())?;
+        fs::write(path, json + "\n").map_err(|err| format!("Can't write {}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+// tic-tac-toe openings --games 100000 --a random --b heuristic
+fn run_openings(args: OpeningsArgs) -> Result<(), String> {
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let cpu = |difficulty: Option<Difficulty>| Cpu {
+        difficulty: difficulty.unwrap_or(args.common.difficulty),
+        personality: args.common.personality,
+        depth: args.common.depth.map(usize::from),
+    };
+    let (a, b) = (cpu(args.a), cpu(args.b));
+    let seed = args.common.seed.unwrap_or(0);
+    for (x, o) in [(&a, &b), (&b, &a)] {
+        let report = in_pool(args.threads, || {
+            arena::simulate_openings(&rules, x, o, seed, args.games)
+        })?;
+        println!("X {} vs O {}", x, o);
+        println!("X win rate by first move:");
+        print!("{}", report.grid());
+        println!();
+        print!("{}", arena::titled_table("First move", &report.rows()));
+        println!();
+    }
+    Ok(())
+}
+
+// tic-tac-toe calibrate --games 1000 --seed 7
+fn run_calibrate(args: CalibrateArgs) -> Result<(), String> {
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let cpus = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard].map(|difficulty| Cpu {
+        difficulty,
+        personality: args.common.personality,
+        depth: args.common.depth.map(usize::from),
+    });
+    let seed = args.common.seed.unwrap_or(0);
+    let report = in_pool(args.threads, || {
+        arena::calibrate(&rules, &cpus, seed, args.games)
+    })?;
+    print!("{}", report.table());
+    Ok(())
+}
+
+// tic-tac-toe serve --addr 0.0.0.0:8080
+#[cfg(feature = "server")]
+fn run_serve(args: ServeArgs) -> Result<(), String> {
+    let config = ServerConfig {
+        idle: Duration::from_secs(args.idle_minutes * 60),
+        moves: Limit {
+            burst: args.moves_per_second.get(),
+            per_second: args.moves_per_second.get() as f64,
+        },
+        games: Limit {
+            burst: args.games_per_minute.get(),
+            per_second: args.games_per_minute.get() as f64 / 60.0,
+        },
+        abuse_threshold: args.abuse_threshold,
+        token_lifetime: Duration::from_secs(args.token_days * 24 * 60 * 60),
+        anonymous: !args.require_auth,
+        matches: args.matches_per_client,
+        history: args.history,
+        cors_origin: args.cors_origin,
+    };
+    server::serve(args.addr, config)
+}
+
+// tic-tac-toe bench --playouts 100000
+fn run_bench(args: BenchArgs) -> Result<(), String> {
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let board = rules.new_board();
+
+    let started = Instant::now();
+    if board.count(State::Empty) <= ai::MAX_SEARCH_CELLS {
+        let (score, _) = ai::solve(&board, &rules, board.to_move());
+        println!(
+            "Solve empty {}x{}: {} in {:.2?}",
+            rules.rows,
+            rules.cols,
+            ai::describe(score, board.to_move()),
+            started.elapsed()
+        );
+    } else {
+        // Too big to solve, time a search as deep as the hard CPU's instead
+        let depth = args.common.depth.map_or(ai::DEFAULT_DEPTH, usize::from);
+        let search = ai::search_to_depth(&board, &rules, board.to_move(), depth, &mut || false)
+            .ok_or("The search was stopped")?;
+        println!(
+            "Search empty {}x{} {} plies deep: {} nodes in {:.2?}",
+            rules.rows,
+            rules.cols,
+            search.depth,
+            search.nodes,
+            started.elapsed()
+        );
+    }
+
+    let cpu = Cpu {
+        difficulty: args.common.difficulty,
+        personality: args.common.personality,
+        depth: args.common.depth.map(usize::from),
+    };
+    let config = SimulationConfig {
+        rules,
+        x: cpu,
+        o: cpu,
+        seed: args.common.seed.unwrap_or(0),
+    };
+    let report = in_pool(args.threads, || {
+        arena::simulate_many(&config, args.playouts)
+    })?;
+    let per_second = report.games as f64 / report.elapsed.as_secs_f64().max(f64::EPSILON);
+    println!(
+        "{} {} playouts in {:.2?} ({:.0}/s)",
+        report.games, args.common.difficulty, report.elapsed, per_second
+    );
+    Ok(())
+}
+
+// tic-tac-toe train --episodes 100000 --out model.bin
+#[cfg(feature = "serde")]
+fn run_train(args: TrainArgs) -> Result<(), String> {
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let config = TrainingConfig {
+        episodes: args.episodes,
+        ..TrainingConfig::default()
+    };
+    let mut rng = GameRng::from_seed(args.common.seed);
+    let started = Instant::now();
+    let agent = learn::train(rules, &config, &mut rng)?;
+    println!(
+        "{} games in {:.2?}, {} positions learned",
+        config.episodes,
+        started.elapsed(),
+        agent.states()
+    );
+
+    // Check it against the random CPU from both sides
+    let random = Cpu::default();
+    let (mut wins, mut losses, mut ties) = (0, 0, 0);
+    for game in 0..1000 {
+        let learned_mark = if game % 2 == 0 { State::X } else { State::O };
+        let (x, o): (&dyn Player, &dyn Player) = match learned_mark {
+            State::X => (&agent, &random),
+            _ => (&random, &agent),
+        };
+        match arena::play_between(&rules, x, o, &mut rng).0 {
+            Status::Won(mark) if mark == learned_mark => wins += 1,
+            Status::Won(_) => losses += 1,
+            _ => ties += 1,
+        }
+    }
+    println!(
+        "Against the random CPU: {} wins, {} losses, {} ties",
+        wins, losses, ties
+    );
+    Model::Learned(agent)
+        .save(&args.out)
+        .map_err(|err| format!("Can't save {}: {}", args.out, err))?;
+    println!("Saved to {}", args.out);
+    Ok(())
+}
+
+// tic-tac-toe book build --size 4x4 --plies 2 --out 4x4.book
+#[cfg(feature = "serde")]
+fn run_book(args: BookArgs) -> Result<(), String> {
+    let BookCommand::Build(args) = args.command;
+    let rules = args.common.rules(Variant::Classic, false)?;
+    let started = Instant::now();
+    let book = Book::build(rules, args.plies, &mut |ply, positions| {
+        println!("Ply {}: solving {} positions", ply + 1, positions)
+    })?;
+    println!("{} in {:.2?}", book, started.elapsed());
+    book.save(&args.out)
+        .map_err(|err| format!("Can't save {}: {}", args.out, err))?;
+    println!("Saved to {}", args.out);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn load_model(path: &str, rules: &Rules) -> Result<Model, String> {
+    let model = Model::load(path).and_then(|model| model.check_rules(rules).map(|_| model));
+    model.map_err(|err| format!("Can't load {}: {}", path, err))
+}
+
+// tic-tac-toe replay round-1.ttt, or with --watch --delay 1s or --watch --realtime to play
+// it back move by move
+#[cfg(feature = "serde")]
+fn run_replay(path: &str, pacing: Option<Pacing>) -> Result<(), String> {
+    let replay = Replay::load(path)?;
+    println!(
+        "Replay format v{}, recorded {}",
+        replay.version, replay.date
+    );
+    println!(
+        "Rules: {}x{}{} board, {} in a row, {:?}",
+        replay.rules.rows,
+        replay.rules.cols,
+        if replay.rules.layers > 1 {
+            format!("x{}", replay.rules.layers)
+        } else {
+            String::new()
+        },
+        replay.rules.win_len,
+        replay.rules.variant
+    );
+    for player in &replay.players {
+        match &player.difficulty {
+            Some(difficulty) => println!("{:?}: {} ({})", player.mark, player.kind, difficulty),
+            None => println!("{:?}: {}", player.mark, player.kind),
+        }
+    }
+    if let Some(seed) = replay.seed {
+        println!("Seed: {} ({})", seed, replay.rng.unwrap_or_default());
+    }
+
+    let boards = replay.boards()?;
+    let mut playback = pacing.map(|pacing| Playback::new(SystemClock::start(), pacing));
+    // Replays recorded before moves were timed show no times
+    let timed = replay.moves.iter().any(|step| step.elapsed_ms > 0);
+    for (number, (step, board)) in replay.moves.iter().zip(&boards).enumerate() {
+        if let Some(playback) = &mut playback {
+            playback.wait_for(step);
+        }
+        // Moves played for an idle player are told apart
+        let mut notes = String::new();
+        if timed {
+            let elapsed = Duration::from_millis(step.elapsed_ms);
+            notes.push_str(&format!(" ({})", game::seconds(elapsed)));
+        }
+        if step.auto {
+            notes.push_str(" (auto)");
+        }
+        match step.digit {
+            Some(digit) => println!(
+                "{}. {:?} plays {} at {}{}",
+                number + 1,
+                step.mark,
+                digit,
+                step.index,
+                notes
+            ),
+            None => println!("{}. {:?} at {}{}", number + 1, step.mark, step.index, notes),
+        }
+        print_board(board);
+    }
+    if let Some(ending) = replay.ending {
+        println!("{}", ending);
+    }
+    println!("Result: {:?}", replay.result);
+    Ok(())
+}
+
+// Output piped into a closed reader (such as `head`) makes println! panic, end quietly
+// instead. Other panics first put the terminal's colors back. The game in progress was
+// saved after its latest move.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<String>()
+            .map_or("", String::as_str);
+        if message.contains("Broken pipe") {
+            process::exit(0);
+        }
+        let _ = crossterm::execute!(io::stdout(), crossterm::style::ResetColor);
+        default_hook(info);
+    }));
+}
+
+fn main() -> ExitCode {
+    install_panic_hook();
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            // --help and --version print to stdout and succeed
+            let _ = err.print();
+            return if err.use_stderr() {
+                ExitCode::from(EXIT_ERROR)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    // Straight into a game when scripted: with any argument, or input that isn't a terminal
+    let menu = env::args_os().len() == 1 && io::stdin().is_terminal();
+    let result = match cli.command {
+        None if menu => run_menu(cli.play).map(|()| ExitCode::SUCCESS),
+        None => run_play(cli.play),
+        Some(Command::Play(args)) => run_play(args),
+        Some(command) => run_tool(command).map(|()| ExitCode::SUCCESS),
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+// The subcommands other than playing, which exit with 0 unless they fail
+fn run_tool(command: Command) -> Result<(), String> {
+    match command {
+        Command::Play(args) => run_play(args).map(drop),
+        #[cfg(feature = "serde")]
+        Command::Replay(args) => {
+            let pacing = if args.realtime {
+                Pacing::Realtime
+            } else {
+                Pacing::Fixed(args.delay)
+            };
+            run_replay(&args.file, args.watch.then_some(pacing))
+        }
+        Command::Analyze(args) => run_analyze(args),
+        Command::Solve(args) => run_solve(args),
+        Command::Tree(args) => run_tree(args),
+        Command::Render(args) => run_render(args),
+        Command::Arena(args) => run_arena(args),
+        Command::Openings(args) => run_openings(args),
+        Command::Calibrate(args) => run_calibrate(args),
+        Command::Bench(args) => run_bench(args),
+        Command::Engine => engine::run(io::stdin().lock(), io::stdout())
+            .map_err(|err| format!("Engine stopped: {}", err)),
+        #[cfg(feature = "server")]
+        Command::Serve(args) => run_serve(args),
+        #[cfg(feature = "serde")]
+        Command::Train(args) => run_train(args),
+        #[cfg(feature = "serde")]
+        Command::Book(args) => run_book(args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["tic-tac-toe"].iter().chain(args))
+    }
+
+    #[test]
+    fn no_subcommand_plays() {
+        let cli = parse(&["--difficulty", "hard", "--seed", "3"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.play.common.difficulty, Difficulty::Hard);
+        assert_eq!(cli.play.common.seed, Some(3));
+    }
+
+    fn summary(player: u16, cpu: u16, tie: u16) -> SessionSummary {
+        let score = Score { player, cpu, tie };
+        SessionSummary {
+            score,
+            scores: Scores::new(),
+            rounds: score.rounds(),
+            distinct_games: 0,
+            duration: Duration::ZERO,
+            times: Default::default(),
+            sides: ["You", "Cpu"],
+        }
+    }
+
+    #[test]
+    fn scripted_rounds_exit_with_their_outcome() {
+        assert_eq!(exit_code(&summary(1, 0, 0), true), EXIT_PLAYER_WIN);
+        assert_eq!(exit_code(&summary(0, 1, 0), true), EXIT_CPU_WIN);
+        assert_eq!(exit_code(&summary(0, 0, 1), true), EXIT_TIE);
+        // Nothing finished, several rounds, or a player at the terminal
+        assert_eq!(exit_code(&summary(0, 0, 0), true), 0);
+        assert_eq!(exit_code(&summary(0, 2, 1), true), 0);
+        assert_eq!(exit_code(&summary(0, 1, 0), false), 0);
+    }
+
+    #[test]
+    fn the_cpu_can_play_x_and_open() {
+        let cli = parse(&["--mark", "o", "--cpu-first"]).unwrap();
+        assert_eq!((cli.play.mark, cli.play.cpu_first), (State::O, true));
+        let err = parse(&["--two-players", "--mark", "o"]).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn play_subcommand_takes_the_same_flags() {
+        let cli = parse(&["play", "--size", "4x5", "--win", "4", "--first-to", "2"]).unwrap();
+        let args = match cli.command {
+            Some(Command::Play(args)) => args,
+            _ => panic!("not play"),
+        };
+        assert_eq!(args.first_to, Some(2));
+        let rules = args.common.rules(Variant::Classic, false).unwrap();
+        assert_eq!((rules.rows, rules.cols, rules.win_len), (4, 5, 4));
+    }
+
+    #[test]
+    fn subcommands_get_their_own_args() {
+        match parse(&["solve", "X...O...."]).unwrap().command {
+            Some(Command::Solve(args)) => assert_eq!(args.board.as_deref(), Some("X...O....")),
+            _ => panic!("not solve"),
+        }
+        match parse(&["arena", "--games", "5", "--x", "hard"])
+            .unwrap()
+            .command
+        {
+            Some(Command::Arena(args)) => {
+                assert_eq!((args.games, args.x), (5, Some(Difficulty::Hard)));
+                assert_eq!(args.common.difficulty, Difficulty::Easy);
+            }
+            _ => panic!("not arena"),
+        }
+        match parse(&["openings", "--a", "random", "--b", "heuristic"])
+            .unwrap()
+            .command
+        {
+            Some(Command::Openings(args)) => {
+                assert_eq!(args.a, Some(Difficulty::Easy));
+                assert_eq!(args.b, Some(Difficulty::Medium));
+            }
+            _ => panic!("not openings"),
+        }
+        assert!(matches!(
+            parse(&["calibrate", "--games", "50"]).unwrap().command,
+            Some(Command::Calibrate(CalibrateArgs { games: 50, .. }))
+        ));
+        assert!(matches!(
+            parse(&["bench", "--playouts", "10"]).unwrap().command,
+            Some(Command::Bench(BenchArgs { playouts: 10, .. }))
+        ));
+        assert!(matches!(
+            parse(&["analyze", "--position", "abc"]).unwrap().command,
+            Some(Command::Analyze(_))
+        ));
+    }
+
+    #[test]
+    fn bad_arguments_are_refused() {
+        let kind = |args: &[&str]| parse(args).err().map(|err| err.kind());
+        assert_eq!(
+            kind(&["--wild", "--gravity"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+        assert_eq!(kind(&["--first-to", "0"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["--size", "ten"]), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind(&["solve"]), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(
+            kind(&["--seed", "3", "solve", "X"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+        #[cfg(feature = "serde")]
+        assert_eq!(
+            kind(&["replay", "round-1.ttt", "--realtime"]),
+            Some(ErrorKind::MissingRequiredArgument)
+        );
+        assert_eq!(
+            kind(&["--summary-only"]),
+            Some(ErrorKind::MissingRequiredArgument)
+        );
+        assert_eq!(
+            kind(&["--rounds", "3", "--best-of", "3"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+        for cell in ["0", "1001"] {
+            assert_eq!(
+                kind(&["render", "X........", "--cell", cell]),
+                Some(ErrorKind::ValueValidation)
+            );
+        }
+    }
+
+    // Menu input from a script, with everything the menu and its games show collected
+    struct Scripted {
+        input: &'static [u8],
+        output: Vec<u8>,
+    }
+
+    impl MenuIo for Scripted {
+        fn read_line(&mut self) -> Option<String> {
+            let mut line = String::new();
+            match io::BufRead::read_line(&mut self.input, &mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim().to_string()),
+            }
+        }
+
+        fn show(&mut self, line: &str) {
+            io::Write::write_all(&mut self.output, format!("{}\n", line).as_bytes()).unwrap();
+        }
+
+        fn play(&mut self, session: &mut Session) -> SessionSummary {
+            session
+                .start_with(&mut self.input, &mut self.output)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn menu_games_take_the_slot_commands() {
+        let dir = env::temp_dir().join(format!("ttt-menu-{}", process::id()));
+        env::set_var("TIC_TAC_TOE_DIR", &dir);
+        let args = parse(&["--seed", "1"]).unwrap().play;
+        let mut io = Scripted {
+            input: concat!(
+                "2\n",
+                "game new second\n",
+                "game list\n",
+                "game switch main\n",
+                "0\n3\n1\n4\n2\n",
+                "n\n",
+                "5\n",
+                "6\n",
+            )
+            .as_bytes(),
+            output: Vec::new(),
+        };
+        menu(&args, None, &mut io).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        let output = String::from_utf8(io.output).unwrap();
+        assert!(output.contains("** Started second **"), "{}", output);
+        assert!(output.contains("  main: two players, 0-0-0"), "{}", output);
+        assert!(output.contains("* second: vs Cpu easy"), "{}", output);
+        assert!(output.contains("** Switched to main **"), "{}", output);
+        assert!(output.contains("** X wins! **"), "{}", output);
+        // Back on the menu once the session ends, with its round counted
+        assert!(
+            output.contains("Two players:\nX    1  100%\nO    0    0%\nTie  0    0%\n"),
+            "{}",
+            output
+        );
+        // Before the game, after it and after the statistics
+        assert_eq!(output.matches("1. Play vs CPU").count(), 3, "{}", output);
+    }
+
+    #[test]
+    fn menu_asks_again_and_ends_with_its_input() {
+        let args = parse(&[]).unwrap().play;
+        let mut io = Scripted {
+            input: b"9\nthree\n",
+            output: Vec::new(),
+        };
+        menu(&args, None, &mut io).unwrap();
+        let output = String::from_utf8(io.output).unwrap();
+        assert_eq!(
+            output.matches("Please enter a number from 1 to 6").count(),
+            2,
+            "{}",
+            output
+        );
+    }
 }
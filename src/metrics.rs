@@ -0,0 +1,182 @@
+use crate::board::State;
+use crate::events::{Event, Observer};
+use crate::game::Status;
+use prometheus::core::Collector;
+use prometheus::{
+    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+// What a running server counts, scraped by Prometheus from GET /metrics
+pub struct Metrics {
+    registry: Registry,
+    pub games_created: IntCounter,
+    // Finished rounds by result: x_wins, o_wins or tie
+    pub rounds_completed: IntCounterVec,
+    // Moves by player or cpu
+    pub moves: IntCounterVec,
+    // Failed requests by error code
+    pub errors: IntCounterVec,
+    pub active_games: IntGauge,
+    pub cpu_move_seconds: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("tic_tac_toe".to_string()), None)?;
+        let games_created = register(
+            &registry,
+            IntCounter::new("games_created_total", "Games started"),
+        )?;
+        let rounds_completed = register(
+            &registry,
+            IntCounterVec::new(
+                Opts::new("rounds_completed_total", "Rounds played to the end"),
+                &["result"],
+            ),
+        )?;
+        let moves = register(
+            &registry,
+            IntCounterVec::new(Opts::new("moves_total", "Moves made"), &["by"]),
+        )?;
+        let errors = register(
+            &registry,
+            IntCounterVec::new(Opts::new("errors_total", "Requests refused"), &["code"]),
+        )?;
+        let active_games = register(
+            &registry,
+            IntGauge::new("active_games", "Games kept in memory"),
+        )?;
+        // From 10µs up to about 2.6s
+        let buckets = prometheus::exponential_buckets(0.000_01, 4.0, 10);
+        let cpu_move_seconds = register(
+            &registry,
+            buckets.and_then(|buckets| {
+                Histogram::with_opts(
+                    HistogramOpts::new("cpu_move_seconds", "Time the CPU took per move")
+                        .buckets(buckets),
+                )
+            }),
+        )?;
+        Ok(Metrics {
+            registry,
+            games_created,
+            rounds_completed,
+            moves,
+            errors,
+            active_games,
+            cpu_move_seconds,
+        })
+    }
+
+    pub fn cpu_move(&self, took: Duration) {
+        self.cpu_move_seconds.observe(took.as_secs_f64());
+    }
+
+    // Prometheus' text format
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+// Adds `metric` to the registry, failing on invalid options or a name used twice
+fn register<C: Collector + Clone + 'static>(
+    registry: &Registry,
+    metric: prometheus::Result<C>,
+) -> prometheus::Result<C> {
+    let metric = metric?;
+    registry.register(Box::new(metric.clone()))?;
+    Ok(metric)
+}
+
+// Counts the moves and finished rounds of a game
+pub struct GameMetrics(pub Arc<Metrics>);
+
+impl Observer for GameMetrics {
+    fn on_event(&mut self, event: &Event) {
+        match *event {
+            Event::Move { human, .. } => {
+                let by = if human { "player" } else { "cpu" };
+                self.0.moves.with_label_values(&[by]).inc();
+            }
+            Event::RoundEnd { status, .. } => {
+                let result = match status {
+                    Status::Won(State::X) => "x_wins",
+                    Status::Won(_) => "o_wins",
+                    Status::Tie | Status::InProgress => "tie",
+                    Status::Invalid => "invalid",
+                };
+                self.0.rounds_completed.with_label_values(&[result]).inc();
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(human: bool) -> Event {
+        Event::Move {
+            mark: State::X,
+            index: 0,
+            digit: None,
+            human,
+            auto: false,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn game_events_are_counted() {
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let mut observer = GameMetrics(Arc::clone(&metrics));
+        for event in [moved(true), moved(false), moved(true)] {
+            observer.on_event(&event);
+        }
+        observer.on_event(&Event::RoundEnd {
+            status: Status::Won(State::O),
+            ending: None,
+        });
+        observer.on_event(&Event::RoundEnd {
+            status: Status::Tie,
+            ending: None,
+        });
+        observer.on_event(&Event::SessionEnd);
+        assert_eq!(metrics.moves.with_label_values(&["player"]).get(), 2);
+        assert_eq!(metrics.moves.with_label_values(&["cpu"]).get(), 1);
+        let rounds = |result| metrics.rounds_completed.with_label_values(&[result]).get();
+        assert_eq!(
+            (rounds("x_wins"), rounds("o_wins"), rounds("tie")),
+            (0, 1, 1)
+        );
+    }
+
+    #[test]
+    fn render_writes_the_text_format() {
+        let metrics = Metrics::new().unwrap();
+        metrics.games_created.inc();
+        metrics
+            .errors
+            .with_label_values(&["unknown_game"])
+            .inc_by(3);
+        metrics.active_games.set(2);
+        metrics.cpu_move(Duration::from_millis(3));
+        let text = metrics.render();
+        for line in [
+            "# TYPE tic_tac_toe_games_created_total counter",
+            "tic_tac_toe_games_created_total 1",
+            "tic_tac_toe_errors_total{code=\"unknown_game\"} 3",
+            "tic_tac_toe_active_games 2",
+            "tic_tac_toe_cpu_move_seconds_count 1",
+            "tic_tac_toe_cpu_move_seconds_bucket{le=\"0.00256\"} 0",
+            "tic_tac_toe_cpu_move_seconds_bucket{le=\"0.01024\"} 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{} in\n{}", line, text);
+        }
+    }
+}
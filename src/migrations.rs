@@ -0,0 +1,253 @@
+use crate::achievements::STATS_VERSION;
+use crate::replay::REPLAY_VERSION;
+use crate::save::SAVE_VERSION;
+use serde_json::Value;
+
+// Upgrades a file to the next version of its format, in place
+type Step = fn(&mut Value);
+
+// A versioned file format. `steps[i]` takes a file of version i + 1 to version i + 2, so
+// a file of any older version reaches the current one step by step.
+pub struct Format {
+    pub name: &'static str,
+    pub current: u32,
+    steps: &'static [Step],
+}
+
+pub const REPLAY: Format = Format {
+    name: "replay",
+    current: REPLAY_VERSION,
+    steps: &[lowercase_marks, std_rng_seeds],
+};
+
+pub const SAVE: Format = Format {
+    name: "saved game",
+    current: SAVE_VERSION,
+    steps: &[std_rng, score_buckets],
+};
+
+pub const STATS: Format = Format {
+    name: "stats",
+    current: STATS_VERSION,
+    steps: &[],
+};
+
+// Brings a parsed file up to the current version of `format`, ready to deserialize
+pub fn upgrade(format: &Format, mut value: Value) -> Result<Value, String> {
+    let version = match value.get("version").and_then(Value::as_u64) {
+        Some(version) if version >= 1 => version,
+        Some(version) => return Err(format!("Unknown {} version {}", format.name, version)),
+        None => return Err(format!("Not a {} file: missing version", format.name)),
+    };
+    if version > format.current as u64 {
+        return Err(format!(
+            "This {} file was created by a newer version (format v{}, this build reads up to v{})",
+            format.name, version, format.current
+        ));
+    }
+    for step in &format.steps[version as usize - 1..] {
+        step(&mut value);
+    }
+    value["version"] = Value::from(format.current);
+    Ok(value)
+}
+
+// v1 → v2: marks were written capitalized ("X", "O", "Empty")
+fn lowercase_marks(value: &mut Value) {
+    fn lowercase(mark: &mut Value) {
+        if let Value::String(name) = mark {
+            *name = name.to_lowercase();
+        }
+    }
+    for list in ["players", "moves"] {
+        if let Some(Value::Array(items)) = value.get_mut(list) {
+            for item in items {
+                if let Some(mark) = item.get_mut("mark") {
+                    lowercase(mark);
+                }
+            }
+        }
+    }
+    if let Some(winner) = value
+        .get_mut("result")
+        .and_then(|result| result.get_mut("Won"))
+    {
+        lowercase(winner);
+    }
+}
+
+// v2 → v3: seeds fed StdRng, before the generator was recorded next to them
+fn std_rng_seeds(value: &mut Value) {
+    if value.get("seed").is_some_and(Value::is_u64) {
+        value["rng"] = Value::from("std");
+    }
+}
+
+// Saved game v1 → v2: the seed fed StdRng
+fn std_rng(value: &mut Value) {
+    value["rng"] = Value::from("std");
+}
+
+// Saved game v2 → v3: one flat score, now split by opponent. It goes to the opponent
+// the saved settings name, as `Game::opponent` keys it; a trained model isn't part of
+// the save, so its rounds count as the built-in Cpu's.
+fn score_buckets(value: &mut Value) {
+    let state = match value.get_mut("state") {
+        Some(state) => state,
+        None => return,
+    };
+    let score = match state
+        .as_object_mut()
+        .and_then(|state| state.remove("score"))
+    {
+        Some(score) => score,
+        None => return,
+    };
+    let settings = &state["settings"];
+    let setting =
+        |name: &str, default: &str| settings[name].as_str().unwrap_or(default).to_lowercase();
+    let opponent = if settings["two_players"].as_bool() == Some(true) {
+        "two players".to_string()
+    } else {
+        format!(
+            "cpu {} ({})",
+            setting("difficulty", "easy"),
+            setting("personality", "balanced")
+        )
+    };
+    let empty = ["player", "cpu", "tie"]
+        .iter()
+        .all(|side| score[side].as_u64().unwrap_or(0) == 0);
+    let mut scores = serde_json::Map::new();
+    if !empty {
+        scores.insert(opponent, score);
+    }
+    state["scores"] = Value::Object(scores);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::achievements::{Achievement, Stats};
+    use crate::board::State;
+    use crate::game::{Game, Score, Status};
+    use crate::replay::Replay;
+    use crate::rng::RngKind;
+    use crate::rules::Rules;
+    use crate::save::SavedGame;
+
+    fn upgraded(format: &Format, text: &str) -> Result<Value, String> {
+        upgrade(format, serde_json::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn v1_replays_get_lowercase_marks_and_their_rng() {
+        let replay = Replay::parse(include_str!("../tests/fixtures/replay-v1.ttt")).unwrap();
+        let current = Replay::parse(include_str!("../tests/fixtures/replay-v3.ttt")).unwrap();
+        assert_eq!(replay.version, REPLAY_VERSION);
+        assert_eq!(replay.rng, Some(RngKind::Std));
+        assert_eq!(replay.result, Status::Won(State::O));
+        assert_eq!(replay.players, current.players);
+        let moves = |replay: &Replay| -> Vec<(State, usize)> {
+            replay
+                .moves
+                .iter()
+                .map(|step| (step.mark, step.index))
+                .collect()
+        };
+        assert_eq!(moves(&replay), moves(&current));
+        assert_eq!(replay.boards(), current.boards());
+    }
+
+    #[test]
+    fn v1_saves_resume_with_std_rng() {
+        let value = upgraded(&SAVE, include_str!("../tests/fixtures/save-v1.json")).unwrap();
+        let saved: SavedGame = serde_json::from_value(value).unwrap();
+        assert_eq!(saved.version, SAVE_VERSION);
+        assert_eq!((saved.seed, saved.rng), (42, RngKind::Std));
+        assert!(saved.state.settings.two_players);
+        assert_eq!(saved.state.scores["two players"].player, 1);
+        let board = saved.state.position.unwrap().board;
+        assert_eq!(
+            (board[0], board[4], board[8]),
+            (State::O, State::X, State::X)
+        );
+    }
+
+    #[test]
+    fn v2_saves_split_their_score_by_opponent() {
+        let v2 = r#"{
+            "version": 2,
+            "date": "2026-10-16T15:29:38.675Z",
+            "seed": 42,
+            "rng": "chacha8",
+            "state": {
+                "rules": {"rows": 3, "cols": 3, "layers": 1, "win_len": 3, "variant": "Classic"},
+                "settings": {"difficulty": "Hard", "personality": "Aggressive"},
+                "score": {"player": 0, "cpu": 2, "tie": 3},
+                "match_score": {"player": 0, "cpu": 0, "tie": 0},
+                "human_mark": "x",
+                "cpu_opens": false,
+                "position": null
+            }
+        }"#;
+        let saved: SavedGame = serde_json::from_value(upgraded(&SAVE, v2).unwrap()).unwrap();
+        let rng = saved.rng();
+        let game = Game::from_state(saved.state, rng).unwrap();
+        let scores: Vec<_> = game.scores().iter().collect();
+        let score = Score {
+            player: 0,
+            cpu: 2,
+            tie: 3,
+        };
+        assert_eq!(scores, [(&"cpu hard (aggressive)".to_string(), &score)]);
+        // The key is the one the resumed game goes on counting under
+        assert_eq!(game.opponent(), "cpu hard (aggressive)");
+        assert_eq!(game.score(), score);
+        // An empty score leaves no bucket behind
+        let fresh = v2.replace(r#""cpu": 2, "tie": 3"#, r#""cpu": 0, "tie": 0"#);
+        let saved: SavedGame = serde_json::from_value(upgraded(&SAVE, &fresh).unwrap()).unwrap();
+        assert!(saved.state.scores.is_empty());
+    }
+
+    #[test]
+    fn v1_stats_load_as_they_are() {
+        let value = upgraded(&STATS, include_str!("../tests/fixtures/stats-v1.json")).unwrap();
+        let stats: Stats = serde_json::from_value(value).unwrap();
+        assert_eq!(stats.version, STATS_VERSION);
+        assert_eq!(stats.streak, 2);
+        assert!(stats.unlocked.contains_key(&Achievement::FirstWin));
+    }
+
+    #[test]
+    fn newer_and_unversioned_files_are_refused() {
+        let newer = format!(r#"{{"version": {}}}"#, SAVE_VERSION + 1);
+        let err = upgraded(&SAVE, &newer).unwrap_err();
+        assert!(err.contains("created by a newer version"), "{}", err);
+        assert_eq!(
+            upgraded(&REPLAY, r#"{"moves": []}"#),
+            Err("Not a replay file: missing version".to_string())
+        );
+        assert_eq!(
+            upgraded(&STATS, r#"{"version": 0}"#),
+            Err("Unknown stats version 0".to_string())
+        );
+    }
+
+    #[test]
+    fn new_files_start_at_the_latest_version() {
+        assert_eq!(Replay::new(Rules::default()).version, REPLAY_VERSION);
+        assert_eq!(Stats::default().version, STATS_VERSION);
+        let state = Game::new().state();
+        assert_eq!(SavedGame::new(state, None).version, SAVE_VERSION);
+        // Every version but the latest has a step up
+        for format in [REPLAY, SAVE, STATS] {
+            assert_eq!(
+                format.steps.len() as u32,
+                format.current - 1,
+                "{}",
+                format.name
+            );
+        }
+    }
+}
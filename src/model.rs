@@ -0,0 +1,297 @@
+use crate::ai::{self, MoveDecision, Player, Weights};
+use crate::board::{Board, State};
+use crate::learn::Agent;
+use crate::rules::Rules;
+use bincode::Options;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// First bytes of every model file
+pub const MODEL_MAGIC: &[u8; 4] = b"TTTM";
+
+// Bumped on every incompatible change of the model format
+pub const MODEL_VERSION: u32 = 1;
+
+// A CPU saved to a file: a learned table or a tuned weight set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Model {
+    Learned(Agent),
+    Heuristic { rules: Rules, weights: Weights },
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(io::Error),
+    // The magic header is missing
+    NotAModel,
+    UnsupportedVersion(u32),
+    // Truncated or damaged after the header
+    Corrupt(String),
+    // The file names rules no CPU can play
+    InvalidRules(String),
+    WrongRules { model: Rules, game: Rules },
+    // The model couldn't be written out
+    Encode(String),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Io(err) => write!(f, "{}", err),
+            ModelError::NotAModel => write!(f, "not a model file"),
+            ModelError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported model version {} (this build reads version {})",
+                version, MODEL_VERSION
+            ),
+            ModelError::Corrupt(reason) => write!(f, "corrupt model: {}", reason),
+            ModelError::InvalidRules(reason) => write!(f, "invalid model: {}", reason),
+            ModelError::WrongRules { model, game } => write!(
+                f,
+                "the model plays {}x{} boards with {} in a row, not {}x{} with {}",
+                model.rows, model.cols, model.win_len, game.rows, game.cols, game.win_len
+            ),
+            ModelError::Encode(reason) => write!(f, "can't encode the model: {}", reason),
+        }
+    }
+}
+
+impl Error for ModelError {}
+
+impl From<io::Error> for ModelError {
+    fn from(err: io::Error) -> Self {
+        ModelError::Io(err)
+    }
+}
+
+impl Model {
+    pub fn rules(&self) -> Rules {
+        match self {
+            Model::Learned(agent) => agent.rules(),
+            Model::Heuristic { rules, .. } => *rules,
+        }
+    }
+
+    // Fails unless the model was made for exactly these rules
+    pub fn check_rules(&self, rules: &Rules) -> Result<(), ModelError> {
+        if self.rules() != *rules {
+            return Err(ModelError::WrongRules {
+                model: self.rules(),
+                game: *rules,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ModelError> {
+        let mut bytes = MODEL_MAGIC.to_vec();
+        bytes.extend_from_slice(&MODEL_VERSION.to_le_bytes());
+        bincode::DefaultOptions::new()
+            .serialize_into(&mut bytes, self)
+            .map_err(|err| ModelError::Encode(err.to_string()))?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Model, ModelError> {
+        let body = bytes
+            .strip_prefix(MODEL_MAGIC)
+            .ok_or(ModelError::NotAModel)?;
+        let (version, body) = match body.split_first_chunk::<4>() {
+            Some((version, body)) => (u32::from_le_bytes(*version), body),
+            None => return Err(ModelError::Corrupt("missing version".to_string())),
+        };
+        if version != MODEL_VERSION {
+            return Err(ModelError::UnsupportedVersion(version));
+        }
+        // The limit stops a damaged length from allocating more than the file holds
+        let model: Model = bincode::DefaultOptions::new()
+            .with_limit(body.len() as u64)
+            .reject_trailing_bytes()
+            .deserialize(body)
+            .map_err(|err| ModelError::Corrupt(err.to_string()))?;
+        match &model {
+            Model::Learned(agent) => Agent::new(agent.rules()).map(drop),
+            Model::Heuristic { rules, .. } => rules.validate(),
+        }
+        .map_err(ModelError::InvalidRules)?;
+        Ok(model)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Model, ModelError> {
+        Model::from_bytes(&fs::read(path)?)
+    }
+}
+
+impl Player for Model {
+    fn choose_move(
+        &self,
+        board: &Board,
+        rules: &Rules,
+        mark: State,
+        mut rng: &mut dyn RngCore,
+    ) -> Option<MoveDecision> {
+        match self {
+            Model::Learned(agent) => agent.choose_move(board, rules, mark, rng),
+            Model::Heuristic { weights, .. } => {
+                ai::choose_weighted(board, rules, mark, weights, &mut rng)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Model::Learned(agent) => write!(f, "{}", agent),
+            Model::Heuristic { .. } => write!(f, "tuned weights"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Difficulty, First, Personality};
+    use crate::learn::{self, TrainingConfig};
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::env;
+    use std::process;
+
+    // Defensive weights for the 3x3 board, saved by version 1
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/model-v1.ttm");
+
+    fn learned() -> Model {
+        let config = TrainingConfig {
+            episodes: 500,
+            ..TrainingConfig::default()
+        };
+        let agent = learn::train(Rules::default(), &config, &mut StdRng::seed_from_u64(1));
+        Model::Learned(agent.unwrap())
+    }
+
+    #[test]
+    fn fixture_still_loads() {
+        match Model::from_bytes(FIXTURE).unwrap() {
+            Model::Heuristic { rules, weights } => {
+                assert_eq!(rules, Rules::default());
+                assert_eq!(weights, Personality::Defensive.weights());
+            }
+            model => panic!("expected weights, got {:?}", model),
+        }
+    }
+
+    #[test]
+    fn models_round_trip_through_a_file() {
+        let path = env::temp_dir().join(format!("ttt-model-{}.ttm", process::id()));
+        let model = learned();
+        model.save(&path).unwrap();
+        let loaded = Model::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.to_string(), model.to_string());
+        // The table's order isn't kept, so compare how the two play
+        let rules = Rules::default();
+        let mut board = rules.new_board();
+        let mut mark = State::X;
+        loop {
+            let original = model.choose_move(&board, &rules, mark, &mut StepRng::new(0, 0));
+            let reloaded = loaded.choose_move(&board, &rules, mark, &mut StepRng::new(0, 0));
+            assert_eq!(original, reloaded);
+            match original {
+                Some(decision) if rules.winner(&board).is_none() => board[decision.index] = mark,
+                _ => break,
+            }
+            mark = mark.opponent();
+        }
+    }
+
+    #[test]
+    fn heuristic_model_plays_its_weights() {
+        let model = Model::from_bytes(FIXTURE).unwrap();
+        let board = Rules::default().new_board();
+        let decision =
+            model.choose_move(&board, &Rules::default(), State::X, &mut StepRng::new(0, 0));
+        let cpu = ai::Cpu {
+            difficulty: Difficulty::Medium,
+            personality: Personality::Defensive,
+            depth: None,
+        };
+        let expected = ai::choose_move(&board, &Rules::default(), State::X, cpu, &mut First);
+        assert_eq!(decision, expected);
+    }
+
+    #[test]
+    fn damaged_files_are_refused() {
+        assert!(matches!(
+            Model::from_bytes(b"PNG\x89 not a model"),
+            Err(ModelError::NotAModel)
+        ));
+        assert!(matches!(
+            Model::from_bytes(b"TTTM\x01"),
+            Err(ModelError::Corrupt(_))
+        ));
+        let mut newer = FIXTURE.to_vec();
+        newer[4] = 2;
+        assert!(matches!(
+            Model::from_bytes(&newer),
+            Err(ModelError::UnsupportedVersion(2))
+        ));
+        for len in 8..FIXTURE.len() {
+            assert!(
+                matches!(
+                    Model::from_bytes(&FIXTURE[..len]),
+                    Err(ModelError::Corrupt(_))
+                ),
+                "truncated to {} bytes",
+                len
+            );
+        }
+        let mut longer = FIXTURE.to_vec();
+        longer.push(0);
+        assert!(matches!(
+            Model::from_bytes(&longer),
+            Err(ModelError::Corrupt(_))
+        ));
+        let missing = env::temp_dir().join(format!("ttt-no-model-{}.ttm", process::id()));
+        assert!(matches!(Model::load(missing), Err(ModelError::Io(_))));
+    }
+
+    #[test]
+    fn models_only_play_their_board() {
+        let model = learned();
+        assert!(model.check_rules(&Rules::default()).is_ok());
+        let bigger = Rules {
+            rows: 4,
+            cols: 4,
+            ..Rules::default()
+        };
+        let err = model.check_rules(&bigger).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the model plays 3x3 boards with 3 in a row, not 4x4 with 3"
+        );
+        let unplayable = Model::Heuristic {
+            rules: Rules {
+                win_len: 9,
+                ..Rules::default()
+            },
+            weights: Weights::default(),
+        };
+        assert!(matches!(
+            Model::from_bytes(&unplayable.to_bytes().unwrap()),
+            Err(ModelError::InvalidRules(_))
+        ));
+    }
+}
@@ -0,0 +1,456 @@
+use crate::board::{Board, Line, State};
+use crate::game::Status;
+use crate::rules::{Rules, Variant};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// A lightweight copy of a round: cheap to store, compare, hash and replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    pub board: Board,
+    pub to_move: State,
+    pub status: Status,
+}
+
+// One cell that differs between two positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellChange {
+    pub index: usize,
+    pub from: State,
+    pub to: State,
+}
+
+// Why a position can't come up in a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalPosition {
+    // A mark or digit outside the board, or a digit other than 1 to 9
+    OutOfRange { index: usize },
+    WrongSize { rows: usize, cols: usize },
+    CountImbalance { x: usize, o: usize },
+    // The side with more marks is to move again
+    WrongSideToMove { to_move: State },
+    BothWon,
+    // Only the side that moved last can have completed a line
+    LineButNotLastMover { winner: State },
+}
+
+impl fmt::Display for IllegalPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Illegal position: ")?;
+        match self {
+            IllegalPosition::OutOfRange { index } => write!(f, "invalid cell {}", index),
+            IllegalPosition::WrongSize { rows, cols } => {
+                write!(f, "a {}x{} board doesn't fit the rules", rows, cols)
+            }
+            IllegalPosition::CountImbalance { x, o } => write!(f, "{} X against {} O", x, o),
+            IllegalPosition::WrongSideToMove { to_move } => {
+                write!(f, "{:?} can't be to move", to_move)
+            }
+            IllegalPosition::BothWon => write!(f, "both sides have a line"),
+            IllegalPosition::LineButNotLastMover { winner } => {
+                write!(f, "{:?} has a line but didn't move last", winner)
+            }
+        }
+    }
+}
+
+impl Error for IllegalPosition {}
+
+// Alphabet of base64url, RFC 4648
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// Characters in a share code: 9 cells of 2 bits and the side to move fill 3 bytes
+const CODE_LEN: usize = 4;
+
+impl Position {
+    // A short code for sharing, such as "AAAA" for the empty board. Codes only exist for
+    // classic 3x3 positions, None for anything else.
+    pub fn encode(&self) -> Option<String> {
+        let board = &self.board;
+        if (board.rows(), board.cols(), board.layers()) != (3, 3, 1)
+            || (0..9).any(|index| board.digit(index).is_some())
+        {
+            return None;
+        }
+        // Bits 0-17 hold the cells, bit 18 the side to move, the rest stay 0
+        let mut bits = board
+            .cells()
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (index, &cell)| {
+                let trit = match cell {
+                    State::Empty => 0,
+                    State::X => 1,
+                    State::O => 2,
+                };
+                bits | trit << (2 * index)
+            });
+        if self.to_move == State::O {
+            bits |= 1 << 18;
+        }
+        let code = (0..CODE_LEN)
+            .map(|char| BASE64URL[(bits >> (6 * (CODE_LEN - 1 - char)) & 63) as usize] as char)
+            .collect();
+        Some(code)
+    }
+
+    pub fn decode(code: &str) -> Result<Position, String> {
+        let code = code.trim();
+        if code.len() != CODE_LEN {
+            return Err(format!(
+                "A position code has {} characters, not {}",
+                CODE_LEN,
+                code.len()
+            ));
+        }
+        let mut bits = 0u32;
+        for char in code.bytes() {
+            let value = BASE64URL.iter().position(|&c| c == char).ok_or(format!(
+                "Invalid character in position code: {}",
+                char as char
+            ))?;
+            bits = bits << 6 | value as u32;
+        }
+        if bits >> 19 != 0 {
+            return Err("Unknown position code format".to_string());
+        }
+
+        let rules = Rules::default();
+        let mut board = rules.new_board();
+        for index in 0..9 {
+            board[index] = match bits >> (2 * index) & 3 {
+                0 => State::Empty,
+                1 => State::X,
+                2 => State::O,
+                _ => return Err(format!("Invalid value for cell {} in position code", index)),
+            };
+        }
+        let to_move = if bits >> 18 & 1 == 1 {
+            State::O
+        } else {
+            State::X
+        };
+
+        let position = Position::new(board, to_move, &rules);
+        position.validate(&rules).map_err(|err| err.to_string())?;
+        Ok(position)
+    }
+
+    // A position with its status worked out from the board; the side that isn't to
+    // move is taken to have moved last
+    pub fn new(board: Board, to_move: State, rules: &Rules) -> Position {
+        let last_mover = to_move.opponent();
+        if rules.both_won(&board) {
+            return Position {
+                board,
+                to_move,
+                status: Status::Invalid,
+            };
+        }
+        let winner = match rules.variant {
+            Variant::Classic | Variant::Gravity => rules.winner(&board),
+            Variant::Wild => rules.winner(&board).map(|_| last_mover),
+            Variant::Numerical => board.has_sum_line(rules.win_len, 15).then_some(last_mover),
+        };
+        let status = match winner {
+            Some(mark) => Status::Won(mark),
+            None if board.is_full() => Status::Tie,
+            None => Status::InProgress,
+        };
+        Position {
+            board,
+            to_move,
+            status,
+        }
+    }
+
+    // The completed line of a won position
+    pub fn winning_line(&self, rules: &Rules) -> Option<Line> {
+        match (self.status, rules.variant) {
+            (Status::Won(_), Variant::Numerical) => self.board.find_sum_line(rules.win_len, 15),
+            (Status::Won(_), _) => [State::X, State::O]
+                .into_iter()
+                .find_map(|mark| self.board.find_line(mark, rules.win_len)),
+            _ => None,
+        }
+    }
+
+    // Whether the position can come up in a game under `rules`. Turns alternate and only
+    // the side that moved last can have a line. Either side may open, except in numerical
+    // mode where X always does.
+    pub fn validate(&self, rules: &Rules) -> Result<(), IllegalPosition> {
+        let board = &self.board;
+        if let Some(index) = board.stray_cell() {
+            return Err(IllegalPosition::OutOfRange { index });
+        }
+        if (board.rows(), board.cols(), board.layers()) != (rules.rows, rules.cols, rules.layers) {
+            return Err(IllegalPosition::WrongSize {
+                rows: board.rows(),
+                cols: board.cols(),
+            });
+        }
+        if let Some(index) = (0..board.size()).find(|&index| {
+            board
+                .digit(index)
+                .is_some_and(|digit| !(1..=9).contains(&digit) || board[index] == State::Empty)
+        }) {
+            return Err(IllegalPosition::OutOfRange { index });
+        }
+        if rules.both_won(board) {
+            return Err(IllegalPosition::BothWon);
+        }
+        // In wild mode either side places either mark, so the marks say nothing about turns
+        if rules.variant == Variant::Wild {
+            return Ok(());
+        }
+
+        let (x, o) = (board.count(State::X), board.count(State::O));
+        if x.abs_diff(o) > 1 {
+            return Err(IllegalPosition::CountImbalance { x, o });
+        }
+        let fewer = match x.cmp(&o) {
+            Ordering::Less => Some(State::X),
+            Ordering::Greater => Some(State::O),
+            Ordering::Equal => None,
+        };
+        if fewer.is_some_and(|mark| mark != self.to_move) {
+            return Err(IllegalPosition::WrongSideToMove {
+                to_move: self.to_move,
+            });
+        }
+        if rules.variant == Variant::Numerical {
+            return Ok(());
+        }
+        match (
+            board.has_line(State::X, rules.win_len),
+            board.has_line(State::O, rules.win_len),
+        ) {
+            (true, false) if self.to_move == State::X => {
+                Err(IllegalPosition::LineButNotLastMover { winner: State::X })
+            }
+            (false, true) if self.to_move == State::O => {
+                Err(IllegalPosition::LineButNotLastMover { winner: State::O })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Cells whose state differs from `self` to `other`, in index order
+    pub fn diff(&self, other: &Position) -> Vec<CellChange> {
+        self.board
+            .cells()
+            .iter()
+            .zip(other.board.cells())
+            .enumerate()
+            .filter(|(_, (from, to))| from != to)
+            .map(|(index, (&from, &to))| CellChange { index, from, to })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn position(cells: &str, to_move: State) -> Position {
+        Position::new(cells.parse().unwrap(), to_move, &Rules::default())
+    }
+
+    #[test]
+    fn diff_of_a_position_with_itself_is_empty() {
+        let position = position("X...O....", State::X);
+        assert!(position.diff(&position).is_empty());
+    }
+
+    #[test]
+    fn diff_lists_every_changed_cell_in_order() {
+        let from = position("X...O....", State::X);
+        let to = position("..X.O...O", State::X);
+        let changes = from.diff(&to);
+        assert_eq!(
+            changes,
+            [
+                CellChange {
+                    index: 0,
+                    from: State::X,
+                    to: State::Empty
+                },
+                CellChange {
+                    index: 2,
+                    from: State::Empty,
+                    to: State::X
+                },
+                CellChange {
+                    index: 8,
+                    from: State::Empty,
+                    to: State::O
+                },
+            ]
+        );
+        // Going back undoes the same cells
+        let back: Vec<usize> = to.diff(&from).iter().map(|change| change.index).collect();
+        assert_eq!(back, [0, 2, 8]);
+    }
+
+    // Every position a classic game opened by X goes through, finished ones included
+    fn reachable(rules: &Rules) -> HashSet<Position> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![Position::new(rules.new_board(), State::X, rules)];
+        while let Some(position) = stack.pop() {
+            if !seen.insert(position) || position.status != Status::InProgress {
+                continue;
+            }
+            for &index in rules.legal_moves(&position.board).iter() {
+                let mut board = position.board;
+                board[index] = position.to_move;
+                stack.push(Position::new(board, position.to_move.opponent(), rules));
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn every_reachable_position_survives_its_code() {
+        let rules = Rules::default();
+        let positions = reachable(&rules);
+        let boards: HashSet<Board> = positions.iter().map(|position| position.board).collect();
+        assert_eq!(boards.len(), 5478);
+        for position in positions {
+            let code = position.encode().unwrap();
+            assert_eq!(code.len(), 4);
+            assert!(code.bytes().all(|char| BASE64URL.contains(&char)));
+            assert_eq!(Position::decode(&code), Ok(position), "{}", code);
+        }
+        assert_eq!(
+            position(".........", State::X).encode().as_deref(),
+            Some("AAAA")
+        );
+    }
+
+    #[test]
+    fn bad_codes_say_what_is_wrong() {
+        assert_eq!(
+            Position::decode("AAAAA"),
+            Err("A position code has 4 characters, not 5".to_string())
+        );
+        assert_eq!(
+            Position::decode("AA+A"),
+            Err("Invalid character in position code: +".to_string())
+        );
+        assert_eq!(
+            Position::decode("AAAD"),
+            Err("Invalid value for cell 0 in position code".to_string())
+        );
+        assert_eq!(
+            Position::decode("gAAA"),
+            Err("Unknown position code format".to_string())
+        );
+        let code = position("XXX......", State::O).encode().unwrap();
+        assert_eq!(
+            Position::decode(&code),
+            Err("Illegal position: 3 X against 0 O".to_string())
+        );
+    }
+
+    #[test]
+    fn only_classic_boards_have_codes() {
+        let rules = Rules {
+            rows: 4,
+            cols: 4,
+            ..Rules::default()
+        };
+        let position = Position::new(rules.new_board(), State::X, &rules);
+        assert_eq!(position.encode(), None);
+    }
+
+    fn check(cells: &str, to_move: State) -> Result<(), IllegalPosition> {
+        position(cells, to_move).validate(&Rules::default())
+    }
+
+    #[test]
+    fn each_illegal_class_is_named() {
+        assert_eq!(
+            check("XXX.O....", State::O),
+            Err(IllegalPosition::CountImbalance { x: 3, o: 1 })
+        );
+        assert_eq!(
+            check("X........", State::X),
+            Err(IllegalPosition::WrongSideToMove { to_move: State::X })
+        );
+        assert_eq!(check("XXXOOO...", State::X), Err(IllegalPosition::BothWon));
+        assert_eq!(
+            check("XXXOO.O..", State::X),
+            Err(IllegalPosition::LineButNotLastMover { winner: State::X })
+        );
+        assert_eq!(
+            check("OOOXX.X..", State::O),
+            Err(IllegalPosition::LineButNotLastMover { winner: State::O })
+        );
+        let rules = Rules {
+            rows: 4,
+            cols: 4,
+            ..Rules::default()
+        };
+        let big = Position::new(rules.new_board(), State::X, &rules);
+        assert_eq!(
+            big.validate(&Rules::default()),
+            Err(IllegalPosition::WrongSize { rows: 4, cols: 4 })
+        );
+        let numerical = Rules {
+            variant: Variant::Numerical,
+            ..Rules::default()
+        };
+        let mut board = numerical.new_board();
+        board.place_digit(4, State::X, 12);
+        let position = Position::new(board, State::O, &numerical);
+        assert_eq!(
+            position.validate(&numerical),
+            Err(IllegalPosition::OutOfRange { index: 4 })
+        );
+    }
+
+    #[test]
+    fn lines_of_both_sides_are_invalid_rather_than_a_win() {
+        let both = position("XXXOOO...", State::X);
+        assert_eq!(both.status, Status::Invalid);
+        assert_eq!(both.winning_line(&Rules::default()), None);
+        assert_eq!(
+            both.validate(&Rules::default()),
+            Err(IllegalPosition::BothWon)
+        );
+        // Wild mode checks marks the same way, whoever placed them
+        let wild = Rules {
+            variant: Variant::Wild,
+            ..Rules::default()
+        };
+        let both = Position::new("XXXOOO...".parse().unwrap(), State::O, &wild);
+        assert_eq!(both.status, Status::Invalid);
+        assert_eq!(both.validate(&wild), Err(IllegalPosition::BothWon));
+    }
+
+    #[test]
+    fn legal_edge_cases_pass() {
+        // The last move completing two lines at once
+        assert_eq!(check("XXXXOOXOO", State::O), Ok(()));
+        // Full boards, won on the last move or tied
+        assert_eq!(check("XOXOXOXOX", State::O), Ok(()));
+        assert_eq!(check("XOXXOOOXX", State::O), Ok(()));
+        // Either side may open classic games
+        assert_eq!(check("....O....", State::X), Ok(()));
+        let wild = Rules {
+            variant: Variant::Wild,
+            ..Rules::default()
+        };
+        let board = "XX.X.....".parse().unwrap();
+        assert_eq!(
+            Position::new(board, State::O, &wild).validate(&wild),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// Requests allowed at once, and how many more each second brings (more than 0)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+// What a request gets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Allowed,
+    // Refused; the next request would be allowed after this long
+    Limited(Duration),
+    // Refused again after more refusals in a row than the abuse threshold
+    Abusive,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // Refusals since the last allowed request
+    refused: u32,
+}
+
+// A token bucket for each key, such as a client's address. The caller passes the time to
+// every call, so any clock will do.
+#[derive(Debug, Clone)]
+pub struct RateLimiter<K> {
+    limit: Limit,
+    abuse_threshold: u32,
+    buckets: HashMap<K, Bucket>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(limit: Limit, abuse_threshold: u32) -> Self {
+        RateLimiter {
+            limit,
+            abuse_threshold,
+            buckets: HashMap::new(),
+        }
+    }
+
+    // Takes a token from `key`'s bucket if there is one
+    pub fn check(&mut self, key: K, now: Instant) -> Verdict {
+        let limit = self.limit;
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+            refused: 0,
+        });
+        bucket.tokens = refilled(bucket, limit, now);
+        bucket.updated = bucket.updated.max(now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.refused = 0;
+            return Verdict::Allowed;
+        }
+        bucket.refused = bucket.refused.saturating_add(1);
+        if bucket.refused > self.abuse_threshold {
+            Verdict::Abusive
+        } else {
+            Verdict::Limited(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.per_second,
+            ))
+        }
+    }
+
+    // Drops the buckets that are full again, which a new one would be as well
+    pub fn forget_idle(&mut self, now: Instant) {
+        let limit = self.limit;
+        self.buckets
+            .retain(|_, bucket| refilled(bucket, limit, now) < limit.burst as f64);
+    }
+}
+
+fn refilled(bucket: &Bucket, limit: Limit, now: Instant) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    (bucket.tokens + elapsed * limit.per_second).min(limit.burst as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Limit = Limit {
+        burst: 3,
+        per_second: 2.0,
+    };
+
+    fn secs(seconds: f64) -> Duration {
+        Duration::from_secs_f64(seconds)
+    }
+
+    #[test]
+    fn a_burst_is_allowed_then_refused_until_refilled() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(LIMIT, 5);
+        for _ in 0..3 {
+            assert_eq!(limiter.check("a", start), Verdict::Allowed);
+        }
+        assert_eq!(limiter.check("a", start), Verdict::Limited(secs(0.5)));
+        assert_eq!(
+            limiter.check("a", start + secs(0.25)),
+            Verdict::Limited(secs(0.25))
+        );
+        assert_eq!(limiter.check("a", start + secs(0.5)), Verdict::Allowed);
+        assert!(matches!(
+            limiter.check("a", start + secs(0.5)),
+            Verdict::Limited(_)
+        ));
+        // Never more than the burst, however long the wait
+        let later = start + secs(60.0);
+        for _ in 0..3 {
+            assert_eq!(limiter.check("a", later), Verdict::Allowed);
+        }
+        assert!(matches!(limiter.check("a", later), Verdict::Limited(_)));
+    }
+
+    #[test]
+    fn keys_have_their_own_buckets() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(LIMIT, 5);
+        for _ in 0..3 {
+            limiter.check(1, now);
+        }
+        assert!(matches!(limiter.check(1, now), Verdict::Limited(_)));
+        assert_eq!(limiter.check(2, now), Verdict::Allowed);
+    }
+
+    #[test]
+    fn refusals_past_the_threshold_are_abusive() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(LIMIT, 2);
+        for _ in 0..3 {
+            limiter.check("a", now);
+        }
+        assert!(matches!(limiter.check("a", now), Verdict::Limited(_)));
+        assert!(matches!(limiter.check("a", now), Verdict::Limited(_)));
+        assert_eq!(limiter.check("a", now), Verdict::Abusive);
+        assert_eq!(limiter.check("a", now), Verdict::Abusive);
+        // An allowed request starts the count over
+        assert_eq!(limiter.check("a", now + secs(0.5)), Verdict::Allowed);
+        assert!(matches!(
+            limiter.check("a", now + secs(0.5)),
+            Verdict::Limited(_)
+        ));
+    }
+
+    #[test]
+    fn a_clock_going_back_does_not_add_tokens() {
+        let now = Instant::now() + secs(10.0);
+        let mut limiter = RateLimiter::new(LIMIT, 5);
+        for _ in 0..3 {
+            limiter.check("a", now);
+        }
+        assert!(matches!(
+            limiter.check("a", now - secs(5.0)),
+            Verdict::Limited(_)
+        ));
+        assert!(matches!(limiter.check("a", now), Verdict::Limited(_)));
+    }
+
+    #[test]
+    fn idle_buckets_are_forgotten_once_full() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(LIMIT, 5);
+        limiter.check("used", now);
+        for _ in 0..3 {
+            limiter.check("drained", now);
+        }
+        limiter.forget_idle(now + secs(0.4));
+        assert_eq!(limiter.buckets.len(), 2);
+        limiter.forget_idle(now + secs(1.0));
+        assert_eq!(limiter.buckets.len(), 1);
+        assert!(limiter.buckets.contains_key("drained"));
+        limiter.forget_idle(now + secs(1.5));
+        assert!(limiter.buckets.is_empty());
+    }
+}
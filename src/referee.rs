@@ -0,0 +1,260 @@
+use crate::ai;
+use crate::arena::SimulationReport;
+use crate::board::{Board, State};
+use crate::game::Status;
+use crate::rules::Rules;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Time past the movetime for an answer to arrive, and for an engine to quit
+const GRACE: Duration = Duration::from_millis(500);
+
+// Why a side lost a game without it being played out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Forfeit {
+    // The move as the engine sent it
+    IllegalMove(String),
+    Timeout,
+    // The engine exited or closed its pipes
+    Crashed,
+}
+
+impl fmt::Display for Forfeit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Forfeit::IllegalMove(answer) => write!(f, "illegal move '{}'", answer),
+            Forfeit::Timeout => write!(f, "timeout"),
+            Forfeit::Crashed => write!(f, "crash"),
+        }
+    }
+}
+
+// An executable speaking the engine protocol, running as a child process. One that hung
+// or died is killed and started again before its next game.
+pub struct EngineProcess {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    // Lines of its stdout, read on a thread so that waiting for them can time out
+    lines: Receiver<String>,
+    running: bool,
+}
+
+impl EngineProcess {
+    // `command` is the program followed by its arguments, split on whitespace
+    pub fn spawn(command: &str) -> Result<Self, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("The engine command is empty")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Can't start {}: {}", command, err))?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => return Err(format!("Can't talk to {}: no pipes", command)),
+        };
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(EngineProcess {
+            command: command.to_string(),
+            child,
+            stdin,
+            lines,
+            running: true,
+        })
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), Forfeit> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|()| self.stdin.flush())
+            .map_err(|_| Forfeit::Crashed)
+    }
+
+    // Sends `board` and waits for the move until `movetime` and the grace are up. The
+    // answer comes back as sent, checking it is up to the referee.
+    fn best_move(&mut self, board: &Board, movetime: Duration) -> Result<String, Forfeit> {
+        self.send(&format!("position {}", board))?;
+        self.send(&format!("go movetime {}", movetime.as_millis()))?;
+        let deadline = Instant::now() + movetime + GRACE;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) => {
+                    let mut words = line.split_whitespace();
+                    if words.next() == Some("bestmove") {
+                        return Ok(words.next().unwrap_or_default().to_string());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(Forfeit::Timeout),
+                Err(RecvTimeoutError::Disconnected) => return Err(Forfeit::Crashed),
+            }
+        }
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.running = false;
+    }
+
+    fn restart(&mut self) -> Result<(), String> {
+        if !self.running {
+            *self = EngineProcess::spawn(&self.command)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for EngineProcess {
+    // Asks it to quit, and kills it if it doesn't in time
+    fn drop(&mut self) {
+        if !self.running {
+            return;
+        }
+        let _ = self.send("quit");
+        let deadline = Instant::now() + GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.kill();
+    }
+}
+
+// A batch of games between two engines on the same rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EngineMatch {
+    pub rules: Rules,
+    pub games: u32,
+    pub movetime: Duration,
+}
+
+// Totals like those of CPU games, forfeits counting as wins of the other side, and every
+// forfeit with the number of its game (from 1) and the side that lost it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineReport {
+    pub report: SimulationReport,
+    pub forfeits: Vec<(u32, State, Forfeit)>,
+}
+
+// How one refereed game ended
+#[derive(Debug, Clone, PartialEq)]
+struct Refereed {
+    status: Status,
+    moves: usize,
+    forks: [bool; 2],
+    forfeit: Option<(State, Forfeit)>,
+}
+
+// Plays `config.games` games with `x` moving first in all of them
+pub fn play_engines(
+    config: &EngineMatch,
+    x: &mut EngineProcess,
+    o: &mut EngineProcess,
+) -> Result<EngineReport, String> {
+    let started = Instant::now();
+    let mut result = EngineReport::default();
+    for game in 1..=config.games {
+        let refereed = referee(&config.rules, config.movetime, x, o)?;
+        let report = &mut result.report;
+        report.games += 1;
+        match refereed.status {
+            Status::Won(State::X) => report.x_wins += 1,
+            Status::Won(_) => report.o_wins += 1,
+            _ => report.ties += 1,
+        }
+        report.x_forks += refereed.forks[0] as u32;
+        report.o_forks += refereed.forks[1] as u32;
+        report.total_moves += refereed.moves as u64;
+        if let Some((side, forfeit)) = refereed.forfeit {
+            result.forfeits.push((game, side, forfeit));
+        }
+    }
+    result.report.elapsed = started.elapsed();
+    Ok(result)
+}
+
+// One game on the referee's own board. The engines only see the positions sent to them,
+// and their answers are checked against that board before they count.
+fn referee(
+    rules: &Rules,
+    movetime: Duration,
+    x: &mut EngineProcess,
+    o: &mut EngineProcess,
+) -> Result<Refereed, String> {
+    let mut board = rules.new_board();
+    let mut to_move = State::X;
+    let mut refereed = Refereed {
+        status: Status::Tie,
+        moves: 0,
+        forks: [false; 2],
+        forfeit: None,
+    };
+    for (side, engine) in [(State::X, &mut *x), (State::O, &mut *o)] {
+        engine.restart()?;
+        if let Err(forfeit) = engine.send("newgame") {
+            engine.kill();
+            refereed.status = Status::Won(side.opponent());
+            refereed.forfeit = Some((side, forfeit));
+            return Ok(refereed);
+        }
+    }
+    loop {
+        let legal = rules.legal_moves(&board);
+        if legal.is_empty() {
+            return Ok(refereed);
+        }
+        let engine = if to_move == State::X {
+            &mut *x
+        } else {
+            &mut *o
+        };
+        let answer = engine.best_move(&board, movetime);
+        let index = match answer {
+            Ok(answer) => match answer.parse::<usize>() {
+                Ok(index) if legal.contains(&index) => Ok(index),
+                _ => Err(Forfeit::IllegalMove(answer)),
+            },
+            Err(forfeit) => {
+                engine.kill();
+                Err(forfeit)
+            }
+        };
+        let index = match index {
+            Ok(index) => index,
+            Err(forfeit) => {
+                refereed.status = Status::Won(to_move.opponent());
+                refereed.forfeit = Some((to_move, forfeit));
+                return Ok(refereed);
+            }
+        };
+        board[index] = to_move;
+        refereed.moves += 1;
+        if board.has_line(to_move, rules.win_len) {
+            refereed.status = Status::Won(to_move);
+            return Ok(refereed);
+        }
+        let fork = &mut refereed.forks[(to_move == State::O) as usize];
+        *fork = *fork || ai::has_fork(&board, rules, to_move);
+        to_move = to_move.opponent();
+    }
+}
@@ -0,0 +1,497 @@
+use crate::ai::Difficulty;
+use crate::board::{Board, State};
+use crate::events::{Event, Observer};
+use crate::game::{Ending, Status};
+use crate::migrations;
+use crate::position::Position;
+use crate::rng::RngKind;
+use crate::rules::{Rules, Variant};
+use crate::save;
+use crate::timestamp::rfc3339_now;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Bumped on every incompatible change of the .ttt format, with a step in `migrations`
+pub const REPLAY_VERSION: u32 = 3;
+
+// Who played one of the marks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Participant {
+    pub mark: State,
+    // "human" or "cpu"
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReplayMove {
+    pub mark: State,
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digit: Option<u8>,
+    // Played for an idle player rather than by them
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub auto: bool,
+    // Milliseconds since the round started, 0 in replays recorded before moves were timed
+    #[serde(default)]
+    pub elapsed_ms: u64,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+// A recorded round: the header describing how it was played, then its moves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub date: String,
+    pub rules: Rules,
+    pub players: Vec<Participant>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // The generator `seed` fed, set along with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rng: Option<RngKind>,
+    pub moves: Vec<ReplayMove>,
+    pub result: Status,
+    // How the players ended the round after the last move, if the board didn't decide it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ending: Option<Ending>,
+}
+
+impl Replay {
+    pub fn new(rules: Rules) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            date: rfc3339_now(),
+            rules,
+            players: Vec::new(),
+            seed: None,
+            rng: None,
+            moves: Vec::new(),
+            result: Status::InProgress,
+            ending: None,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        save::write_atomic(path.as_ref(), &(json + "\n"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Replay, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        Replay::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        // Look at the version first so newer files fail clearly instead of on a missing field
+        let value: serde_json::Value =
+            serde_json::from_str(text).map_err(|err| format!("Not a replay file: {}", err))?;
+        let value = migrations::upgrade(&migrations::REPLAY, value)?;
+        let replay: Replay =
+            serde_json::from_value(value).map_err(|err| format!("Invalid replay: {}", err))?;
+        replay.rules.validate()?;
+        replay.boards()?;
+        match (replay.ending, replay.result) {
+            (Some(Ending::Resigned(mark)), Status::Won(winner)) if winner == mark.opponent() => (),
+            (Some(Ending::DrawAgreed), Status::Tie) | (None, _) => (),
+            (Some(ending), result) => {
+                return Err(format!(
+                    "Invalid replay: {} can't end in {:?}",
+                    ending, result
+                ))
+            }
+        }
+        Ok(replay)
+    }
+
+    // The board after each move, checking every move against the rules
+    pub fn boards(&self) -> Result<Vec<Board>, String> {
+        let mut board = self.rules.new_board();
+        let mut boards = Vec::with_capacity(self.moves.len());
+        for (number, step) in self.moves.iter().enumerate() {
+            let legal = self.rules.legal_moves(&board);
+            if !legal.contains(&step.index) || step.mark == State::Empty {
+                return Err(format!(
+                    "Move {} at {} is not legal",
+                    number + 1,
+                    step.index
+                ));
+            }
+            match (self.rules.variant, step.digit) {
+                (Variant::Numerical, Some(digit @ 1..=9))
+                    if step.mark.owns_digit(digit) && !board.digit_used(digit) =>
+                {
+                    board.place_digit(step.index, step.mark, digit)
+                }
+                (Variant::Numerical, _) => {
+                    return Err(format!("Move {} has an invalid digit", number + 1))
+                }
+                _ => board[step.index] = step.mark,
+            }
+            Position::new(board, step.mark.opponent(), &self.rules)
+                .validate(&self.rules)
+                .map_err(|err| format!("Move {}: {}", number + 1, err))?;
+            boards.push(board);
+        }
+        Ok(boards)
+    }
+}
+
+// The time playback waits by, a fake one in tests
+pub trait Clock {
+    // Time passed since the clock was made
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+// The real time, counted from when it was made
+pub struct SystemClock(Instant);
+
+impl SystemClock {
+    pub fn start() -> Self {
+        SystemClock(Instant::now())
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// How long playback waits before each move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    // The same pause before every move
+    Fixed(Duration),
+    // Each move as long after the start as it was played, by its `elapsed_ms`
+    Realtime,
+}
+
+// Waits out the pause before each move of a replay played back
+pub struct Playback<C> {
+    clock: C,
+    pacing: Pacing,
+    started: Duration,
+}
+
+impl<C: Clock> Playback<C> {
+    pub fn new(clock: C, pacing: Pacing) -> Self {
+        let started = clock.now();
+        Playback {
+            clock,
+            pacing,
+            started,
+        }
+    }
+
+    // Returns once `step` is due. In realtime, the time spent showing the moves before it
+    // is part of its wait rather than added to it.
+    pub fn wait_for(&mut self, step: &ReplayMove) {
+        let pause = match self.pacing {
+            Pacing::Fixed(delay) => delay,
+            Pacing::Realtime => {
+                let due = self.started + Duration::from_millis(step.elapsed_ms);
+                due.saturating_sub(self.clock.now())
+            }
+        };
+        if !pause.is_zero() {
+            self.clock.sleep(pause);
+        }
+    }
+}
+
+// Saves every finished round as `round-<n>.ttt` in a directory
+pub struct ReplayRecorder {
+    dir: PathBuf,
+    rules: Rules,
+    difficulty: Difficulty,
+    seed: Option<u64>,
+    current: Option<(u32, Replay)>,
+}
+
+impl ReplayRecorder {
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        rules: Rules,
+        difficulty: Difficulty,
+    ) -> Result<Self, String> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Can't create {}: {}", dir.display(), err))?;
+        Ok(ReplayRecorder {
+            dir,
+            rules,
+            difficulty,
+            seed: None,
+            current: None,
+        })
+    }
+
+    // Seed of the game's ChaCha8 RNG, stored in every replay header
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Observer for ReplayRecorder {
+    fn on_event(&mut self, event: &Event) {
+        match *event {
+            Event::RoundStart { round } => {
+                let mut replay = Replay::new(self.rules);
+                replay.seed = self.seed;
+                replay.rng = self.seed.map(|_| RngKind::ChaCha8);
+                self.current = Some((round, replay));
+            }
+            Event::Move {
+                mark,
+                index,
+                digit,
+                human,
+                auto,
+                elapsed,
+            } => {
+                if let Some((_, replay)) = &mut self.current {
+                    // Wild mode lets both sides play either mark, so the first mark seen names the side
+                    let kind = if human { "human" } else { "cpu" };
+                    // Wild and numerical CPUs always play at random
+                    let difficulty = match self.rules.variant {
+                        Variant::Classic | Variant::Gravity => self.difficulty,
+                        _ => Difficulty::Easy,
+                    };
+                    if !replay.players.iter().any(|player| player.kind == kind) {
+                        replay.players.push(Participant {
+                            mark,
+                            kind: kind.to_string(),
+                            difficulty: (!human).then(|| difficulty.to_string()),
+                        });
+                    }
+                    replay.moves.push(ReplayMove {
+                        mark,
+                        index,
+                        digit,
+                        auto,
+                        elapsed_ms: elapsed.as_millis() as u64,
+                    });
+                }
+            }
+            Event::RoundEnd { status, ending } => {
+                if let Some((round, mut replay)) = self.current.take() {
+                    replay.result = status;
+                    replay.ending = ending;
+                    let path = self.dir.join(format!("round-{}.ttt", round));
+                    if let Err(err) = replay.save(&path) {
+                        eprintln!("Warning: {}", err);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::{env, process};
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/replay-v1.ttt");
+
+    #[test]
+    fn fixture_still_loads() {
+        let replay = Replay::parse(FIXTURE).unwrap();
+        assert_eq!(replay.version, REPLAY_VERSION);
+        assert_eq!(replay.rules, Rules::default());
+        assert_eq!(replay.seed, Some(7));
+        assert_eq!(replay.players[1].difficulty.as_deref(), Some("hard"));
+        let indexes: Vec<usize> = replay.moves.iter().map(|step| step.index).collect();
+        assert_eq!(indexes, [4, 6, 0, 8, 2, 7]);
+        assert_eq!(replay.result, Status::Won(State::O));
+        let last = *replay.boards().unwrap().last().unwrap();
+        assert_eq!(last.to_string(), "X.X.X.OOO");
+    }
+
+    #[test]
+    fn saved_replay_loads_back_the_same() {
+        let replay = Replay::parse(FIXTURE).unwrap();
+        let path = env::temp_dir().join(format!("ttt-replay-{}.ttt", process::id()));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), replay);
+    }
+
+    #[test]
+    fn newer_version_is_unsupported() {
+        let newer = FIXTURE.replace(
+            "\"version\": 1",
+            &format!("\"version\": {}", REPLAY_VERSION + 1),
+        );
+        let err = Replay::parse(&newer).unwrap_err();
+        assert!(err.contains("created by a newer version"), "{}", err);
+    }
+
+    #[test]
+    fn endings_are_kept_and_checked_against_the_result() {
+        let mut replay = Replay::parse(FIXTURE).unwrap();
+        replay.ending = Some(Ending::Resigned(State::X));
+        let json = serde_json::to_string(&replay).unwrap();
+        assert!(json.contains(r#""ending":{"Resigned":"x"}"#), "{}", json);
+        assert_eq!(Replay::parse(&json).unwrap(), replay);
+        replay.ending = Some(Ending::Resigned(State::O));
+        let json = serde_json::to_string(&replay).unwrap();
+        assert_eq!(
+            Replay::parse(&json).unwrap_err(),
+            "Invalid replay: O resigns can't end in Won(O)"
+        );
+        replay.ending = Some(Ending::DrawAgreed);
+        replay.result = Status::Tie;
+        let json = serde_json::to_string(&replay).unwrap();
+        assert!(json.contains(r#""ending":"DrawAgreed""#), "{}", json);
+        assert_eq!(
+            Replay::parse(&json).unwrap().ending,
+            Some(Ending::DrawAgreed)
+        );
+    }
+
+    #[test]
+    fn illegal_moves_are_refused() {
+        let replayed = FIXTURE.replacen("\"index\": 6", "\"index\": 4", 1);
+        assert_eq!(
+            Replay::parse(&replayed).unwrap_err(),
+            "Move 2 at 4 is not legal"
+        );
+    }
+
+    #[test]
+    fn damaged_replays_fail_cleanly_or_load_back_the_same() {
+        use crate::ai::{Tiebreak, XorShift};
+        let pieces = [
+            "",
+            "0",
+            "9",
+            "-1",
+            "\"",
+            "{",
+            "}",
+            ",",
+            "x",
+            "null",
+            "4294967296",
+            "é",
+        ];
+        let mut rng = XorShift::new(3);
+        for _ in 0..2_000 {
+            let mut text = FIXTURE.to_string();
+            for _ in 0..1 + rng.below(3) {
+                let mut at = rng.below(text.len());
+                while !text.is_char_boundary(at) {
+                    at -= 1;
+                }
+                let end = (at + rng.below(4)).min(text.len());
+                let end = (end..=text.len())
+                    .find(|&end| text.is_char_boundary(end))
+                    .unwrap();
+                text.replace_range(at..end, pieces[rng.below(pieces.len())]);
+            }
+            if let Ok(replay) = Replay::parse(&text) {
+                let json = serde_json::to_string(&replay).unwrap();
+                assert_eq!(Replay::parse(&json).unwrap(), replay);
+            }
+        }
+    }
+
+    #[test]
+    fn move_times_are_saved_and_old_moves_have_none() {
+        let mut replay = Replay::parse(FIXTURE).unwrap();
+        assert!(replay.moves.iter().all(|step| step.elapsed_ms == 0));
+        for (number, step) in replay.moves.iter_mut().enumerate() {
+            step.elapsed_ms = 1_250 * (number as u64 + 1);
+        }
+        let json = serde_json::to_string(&replay).unwrap();
+        assert!(json.contains(r#""elapsed_ms":2500"#), "{}", json);
+        assert_eq!(Replay::parse(&json).unwrap(), replay);
+    }
+
+    // Time that only passes when slept or moved on by hand, logging the sleeps
+    #[derive(Clone, Default)]
+    struct FakeClock(Rc<RefCell<(Duration, Vec<Duration>)>>);
+
+    impl FakeClock {
+        fn pass(&self, duration: Duration) {
+            self.0.borrow_mut().0 += duration;
+        }
+
+        fn sleeps(&self) -> Vec<Duration> {
+            self.0.borrow().1.clone()
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            self.0.borrow().0
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            let mut clock = self.0.borrow_mut();
+            clock.0 += duration;
+            clock.1.push(duration);
+        }
+    }
+
+    fn timed(elapsed_ms: &[u64]) -> Vec<ReplayMove> {
+        elapsed_ms
+            .iter()
+            .map(|&elapsed_ms| ReplayMove {
+                mark: State::X,
+                index: 0,
+                digit: None,
+                auto: false,
+                elapsed_ms,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn realtime_playback_keeps_the_recorded_pace() {
+        let clock = FakeClock::default();
+        // Playback starts a while after the clock was made
+        clock.pass(Duration::from_secs(5));
+        let mut playback = Playback::new(clock.clone(), Pacing::Realtime);
+        for step in &timed(&[0, 1_500, 1_500, 4_000]) {
+            playback.wait_for(step);
+            // Showing a move takes time too
+            clock.pass(Duration::from_millis(100));
+        }
+        let ms = Duration::from_millis;
+        assert_eq!(clock.sleeps(), [ms(1_400), ms(2_300)]);
+        assert_eq!(clock.now(), ms(9_100));
+    }
+
+    #[test]
+    fn fixed_playback_waits_the_same_before_every_move() {
+        let clock = FakeClock::default();
+        let delay = Duration::from_millis(250);
+        let mut playback = Playback::new(clock.clone(), Pacing::Fixed(delay));
+        for step in &timed(&[0, 1_500, 4_000]) {
+            playback.wait_for(step);
+        }
+        assert_eq!(clock.sleeps(), [delay; 3]);
+    }
+}
@@ -0,0 +1,232 @@
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// Which generator a seed feeds. Saves and replays record it, so they are played back with
+// the generator that made them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum RngKind {
+    // rand_chacha documents its output as stable, so a seed gives the same game in
+    // every build
+    #[default]
+    ChaCha8,
+    // rand's StdRng, which may change with rand's version; only older files use it
+    Std,
+}
+
+impl fmt::Display for RngKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RngKind::ChaCha8 => write!(f, "chacha8"),
+            RngKind::Std => write!(f, "std"),
+        }
+    }
+}
+
+// The randomness of the CPU and of simulations
+#[derive(Debug, Clone)]
+pub enum GameRng {
+    ChaCha8(ChaCha8Rng),
+    Std(StdRng),
+}
+
+impl GameRng {
+    pub fn seeded(kind: RngKind, seed: u64) -> Self {
+        match kind {
+            RngKind::ChaCha8 => GameRng::ChaCha8(ChaCha8Rng::seed_from_u64(seed)),
+            RngKind::Std => GameRng::Std(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    // Casual play, different every time
+    pub fn from_entropy() -> Self {
+        GameRng::ChaCha8(ChaCha8Rng::from_entropy())
+    }
+
+    // ChaCha8 from `seed` when there is one, so the run can be repeated, else casual
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        match seed {
+            Some(seed) => GameRng::seeded(RngKind::ChaCha8, seed),
+            None => GameRng::from_entropy(),
+        }
+    }
+}
+
+// Where a ChaCha8 generator is in its output, so a saved game carries on with the very
+// numbers it would have drawn
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngState {
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub seed: [u8; 32],
+    pub stream: u64,
+    pub word_pos: u128,
+}
+
+#[cfg(feature = "serde")]
+impl GameRng {
+    // None for StdRng, which doesn't show its state
+    pub fn state(&self) -> Option<RngState> {
+        match self {
+            GameRng::ChaCha8(rng) => Some(RngState {
+                seed: rng.get_seed(),
+                stream: rng.get_stream(),
+                word_pos: rng.get_word_pos(),
+            }),
+            GameRng::Std(_) => None,
+        }
+    }
+
+    pub fn from_state(state: RngState) -> Self {
+        let mut rng = ChaCha8Rng::from_seed(state.seed);
+        rng.set_stream(state.stream);
+        rng.set_word_pos(state.word_pos);
+        GameRng::ChaCha8(rng)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn to_hex<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    serializer.serialize_str(&hex)
+}
+
+#[cfg(feature = "serde")]
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    let mut bytes = [0; 32];
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(de::Error::custom("a seed has 64 hex digits"));
+    }
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(de::Error::custom)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(de::Error::custom)?;
+    }
+    Ok(bytes)
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            GameRng::ChaCha8(rng) => rng.next_u32(),
+            GameRng::Std(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            GameRng::ChaCha8(rng) => rng.next_u64(),
+            GameRng::Std(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            GameRng::ChaCha8(rng) => rng.fill_bytes(dest),
+            GameRng::Std(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            GameRng::ChaCha8(rng) => rng.try_fill_bytes(dest),
+            GameRng::Std(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{self, Difficulty};
+    use crate::board::State;
+    use crate::rules::Rules;
+
+    // Casual games use ChaCha8 too, so their state can be saved
+    #[test]
+    fn every_new_game_gets_chacha8() {
+        assert!(matches!(GameRng::from_entropy(), GameRng::ChaCha8(_)));
+        assert!(matches!(GameRng::from_seed(None), GameRng::ChaCha8(_)));
+        let mut seeded = GameRng::from_seed(Some(3));
+        let mut chacha = GameRng::seeded(RngKind::ChaCha8, 3);
+        assert_eq!(seeded.next_u64(), chacha.next_u64());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn restored_state_draws_the_same_numbers() {
+        let mut rng = GameRng::seeded(RngKind::ChaCha8, 5);
+        for _ in 0..3 {
+            rng.next_u32();
+        }
+        let state = rng.state().unwrap();
+        let json = serde_json::to_string(&state).unwrap();
+        let mut restored = GameRng::from_state(serde_json::from_str(&json).unwrap());
+        for _ in 0..10 {
+            assert_eq!(restored.next_u64(), rng.next_u64());
+        }
+        assert_eq!(GameRng::seeded(RngKind::Std, 5).state(), None);
+        let short = json.replacen("\"seed\":\"", "\"seed\":\"0", 1);
+        assert!(serde_json::from_str::<RngState>(&short).is_err());
+    }
+
+    // A game of the easy CPU as X against the medium one, until a win or a full board
+    fn cpu_game(mut rng: GameRng) -> Vec<usize> {
+        let rules = Rules::default();
+        let mut board = rules.new_board();
+        let mut mark = State::X;
+        let mut moves = Vec::new();
+        while rules.winner(&board).is_none() {
+            let difficulty = if mark == State::X {
+                Difficulty::Easy
+            } else {
+                Difficulty::Medium
+            };
+            let Some(decision) = ai::choose_move(&board, &rules, mark, difficulty.into(), &mut rng)
+            else {
+                break;
+            };
+            board[decision.index] = mark;
+            moves.push(decision.index);
+            mark = mark.opponent();
+        }
+        moves
+    }
+
+    // These pin ChaCha8's output: seeded games and replays depend on it never changing
+    #[test]
+    fn chacha8_draws_the_same_numbers_forever() {
+        let mut rng = GameRng::seeded(RngKind::ChaCha8, 42);
+        let drawn: Vec<u32> = (0..4).map(|_| rng.next_u32()).collect();
+        assert_eq!(drawn, [962419617, 2928721845, 628724104, 4081401798]);
+    }
+
+    #[test]
+    fn a_seed_gives_the_same_cpu_moves_forever() {
+        let games: Vec<Vec<usize>> = [1, 2, 42]
+            .iter()
+            .map(|&seed| cpu_game(GameRng::seeded(RngKind::ChaCha8, seed)))
+            .collect();
+        assert_eq!(
+            games,
+            [
+                vec![5, 2, 4, 3, 8, 0, 6, 1],
+                vec![7, 5, 8, 6, 1, 4, 3, 2],
+                vec![6, 3, 5, 2, 1, 8, 7, 4, 0],
+            ]
+        );
+        assert_eq!(
+            cpu_game(GameRng::from_seed(Some(42))),
+            cpu_game(GameRng::seeded(RngKind::ChaCha8, 42))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use crate::board::{Board, MoveList, State};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Variant {
+    Classic,
+    // Marks fall to the lowest empty row of the chosen column
+    Gravity,
+    // Either side may place either mark; completing any line wins for the mover
+    Wild,
+    // Odd digits against even digits, a full line summing to 15 wins for the mover
+    Numerical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rules {
+    pub rows: usize,
+    pub cols: usize,
+    // More than one layer makes a 3D board, such as the 3x3x3 cube
+    pub layers: usize,
+    pub win_len: usize,
+    pub variant: Variant,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            rows: 3,
+            cols: 3,
+            layers: 1,
+            win_len: 3,
+            variant: Variant::Classic,
+        }
+    }
+}
+
+impl Rules {
+    pub fn gravity(rows: usize, cols: usize) -> Self {
+        Rules {
+            rows,
+            cols,
+            layers: 1,
+            win_len: if rows.max(cols) > 3 { 4 } else { 3 },
+            variant: Variant::Gravity,
+        }
+    }
+
+    pub fn cube() -> Self {
+        Rules {
+            layers: 3,
+            ..Rules::default()
+        }
+    }
+
+    pub fn cells(&self) -> usize {
+        self.rows * self.cols * self.layers
+    }
+
+    #[cfg(feature = "std")]
+    pub fn validate(&self) -> Result<(), String> {
+        use crate::board::MAX_CELLS;
+
+        // Checked, as the dimensions may come from a file
+        let cells = self
+            .rows
+            .checked_mul(self.cols)
+            .and_then(|area| area.checked_mul(self.layers));
+        if !matches!(cells, Some(1..=MAX_CELLS)) {
+            return Err(format!(
+                "Board {}x{} is not supported (at most {} cells)",
+                self.rows, self.cols, MAX_CELLS
+            ));
+        }
+        if self.variant == Variant::Numerical && (self.cells() != 9 || self.win_len != 3) {
+            return Err("Numerical mode is played on a 3x3 board".to_string());
+        }
+        if self.layers > 1 && self.variant == Variant::Gravity {
+            return Err("Gravity is only supported on flat boards".to_string());
+        }
+        if self.win_len < 2 || self.win_len > self.rows.max(self.cols).max(self.layers) {
+            return Err(format!(
+                "Win length {} does not fit a {}x{} board",
+                self.win_len, self.rows, self.cols
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn new_board(&self) -> Board {
+        Board::new(self.rows, self.cols, self.layers)
+    }
+
+    // Cells a mark can be placed on this turn
+    pub fn legal_moves(&self, board: &Board) -> MoveList {
+        match self.variant {
+            Variant::Gravity => (0..board.cols())
+                .filter_map(|col| board.drop_target(col))
+                .collect(),
+            _ => (0..board.size())
+                .filter(|&i| board[i] == State::Empty)
+                .collect(),
+        }
+    }
+
+    // The mark that completed a line, if any
+    pub fn winner(&self, board: &Board) -> Option<State> {
+        [State::X, State::O]
+            .into_iter()
+            .find(|&mark| board.has_line(mark, self.win_len))
+    }
+
+    // Whether X and O both have a line, which no game can reach: the round ends at the
+    // first line. Numerical mode wins by sums, so its marks never count.
+    pub fn both_won(&self, board: &Board) -> bool {
+        self.variant != Variant::Numerical
+            && [State::X, State::O]
+                .into_iter()
+                .all(|mark| board.has_line(mark, self.win_len))
+    }
+
+    // Whether every line holds both marks, so that neither side can win any more. Wild
+    // and numerical rounds can still be won on such a board, they never count as dead.
+    pub fn dead_draw(&self, board: &Board) -> bool {
+        matches!(self.variant, Variant::Classic | Variant::Gravity)
+            && board.lines(self.win_len).all(|line| {
+                line.cells().any(|i| board[i] == State::X)
+                    && line.cells().any(|i| board[i] == State::O)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gravity_moves_are_the_lowest_empty_cell_of_each_column() {
+        let rules = Rules::gravity(6, 7);
+        let mut board = rules.new_board();
+        assert_eq!(*rules.legal_moves(&board), [35, 36, 37, 38, 39, 40, 41]);
+        board[38] = State::X;
+        board[31] = State::O;
+        assert!(rules.legal_moves(&board).contains(&24));
+        assert!(!rules.legal_moves(&board).contains(&38));
+    }
+
+    #[test]
+    fn full_gravity_columns_have_no_move() {
+        let rules = Rules::gravity(4, 4);
+        let mut board = rules.new_board();
+        for row in 0..4 {
+            board[row * 4 + 1] = if row % 2 == 0 { State::X } else { State::O };
+        }
+        assert_eq!(board.drop_target(1), None);
+        assert_eq!(*rules.legal_moves(&board), [12, 14, 15]);
+    }
+
+    #[test]
+    fn boards_with_every_line_blocked_are_dead() {
+        let mut board = Rules::default().new_board();
+        for (index, mark) in "XXOOOXXO".chars().enumerate() {
+            board[index] = if mark == 'X' { State::X } else { State::O };
+        }
+        assert!(Rules::default().dead_draw(&board));
+        // Wild marks are anyone's, the last cell may still complete a line
+        let wild = Rules {
+            variant: Variant::Wild,
+            ..Rules::default()
+        };
+        assert!(!wild.dead_draw(&board));
+        board[7] = State::X;
+        assert!(!Rules::default().dead_draw(&board));
+    }
+
+    // Each cube line as a bit mask of its cells, found from scratch: every axis is either
+    // fixed or runs up or down
+    fn cube_lines() -> ([u32; 64], usize) {
+        let mut masks = [0; 64];
+        let mut count = 0;
+        // 0..3 fixes the axis there, 3 runs it up, 4 runs it down
+        for modes in 0..125 {
+            let axes = [modes % 5, modes / 5 % 5, modes / 25];
+            if axes.iter().all(|&mode| mode < 3) {
+                continue;
+            }
+            let mut mask = 0;
+            for t in 0..3 {
+                let [layer, row, col] = axes.map(|mode| match mode {
+                    3 => t,
+                    4 => 2 - t,
+                    fixed => fixed,
+                });
+                mask |= 1 << (layer * 9 + row * 3 + col);
+            }
+            if !masks[..count].contains(&mask) {
+                masks[count] = mask;
+                count += 1;
+            }
+        }
+        (masks, count)
+    }
+
+    #[test]
+    fn cube_has_the_49_lines_of_3x3x3() {
+        let (expected, count) = cube_lines();
+        assert_eq!(count, 49);
+        let board = Rules::cube().new_board();
+        let mut found = 0;
+        for line in board.lines(3) {
+            let mask = line.cells().fold(0, |mask, i| mask | 1 << i);
+            assert!(expected[..count].contains(&mask), "not a line: {:?}", line);
+            found += 1;
+        }
+        assert_eq!(found, 49);
+    }
+
+    #[test]
+    fn cube_win_detection_is_exhaustive() {
+        let (lines, count) = cube_lines();
+        let rules = Rules::cube();
+        for a in 0..27 {
+            for b in a + 1..27 {
+                for c in b + 1..27 {
+                    let mut board = rules.new_board();
+                    for i in [a, b, c] {
+                        board[i] = State::O;
+                    }
+                    let is_line = lines[..count].contains(&(1 << a | 1 << b | 1 << c));
+                    assert_eq!(
+                        rules.winner(&board) == Some(State::O),
+                        is_line,
+                        "{a} {b} {c}"
+                    );
+                }
+            }
+        }
+    }
+}
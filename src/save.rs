@@ -0,0 +1,141 @@
+use crate::game::GameState;
+use crate::migrations;
+use crate::rng::{GameRng, RngKind, RngState};
+use crate::timestamp::rfc3339_now;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Bumped on every incompatible change of saved games, with a step in `migrations`
+pub const SAVE_VERSION: u32 = 3;
+
+// A game put aside mid-round. The CPU goes on from `rng_state`, the state its RNG was
+// in; without one, as for generators that don't show their state, with a fresh RNG of
+// kind `rng` seeded from `seed`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    pub date: String,
+    pub seed: u64,
+    pub rng: RngKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rng_state: Option<RngState>,
+    pub state: GameState,
+}
+
+impl SavedGame {
+    pub fn new(state: GameState, rng_state: Option<RngState>) -> Self {
+        SavedGame {
+            version: SAVE_VERSION,
+            date: rfc3339_now(),
+            seed: rand::random(),
+            rng: RngKind::ChaCha8,
+            rng_state,
+            state,
+        }
+    }
+
+    // The RNG to resume the game with
+    pub fn rng(&self) -> GameRng {
+        match self.rng_state {
+            Some(state) => GameRng::from_state(state),
+            None => GameRng::seeded(self.rng, self.seed),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        write_atomic(path.as_ref(), &(json + "\n"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<SavedGame, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|err| format!("Not a saved game: {}", err))?;
+        let value = migrations::upgrade(&migrations::SAVE, value)?;
+        serde_json::from_value(value).map_err(|err| format!("Invalid saved game: {}", err))
+    }
+}
+
+// Writes `contents` to a file next to `path`, then renames it over `path`, so that a
+// crash midway leaves the old file whole instead of half a new one. Creates the
+// directory if need be.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("Can't create {}: {}", dir.display(), err))?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|err| format!("Can't write {}: {}", path.display(), err))
+}
+
+// Where saved games and other files kept between sessions go: $TIC_TAC_TOE_DIR, else the
+// platform's data directory. None when neither can be found.
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("TIC_TAC_TOE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+    };
+    base.map(|base| base.join("tic-tac-toe"))
+}
+
+// The single slot `pause` writes to and the next launch offers to resume
+pub fn autosave_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("autosave.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ttt-{}-{}", name, process::id()))
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_whole() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("nested").join("save.json");
+        write_atomic(&path, "first\n").unwrap();
+        write_atomic(&path, "second\n").unwrap();
+        let written = fs::read_to_string(&path);
+        let entries = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written.unwrap(), "second\n");
+        // The temporary file was renamed, not left behind
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn a_failed_write_leaves_the_old_file() {
+        let dir = temp_dir("atomic-fail");
+        let path = dir.join("save.json");
+        write_atomic(&path, "kept\n").unwrap();
+        // The temporary file can't be created where a directory is in the way
+        fs::create_dir_all(dir.join("save.json.tmp")).unwrap();
+        let err = write_atomic(&path, "lost\n");
+        let kept = fs::read_to_string(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(err.unwrap_err().starts_with("Can't write "));
+        assert_eq!(kept.unwrap(), "kept\n");
+    }
+}
@@ -0,0 +1,2245 @@
+use crate::auth::{self, Identity, Tokens};
+use crate::board::State;
+use crate::events::{Event, Observer};
+use crate::game::{Ending, Game, Move, Phase, PickError, Score, Status};
+use crate::history::{Archive, Played, Record};
+use crate::metrics::{GameMetrics, Metrics};
+use crate::rate_limit::{Limit, RateLimiter, Verdict};
+use crate::replay::ReplayMove;
+use crate::rules::Rules;
+use crate::session_log::event_fields;
+use crate::settings::Settings;
+use axum::body::Bytes;
+use axum::extract::rejection::{BytesRejection, QueryRejection};
+use axum::extract::{self, ConnectInfo, DefaultBodyLimit, Path, Query, Request};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// How often games left idle are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// Events a slow subscriber may fall behind by before it is sent the whole state instead
+const EVENT_BUFFER: usize = 64;
+
+// Largest request body, far more than any valid one needs
+const MAX_BODY: usize = 1024;
+
+// The page served at /, playing through the API below
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+// Finished matches stay this long after their last request, for the players to see how
+// they ended
+const FINISHED_MATCH: Duration = Duration::from_secs(5 * 60);
+
+// Letters and digits of join codes, leaving out those easily mistaken for another
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+
+// How the server treats its clients
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    // Games left alone this long are dropped
+    pub idle: Duration,
+    // Moves and new games each client address may make
+    pub moves: Limit,
+    pub games: Limit,
+    // Refused requests in a row after which a client's games are dropped
+    pub abuse_threshold: u32,
+    // How long a token from POST /players stays valid
+    pub token_lifetime: Duration,
+    // Whether games may be started without a token, owned by the client's address
+    pub anonymous: bool,
+    // Matches between two people each client address may take part in at once
+    pub matches: usize,
+    // SQLite database finished matches are kept in, None to keep none
+    pub history: Option<String>,
+    // Origin of pages served elsewhere that may use the API, such as a dashboard under
+    // development; None allows only the server's own page
+    pub cors_origin: Option<String>,
+}
+
+struct Entry {
+    game: Game,
+    // Address of the client that created it
+    owner: IpAddr,
+    // The player whose token created it. Only they may change it, or without a token only
+    // `owner`; everyone else spectates.
+    player: Option<Identity>,
+    // Last time a request used the game
+    touched: Instant,
+    // Subscribes to the game's events
+    events: broadcast::Sender<Event>,
+    // Moves of the current round, so spectators joining late see how it came about
+    moves: Arc<Mutex<Vec<ReplayMove>>>,
+}
+
+impl Entry {
+    // Refuses a request to change the game from anyone but its owner
+    fn check_owner(&self, client: IpAddr, identity: Option<&Identity>) -> Result<(), ApiError> {
+        if is_player(self.owner, self.player.as_ref(), client, identity) {
+            Ok(())
+        } else {
+            Err(ApiError::spectator())
+        }
+    }
+}
+
+// Whether a request comes from whoever took a side: by their token if they had one, else
+// from their address
+fn is_player(
+    address: IpAddr,
+    player: Option<&Identity>,
+    client: IpAddr,
+    identity: Option<&Identity>,
+) -> bool {
+    match (player, identity) {
+        (Some(player), Some(identity)) => auth::same(&player.hash, &identity.hash),
+        (Some(_), None) => false,
+        (None, _) => client == address,
+    }
+}
+
+// Passes a game's events on to its subscribers, if there are any
+struct Broadcast(broadcast::Sender<Event>);
+
+impl Observer for Broadcast {
+    fn on_event(&mut self, event: &Event) {
+        let _ = self.0.send(*event);
+    }
+}
+
+// Keeps the moves of the round being played
+struct History(Arc<Mutex<Vec<ReplayMove>>>);
+
+impl Observer for History {
+    fn on_event(&mut self, event: &Event) {
+        match *event {
+            Event::RoundStart { .. } => lock(&self.0).clear(),
+            Event::Move {
+                mark,
+                index,
+                digit,
+                auto,
+                elapsed,
+                ..
+            } => lock(&self.0).push(ReplayMove {
+                mark,
+                index,
+                digit,
+                auto,
+                elapsed_ms: elapsed.as_millis() as u64,
+            }),
+            _ => (),
+        }
+    }
+}
+
+// A side of a match: who may play it and the name shown for them
+struct Seat {
+    address: IpAddr,
+    player: Option<Identity>,
+    name: Option<String>,
+}
+
+// A game between two people, found by its join code. Whoever created it plays X, whoever
+// joined by the code O.
+struct Match {
+    game: Game,
+    x: Seat,
+    // None until someone joins
+    o: Option<Seat>,
+    // Last time a request used the match
+    touched: Instant,
+    // When both seats were taken, or until then when it was created
+    started: SystemTime,
+    events: broadcast::Sender<Event>,
+    moves: Arc<Mutex<Vec<ReplayMove>>>,
+}
+
+impl Match {
+    // The mark the request's sender plays, None for a spectator
+    fn mark_of(&self, client: IpAddr, identity: Option<&Identity>) -> Option<State> {
+        let plays = |seat: &Seat| is_player(seat.address, seat.player.as_ref(), client, identity);
+        if plays(&self.x) {
+            Some(State::X)
+        } else if self.o.as_ref().is_some_and(plays) {
+            Some(State::O)
+        } else {
+            None
+        }
+    }
+
+    // Whether `client` has a seat, for the limit on matches per address
+    fn has_seat(&self, client: IpAddr) -> bool {
+        self.x.address == client || self.o.as_ref().is_some_and(|o| o.address == client)
+    }
+
+    fn is_over(&self) -> bool {
+        matches!(self.game.phase(), Phase::RoundOver(_) | Phase::Invalid)
+    }
+}
+
+#[derive(Default)]
+struct Games {
+    next_id: u64,
+    games: HashMap<String, Entry>,
+    // By join code
+    matches: HashMap<String, Match>,
+}
+
+type Shared = Arc<Mutex<Games>>;
+
+struct Limiters {
+    moves: RateLimiter<IpAddr>,
+    games: RateLimiter<IpAddr>,
+}
+
+// What the handlers share
+#[derive(Clone)]
+struct App {
+    games: Shared,
+    metrics: Arc<Metrics>,
+    limiters: Arc<Mutex<Limiters>>,
+    tokens: Arc<Mutex<Tokens>>,
+    anonymous: bool,
+    matches: usize,
+    archive: Archive,
+    cors_origin: Option<HeaderValue>,
+}
+
+impl App {
+    fn new(config: &ServerConfig) -> Result<Self, String> {
+        let cors_origin = match &config.cors_origin {
+            Some(origin) => Some(
+                HeaderValue::from_str(origin)
+                    .map_err(|_| format!("Invalid CORS origin: {}", origin))?,
+            ),
+            None => None,
+        };
+        // A server that can't keep its history still plays, it only says so
+        let archive = match &config.history {
+            Some(path) => Archive::open(path).unwrap_or_else(|err| {
+                eprintln!("{}, no match history will be kept", err);
+                Archive::default()
+            }),
+            None => Archive::default(),
+        };
+        Ok(App {
+            games: Shared::default(),
+            metrics: Arc::new(
+                Metrics::new().map_err(|err| format!("Can't set up the metrics: {}", err))?,
+            ),
+            limiters: Arc::new(Mutex::new(Limiters {
+                moves: RateLimiter::new(config.moves, config.abuse_threshold),
+                games: RateLimiter::new(config.games, config.abuse_threshold),
+            })),
+            tokens: Arc::new(Mutex::new(Tokens::new(config.token_lifetime))),
+            anonymous: config.anonymous,
+            matches: config.matches,
+            archive,
+            cors_origin,
+        })
+    }
+
+    // The player named by the request's `Authorization: Bearer <token>`, None without one
+    fn identify(&self, headers: &HeaderMap) -> Result<Option<Identity>, ApiError> {
+        let value = match headers.get(header::AUTHORIZATION) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let token = value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                ApiError::unauthorized("invalid_token", "Send the token as Bearer <token>")
+            })?;
+        lock(&self.tokens)
+            .identify(token.trim(), Instant::now())
+            .map(Some)
+            .ok_or_else(|| {
+                ApiError::unauthorized("invalid_token", "The token is unknown or has expired")
+            })
+    }
+
+    // Refuses a request of a client that made too many of its kind lately. One that
+    // keeps on loses its games as well.
+    fn limit(
+        &self,
+        client: IpAddr,
+        limiter: fn(&mut Limiters) -> &mut RateLimiter<IpAddr>,
+    ) -> Result<(), ApiError> {
+        let verdict = limiter(&mut lock(&self.limiters)).check(client, Instant::now());
+        match verdict {
+            Verdict::Allowed => Ok(()),
+            Verdict::Limited(wait) => Err(ApiError {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                code: "rate_limited",
+                message: format!("Too many requests, retry in {:.1}s", wait.as_secs_f64()),
+                retry_after: Some(wait),
+            }),
+            Verdict::Abusive => {
+                let mut games = lock(&self.games);
+                games.games.retain(|_, entry| entry.owner != client);
+                games.matches.retain(|_, entry| !entry.has_seat(client));
+                Err(ApiError {
+                    status: StatusCode::TOO_MANY_REQUESTS,
+                    code: "abusive",
+                    message: "Too many refused requests, your games were dropped".to_string(),
+                    retry_after: None,
+                })
+            }
+        }
+    }
+}
+
+// Body of POST /games, which may also be empty
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NewGame {
+    difficulty: Option<String>,
+}
+
+// Body of POST /matches and POST /matches/{code}/join, which may also be empty. The
+// name is only used without a token, which brings its own.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Joining {
+    name: Option<String>,
+}
+
+// Body of POST /players
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Registration {
+    name: String,
+}
+
+// Answer to POST /players, the only time the token is ever shown
+#[derive(Debug, Serialize)]
+struct Registered {
+    name: String,
+    token: String,
+    // Seconds until the token expires
+    expires_in: u64,
+}
+
+// Body of POST /games/{id}/moves
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MoveRequest {
+    index: usize,
+}
+
+// A game as the API answers with it
+#[derive(Debug, Serialize)]
+struct GameView {
+    id: String,
+    // Display name of the player that owns it, None for an anonymous game
+    player: Option<String>,
+    // Compact form, row by row: X, O or . for each cell
+    board: String,
+    rules: Rules,
+    difficulty: String,
+    human_mark: State,
+    to_move: State,
+    phase: Phase,
+    status: Status,
+    // Set when the round was resigned or drawn by agreement
+    ending: Option<Ending>,
+    // The CPU's latest move, None before it has moved
+    cpu_move: Option<usize>,
+    score: Score,
+    // Every move of the round so far, in order
+    moves: Vec<ReplayMove>,
+}
+
+impl GameView {
+    fn new(id: &str, entry: &Entry) -> Self {
+        let game = &entry.game;
+        GameView {
+            id: id.to_string(),
+            player: entry.player.as_ref().map(|player| player.name.clone()),
+            board: game
+                .board()
+                .map(|board| board.to_string())
+                .unwrap_or_default(),
+            rules: game.rules(),
+            difficulty: game.settings().difficulty.to_string(),
+            human_mark: game.human_mark(),
+            to_move: game.whose_turn(),
+            phase: game.phase(),
+            status: game.status(),
+            ending: game.ending(),
+            cpu_move: game.last_decision().map(|decision| decision.index),
+            score: game.score(),
+            moves: lock(&entry.moves).clone(),
+        }
+    }
+}
+
+// A match as the API answers with it
+#[derive(Debug, Serialize)]
+struct MatchView {
+    code: String,
+    // Names of the players of X and O, None for one who gave none or a free seat
+    x: Option<String>,
+    o: Option<String>,
+    // Whether O's seat is still free
+    waiting: bool,
+    // The mark of whoever asked, None for spectators
+    you: Option<State>,
+    // Compact form, row by row: X, O or . for each cell
+    board: String,
+    rules: Rules,
+    to_move: State,
+    phase: Phase,
+    status: Status,
+    // Every move so far, in order
+    moves: Vec<ReplayMove>,
+}
+
+impl MatchView {
+    fn new(code: &str, entry: &Match, you: Option<State>) -> Self {
+        let game = &entry.game;
+        MatchView {
+            code: code.to_string(),
+            x: entry.x.name.clone(),
+            o: entry.o.as_ref().and_then(|o| o.name.clone()),
+            waiting: entry.o.is_none(),
+            you,
+            board: game
+                .board()
+                .map(|board| board.to_string())
+                .unwrap_or_default(),
+            rules: game.rules(),
+            to_move: game.whose_turn(),
+            phase: game.phase(),
+            status: game.status(),
+            moves: lock(&entry.moves).clone(),
+        }
+    }
+}
+
+// A failed request: the HTTP status, a code for programs and a message for people
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    // When a rate limited client may try again
+    retry_after: Option<Duration>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+    message: &'a str,
+}
+
+impl ApiError {
+    fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status: StatusCode::BAD_REQUEST,
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn unauthorized(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status: StatusCode::UNAUTHORIZED,
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn unknown_game(id: &str) -> Self {
+        ApiError {
+            status: StatusCode::NOT_FOUND,
+            code: "unknown_game",
+            message: format!("There is no game {}", id),
+            retry_after: None,
+        }
+    }
+
+    fn unknown_match(code: &str) -> Self {
+        ApiError {
+            status: StatusCode::NOT_FOUND,
+            code: "unknown_match",
+            message: format!("There is no match {}", code),
+            retry_after: None,
+        }
+    }
+
+    fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status: StatusCode::CONFLICT,
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn spectator() -> Self {
+        ApiError {
+            status: StatusCode::FORBIDDEN,
+            code: "spectator",
+            message: "Spectators can watch the game but not play it".to_string(),
+            retry_after: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.code,
+            message: &self.message,
+        };
+        let mut response = (self.status, Json(body)).into_response();
+        response.extensions_mut().insert(ErrorCode(self.code));
+        if let Some(wait) = self.retry_after {
+            let seconds = wait.as_secs_f64().ceil() as u64;
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+// Marks the response to a failed request, for the metrics
+#[derive(Debug, Clone, Copy)]
+struct ErrorCode(&'static str);
+
+impl From<PickError> for ApiError {
+    fn from(err: PickError) -> Self {
+        let (code, message) = match err {
+            PickError::AreaOccupied { index, occupant } => {
+                let message = format!("Cell {} is already taken by {:?}", index, occupant);
+                return ApiError::bad_request("area_occupied", message);
+            }
+            PickError::ColumnFull => ("column_full", "That column is already full"),
+            PickError::DigitNotYours => ("digit_not_yours", "That digit is the other side's"),
+            PickError::DigitUsed => ("digit_used", "That digit has already been played"),
+            PickError::DrawOffered => ("draw_offered", "A draw offer awaits an answer"),
+            PickError::InvalidBoard => ("invalid_board", "Both sides have a line on this board"),
+            PickError::MovesMapNotInitialized => {
+                ("moves_map_not_initialized", "The game has not started")
+            }
+            PickError::NoDrawOffer => ("no_draw_offer", "No draw has been offered"),
+            PickError::NotYourTurn => ("not_your_turn", "It's not your turn"),
+            PickError::OutOfBounds { given, max } => {
+                let message = format!("There is no cell {}, the last one is {}", given, max);
+                return ApiError::bad_request("out_of_bounds", message);
+            }
+            PickError::WrongPhase => ("wrong_phase", "The round is already over"),
+        };
+        ApiError::bad_request(code, message)
+    }
+}
+
+// A handler that panicked mid-move can't be undone, the other games are still fine
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// The body within `MAX_BODY`
+fn read_body(body: Result<Bytes, BytesRejection>) -> Result<Bytes, ApiError> {
+    body.map_err(|err| {
+        let code = if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            "body_too_large"
+        } else {
+            "invalid_body"
+        };
+        ApiError {
+            status: err.status(),
+            code,
+            message: err.body_text(),
+            retry_after: None,
+        }
+    })
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body)
+        .map_err(|err| ApiError::bad_request("invalid_body", format!("Invalid body: {}", err)))
+}
+
+// Runs `f` on the game `id`, marking it as used
+fn with_game<T>(
+    games: &Mutex<Games>,
+    id: &str,
+    f: impl FnOnce(&mut Entry) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let mut games = lock(games);
+    let entry = games
+        .games
+        .get_mut(id)
+        .ok_or_else(|| ApiError::unknown_game(id))?;
+    entry.touched = Instant::now();
+    f(entry)
+}
+
+// Runs `f` on the match with join code `code`, marking it as used
+fn with_match<T>(
+    games: &Mutex<Games>,
+    code: &str,
+    f: impl FnOnce(&mut Match) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let mut games = lock(games);
+    let entry = games
+        .matches
+        .get_mut(code)
+        .ok_or_else(|| ApiError::unknown_match(code))?;
+    entry.touched = Instant::now();
+    f(entry)
+}
+
+// The whole game as an event, first on every stream
+fn state_event(id: &str, entry: &Entry) -> sse::Event {
+    view_event("game", &GameView::new(id, entry))
+}
+
+// The same for a match, as spectators see it
+fn match_event(code: &str, entry: &Match) -> sse::Event {
+    view_event("match", &MatchView::new(code, entry, None))
+}
+
+fn view_event(kind: &str, view: &impl Serialize) -> sse::Event {
+    // Views are plain data, a failure here is a bug
+    let view = serde_json::to_string(view);
+    debug_assert!(view.is_ok(), "a view always serializes");
+    let view = view.unwrap_or_else(|_| "null".to_string());
+    sse::Event::default().data(format!("{{\"event\":\"state\",\"{}\":{}}}", kind, view))
+}
+
+// `state`, then each event as it happens until the sender is dropped. A subscriber that
+// fell behind is sent `current` instead of what it missed, the stream ends once that is
+// None.
+fn follow(
+    state: sse::Event,
+    receiver: broadcast::Receiver<Event>,
+    current: impl Fn() -> Option<sse::Event> + Send + 'static,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let updates = stream::unfold((receiver, current), |(mut receiver, current)| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => sse::Event::default().data(format!("{{{}}}", event_fields(&event))),
+            // Missed events are made up for with the whole state
+            Err(RecvError::Lagged(_)) => current()?,
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), (receiver, current)))
+    });
+    let stream = stream::once(async { Ok(state) }).chain(updates);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+// POST /players: a token for the name in the body, to send with the requests of the
+// games it starts
+async fn register(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<(StatusCode, Json<Registered>), ApiError> {
+    app.limit(client.ip(), |limiters| &mut limiters.games)?;
+    let request: Registration = parse_body(&read_body(body)?)?;
+    let mut tokens = lock(&app.tokens);
+    let token = tokens
+        .register(&request.name, Instant::now())
+        .map_err(|err| ApiError::bad_request("invalid_name", err))?;
+    let registered = Registered {
+        name: request.name.trim().to_string(),
+        token,
+        expires_in: tokens.lifetime().as_secs(),
+    };
+    Ok((StatusCode::CREATED, Json(registered)))
+}
+
+// POST /games: a classic game against the CPU, its first round started. With a token it
+// belongs to that player, else to the client's address if the server allows that.
+async fn create(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<(StatusCode, Json<GameView>), ApiError> {
+    app.limit(client.ip(), |limiters| &mut limiters.games)?;
+    let player = app.identify(&headers)?;
+    if player.is_none() && !app.anonymous {
+        return Err(ApiError::unauthorized(
+            "auth_required",
+            "Games need a token, get one from POST /players",
+        ));
+    }
+    let body = read_body(body)?;
+    let request: NewGame = if body.is_empty() {
+        NewGame::default()
+    } else {
+        parse_body(&body)?
+    };
+    let difficulty = match request.difficulty {
+        Some(difficulty) => difficulty
+            .parse()
+            .map_err(|err: String| ApiError::bad_request("invalid_difficulty", err))?,
+        None => Default::default(),
+    };
+    let settings = Settings {
+        difficulty,
+        ..Settings::default()
+    };
+    let mut game = Game::with_settings(Rules::default(), settings);
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let moves = Arc::default();
+    game.add_observer(Box::new(Broadcast(events.clone())));
+    game.add_observer(Box::new(History(Arc::clone(&moves))));
+    game.add_observer(Box::new(GameMetrics(Arc::clone(&app.metrics))));
+    game.new_round();
+    app.metrics.games_created.inc();
+    let mut games = lock(&app.games);
+    games.next_id += 1;
+    let id = games.next_id.to_string();
+    let entry = Entry {
+        game,
+        owner: client.ip(),
+        player,
+        touched: Instant::now(),
+        events,
+        moves,
+    };
+    let view = GameView::new(&id, &entry);
+    games.games.insert(id, entry);
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+// GET /games/{id}, open to spectators as well
+async fn show(
+    extract::State(App { games, .. }): extract::State<App>,
+    Path(id): Path<String>,
+) -> Result<Json<GameView>, ApiError> {
+    with_game(&games, &id, |entry| Ok(Json(GameView::new(&id, entry))))
+}
+
+// POST /games/{id}/moves: the player's move, answered by the CPU's unless it ended the round
+async fn play(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Json<GameView>, ApiError> {
+    app.limit(client.ip(), |limiters| &mut limiters.moves)?;
+    let identity = app.identify(&headers)?;
+    let request: MoveRequest = parse_body(&read_body(body)?)?;
+    with_game(&app.games, &id, |entry| {
+        entry.check_owner(client.ip(), identity.as_ref())?;
+        let game = &mut entry.game;
+        let cpu = game.human_mark().opponent();
+        let cpu_marks = |game: &Game| game.board().map_or(0, |board| board.count(cpu));
+        let (marks, thought) = (cpu_marks(game), game.session_times().cpu);
+        game.submit(Move {
+            index: request.index,
+            mark: None,
+            digit: None,
+        })?;
+        if cpu_marks(game) > marks {
+            app.metrics.cpu_move(game.session_times().cpu - thought);
+        }
+        Ok(Json(GameView::new(&id, entry)))
+    })
+}
+
+// POST /games/{id}/resign: the player gives up the round, which the CPU wins
+async fn resign(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<GameView>, ApiError> {
+    let identity = app.identify(&headers)?;
+    with_game(&app.games, &id, |entry| {
+        entry.check_owner(client.ip(), identity.as_ref())?;
+        entry.game.resign()?;
+        Ok(Json(GameView::new(&id, entry)))
+    })
+}
+
+// POST /games/{id}/draw: the player offers a draw. The CPU answers at once, taking it
+// only when it can't do better; the round goes on otherwise.
+async fn offer_draw(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<GameView>, ApiError> {
+    let identity = app.identify(&headers)?;
+    with_game(&app.games, &id, |entry| {
+        entry.check_owner(client.ip(), identity.as_ref())?;
+        entry.game.offer_draw()?;
+        Ok(Json(GameView::new(&id, entry)))
+    })
+}
+
+// POST /games/{id}/rounds: the next round, once the current one is over
+async fn next_round(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<GameView>, ApiError> {
+    let identity = app.identify(&headers)?;
+    with_game(&app.games, &id, |entry| {
+        entry.check_owner(client.ip(), identity.as_ref())?;
+        let game = &mut entry.game;
+        if !matches!(game.phase(), Phase::RoundOver(_) | Phase::Invalid) {
+            return Err(ApiError::bad_request(
+                "round_in_progress",
+                "The round isn't over yet",
+            ));
+        }
+        game.new_round();
+        Ok(Json(GameView::new(&id, entry)))
+    })
+}
+
+// GET /games/{id}/events: a stream of the game's state, then of each event as it
+// happens. Anyone may follow a game this way. It ends once the game is abandoned or
+// expires.
+async fn events(
+    extract::State(App { games, .. }): extract::State<App>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, ApiError> {
+    // Moves are made under the same lock, so none falls between the state and the events
+    let (state, receiver) = with_game(&games, &id, |entry| {
+        Ok((state_event(&id, entry), entry.events.subscribe()))
+    })?;
+    Ok(follow(state, receiver, move || {
+        let games = lock(&games);
+        Some(state_event(&id, games.games.get(&id)?))
+    }))
+}
+
+// DELETE /games/{id}: abandons the game
+async fn abandon(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let identity = app.identify(&headers)?;
+    let mut games = lock(&app.games);
+    let entry = games
+        .games
+        .get(&id)
+        .ok_or_else(|| ApiError::unknown_game(&id))?;
+    entry.check_owner(client.ip(), identity.as_ref())?;
+    games.games.remove(&id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The seat a request to create or join a match takes. With a token it is that player's,
+// else the client's address under the name in the body, if the server allows that.
+fn take_seat(
+    app: &App,
+    client: IpAddr,
+    headers: &HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Seat, ApiError> {
+    let player = app.identify(headers)?;
+    if player.is_none() && !app.anonymous {
+        return Err(ApiError::unauthorized(
+            "auth_required",
+            "Matches need a token, get one from POST /players",
+        ));
+    }
+    let body = read_body(body)?;
+    let request: Joining = if body.is_empty() {
+        Joining::default()
+    } else {
+        parse_body(&body)?
+    };
+    let name = match (&player, request.name) {
+        (Some(player), _) => Some(player.name.clone()),
+        (None, Some(name)) => Some(
+            auth::check_name(&name)
+                .map_err(|err| ApiError::bad_request("invalid_name", err))?
+                .to_string(),
+        ),
+        (None, None) => None,
+    };
+    Ok(Seat {
+        address: client,
+        player,
+        name,
+    })
+}
+
+// Refuses a seat in one more match to a client already playing as many as allowed
+fn check_open_matches(app: &App, games: &Games, client: IpAddr) -> Result<(), ApiError> {
+    let open = games
+        .matches
+        .values()
+        .filter(|entry| !entry.is_over() && entry.has_seat(client))
+        .count();
+    if open < app.matches {
+        Ok(())
+    } else {
+        Err(ApiError {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            code: "too_many_matches",
+            message: format!("Finish a match first, {} at once is the most", app.matches),
+            retry_after: None,
+        })
+    }
+}
+
+// A join code no match has yet
+fn new_code(matches: &HashMap<String, Match>) -> String {
+    let mut rng = rand::thread_rng();
+    loop {
+        let code: String = (0..CODE_LEN)
+            .map(|_| char::from(CODE_CHARS[rng.gen_range(0..CODE_CHARS.len())]))
+            .collect();
+        if !matches.contains_key(&code) {
+            return code;
+        }
+    }
+}
+
+// POST /matches: a classic game against another person, who joins it with the code in
+// the answer. Its creator plays X.
+async fn create_match(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<(StatusCode, Json<MatchView>), ApiError> {
+    app.limit(client.ip(), |limiters| &mut limiters.games)?;
+    let seat = take_seat(&app, client.ip(), &headers, body)?;
+    let mut games = lock(&app.games);
+    check_open_matches(&app, &games, client.ip())?;
+    let settings = Settings {
+        two_players: true,
+        ..Settings::default()
+    };
+    let mut game = Game::with_settings(Rules::default(), settings);
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let moves = Arc::default();
+    game.add_observer(Box::new(Broadcast(events.clone())));
+    game.add_observer(Box::new(History(Arc::clone(&moves))));
+    game.add_observer(Box::new(GameMetrics(Arc::clone(&app.metrics))));
+    game.new_round();
+    let code = new_code(&games.matches);
+    let entry = Match {
+        game,
+        x: seat,
+        o: None,
+        touched: Instant::now(),
+        started: SystemTime::now(),
+        events,
+        moves,
+    };
+    let view = MatchView::new(&code, &entry, Some(State::X));
+    games.matches.insert(code, entry);
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+// POST /matches/{code}/join: takes the free seat, playing O
+async fn join_match(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Json<MatchView>, ApiError> {
+    app.limit(client.ip(), |limiters| &mut limiters.games)?;
+    let seat = take_seat(&app, client.ip(), &headers, body)?;
+    let mut games = lock(&app.games);
+    check_open_matches(&app, &games, client.ip())?;
+    let entry = games
+        .matches
+        .get_mut(&code)
+        .ok_or_else(|| ApiError::unknown_match(&code))?;
+    if entry.mark_of(client.ip(), seat.player.as_ref()).is_some() {
+        return Err(ApiError::conflict(
+            "own_match",
+            "You already play this match, someone else has to join it",
+        ));
+    }
+    if entry.o.is_some() {
+        return Err(ApiError::conflict("match_full", "Both seats are taken"));
+    }
+    entry.o = Some(seat);
+    entry.touched = Instant::now();
+    entry.started = SystemTime::now();
+    Ok(Json(MatchView::new(&code, entry, Some(State::O))))
+}
+
+// GET /matches/{code}, open to spectators as well
+async fn show_match(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+) -> Result<Json<MatchView>, ApiError> {
+    let identity = app.identify(&headers)?;
+    with_match(&app.games, &code, |entry| {
+        let you = entry.mark_of(client.ip(), identity.as_ref());
+        Ok(Json(MatchView::new(&code, entry, you)))
+    })
+}
+
+// POST /matches/{code}/moves: a move of the player whose turn it is
+async fn play_match(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Json<MatchView>, ApiError> {
+    app.limit(client.ip(), |limiters| &mut limiters.moves)?;
+    let identity = app.identify(&headers)?;
+    let request: MoveRequest = parse_body(&read_body(body)?)?;
+    with_match(&app.games, &code, |entry| {
+        let mark = entry
+            .mark_of(client.ip(), identity.as_ref())
+            .ok_or_else(ApiError::spectator)?;
+        if entry.o.is_none() {
+            return Err(ApiError::conflict(
+                "waiting_for_opponent",
+                "Nobody has joined the match yet",
+            ));
+        }
+        let was_over = entry.is_over();
+        if mark != entry.game.whose_turn() && !was_over {
+            return Err(PickError::NotYourTurn.into());
+        }
+        entry.game.submit(Move {
+            index: request.index,
+            mark: None,
+            digit: None,
+        })?;
+        if !was_over && entry.is_over() {
+            app.archive.save(played(&code, entry));
+        }
+        Ok(Json(MatchView::new(&code, entry, Some(mark))))
+    })
+}
+
+// The match that just ended, as the history keeps it
+fn played(code: &str, entry: &Match) -> Played {
+    let millis = |time: SystemTime| {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    };
+    let ended = SystemTime::now();
+    Played {
+        code: code.to_string(),
+        x: entry.x.name.clone(),
+        o: entry.o.as_ref().and_then(|o| o.name.clone()),
+        winner: match entry.game.status() {
+            Status::Won(mark) => Some(mark),
+            _ => None,
+        },
+        moves: lock(&entry.moves)
+            .iter()
+            .map(|played| played.index)
+            .collect(),
+        started_ms: millis(entry.started),
+        ended_ms: millis(ended),
+        duration_ms: ended
+            .duration_since(entry.started)
+            .unwrap_or_default()
+            .as_millis() as u64,
+    }
+}
+
+const HISTORY_LIMIT: usize = 20;
+const MAX_HISTORY_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    player: Option<String>,
+    opponent: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct HistoryView {
+    // How `player` did against `opponent`, None when the query named neither
+    record: Option<Record>,
+    // Newest first
+    matches: Vec<Played>,
+}
+
+// GET /history: the latest finished matches, or with `player` and `opponent` only theirs
+// and how the first did against the second, at most `limit` of them
+async fn history(
+    extract::State(app): extract::State<App>,
+    query: Result<Query<HistoryQuery>, QueryRejection>,
+) -> Result<Json<HistoryView>, ApiError> {
+    let Query(query) =
+        query.map_err(|err| ApiError::bad_request("invalid_query", err.body_text()))?;
+    let limit = query.limit.unwrap_or(HISTORY_LIMIT).min(MAX_HISTORY_LIMIT);
+    let unavailable = |message| ApiError {
+        status: StatusCode::SERVICE_UNAVAILABLE,
+        code: "history_unavailable",
+        message,
+        retry_after: None,
+    };
+    let view = match (query.player, query.opponent) {
+        (Some(player), Some(opponent)) => {
+            let (record, matches) = app
+                .archive
+                .between(&player, &opponent, limit)
+                .await
+                .map_err(unavailable)?;
+            HistoryView {
+                record: Some(record),
+                matches,
+            }
+        }
+        (None, None) => HistoryView {
+            record: None,
+            matches: app.archive.recent(limit).await.map_err(unavailable)?,
+        },
+        _ => {
+            return Err(ApiError::bad_request(
+                "invalid_query",
+                "Give both player and opponent, or neither",
+            ))
+        }
+    };
+    Ok(Json(view))
+}
+
+// GET /matches/{code}/events: a stream of the match's state, then of each event as it
+// happens, for players and spectators alike. It ends once the match is left or expires.
+async fn match_events(
+    extract::State(App { games, .. }): extract::State<App>,
+    Path(code): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, ApiError> {
+    let (state, receiver) = with_match(&games, &code, |entry| {
+        Ok((match_event(&code, entry), entry.events.subscribe()))
+    })?;
+    Ok(follow(state, receiver, move || {
+        let games = lock(&games);
+        Some(match_event(&code, games.matches.get(&code)?))
+    }))
+}
+
+// DELETE /matches/{code}: one of the players leaves, which ends the match for both
+async fn leave_match(
+    extract::State(app): extract::State<App>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(code): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let identity = app.identify(&headers)?;
+    let mut games = lock(&app.games);
+    let entry = games
+        .matches
+        .get(&code)
+        .ok_or_else(|| ApiError::unknown_match(&code))?;
+    if entry.mark_of(client.ip(), identity.as_ref()).is_none() {
+        return Err(ApiError::spectator());
+    }
+    games.matches.remove(&code);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /: the dashboard
+async fn dashboard() -> Html<&'static str> {
+    Html(DASHBOARD)
+}
+
+// GET /metrics: Prometheus' text format
+async fn metrics(extract::State(app): extract::State<App>) -> impl IntoResponse {
+    let active = lock(&app.games).games.len();
+    app.metrics.active_games.set(active as i64);
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        app.metrics.render(),
+    )
+}
+
+// Counts the requests refused with an `ApiError`
+async fn count_errors(
+    extract::State(app): extract::State<App>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    if let Some(ErrorCode(code)) = response.extensions().get() {
+        app.metrics.errors.with_label_values(&[code]).inc();
+    }
+    response
+}
+
+// Lets the pages of the configured origin use the API, answering their preflight requests
+async fn cors(extract::State(app): extract::State<App>, request: Request, next: Next) -> Response {
+    let origin = match &app.cors_origin {
+        Some(origin) => origin.clone(),
+        None => return next.run(request).await,
+    };
+    let mut response = if request.method() == Method::OPTIONS {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, DELETE"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("authorization, content-type"),
+        );
+        response
+    } else {
+        next.run(request).await
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static("retry-after"),
+    );
+    headers.insert(header::VARY, HeaderValue::from_static("origin"));
+    response
+}
+
+fn router(app: App) -> Router {
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/games", post(create))
+        .route("/games/{id}", get(show).delete(abandon))
+        .route("/games/{id}/events", get(events))
+        .route("/games/{id}/moves", post(play))
+        .route("/games/{id}/resign", post(resign))
+        .route("/games/{id}/draw", post(offer_draw))
+        .route("/games/{id}/rounds", post(next_round))
+        .route("/matches", post(create_match))
+        .route("/matches/{code}", get(show_match).delete(leave_match))
+        .route("/matches/{code}/events", get(match_events))
+        .route("/matches/{code}/join", post(join_match))
+        .route("/matches/{code}/moves", post(play_match))
+        .route("/history", get(history))
+        .route("/metrics", get(metrics))
+        .route("/players", post(register))
+        .layer(middleware::from_fn_with_state(app.clone(), count_errors))
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+        .layer(middleware::from_fn_with_state(app.clone(), cors))
+        .with_state(app)
+}
+
+// Drops the games nobody used for `idle` by `now`, matches as well or after
+// `FINISHED_MATCH` once they are over, the rate limits of clients gone quiet and expired
+// tokens
+fn expire(app: &App, idle: Duration, now: Instant) {
+    let mut games = lock(&app.games);
+    games
+        .games
+        .retain(|_, entry| now.saturating_duration_since(entry.touched) < idle);
+    games.matches.retain(|_, entry| {
+        let keep = if entry.is_over() {
+            idle.min(FINISHED_MATCH)
+        } else {
+            idle
+        };
+        now.saturating_duration_since(entry.touched) < keep
+    });
+    drop(games);
+    let mut limiters = lock(&app.limiters);
+    limiters.moves.forget_idle(now);
+    limiters.games.forget_idle(now);
+    lock(&app.tokens).forget_expired(now);
+}
+
+async fn sweep(app: App, idle: Duration) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL.min(idle));
+    loop {
+        interval.tick().await;
+        expire(&app, idle, Instant::now());
+    }
+}
+
+// Serves the API on `addr` until the process is stopped. Games are only kept in memory.
+pub fn serve(addr: SocketAddr, config: ServerConfig) -> Result<(), String> {
+    let runtime =
+        tokio::runtime::Runtime::new().map_err(|err| format!("Can't start the server: {}", err))?;
+    runtime.block_on(async {
+        let app = App::new(&config)?;
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|err| format!("Can't listen on {}: {}", addr, err))?;
+        tokio::spawn(sweep(app.clone(), config.idle));
+        println!("Serving games on http://{}, play at http://{}/", addr, addr);
+        let service = router(app).into_make_service_with_connect_info::<SocketAddr>();
+        axum::serve(listener, service)
+            .await
+            .map_err(|err| format!("Server stopped: {}", err))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{self, Body};
+    use axum::http::{header, Method};
+    use serde_json::{json, Value};
+    use std::net::Ipv4Addr;
+    use tower::ServiceExt;
+
+    const OWNER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn config() -> ServerConfig {
+        ServerConfig {
+            idle: Duration::from_secs(60 * 60),
+            moves: Limit {
+                burst: 100,
+                per_second: 100.0,
+            },
+            games: Limit {
+                burst: 100,
+                per_second: 100.0,
+            },
+            abuse_threshold: 10,
+            token_lifetime: Duration::from_secs(60 * 60),
+            anonymous: true,
+            matches: 3,
+            history: None,
+            cors_origin: None,
+        }
+    }
+
+    // A request from `client`, as if it came in over a connection
+    fn request(client: IpAddr, method: Method, uri: &str, body: &str) -> Request {
+        let mut request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(client, 40000)));
+        request
+    }
+
+    fn get(uri: &str) -> Request {
+        request(OWNER, Method::GET, uri, "")
+    }
+
+    fn post(uri: &str, body: &str) -> Request {
+        request(OWNER, Method::POST, uri, body)
+    }
+
+    async fn send(app: &App, request: Request) -> Response {
+        router(app.clone()).oneshot(request).await.unwrap()
+    }
+
+    // The status and JSON body of the answer, Null without a body
+    async fn call(app: &App, request: Request) -> (StatusCode, Value) {
+        let response = send(app, request).await;
+        let status = response.status();
+        let bytes = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        if bytes.is_empty() {
+            (status, Value::Null)
+        } else {
+            (status, serde_json::from_slice(&bytes).unwrap())
+        }
+    }
+
+    // A new game's id
+    async fn create_game(app: &App) -> String {
+        let (status, game) = call(app, post("/games", "")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        game["id"].as_str().unwrap().to_string()
+    }
+
+    // Plays the first free cell until the round is over, returning the game then
+    async fn play_out(app: &App, id: &str) -> Value {
+        loop {
+            let (_, game) = call(app, get(&format!("/games/{}", id))).await;
+            if game["phase"] != "AwaitingPlayer" {
+                return game;
+            }
+            let index = game["board"].as_str().unwrap().find('.').unwrap();
+            let body = json!({ "index": index }).to_string();
+            let (status, _) = call(app, post(&format!("/games/{}/moves", id), &body)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+    }
+
+    fn code(answer: &(StatusCode, Value)) -> (StatusCode, &str) {
+        (answer.0, answer.1["error"].as_str().unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn a_game_is_created_played_fetched_and_abandoned() {
+        let app = App::new(&config()).unwrap();
+        let (status, game) = call(&app, post("/games", r#"{"difficulty":"hard"}"#)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(game["board"], ".........");
+        assert_eq!(game["difficulty"], "hard");
+        assert_eq!(game["phase"], "AwaitingPlayer");
+        let id = game["id"].as_str().unwrap();
+
+        let (status, game) = call(
+            &app,
+            post(&format!("/games/{}/moves", id), r#"{"index":4}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let board = game["board"].as_str().unwrap();
+        assert_eq!(&board[4..5], "X");
+        assert_eq!(board.matches('O').count(), 1);
+        let cpu = game["cpu_move"].as_u64().unwrap() as usize;
+        assert_eq!(&board[cpu..cpu + 1], "O");
+        assert_eq!(game["moves"].as_array().unwrap().len(), 2);
+
+        let (status, fetched) = call(&app, get(&format!("/games/{}", id))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(fetched, game);
+
+        let deleted = call(
+            &app,
+            request(OWNER, Method::DELETE, &format!("/games/{}", id), ""),
+        )
+        .await;
+        assert_eq!(deleted, (StatusCode::NO_CONTENT, Value::Null));
+        let fetched = call(&app, get(&format!("/games/{}", id))).await;
+        assert_eq!(code(&fetched), (StatusCode::NOT_FOUND, "unknown_game"));
+    }
+
+    #[tokio::test]
+    async fn a_finished_round_is_followed_by_the_next() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        let rounds = format!("/games/{}/rounds", id);
+        let early = call(&app, post(&rounds, "")).await;
+        assert_eq!(code(&early), (StatusCode::BAD_REQUEST, "round_in_progress"));
+
+        let game = play_out(&app, &id).await;
+        assert!(game["phase"]["RoundOver"].is_string(), "{}", game);
+        let (status, game) = call(&app, post(&rounds, "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(game["board"], ".........");
+        assert_eq!(game["moves"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn refused_moves_answer_with_their_code() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        let moves = format!("/games/{}/moves", id);
+        call(&app, post(&moves, r#"{"index":0}"#)).await;
+
+        let taken = call(&app, post(&moves, r#"{"index":0}"#)).await;
+        assert_eq!(code(&taken), (StatusCode::BAD_REQUEST, "area_occupied"));
+        let outside = call(&app, post(&moves, r#"{"index":9}"#)).await;
+        assert_eq!(code(&outside), (StatusCode::BAD_REQUEST, "out_of_bounds"));
+        for body in ["", "{", r#"{"index":-1}"#, r#"{"index":1,"mark":"x"}"#] {
+            let invalid = call(&app, post(&moves, body)).await;
+            assert_eq!(
+                code(&invalid),
+                (StatusCode::BAD_REQUEST, "invalid_body"),
+                "{}",
+                body
+            );
+        }
+        let unknown = call(&app, post("/games/99/moves", r#"{"index":1}"#)).await;
+        assert_eq!(code(&unknown), (StatusCode::NOT_FOUND, "unknown_game"));
+        assert_eq!(unknown.1["message"], "There is no game 99");
+
+        play_out(&app, &id).await;
+        let over = call(&app, post(&moves, r#"{"index":1}"#)).await;
+        assert_eq!(code(&over), (StatusCode::BAD_REQUEST, "wrong_phase"));
+    }
+
+    #[tokio::test]
+    async fn rounds_end_by_resigning_or_an_accepted_draw() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        // The empty board is a draw with best play, so the CPU takes it
+        let (status, game) = call(&app, post(&format!("/games/{}/draw", id), "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(game["phase"]["RoundOver"], "Tie");
+        assert_eq!(game["ending"], "DrawAgreed");
+        assert_eq!(game["score"]["tie"], 1);
+
+        call(&app, post(&format!("/games/{}/rounds", id), "")).await;
+        let resign = format!("/games/{}/resign", id);
+        let (status, game) = call(&app, post(&resign, "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(game["phase"]["RoundOver"], "CpuWin");
+        assert_eq!(game["ending"]["Resigned"], "x");
+        assert_eq!(game["score"]["cpu"], 1);
+        let again = call(&app, post(&resign, "")).await;
+        assert_eq!(code(&again), (StatusCode::BAD_REQUEST, "wrong_phase"));
+    }
+
+    #[tokio::test]
+    async fn new_games_check_their_options() {
+        let app = App::new(&config()).unwrap();
+        let unknown = call(&app, post("/games", r#"{"difficulty":"godlike"}"#)).await;
+        assert_eq!(
+            code(&unknown),
+            (StatusCode::BAD_REQUEST, "invalid_difficulty")
+        );
+        let extra = call(&app, post("/games", r#"{"rows":4}"#)).await;
+        assert_eq!(code(&extra), (StatusCode::BAD_REQUEST, "invalid_body"));
+    }
+
+    #[test]
+    fn every_pick_error_has_its_own_code() {
+        let errors = [
+            PickError::AreaOccupied {
+                index: 4,
+                occupant: State::X,
+            },
+            PickError::ColumnFull,
+            PickError::DigitNotYours,
+            PickError::DigitUsed,
+            PickError::DrawOffered,
+            PickError::InvalidBoard,
+            PickError::MovesMapNotInitialized,
+            PickError::NoDrawOffer,
+            PickError::NotYourTurn,
+            PickError::OutOfBounds { given: 9, max: 8 },
+            PickError::WrongPhase,
+        ];
+        let mut codes = Vec::new();
+        for err in errors {
+            let api = ApiError::from(err);
+            assert_eq!(api.status, StatusCode::BAD_REQUEST);
+            assert!(!api.message.is_empty());
+            assert!(!codes.contains(&api.code), "{}", api.code);
+            codes.push(api.code);
+        }
+        assert_eq!(ApiError::from(PickError::NotYourTurn).code, "not_your_turn");
+        let api = ApiError::from(PickError::OutOfBounds { given: 9, max: 8 });
+        assert_eq!(api.message, "There is no cell 9, the last one is 8");
+    }
+
+    #[tokio::test]
+    async fn idle_games_expire() {
+        let app = App::new(&config()).unwrap();
+        let old = create_game(&app).await;
+        let later = Instant::now() + Duration::from_secs(30 * 60);
+        lock(&app.games).games.get_mut(&old).unwrap().touched -= Duration::from_secs(45 * 60);
+        let recent = create_game(&app).await;
+
+        expire(&app, config().idle, later);
+        let games = lock(&app.games);
+        assert!(!games.games.contains_key(&old));
+        assert!(games.games.contains_key(&recent));
+    }
+
+    // The data of a stream's events, one by one
+    struct Events {
+        stream: axum::body::BodyDataStream,
+        read: String,
+    }
+
+    impl Events {
+        async fn subscribe(app: &App, id: &str) -> Self {
+            Self::follow(app, &format!("/games/{}/events", id)).await
+        }
+
+        async fn follow(app: &App, uri: &str) -> Self {
+            let response = send(app, get(uri)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                "text/event-stream"
+            );
+            Events {
+                stream: response.into_body().into_data_stream(),
+                read: String::new(),
+            }
+        }
+
+        // None once the stream ends
+        async fn next(&mut self) -> Option<Value> {
+            loop {
+                if let Some(end) = self.read.find("\n\n") {
+                    let event: String = self.read.drain(..end + 2).collect();
+                    if let Some(data) = event.lines().find_map(|line| line.strip_prefix("data: ")) {
+                        return Some(serde_json::from_str(data).unwrap());
+                    }
+                    continue;
+                }
+                let chunk = tokio::time::timeout(Duration::from_secs(5), self.stream.next())
+                    .await
+                    .expect("an event within 5s")?;
+                self.read
+                    .push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn events_follow_the_game() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        let mut events = Events::subscribe(&app, &id).await;
+        let state = events.next().await.unwrap();
+        assert_eq!(state["event"], "state");
+        assert_eq!(state["game"]["board"], ".........");
+
+        call(
+            &app,
+            post(&format!("/games/{}/moves", id), r#"{"index":4}"#),
+        )
+        .await;
+        let player = events.next().await.unwrap();
+        assert_eq!(
+            player,
+            json!({"event": "move", "mark": "x", "index": 4, "by": "player"})
+        );
+        let cpu = events.next().await.unwrap();
+        assert_eq!(
+            (&cpu["event"], &cpu["mark"], &cpu["by"]),
+            (&json!("move"), &json!("o"), &json!("cpu"))
+        );
+
+        let game = play_out(&app, &id).await;
+        let mut seen = Vec::new();
+        loop {
+            let event = events.next().await.unwrap();
+            if event["event"] == "result" {
+                let result = match game["status"]["Won"].as_str() {
+                    Some(mark) => format!("{}_wins", mark),
+                    None => "tie".to_string(),
+                };
+                assert_eq!(event["result"], result);
+                break;
+            }
+            assert_eq!(event["event"], "move");
+            seen.push(event["index"].as_u64().unwrap());
+        }
+        let moves: Vec<_> = game["moves"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["index"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seen, moves[2..]);
+
+        call(&app, post(&format!("/games/{}/rounds", id), "")).await;
+        let mut next = events.next().await.unwrap();
+        while next["event"] != "round_start" {
+            next = events.next().await.unwrap();
+        }
+        assert_eq!(next["round"], 2);
+    }
+
+    #[tokio::test]
+    async fn late_subscribers_start_from_the_current_state() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        call(
+            &app,
+            post(&format!("/games/{}/moves", id), r#"{"index":0}"#),
+        )
+        .await;
+        let mut events = Events::subscribe(&app, &id).await;
+        let state = events.next().await.unwrap();
+        let board = state["game"]["board"].as_str().unwrap();
+        assert!(board.starts_with('X'));
+        assert_eq!(board.matches('O').count(), 1);
+        assert_eq!(state["game"]["moves"].as_array().unwrap().len(), 2);
+
+        let missing = send(&app, get("/games/7/events")).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn streams_end_with_their_game() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        let mut events = Events::subscribe(&app, &id).await;
+        events.next().await.unwrap();
+        call(
+            &app,
+            request(OWNER, Method::DELETE, &format!("/games/{}", id), ""),
+        )
+        .await;
+        assert_eq!(events.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn metrics_count_what_the_api_did() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        create_game(&app).await;
+        let game = play_out(&app, &id).await;
+        call(&app, post("/games/99/moves", r#"{"index":0}"#)).await;
+
+        let response = send(&app, get("/metrics")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let moves = game["moves"].as_array().unwrap();
+        let by = |who| moves.iter().filter(|m| m["mark"] == who).count();
+        let result = match game["status"]["Won"].as_str() {
+            Some(mark) => format!("{}_wins", mark),
+            None => "tie".to_string(),
+        };
+        for line in [
+            "tic_tac_toe_games_created_total 2".to_string(),
+            "tic_tac_toe_active_games 2".to_string(),
+            format!("tic_tac_toe_moves_total{{by=\"player\"}} {}", by("x")),
+            format!("tic_tac_toe_moves_total{{by=\"cpu\"}} {}", by("o")),
+            format!("tic_tac_toe_cpu_move_seconds_count {}", by("o")),
+            format!(
+                "tic_tac_toe_rounds_completed_total{{result=\"{}\"}} 1",
+                result
+            ),
+            "tic_tac_toe_errors_total{code=\"unknown_game\"} 1".to_string(),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{} in\n{}", line, text);
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_over_their_limit_are_refused() {
+        let app = App::new(&ServerConfig {
+            games: Limit {
+                burst: 2,
+                per_second: 0.5,
+            },
+            abuse_threshold: 2,
+            ..config()
+        })
+        .unwrap();
+        let kept = create_game(&app).await;
+        create_game(&app).await;
+        let response = send(&app, post("/games", "")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+        let limited = call(&app, post("/games", "")).await;
+        assert_eq!(
+            code(&limited),
+            (StatusCode::TOO_MANY_REQUESTS, "rate_limited")
+        );
+        // Other clients and other kinds of request still go through
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let (status, _) = call(&app, request(other, Method::POST, "/games", "")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let moved = call(
+            &app,
+            post(&format!("/games/{}/moves", kept), r#"{"index":4}"#),
+        )
+        .await;
+        assert_eq!(moved.0, StatusCode::OK);
+
+        let abusive = call(&app, post("/games", "")).await;
+        assert_eq!(code(&abusive), (StatusCode::TOO_MANY_REQUESTS, "abusive"));
+        let games = lock(&app.games);
+        assert_eq!(games.games.len(), 1);
+        assert!(games.games.values().all(|entry| entry.owner == other));
+    }
+
+    #[tokio::test]
+    async fn large_bodies_and_long_names_are_refused() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        let padding = " ".repeat(MAX_BODY);
+        let body = format!(r#"{{"index":4}}{}"#, padding);
+        let large = call(&app, post(&format!("/games/{}/moves", id), &body)).await;
+        assert_eq!(
+            code(&large),
+            (StatusCode::PAYLOAD_TOO_LARGE, "body_too_large")
+        );
+        let name = json!({ "name": "n".repeat(200) }).to_string();
+        let long = call(&app, post("/players", &name)).await;
+        assert_eq!(code(&long), (StatusCode::BAD_REQUEST, "invalid_name"));
+    }
+
+    const SPECTATOR: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+
+    #[tokio::test]
+    async fn spectators_watch_but_cannot_play() {
+        let app = App::new(&config()).unwrap();
+        let id = create_game(&app).await;
+        call(
+            &app,
+            post(&format!("/games/{}/moves", id), r#"{"index":4}"#),
+        )
+        .await;
+        let mut events = Events::subscribe(&app, &id).await;
+        let joined = events.next().await.unwrap();
+        assert_eq!(joined["game"]["moves"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            joined["game"]["score"],
+            json!({"player": 0, "cpu": 0, "tie": 0})
+        );
+
+        for (method, path, body) in [
+            (Method::POST, "moves", r#"{"index":0}"#),
+            (Method::POST, "rounds", ""),
+        ] {
+            let uri = format!("/games/{}/{}", id, path);
+            let refused = call(&app, request(SPECTATOR, method, &uri, body)).await;
+            assert_eq!(
+                code(&refused),
+                (StatusCode::FORBIDDEN, "spectator"),
+                "{}",
+                uri
+            );
+        }
+        let uri = format!("/games/{}", id);
+        let refused = call(&app, request(SPECTATOR, Method::DELETE, &uri, "")).await;
+        assert_eq!(code(&refused), (StatusCode::FORBIDDEN, "spectator"));
+        let (status, watched) = call(&app, request(SPECTATOR, Method::GET, &uri, "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(watched["moves"].as_array().unwrap().len(), 2);
+
+        let game = play_out(&app, &id).await;
+        let mut board = joined["game"]["board"].as_str().unwrap().to_string();
+        loop {
+            let event = events.next().await.unwrap();
+            match event["event"].as_str().unwrap() {
+                "move" => {
+                    let index = event["index"].as_u64().unwrap() as usize;
+                    let mark = event["mark"].as_str().unwrap().to_uppercase();
+                    board.replace_range(index..index + 1, &mark);
+                }
+                "result" => break,
+                _ => (),
+            }
+        }
+        assert_eq!(board, game["board"].as_str().unwrap());
+        let (_, watched) = call(&app, request(SPECTATOR, Method::GET, &uri, "")).await;
+        assert_eq!(watched, game);
+    }
+
+    fn authorized(mut request: Request, token: &str) -> Request {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+        request
+    }
+
+    // A token for `name`
+    async fn register_player(app: &App, name: &str) -> String {
+        let body = json!({ "name": name }).to_string();
+        let (status, registered) = call(app, post("/players", &body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(registered["name"], name);
+        assert_eq!(registered["expires_in"], 60 * 60);
+        registered["token"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn games_belong_to_the_token_that_created_them() {
+        let app = App::new(&ServerConfig {
+            anonymous: false,
+            ..config()
+        })
+        .unwrap();
+        let refused = call(&app, post("/games", "")).await;
+        assert_eq!(code(&refused), (StatusCode::UNAUTHORIZED, "auth_required"));
+        let refused = call(&app, post("/matches", "")).await;
+        assert_eq!(code(&refused), (StatusCode::UNAUTHORIZED, "auth_required"));
+
+        let alice = register_player(&app, "Alice").await;
+        let mallory = register_player(&app, "Mallory").await;
+        let (status, game) = call(&app, authorized(post("/games", ""), &alice)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(game["player"], "Alice");
+        let moves = format!("/games/{}/moves", game["id"].as_str().unwrap());
+
+        // The same address with another token, or none, only spectates
+        let wrong = call(&app, authorized(post(&moves, r#"{"index":4}"#), &mallory)).await;
+        assert_eq!(code(&wrong), (StatusCode::FORBIDDEN, "spectator"));
+        let missing = call(&app, post(&moves, r#"{"index":4}"#)).await;
+        assert_eq!(code(&missing), (StatusCode::FORBIDDEN, "spectator"));
+        // The owner's token works from any address
+        let elsewhere = request(SPECTATOR, Method::POST, &moves, r#"{"index":4}"#);
+        let (status, played) = call(&app, authorized(elsewhere, &alice)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(played["board"].as_str().unwrap().as_bytes()[4], b'X');
+        assert_eq!(played["player"], "Alice");
+    }
+
+    #[tokio::test]
+    async fn bad_tokens_are_refused() {
+        let app = App::new(&config()).unwrap();
+        let unknown = call(&app, authorized(post("/games", ""), "00ff")).await;
+        assert_eq!(code(&unknown), (StatusCode::UNAUTHORIZED, "invalid_token"));
+        assert_eq!(unknown.1["message"], "The token is unknown or has expired");
+        let mut basic = post("/games", "");
+        basic.headers_mut().insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic YTpi"),
+        );
+        let basic = call(&app, basic).await;
+        assert_eq!(code(&basic), (StatusCode::UNAUTHORIZED, "invalid_token"));
+        assert_eq!(basic.1["message"], "Send the token as Bearer <token>");
+
+        let token = register_player(&app, "Carol").await;
+        lock(&app.tokens).forget_expired(Instant::now() + Duration::from_secs(2 * 60 * 60));
+        let expired = call(&app, authorized(post("/games", ""), &token)).await;
+        assert_eq!(code(&expired), (StatusCode::UNAUTHORIZED, "invalid_token"));
+
+        let bad_name = call(&app, post("/players", r#"{"name":""}"#)).await;
+        assert_eq!(code(&bad_name), (StatusCode::BAD_REQUEST, "invalid_name"));
+        // Anonymous games stay open when the server allows them
+        let (status, game) = call(&app, post("/games", "")).await;
+        assert_eq!(
+            (status, &game["player"]),
+            (StatusCode::CREATED, &Value::Null)
+        );
+    }
+
+    #[tokio::test]
+    async fn the_dashboard_uses_routes_that_exist() {
+        let app = App::new(&config()).unwrap();
+        let response = send(&app, get("/")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let page = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page = std::str::from_utf8(&page).unwrap();
+        assert_eq!(page, DASHBOARD);
+
+        let id = create_game(&app).await;
+        let calls = [
+            (Method::POST, "\"/players\"", "/players".to_string()),
+            (Method::POST, "\"/games\"", "/games".to_string()),
+            (Method::GET, "\"/games/\" + id)", format!("/games/{}", id)),
+            (
+                Method::GET,
+                "\"/games/\" + id + \"/events\"",
+                format!("/games/{}/events", id),
+            ),
+            (
+                Method::POST,
+                "\"/games/\" + game.id + \"/moves\"",
+                format!("/games/{}/moves", id),
+            ),
+            (
+                Method::POST,
+                "\"/games/\" + game.id + \"/rounds\"",
+                format!("/games/{}/rounds", id),
+            ),
+            (
+                Method::DELETE,
+                "\"/games/\" + game.id)",
+                format!("/games/{}", id),
+            ),
+        ];
+        for (method, in_page, uri) in calls {
+            assert!(page.contains(in_page), "{}", in_page);
+            let response = send(&app, request(OWNER, method.clone(), &uri, "")).await;
+            // Axum answers routes it doesn't know with an empty 404 or a 405
+            let known = response.extensions().get::<ErrorCode>().is_some()
+                || !matches!(
+                    response.status(),
+                    StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+                );
+            assert!(known, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_configured_origin_may_use_the_api() {
+        let origin = "http://localhost:5173";
+        let app = App::new(&ServerConfig {
+            cors_origin: Some(origin.to_string()),
+            ..config()
+        })
+        .unwrap();
+        let preflight = send(&app, request(OWNER, Method::OPTIONS, "/games", "")).await;
+        assert_eq!(preflight.status(), StatusCode::NO_CONTENT);
+        let headers = preflight.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, POST, DELETE"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "authorization, content-type"
+        );
+        let created = send(&app, post("/games", "")).await;
+        assert_eq!(created.status(), StatusCode::CREATED);
+        assert_eq!(
+            created.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            origin
+        );
+        assert_eq!(
+            created.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            "retry-after"
+        );
+
+        let closed = App::new(&config()).unwrap();
+        let response = send(&closed, post("/games", "")).await;
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        let invalid = App::new(&ServerConfig {
+            cors_origin: Some("http://a\nb".to_string()),
+            ..config()
+        });
+        assert_eq!(
+            invalid.err(),
+            Some("Invalid CORS origin: http://a\nb".to_string())
+        );
+    }
+
+    const GUEST: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+
+    // Plays the first free cell whenever it is `client`'s turn, returning the match once
+    // it is over
+    async fn play_match_out(app: App, client: IpAddr, code: String) -> Value {
+        let uri = format!("/matches/{}", code);
+        loop {
+            let (status, view) = call(&app, request(client, Method::GET, &uri, "")).await;
+            assert_eq!(status, StatusCode::OK);
+            if view["phase"] != "AwaitingPlayer" {
+                return view;
+            }
+            if view["waiting"] == true || view["to_move"] != view["you"] {
+                tokio::task::yield_now().await;
+                continue;
+            }
+            let index = view["board"].as_str().unwrap().find('.').unwrap();
+            let body = json!({ "index": index }).to_string();
+            let moves = format!("{}/moves", uri);
+            let (status, _) = call(&app, request(client, Method::POST, &moves, &body)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn two_people_play_a_match_joined_by_its_code() {
+        let app = App::new(&config()).unwrap();
+        let (status, created) = call(&app, post("/matches", r#"{"name":" Ann "}"#)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["x"], "Ann");
+        assert_eq!(
+            (&created["you"], &created["waiting"]),
+            (&json!("x"), &json!(true))
+        );
+        let code = created["code"].as_str().unwrap().to_string();
+        assert_eq!(code.len(), CODE_LEN);
+        let mut events = Events::follow(&app, &format!("/matches/{}/events", code)).await;
+        let state = events.next().await.unwrap();
+        assert_eq!(state["match"]["you"], Value::Null);
+
+        let host = tokio::spawn(play_match_out(app.clone(), OWNER, code.clone()));
+        let guest = tokio::spawn({
+            let (app, code) = (app.clone(), code.clone());
+            async move {
+                let join = format!("/matches/{}/join", code);
+                let body = r#"{"name":"Bob"}"#;
+                let (status, joined) = call(&app, request(GUEST, Method::POST, &join, body)).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(
+                    (&joined["you"], &joined["waiting"]),
+                    (&json!("o"), &json!(false))
+                );
+                play_match_out(app, GUEST, code).await
+            }
+        });
+        let (host, guest) = (host.await.unwrap(), guest.await.unwrap());
+        assert_eq!(host["board"], "XOXOXOX..");
+        assert_eq!(host["status"], json!({"Won": "x"}));
+        assert_eq!((&host["x"], &host["o"]), (&json!("Ann"), &json!("Bob")));
+        for field in ["board", "status", "moves", "x", "o"] {
+            assert_eq!(host[field], guest[field], "{}", field);
+        }
+
+        // Following the stream, a spectator saw the same game
+        let mut board = state["match"]["board"].as_str().unwrap().to_string();
+        loop {
+            let event = events.next().await.unwrap();
+            match event["event"].as_str().unwrap() {
+                "move" => {
+                    let index = event["index"].as_u64().unwrap() as usize;
+                    let mark = event["mark"].as_str().unwrap().to_uppercase();
+                    board.replace_range(index..index + 1, &mark);
+                }
+                "result" => break,
+                _ => (),
+            }
+        }
+        assert_eq!(board, host["board"].as_str().unwrap());
+    }
+
+    #[tokio::test]
+    async fn matches_refuse_who_cannot_play() {
+        let app = App::new(&config()).unwrap();
+        let unknown = call(&app, get("/matches/NOPE42")).await;
+        assert_eq!(code(&unknown), (StatusCode::NOT_FOUND, "unknown_match"));
+        let (_, created) = call(&app, post("/matches", "")).await;
+        assert_eq!(created["x"], Value::Null);
+        let uri = format!("/matches/{}", created["code"].as_str().unwrap());
+        let (join, moves) = (format!("{}/join", uri), format!("{}/moves", uri));
+
+        let alone = call(&app, post(&moves, r#"{"index":4}"#)).await;
+        assert_eq!(code(&alone), (StatusCode::CONFLICT, "waiting_for_opponent"));
+        let own = call(&app, post(&join, "")).await;
+        assert_eq!(code(&own), (StatusCode::CONFLICT, "own_match"));
+        let name = json!({ "name": "n".repeat(200) }).to_string();
+        let long = call(&app, request(GUEST, Method::POST, &join, &name)).await;
+        assert_eq!(code(&long), (StatusCode::BAD_REQUEST, "invalid_name"));
+        let (status, _) = call(&app, request(GUEST, Method::POST, &join, "")).await;
+        assert_eq!(status, StatusCode::OK);
+        let full = call(&app, request(SPECTATOR, Method::POST, &join, "")).await;
+        assert_eq!(code(&full), (StatusCode::CONFLICT, "match_full"));
+
+        let body = r#"{"index":4}"#;
+        let watching = call(&app, request(SPECTATOR, Method::POST, &moves, body)).await;
+        assert_eq!(code(&watching), (StatusCode::FORBIDDEN, "spectator"));
+        let early = call(&app, request(GUEST, Method::POST, &moves, body)).await;
+        assert_eq!(code(&early), (StatusCode::BAD_REQUEST, "not_your_turn"));
+        let (status, moved) = call(&app, post(&moves, body)).await;
+        assert_eq!((status, &moved["to_move"]), (StatusCode::OK, &json!("o")));
+        let (_, watched) = call(&app, request(SPECTATOR, Method::GET, &uri, "")).await;
+        assert_eq!(watched["you"], Value::Null);
+        assert_eq!(watched["board"], "....X....");
+
+        let refused = call(&app, request(SPECTATOR, Method::DELETE, &uri, "")).await;
+        assert_eq!(code(&refused), (StatusCode::FORBIDDEN, "spectator"));
+        let (status, _) = call(&app, request(GUEST, Method::DELETE, &uri, "")).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let left = call(&app, get(&uri)).await;
+        assert_eq!(code(&left), (StatusCode::NOT_FOUND, "unknown_match"));
+    }
+
+    #[tokio::test]
+    async fn clients_play_a_limited_number_of_matches_at_once() {
+        let app = App::new(&ServerConfig {
+            matches: 1,
+            ..config()
+        })
+        .unwrap();
+        let (_, first) = call(&app, post("/matches", "")).await;
+        let code_of = |view: &Value| view["code"].as_str().unwrap().to_string();
+        let first = code_of(&first);
+        let refused = call(&app, post("/matches", "")).await;
+        assert_eq!(
+            code(&refused),
+            (StatusCode::TOO_MANY_REQUESTS, "too_many_matches")
+        );
+
+        // Once it is over it no longer counts
+        let uri = format!("/matches/{}", first);
+        call(
+            &app,
+            request(GUEST, Method::POST, &format!("{}/join", uri), ""),
+        )
+        .await;
+        for (client, index) in [(OWNER, 0), (GUEST, 3), (OWNER, 1), (GUEST, 4), (OWNER, 2)] {
+            let body = json!({ "index": index }).to_string();
+            let moves = format!("{}/moves", uri);
+            let (status, _) = call(&app, request(client, Method::POST, &moves, &body)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, second) = call(&app, post("/matches", "")).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Finished matches are dropped before the ones still waiting to be played
+        expire(&app, config().idle, Instant::now() + 2 * FINISHED_MATCH);
+        let games = lock(&app.games);
+        assert!(!games.matches.contains_key(&first));
+        assert!(games.matches.contains_key(&code_of(&second)));
+    }
+
+    #[tokio::test]
+    async fn finished_matches_are_kept_in_the_history() {
+        let app = App::new(&ServerConfig {
+            history: Some(":memory:".to_string()),
+            ..config()
+        })
+        .unwrap();
+        let (_, created) = call(&app, post("/matches", r#"{"name":"Ann"}"#)).await;
+        let uri = format!("/matches/{}", created["code"].as_str().unwrap());
+        let join = format!("{}/join", uri);
+        call(
+            &app,
+            request(GUEST, Method::POST, &join, r#"{"name":"Bob"}"#),
+        )
+        .await;
+        let moves = format!("{}/moves", uri);
+        for (client, index) in [(OWNER, 0), (GUEST, 3), (OWNER, 1), (GUEST, 4), (OWNER, 2)] {
+            let body = json!({ "index": index }).to_string();
+            call(&app, request(client, Method::POST, &moves, &body)).await;
+        }
+        // Moves after the end are refused and don't keep the match twice
+        call(&app, request(GUEST, Method::POST, &moves, r#"{"index":5}"#)).await;
+
+        let (status, recent) = call(&app, get("/history")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(recent["record"], Value::Null);
+        let kept = recent["matches"].as_array().unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(
+            (&kept[0]["x"], &kept[0]["o"]),
+            (&json!("Ann"), &json!("Bob"))
+        );
+        assert_eq!(kept[0]["winner"], "x");
+        assert_eq!(kept[0]["moves"], json!([0, 3, 1, 4, 2]));
+
+        let (status, between) = call(&app, get("/history?player=Bob&opponent=Ann")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            between["record"],
+            json!({"wins": 0, "losses": 1, "ties": 0})
+        );
+        let (_, none) = call(&app, get("/history?player=Bob&opponent=Carol&limit=5")).await;
+        assert_eq!(none["matches"], json!([]));
+        let half = call(&app, get("/history?player=Bob")).await;
+        assert_eq!(code(&half), (StatusCode::BAD_REQUEST, "invalid_query"));
+        let bad = call(&app, get("/history?limit=many")).await;
+        assert_eq!(code(&bad), (StatusCode::BAD_REQUEST, "invalid_query"));
+    }
+
+    #[tokio::test]
+    async fn a_server_without_history_still_plays() {
+        let off = App::new(&config()).unwrap();
+        let refused = call(&off, get("/history")).await;
+        assert_eq!(
+            code(&refused),
+            (StatusCode::SERVICE_UNAVAILABLE, "history_unavailable")
+        );
+        let broken = App::new(&ServerConfig {
+            history: Some("/nonexistent/dir/history.db".to_string()),
+            ..config()
+        })
+        .unwrap();
+        let refused = call(&broken, get("/history")).await;
+        assert_eq!(refused.0, StatusCode::SERVICE_UNAVAILABLE);
+        let (status, _) = call(&broken, post("/matches", "")).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+}
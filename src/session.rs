@@ -0,0 +1,282 @@
+use crate::game::{Console, Game, Handoff, Score, Scores, SessionSummary, TurnTimes};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::time::Instant;
+
+// Makes the game of a new slot, two players or against the CPU
+type NewGame = Box<dyn FnMut(bool) -> Result<Game, String>>;
+
+// Named games played side by side; `game new`, `game switch` and `game list` at the
+// prompt pick which one the keyboard plays, the others wait mid-round.
+pub struct Session {
+    games: BTreeMap<String, Game>,
+    active: String,
+    new_game: NewGame,
+    // Plays without showing anything on stdout
+    quiet: bool,
+}
+
+impl Session {
+    // Starts with `game` in a slot named `name`, `new_game` makes the games of later slots.
+    // Prompts name their slot once there is more than one.
+    pub fn new(
+        name: &str,
+        game: Game,
+        new_game: impl FnMut(bool) -> Result<Game, String> + 'static,
+    ) -> Self {
+        Session {
+            games: BTreeMap::from([(name.to_string(), game)]),
+            active: name.to_string(),
+            new_game: Box::new(new_game),
+            quiet: false,
+        }
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+
+    pub fn game(&self, name: &str) -> Option<&Game> {
+        self.games.get(name)
+    }
+
+    // Plays on stdin and stdout until the player stops
+    pub fn start(&mut self) -> SessionSummary {
+        let started = Instant::now();
+        let timed = self
+            .games
+            .values()
+            .any(|game| game.settings().auto_play.is_some());
+        let mut console = Console::terminal(timed, self.quiet);
+        if let Err(err) = self.play(&mut console) {
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("Can't write to the terminal: {}", err);
+            }
+        }
+        self.summary(started)
+    }
+
+    // Like `Game::start_with`, across all the slots
+    pub fn start_with<I: BufRead, W: Write>(
+        &mut self,
+        input: I,
+        output: W,
+    ) -> io::Result<SessionSummary> {
+        let started = Instant::now();
+        self.play(&mut Console {
+            input,
+            output,
+            colors: false,
+            lines: None,
+            debounce: false,
+            moved: false,
+        })?;
+        Ok(self.summary(started))
+    }
+
+    // Totals of every slot. Slots that name their sides differently are added up as the
+    // player's and the Cpu's.
+    fn summary(&self, started: Instant) -> SessionSummary {
+        let mut score = Score::default();
+        let mut scores = Scores::new();
+        let mut times = TurnTimes::default();
+        let mut distinct_games = 0;
+        for game in self.games.values() {
+            score += game.score();
+            for (opponent, game_score) in game.scores() {
+                *scores.entry(opponent.clone()).or_default() += *game_score;
+            }
+            distinct_games += game.distinct_games();
+            times.player += game.session_times().player;
+            times.cpu += game.session_times().cpu;
+        }
+        let mut sides = self.games.values().map(Game::side_names);
+        let first = sides.next().unwrap_or(["You", "Cpu"]);
+        SessionSummary {
+            score,
+            scores,
+            rounds: score.rounds(),
+            distinct_games,
+            duration: started.elapsed(),
+            times,
+            sides: if sides.all(|other| other == first) {
+                first
+            } else {
+                ["You", "Cpu"]
+            },
+        }
+    }
+
+    fn play<I: BufRead, W: Write>(&mut self, console: &mut Console<I, W>) -> io::Result<()> {
+        if !self.active_game()?.begin(console)? {
+            return Ok(());
+        }
+        loop {
+            match self.active_game()?.run(console, true)? {
+                Handoff::Ended => return Ok(()),
+                Handoff::Command(line) => {
+                    if !self.command(&line, console)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    // `active` only ever names a slot that holds a game
+    fn active_game(&mut self) -> io::Result<&mut Game> {
+        let game = self.games.get_mut(&self.active);
+        debug_assert!(game.is_some(), "the active slot always holds a game");
+        game.ok_or_else(|| io::Error::other(format!("There is no game named {}", self.active)))
+    }
+
+    // Handles a `game ...` line; false if the session ended meanwhile
+    fn command<I: BufRead, W: Write>(
+        &mut self,
+        line: &str,
+        console: &mut Console<I, W>,
+    ) -> io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().skip(1).collect();
+        match words.as_slice() {
+            ["new", name] | ["new", name, "--two-player"] => {
+                if self.games.contains_key(*name) {
+                    writeln!(console.output, "There already is a game named {}", name)?;
+                    return Ok(true);
+                }
+                let game = match (self.new_game)(words.len() == 3) {
+                    Ok(game) => game,
+                    Err(err) => {
+                        writeln!(console.output, "{}", err)?;
+                        return Ok(true);
+                    }
+                };
+                self.games.insert(name.to_string(), game);
+                for (name, game) in &mut self.games {
+                    game.set_label(name.as_str());
+                }
+                self.active = name.to_string();
+                writeln!(console.output, "** Started {} **", name)?;
+                self.active_game()?.begin(console)
+            }
+            ["switch", name] => {
+                if self.games.contains_key(*name) {
+                    self.active = name.to_string();
+                    writeln!(console.output, "** Switched to {} **", name)?;
+                } else {
+                    writeln!(console.output, "There is no game named {}", name)?;
+                }
+                Ok(true)
+            }
+            ["list"] => {
+                for (name, game) in &self.games {
+                    let marker = if *name == self.active { '*' } else { ' ' };
+                    let opponent = if game.settings().two_players {
+                        "two players".to_string()
+                    } else {
+                        format!("vs Cpu {}", game.settings().cpu())
+                    };
+                    let score = game.score();
+                    writeln!(
+                        console.output,
+                        "{} {}: {}, {}-{}-{}",
+                        marker, name, opponent, score.player, score.cpu, score.tie
+                    )?;
+                }
+                Ok(true)
+            }
+            _ => {
+                writeln!(
+                    console.output,
+                    "Use game new NAME [--two-player], game switch NAME or game list"
+                )?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::State;
+    use crate::rng::{GameRng, RngKind};
+    use crate::rules::Rules;
+    use crate::settings::Settings;
+
+    fn game(two_players: bool) -> Game {
+        let settings = Settings {
+            two_players,
+            ..Settings::default()
+        };
+        Game::with_rng(
+            Rules::default(),
+            settings,
+            GameRng::seeded(RngKind::ChaCha8, 7),
+        )
+    }
+
+    fn play(session: &mut Session, input: &str) -> String {
+        let mut output = Vec::new();
+        session.start_with(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    // The marks on a slot's board
+    fn marks(session: &Session, name: &str) -> (usize, usize) {
+        let board = session.game(name).unwrap().board().unwrap();
+        (board.count(State::X), board.count(State::O))
+    }
+
+    #[test]
+    fn switching_mid_round_keeps_both_boards() {
+        let mut session = Session::new("vs-cpu", game(false), |two_players| Ok(game(two_players)));
+        let output = play(
+            &mut session,
+            "4\ngame new kids --two-player\n0\ngame switch vs-cpu\n2\ngame switch kids\n8\ngame list\n",
+        );
+        assert_eq!(session.active(), "kids");
+        assert_eq!(marks(&session, "vs-cpu"), (2, 2));
+        assert_eq!(marks(&session, "kids"), (1, 1));
+        let kids = session.game("kids").unwrap().board().unwrap();
+        assert_eq!((kids[0], kids[8]), (State::X, State::O));
+        assert!(output.contains("** Started kids **"), "{}", output);
+        assert!(output.contains("** Switched to vs-cpu **"), "{}", output);
+        assert!(output.contains("  vs-cpu: vs Cpu"), "{}", output);
+        assert!(output.contains("* kids: two players, 0-0-0"), "{}", output);
+    }
+
+    #[test]
+    fn slot_commands_check_their_names() {
+        let mut session = Session::new("main", game(false), |two_players| Ok(game(two_players)));
+        let output = play(
+            &mut session,
+            "game switch nowhere\ngame new main\ngame frobnicate\n",
+        );
+        assert!(
+            output.contains("There is no game named nowhere"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("There already is a game named main"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Use game new NAME"), "{}", output);
+        assert_eq!(session.active(), "main");
+        assert!(session.game("nowhere").is_none());
+    }
+
+    #[test]
+    fn summaries_label_the_sides_the_slots_share() {
+        let mut session = Session::new("kids", game(true), |two_players| Ok(game(two_players)));
+        play(&mut session, "0\n3\n1\n4\n2\n");
+        assert_eq!(session.summary(Instant::now()).sides, ["X", "O"]);
+        play(&mut session, "game new vs-cpu\n");
+        assert_eq!(session.summary(Instant::now()).sides, ["You", "Cpu"]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::board::State;
+use crate::events::{Event, Observer};
+use crate::game::{Ending, Status};
+use crate::timestamp::rfc3339_now;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+// Appends every event to a file as one JSON object per line, flushed per round.
+// A write failure is reported once and then logging stops, the game goes on.
+pub struct FileLog {
+    file: Option<File>,
+    path: String,
+    pending: Vec<String>,
+}
+
+impl FileLog {
+    pub fn create(path: &str) -> std::io::Result<FileLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileLog {
+            file: Some(file),
+            path: path.to_string(),
+            pending: Vec::new(),
+        })
+    }
+
+    fn flush(&mut self) {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let result = self
+            .pending
+            .iter()
+            .try_for_each(|line| writeln!(file, "{}", line))
+            .and_then(|()| file.flush());
+        self.pending.clear();
+        if let Err(err) = result {
+            eprintln!(
+                "Warning: can't write to {} ({}), event logging disabled",
+                self.path, err
+            );
+            self.file = None;
+        }
+    }
+}
+
+impl Observer for FileLog {
+    fn on_event(&mut self, event: &Event) {
+        if self.file.is_none() {
+            return;
+        }
+        let fields = event_fields(event);
+        self.pending
+            .push(format!("{{\"time\":\"{}\",{}}}", rfc3339_now(), fields));
+
+        if matches!(event, Event::Score { .. } | Event::SessionEnd) {
+            self.flush();
+        }
+    }
+}
+
+// The event as JSON fields without the braces, "event" naming its kind
+pub fn event_fields(event: &Event) -> String {
+    match *event {
+        Event::RoundStart { round } => format!("\"event\":\"round_start\",\"round\":{}", round),
+        Event::Move {
+            mark,
+            index,
+            human,
+            auto,
+            ..
+        } => format!(
+            "\"event\":\"move\",\"mark\":\"{}\",\"index\":{},\"by\":\"{}\"{}",
+            mark_name(mark),
+            index,
+            if human { "player" } else { "cpu" },
+            if auto { ",\"auto\":true" } else { "" }
+        ),
+        Event::RoundEnd { status, ending } => {
+            let result = match status {
+                Status::Won(mark) => format!("{}_wins", mark_name(mark)),
+                Status::Tie => "tie".to_string(),
+                Status::InProgress => "in_progress".to_string(),
+                Status::Invalid => "invalid".to_string(),
+            };
+            let ending = match ending {
+                Some(Ending::Resigned(mark)) => {
+                    format!(",\"ending\":\"{}_resigns\"", mark_name(mark))
+                }
+                Some(Ending::DrawAgreed) => ",\"ending\":\"draw_agreed\"".to_string(),
+                None => String::new(),
+            };
+            format!("\"event\":\"result\",\"result\":\"{}\"{}", result, ending)
+        }
+        Event::Score { player, cpu, tie } => format!(
+            "\"event\":\"score\",\"player\":{},\"cpu\":{},\"tie\":{}",
+            player, cpu, tie
+        ),
+        Event::MatchEnd {
+            player,
+            cpu,
+            tiebreak_rounds,
+        } => format!(
+            "\"event\":\"match_end\",\"player\":{},\"cpu\":{},\"tiebreak_rounds\":{}",
+            player, cpu, tiebreak_rounds
+        ),
+        Event::SessionEnd => "\"event\":\"session_end\"".to_string(),
+    }
+}
+
+impl Drop for FileLog {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+fn mark_name(mark: State) -> &'static str {
+    match mark {
+        State::X => "x",
+        State::O => "o",
+        State::Empty => "empty",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use std::{env, fs, process};
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("ttt-{}-{}.jsonl", name, process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn events_are_written_as_json_lines_once_the_score_is_in() {
+        let path = temp_path("log");
+        let mut log = FileLog::create(&path).unwrap();
+        log.on_event(&Event::RoundStart { round: 1 });
+        log.on_event(&Event::RoundEnd {
+            status: Status::Won(State::X),
+            ending: None,
+        });
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        log.on_event(&Event::Score {
+            player: 1,
+            cpu: 0,
+            tie: 0,
+        });
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            let time = line.strip_prefix("{\"time\":\"").unwrap();
+            // Such as 2024-05-01T12:30:05.123Z
+            assert_eq!(&time[10..11], "T");
+            assert_eq!(&time[23..25], "Z\"");
+            assert!(line.ends_with('}'));
+        }
+        assert!(lines[0].ends_with(",\"event\":\"round_start\",\"round\":1}"));
+        assert!(lines[1].ends_with(",\"event\":\"result\",\"result\":\"x_wins\"}"));
+        assert!(lines[2].ends_with(",\"event\":\"score\",\"player\":1,\"cpu\":0,\"tie\":0}"));
+    }
+
+    #[test]
+    fn result_lines_say_how_the_players_ended_the_round() {
+        let resigned = Event::RoundEnd {
+            status: Status::Won(State::X),
+            ending: Some(Ending::Resigned(State::O)),
+        };
+        assert_eq!(
+            event_fields(&resigned),
+            "\"event\":\"result\",\"result\":\"x_wins\",\"ending\":\"o_resigns\""
+        );
+        let agreed = Event::RoundEnd {
+            status: Status::Tie,
+            ending: Some(Ending::DrawAgreed),
+        };
+        assert_eq!(
+            event_fields(&agreed),
+            "\"event\":\"result\",\"result\":\"tie\",\"ending\":\"draw_agreed\""
+        );
+    }
+
+    #[test]
+    fn move_lines_name_who_moved() {
+        let event = Event::Move {
+            mark: State::O,
+            index: 4,
+            digit: None,
+            human: false,
+            auto: false,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(
+            event_fields(&event),
+            "\"event\":\"move\",\"mark\":\"o\",\"index\":4,\"by\":\"cpu\""
+        );
+    }
+
+    // Every write to /dev/full fails for want of space
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_write_turns_logging_off() {
+        let mut log = FileLog::create("/dev/full").unwrap();
+        log.on_event(&Event::SessionEnd);
+        assert!(log.file.is_none());
+        log.on_event(&Event::RoundStart { round: 2 });
+        assert!(log.pending.is_empty());
+    }
+}
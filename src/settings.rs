@@ -0,0 +1,126 @@
+use crate::ai::{Cpu, Difficulty, Personality};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// Session options that don't change the rules of a single round
+// Options missing from saved settings take their defaults
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct Settings {
+    // Start the next round without asking "Play again?"
+    pub auto_rematch: bool,
+    // Let the CPU open every other round
+    pub alternate_opener: bool,
+    // End the match once either side reaches this many round wins
+    pub first_to: Option<u16>,
+    // A match of this many rounds, won by the side with more round wins
+    pub best_of: Option<u16>,
+    // Break a level best-of match with up to this many sudden-death rounds; without it,
+    // or once they run out, a level match is drawn
+    pub sudden_death: Option<u16>,
+    pub difficulty: Difficulty,
+    pub personality: Personality,
+    // Plies the hard CPU looks ahead on boards too big to search to the end
+    pub depth: Option<usize>,
+    // Show who is ahead under the board, toggled in game with `eval`
+    pub show_eval: bool,
+    // Ask before placing each move, toggled in game with `confirm on|off`
+    pub confirm_moves: bool,
+    // Say why the CPU made each move
+    pub explain: bool,
+    // Both sides move from the keyboard in turns, X's wins count as the player's
+    pub two_players: bool,
+    // Draw every cell as a block of ASCII art, e.g. for a projector
+    pub big_board: bool,
+    // Whether cells are typed and shown from 0 or from 1
+    pub numbering: Numbering,
+    // Seconds a player may leave a turn idle before the suggested move is played for them
+    pub auto_play: Option<u32>,
+    // Offer to end a round once best play has decided it
+    pub fast_finish: bool,
+    // End the session after this many rounds instead of asking to play again
+    pub rounds: Option<u16>,
+    // Show the board as one line in the prompt, leaving out the turn's messages and the
+    // summaries between rounds
+    pub summary_only: bool,
+}
+
+// What the first cell, column or coordinate is called in the game's input and output.
+// Boards, replays and logs always count from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Numbering {
+    #[default]
+    ZeroBased,
+    OneBased,
+}
+
+impl Numbering {
+    pub fn first(self) -> usize {
+        match self {
+            Numbering::ZeroBased => 0,
+            Numbering::OneBased => 1,
+        }
+    }
+}
+
+impl fmt::Display for Numbering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Numbering::ZeroBased => write!(f, "zero-based"),
+            Numbering::OneBased => write!(f, "one-based"),
+        }
+    }
+}
+
+impl FromStr for Numbering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Numbering, String> {
+        match s.to_lowercase().as_str() {
+            "zero-based" | "0" => Ok(Numbering::ZeroBased),
+            "one-based" | "1" => Ok(Numbering::OneBased),
+            _ => Err(format!(
+                "Unknown numbering: {} (zero-based or one-based)",
+                s
+            )),
+        }
+    }
+}
+
+impl Settings {
+    pub fn cpu(&self) -> Cpu {
+        Cpu {
+            difficulty: self.difficulty,
+            personality: self.personality,
+            depth: self.depth,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbering_reads_its_names_back() {
+        for numbering in [Numbering::ZeroBased, Numbering::OneBased] {
+            assert_eq!(numbering.to_string().parse(), Ok(numbering));
+        }
+        assert_eq!("1".parse(), Ok(Numbering::OneBased));
+        assert_eq!("Zero-Based".parse(), Ok(Numbering::ZeroBased));
+        assert_eq!(
+            "2".parse::<Numbering>(),
+            Err("Unknown numbering: 2 (zero-based or one-based)".to_string())
+        );
+        assert_eq!(Numbering::default().first(), 0);
+        assert_eq!(Numbering::OneBased.first(), 1);
+    }
+}
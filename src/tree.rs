@@ -0,0 +1,151 @@
+use crate::ai;
+use crate::board::{Board, State};
+use crate::rules::Rules;
+use std::fmt::Write;
+
+// Graphviz DOT rendering of the game tree below `board`, `depth` plies deep
+// and cut off after `max_nodes` nodes
+pub fn export_dot(board: &Board, rules: &Rules, depth: usize, max_nodes: usize) -> String {
+    let mut dot = String::from("digraph tree {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut count = 0;
+    add_node(&mut dot, board, rules, depth, max_nodes, &mut count);
+    dot.push_str("}\n");
+    dot
+}
+
+// Writes the node and its subtree, returning its id
+fn add_node(
+    dot: &mut String,
+    board: &Board,
+    rules: &Rules,
+    depth: usize,
+    max_nodes: usize,
+    count: &mut usize,
+) -> usize {
+    let id = *count;
+    *count += 1;
+
+    let to_move = board.to_move();
+    let winner = rules.winner(board);
+    let moves = rules.legal_moves(board);
+    let terminal = winner.is_some() || moves.is_empty();
+    let label = match winner {
+        Some(mark) => format!("{:?} won", mark),
+        None if moves.is_empty() => "draw".to_string(),
+        None => outlook(board, rules, to_move),
+    };
+    let style = match (terminal, winner) {
+        (false, _) => "",
+        (true, Some(State::X)) => ", style=filled, fillcolor=\"#f4a6a6\"",
+        (true, Some(_)) => ", style=filled, fillcolor=\"#a6c8f4\"",
+        (true, None) => ", style=filled, fillcolor=\"#d9d9d9\"",
+    };
+    let _ = writeln!(
+        dot,
+        "    n{} [label=\"{}\\n{}\"{}];",
+        id, board, label, style
+    );
+
+    if terminal || depth == 0 {
+        return id;
+    }
+    for index in moves {
+        if *count >= max_nodes {
+            break;
+        }
+        let mut child = *board;
+        child[index] = to_move;
+        let child_id = add_node(dot, &child, rules, depth - 1, max_nodes, count);
+        let _ = writeln!(dot, "    n{} -> n{} [label=\"{}\"];", id, child_id, index);
+    }
+    id
+}
+
+// Who wins with best play, or only an estimate on boards too big to search to the end
+fn outlook(board: &Board, rules: &Rules, to_move: State) -> String {
+    if board.count(State::Empty) <= ai::MAX_SEARCH_CELLS {
+        return ai::describe(ai::evaluate(board, rules, to_move), to_move);
+    }
+    match ai::search_to_depth(board, rules, to_move, ai::DEFAULT_DEPTH, &mut || false) {
+        Some(search) if ai::is_decisive(search.score) => ai::describe(search.score, to_move),
+        Some(search) => format!("estimated {:+} for {:?}", search.score, to_move),
+        None => "unknown".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes(dot: &str) -> Vec<&str> {
+        dot.lines()
+            .filter(|line| line.contains("[label=") && !line.contains("->"))
+            .collect()
+    }
+
+    // Balanced, every statement ended, and every edge between declared nodes
+    fn assert_well_formed(dot: &str) {
+        assert!(dot.starts_with("digraph tree {\n"));
+        assert!(dot.ends_with("}\n"));
+        let ids: Vec<&str> = nodes(dot)
+            .iter()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        for line in dot.lines().skip(1).filter(|&line| line != "}") {
+            assert!(line.ends_with(';'), "{}", line);
+            assert_eq!(line.matches('"').count() % 2, 0, "{}", line);
+            if let Some((from, rest)) = line.trim().split_once(" -> ") {
+                let to = rest.split_whitespace().next().unwrap();
+                assert!(ids.contains(&from) && ids.contains(&to), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_tree_is_well_formed_with_every_node() {
+        let board = Board::new(3, 3, 1);
+        let dot = export_dot(&board, &Rules::default(), 1, 1000);
+        assert_well_formed(&dot);
+        assert_eq!(nodes(&dot).len(), 10);
+        assert_eq!(dot.matches(" -> ").count(), 9);
+
+        let dot = export_dot(&board, &Rules::default(), 2, 1000);
+        assert_well_formed(&dot);
+        assert_eq!(nodes(&dot).len(), 1 + 9 + 9 * 8);
+    }
+
+    #[test]
+    fn node_cap_stops_the_walk() {
+        let dot = export_dot(&Board::new(3, 3, 1), &Rules::default(), 4, 25);
+        assert_well_formed(&dot);
+        assert_eq!(nodes(&dot).len(), 25);
+    }
+
+    #[test]
+    fn finished_games_are_coloured_by_result() {
+        let board: Board = "XX.OO....".parse().unwrap();
+        let dot = export_dot(&board, &Rules::default(), 1, 1000);
+        assert_well_formed(&dot);
+        let won: Vec<&str> = nodes(&dot)
+            .into_iter()
+            .filter(|node| node.contains("X won"))
+            .collect();
+        assert_eq!(won.len(), 1);
+        assert!(won[0].contains("fillcolor=\"#f4a6a6\""));
+        assert!(won[0].starts_with("    n1 [label=\"XXXOO....\\nX won\""));
+    }
+
+    #[test]
+    fn big_boards_are_labelled_with_estimates() {
+        let rules = Rules {
+            rows: 4,
+            cols: 4,
+            win_len: 4,
+            ..Rules::default()
+        };
+        let dot = export_dot(&rules.new_board(), &rules, 1, 1000);
+        assert_well_formed(&dot);
+        assert_eq!(nodes(&dot).len(), 17);
+        assert!(nodes(&dot).iter().all(|node| node.contains("estimated")));
+    }
+}
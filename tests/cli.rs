@@ -0,0 +1,366 @@
+// Runs the game binary as a child process, the way scripts and pipes use it
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::{env, fs, process};
+
+// A directory of its own for each test's saves and stats
+fn data_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ttt-cli-{}-{}", test, process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn game(dir: &PathBuf, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_tic-tac-toe-rs"));
+    command
+        .args(args)
+        .env("TIC_TAC_TOE_DIR", dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+// Runs the game with `input` on stdin, then removes its directory
+fn run(test: &str, args: &[&str], input: &str) -> Output {
+    let dir = data_dir(test);
+    let mut child = game(&dir, args).spawn().unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn truncated_move_list_ends_the_session_cleanly() {
+    let output = run("truncated", &["--seed", "1"], "4\n0\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("Thanks for playing"), "{}", stdout);
+    assert!(stdout.contains("Rounds played: 0"), "{}", stdout);
+}
+
+#[test]
+fn empty_input_ends_before_any_move() {
+    let output = run("empty", &["--seed", "1"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Rounds played: 0"));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn scripted_rounds_exit_with_who_won() {
+    let x_wins = run("x-wins", &["--two-players", "--quiet"], "0\n3\n1\n4\n2\n");
+    assert_eq!(x_wins.status.code(), Some(0));
+    assert!(x_wins.stdout.is_empty());
+    assert!(x_wins.stderr.is_empty());
+    let o_wins = run(
+        "o-wins",
+        &["--two-players", "--quiet"],
+        "0\n3\n1\n4\n8\n5\n",
+    );
+    assert_eq!(o_wins.status.code(), Some(1));
+    let usage = run("usage", &["--no-such-flag"], "");
+    assert_eq!(usage.status.code(), Some(3));
+}
+
+// Files and programs the game can't use end it with a message, never a panic
+#[test]
+fn unusable_inputs_are_reported_not_crashed_on() {
+    let dir = data_dir("unusable");
+    fs::write(dir.join("cut.ttt"), "{\"version\": 3").unwrap();
+    fs::write(dir.join("garbage.bin"), "garbage").unwrap();
+    let cases: &[(&[&str], &str)] = &[
+        (&["--size", "0x0"], "Board 0x0 is not supported"),
+        (
+            &[
+                "arena",
+                "--engine-x",
+                "/no/such/engine",
+                "--engine-o",
+                "cat",
+                "--games",
+                "1",
+            ],
+            "Can't start /no/such/engine",
+        ),
+    ];
+    // Replays, books and models are only read with serde
+    #[cfg(feature = "serde")]
+    let cases = &[
+        cases,
+        &[
+            (&["replay", "cut.ttt"][..], "Not a replay file"),
+            (&["--book", "garbage.bin"], "not an opening book"),
+            (&["--model", "garbage.bin"], "not a model file"),
+        ],
+    ]
+    .concat();
+    for &(args, message) in cases {
+        let output = game(&dir, args)
+            .current_dir(&dir)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(3), "{:?}: {}", args, stderr);
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn summary_only_rounds_show_one_line_per_turn() {
+    // X takes the top row, X takes it again, then a tie
+    let input = "0\n3\n1\n4\n2\n0\n3\n1\n4\n2\n4\n0\n2\n6\n3\n5\n1\n7\n8\n";
+    let output = run(
+        "summary-only",
+        &["--two-players", "--rounds", "3", "--summary-only"],
+        input,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Times vary from run to run
+    let lines: Vec<&str> = stdout
+        .lines()
+        .filter(|line| !line.starts_with("Time"))
+        .collect();
+    let won = [
+        "X to move: ...|...|...",
+        "O to move: X..|...|...",
+        "X to move: X..|O..|...",
+        "O to move: XX.|O..|...",
+        "X to move: XX.|OO.|...",
+    ];
+    let mut expected = vec!["Choose index(0 to 8) or a name like top-left:".to_string()];
+    for round in 1..=2 {
+        expected.extend(won.iter().map(|turn| format!("Round {}, {}", round, turn)));
+        expected.push("** X wins! **".to_string());
+    }
+    for turn in [
+        "X to move: ...|...|...",
+        "O to move: ...|.X.|...",
+        "X to move: O..|.X.|...",
+        "O to move: O.X|.X.|...",
+        "X to move: O.X|.X.|O..",
+        "O to move: O.X|XX.|O..",
+        "X to move: O.X|XXO|O..",
+        "O to move: OXX|XXO|O..",
+        "X to move: OXX|XXO|OO.",
+    ] {
+        expected.push(format!("Round 3, {}", turn));
+    }
+    for line in [
+        "** Tie! **",
+        "** Thanks for playing! **",
+        "Rounds played: 3",
+        "Distinct games: 2",
+        "X    2   67%",
+        "O    0    0%",
+        "Tie  1   33%",
+    ] {
+        expected.push(line.to_string());
+    }
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn closed_stdout_is_not_a_crash() {
+    let dir = data_dir("closed");
+    let mut child = game(&dir, &["--seed", "1", "--auto-rematch"])
+        .spawn()
+        .unwrap();
+    // Read a little, then hang up while the game still has plenty to say
+    let mut stdout = child.stdout.take().unwrap();
+    stdout.read_exact(&mut [0; 8]).unwrap();
+    drop(stdout);
+    let mut stdin = child.stdin.take().unwrap();
+    let _ = stdin.write_all("4\n0\n8\n2\n6\n1\n7\n3\n5\n".repeat(50).as_bytes());
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert_ne!(output.status.code(), Some(101));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn new_slots_can_be_started_while_tracing() {
+    let output = run(
+        "trace-slots",
+        &["--trace", "--seed", "1"],
+        "game new second\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("** Started second **"), "{}", stdout);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn engine_mode_answers_a_scripted_dialogue() {
+    let dir = data_dir("engine");
+    let mut child = game(&dir, &["engine"]).spawn().unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    // Sends `commands`, then reads up to the line starting with `last`
+    let mut ask = |commands: &str, last: &str| {
+        writeln!(stdin, "{}", commands).unwrap();
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "{:?}", lines);
+            let line = line.trim_end().to_string();
+            let done = line.starts_with(last);
+            lines.push(line);
+            if done {
+                return lines;
+            }
+        }
+    };
+
+    let hello = ask("uci", "uciok");
+    assert_eq!(hello, ["id name tic-tac-toe-rs", "uciok"]);
+    // Unknown commands get no answer at all
+    assert_eq!(ask("hello there\nisready", "readyok"), ["readyok"]);
+    let win = ask("position XX.OO....\ngo depth 4", "bestmove");
+    assert!(win[0].starts_with("info depth 1 nodes "), "{:?}", win);
+    assert!(
+        win.iter()
+            .all(|line| line.starts_with("info ") || line == "bestmove 2"),
+        "{:?}",
+        win
+    );
+    assert_eq!(win.last().unwrap(), "bestmove 2");
+    // O to move with a line to finish
+    let finish = ask(
+        "position startpos moves 4 0 8 2 6\ngo movetime 500",
+        "bestmove",
+    );
+    assert_eq!(finish.last().unwrap(), "bestmove 1");
+    let refused = ask("position startpos moves 4 4", "info string");
+    assert_eq!(refused, ["info string Move 4 is not legal"]);
+
+    writeln!(stdin, "quit").unwrap();
+    let status = child.wait().unwrap();
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(status.success());
+    assert!(rest.is_empty(), "{}", rest);
+}
+
+// The game binary in engine mode, as an arena starts it
+fn engine_command() -> String {
+    format!("{} engine", env!("CARGO_BIN_EXE_tic-tac-toe-rs"))
+}
+
+#[test]
+fn arena_referees_two_engines() {
+    let engine = engine_command();
+    let args = [
+        "arena",
+        "--engine-x",
+        &engine,
+        "--engine-o",
+        &engine,
+        "--games",
+        "2",
+        "--movetime",
+        "100",
+    ];
+    let output = run("engines", &args, "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Perfect play on both sides ties every game
+    let pairing = stdout
+        .lines()
+        .find(|line| line.starts_with("X "))
+        .unwrap_or_else(|| panic!("{}", stdout));
+    assert!(pairing.contains(" 2  0/0/2 "), "{}", stdout);
+    assert!(!stdout.contains("lost by"), "{}", stdout);
+}
+
+#[cfg(unix)]
+#[test]
+fn arena_forfeits_engines_that_misbehave() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = data_dir("misbehave");
+    // Answers every search with a cell the board doesn't have
+    let script = dir.join("illegal-engine");
+    fs::write(
+        &script,
+        "#!/bin/sh\nwhile read line; do case $line in go*) echo bestmove 9;; esac; done\n",
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let engine = engine_command();
+    let illegal = script.to_str().unwrap();
+    let arena = |x: &str, o: &str| {
+        let args = [
+            "arena",
+            "--engine-x",
+            x,
+            "--engine-o",
+            o,
+            "--games",
+            "1",
+            "--movetime",
+            "100",
+        ];
+        let output = game(&dir, &args).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let stdout = arena(illegal, &engine);
+    assert!(stdout.contains("X lost by illegal move '9'"), "{}", stdout);
+    // cat never answers with a move
+    let stdout = arena(&engine, "cat");
+    assert!(stdout.contains("O lost by timeout"), "{}", stdout);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn big_positions_are_estimated_not_solved() {
+    let output = run("solve-4x4", &["solve", "................"], "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("Too big to solve exactly"), "{}", stdout);
+    assert!(stdout.contains("X to move, estimated"), "{}", stdout);
+    assert!(stdout.contains("Principal variation: "), "{}", stdout);
+}
+
+#[test]
+fn big_boards_are_analyzed_and_benched_to_a_depth() {
+    let analyze = run("analyze-4x4", &["analyze", "................"], "");
+    let stdout = String::from_utf8_lossy(&analyze.stdout);
+    assert_eq!(analyze.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("searched 4 plies ahead"), "{}", stdout);
+    assert_eq!(stdout.matches(": estimated").count(), 16, "{}", stdout);
+
+    let bench = run(
+        "bench-4x4",
+        &["bench", "--size", "4x4", "--playouts", "1"],
+        "",
+    );
+    let stdout = String::from_utf8_lossy(&bench.stdout);
+    assert_eq!(bench.status.code(), Some(0), "{}", stdout);
+    assert!(
+        stdout.contains("Search empty 4x4 4 plies deep"),
+        "{}",
+        stdout
+    );
+}